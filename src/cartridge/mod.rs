@@ -8,7 +8,22 @@
 
 use std::fs::File;
 use std::io::Read;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+
+/// This reads the game title from the header (0x0134-0x0143), without the
+/// trailing NUL padding
+pub fn header_title(rom: &[u8]) -> String {
+    let title_bytes = rom.get(0x0134..=0x0143).unwrap_or(&[]);
+    String::from_utf8_lossy(title_bytes)
+        .trim_end_matches('\0')
+        .to_string()
+}
+
+/// This returns where a ROM's battery RAM is saved by default: next to the ROM
+/// with a .sav extension (game.gb -> game.sav)
+pub fn battery_save_path<P: AsRef<Path>>(rom_path: P) -> PathBuf {
+    rom_path.as_ref().with_extension("sav")
+}
 
 /// This struct represents a loaded cartridge with its ROM data and metadata
 pub struct Cartridge {
@@ -43,10 +58,7 @@ impl Cartridge {
         }
         
         // We extract the game title from bytes 0x0134-0x0143
-        let title_bytes = &rom[0x0134..=0x0143];
-        let title = String::from_utf8_lossy(title_bytes)
-            .trim_end_matches('\0')
-            .to_string();
+        let title = header_title(&rom);
         
         // We read the cartridge type byte which tells us the MBC type
         let cartridge_type = rom[0x0147];
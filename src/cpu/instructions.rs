@@ -6,8 +6,8 @@
 // Each instruction manipulates registers, memory, or flags according to the
 // Game Boy's CPU specification. Instructions are grouped by type.
 
-use super::Cpu;
-use crate::mmu::Mmu;
+use super::{Cpu, StrictnessMode};
+use crate::mmu::Bus;
 
 // Register identifiers for ld_r_r and similar operations
 pub const REG_A: u8 = 0;
@@ -19,22 +19,22 @@ pub const REG_H: u8 = 5;
 pub const REG_L: u8 = 6;
 
 /// This helper reads an 8-bit immediate value from PC and advances PC
-fn read_u8(cpu: &mut Cpu, mmu: &Mmu) -> u8 {
-    let value = mmu.read_byte(cpu.registers.pc);
+fn read_u8(cpu: &mut Cpu, bus: &mut impl Bus) -> u8 {
+    let value = bus.read_byte(cpu.registers.pc);
     cpu.registers.pc = cpu.registers.pc.wrapping_add(1);
     value
 }
 
 /// This helper reads a 16-bit immediate value from PC and advances PC
-fn read_u16(cpu: &mut Cpu, mmu: &Mmu) -> u16 {
-    let value = mmu.read_word(cpu.registers.pc);
+fn read_u16(cpu: &mut Cpu, bus: &mut impl Bus) -> u16 {
+    let value = bus.read_word(cpu.registers.pc);
     cpu.registers.pc = cpu.registers.pc.wrapping_add(2);
     value
 }
 
 /// This helper reads an 8-bit signed immediate value from PC and advances PC
-fn read_i8(cpu: &mut Cpu, mmu: &Mmu) -> i8 {
-    read_u8(cpu, mmu) as i8
+fn read_i8(cpu: &mut Cpu, bus: &mut impl Bus) -> i8 {
+    read_u8(cpu, bus) as i8
 }
 
 /// This helper gets a register value by ID FOR CB INSTRUCTIONS
@@ -102,36 +102,83 @@ pub fn nop(_cpu: &Cpu) -> u8 {
     1
 }
 
-/// STOP - Enters low power mode until button press (2 bytes: 0x10 0x00)
-pub fn stop(cpu: &mut Cpu) -> u8 {
-    cpu.registers.pc = cpu.registers.pc.wrapping_add(1); // Skip next byte
-    // TODO: Implement actual STOP behavior
+/// STOP - Enters low power mode until button press (normally 2 bytes: 0x10 0x00).
+/// What actually happens depends on the joypad and pending interrupts (Pan Docs):
+/// - button held, interrupt pending: 1-byte instruction, nothing else happens
+/// - button held, no interrupt pending: 2-byte instruction, enters HALT instead
+/// - no button held, interrupt pending: 1-byte instruction, enters STOP, DIV reset
+/// - no button held, no interrupt pending: 2-byte instruction, enters STOP, DIV reset
+///
+/// The 1-byte cases are the "corrupted STOP": the byte after 0x10 runs as the next
+/// instruction, so we log them (and any STOP whose second byte isn't 0x00).
+pub fn stop(cpu: &mut Cpu, bus: &mut impl Bus) -> u8 {
+    let stop_pc = cpu.registers.pc.wrapping_sub(1);
+    let button_held = bus.peek_byte(0xFF00) & 0x0F != 0x0F;
+    let interrupt_pending = bus.peek_byte(0xFFFF) & bus.peek_byte(0xFF0F) & 0x1F != 0;
+    let second_byte = bus.read_byte(cpu.registers.pc);
+    
+    if interrupt_pending {
+        if cpu.strictness != StrictnessMode::Lenient {
+            eprintln!(
+                "Warning: corrupted STOP at 0x{:04X} (interrupt pending), next byte 0x{:02X} executes as an instruction",
+                stop_pc, second_byte
+            );
+        }
+    } else {
+        if second_byte != 0x00 && cpu.strictness != StrictnessMode::Lenient {
+            eprintln!(
+                "Warning: STOP at 0x{:04X} followed by 0x{:02X} instead of 0x00 (skipped)",
+                stop_pc, second_byte
+            );
+        }
+        cpu.registers.pc = cpu.registers.pc.wrapping_add(1); // Skip next byte
+    }
+    
+    if button_held {
+        // With a button already held, STOP can't wait for a press
+        if !interrupt_pending {
+            cpu.halted = true;
+        }
+    } else {
+        cpu.stopped = true;
+        bus.reset_div(); // Entering STOP mode resets DIV
+    }
     1
 }
 
-/// HALT - Enters halt mode until interrupt occurs
-pub fn halt(cpu: &mut Cpu) -> u8 {
-    cpu.halted = true;
+/// HALT - Enters halt mode until interrupt occurs. If an enabled interrupt is
+/// already pending (IE & IF != 0), the CPU doesn't halt (Pan Docs "halt bug"):
+/// - IME=0: the PC fails to increment after the next opcode fetch, so the byte
+///   after HALT is read twice
+/// - EI just before (IME about to turn on): the interrupt is serviced with the
+///   HALT's own address as the return address, so HALT runs again afterwards
+pub fn halt(cpu: &mut Cpu, bus: &mut impl Bus) -> u8 {
+    let interrupt_pending = bus.peek_byte(0xFFFF) & bus.peek_byte(0xFF0F) & 0x1F != 0;
+    if !interrupt_pending || cpu.ime {
+        // With IME on, the pending interrupt is serviced right after this instead
+        cpu.halted = true;
+    } else if cpu.ime_pending {
+        cpu.registers.pc = cpu.registers.pc.wrapping_sub(1);
+    } else {
+        cpu.halt_bug = true;
+    }
     1
 }
 
-/// DI - Disable Interrupts
+/// DI - Disable Interrupts (also cancels an EI that hasn't taken effect yet)
 pub fn di(cpu: &mut Cpu) -> u8 {
     cpu.ime = false;
+    cpu.ime_pending = false;
     1
 }
 
-/// EI - Enable Interrupts (takes effect after next instruction)
+/// EI - Enable Interrupts. IME is set once the following instruction has run
+/// (see `Cpu::tick`), so `EI; RETI` and `EI; HALT` can't be interrupted in between.
 pub fn ei(cpu: &mut Cpu) -> u8 {
-    cpu.ime = true; // TODO: Should be delayed by one instruction
+    cpu.ime_pending = true;
     1
 }
 
-/// This handles illegal/undefined opcodes by panicking
-pub fn illegal_opcode(opcode: u8) -> u8 {
-    panic!("Illegal opcode: 0x{:02X}", opcode);
-}
-
 // ===== 8-bit Load Instructions =====
 
 /// LD r,r - Load register to register
@@ -142,257 +189,258 @@ pub fn ld_r_r(cpu: &mut Cpu, dest: u8, src: u8) -> u8 {
 }
 
 /// LD r,u8 - Load immediate 8-bit value into register
-pub fn ld_b_u8(cpu: &mut Cpu, mmu: &Mmu) -> u8 {
-    cpu.registers.b = read_u8(cpu, mmu);
+pub fn ld_b_u8(cpu: &mut Cpu, bus: &mut impl Bus) -> u8 {
+    cpu.registers.b = read_u8(cpu, bus);
     2
 }
 
-pub fn ld_c_u8(cpu: &mut Cpu, mmu: &Mmu) -> u8 {
-    cpu.registers.c = read_u8(cpu, mmu);
+pub fn ld_c_u8(cpu: &mut Cpu, bus: &mut impl Bus) -> u8 {
+    cpu.registers.c = read_u8(cpu, bus);
     2
 }
 
-pub fn ld_d_u8(cpu: &mut Cpu, mmu: &Mmu) -> u8 {
-    cpu.registers.d = read_u8(cpu, mmu);
+pub fn ld_d_u8(cpu: &mut Cpu, bus: &mut impl Bus) -> u8 {
+    cpu.registers.d = read_u8(cpu, bus);
     2
 }
 
-pub fn ld_e_u8(cpu: &mut Cpu, mmu: &Mmu) -> u8 {
-    cpu.registers.e = read_u8(cpu, mmu);
+pub fn ld_e_u8(cpu: &mut Cpu, bus: &mut impl Bus) -> u8 {
+    cpu.registers.e = read_u8(cpu, bus);
     2
 }
 
-pub fn ld_h_u8(cpu: &mut Cpu, mmu: &Mmu) -> u8 {
-    cpu.registers.h = read_u8(cpu, mmu);
+pub fn ld_h_u8(cpu: &mut Cpu, bus: &mut impl Bus) -> u8 {
+    cpu.registers.h = read_u8(cpu, bus);
     2
 }
 
-pub fn ld_l_u8(cpu: &mut Cpu, mmu: &Mmu) -> u8 {
-    cpu.registers.l = read_u8(cpu, mmu);
+pub fn ld_l_u8(cpu: &mut Cpu, bus: &mut impl Bus) -> u8 {
+    cpu.registers.l = read_u8(cpu, bus);
     2
 }
 
-pub fn ld_a_u8(cpu: &mut Cpu, mmu: &Mmu) -> u8 {
-    cpu.registers.a = read_u8(cpu, mmu);
+pub fn ld_a_u8(cpu: &mut Cpu, bus: &mut impl Bus) -> u8 {
+    cpu.registers.a = read_u8(cpu, bus);
     2
 }
 
 /// LD r,(HL) - Load value from memory address HL into register
-pub fn ld_b_hl(cpu: &mut Cpu, mmu: &Mmu) -> u8 {
-    cpu.registers.b = mmu.read_byte(cpu.registers.hl());
+pub fn ld_b_hl(cpu: &mut Cpu, bus: &mut impl Bus) -> u8 {
+    cpu.registers.b = bus.read_byte(cpu.registers.hl());
     2
 }
 
-pub fn ld_c_hl(cpu: &mut Cpu, mmu: &Mmu) -> u8 {
-    cpu.registers.c = mmu.read_byte(cpu.registers.hl());
+pub fn ld_c_hl(cpu: &mut Cpu, bus: &mut impl Bus) -> u8 {
+    cpu.registers.c = bus.read_byte(cpu.registers.hl());
     2
 }
 
-pub fn ld_d_hl(cpu: &mut Cpu, mmu: &Mmu) -> u8 {
-    cpu.registers.d = mmu.read_byte(cpu.registers.hl());
+pub fn ld_d_hl(cpu: &mut Cpu, bus: &mut impl Bus) -> u8 {
+    cpu.registers.d = bus.read_byte(cpu.registers.hl());
     2
 }
 
-pub fn ld_e_hl(cpu: &mut Cpu, mmu: &Mmu) -> u8 {
-    cpu.registers.e = mmu.read_byte(cpu.registers.hl());
+pub fn ld_e_hl(cpu: &mut Cpu, bus: &mut impl Bus) -> u8 {
+    cpu.registers.e = bus.read_byte(cpu.registers.hl());
     2
 }
 
-pub fn ld_h_hl(cpu: &mut Cpu, mmu: &Mmu) -> u8 {
-    cpu.registers.h = mmu.read_byte(cpu.registers.hl());
+pub fn ld_h_hl(cpu: &mut Cpu, bus: &mut impl Bus) -> u8 {
+    cpu.registers.h = bus.read_byte(cpu.registers.hl());
     2
 }
 
-pub fn ld_l_hl(cpu: &mut Cpu, mmu: &Mmu) -> u8 {
-    cpu.registers.l = mmu.read_byte(cpu.registers.hl());
+pub fn ld_l_hl(cpu: &mut Cpu, bus: &mut impl Bus) -> u8 {
+    cpu.registers.l = bus.read_byte(cpu.registers.hl());
     2
 }
 
-pub fn ld_a_hl(cpu: &mut Cpu, mmu: &Mmu) -> u8 {
-    cpu.registers.a = mmu.read_byte(cpu.registers.hl());
+pub fn ld_a_hl(cpu: &mut Cpu, bus: &mut impl Bus) -> u8 {
+    cpu.registers.a = bus.read_byte(cpu.registers.hl());
     2
 }
 
 /// LD (HL),r - Load register into memory address HL
-pub fn ld_hl_b(cpu: &Cpu, mmu: &mut Mmu) -> u8 {
-    mmu.write_byte(cpu.registers.hl(), cpu.registers.b);
+pub fn ld_hl_b(cpu: &Cpu, bus: &mut impl Bus) -> u8 {
+    bus.write_byte(cpu.registers.hl(), cpu.registers.b);
     2
 }
 
-pub fn ld_hl_c(cpu: &Cpu, mmu: &mut Mmu) -> u8 {
-    mmu.write_byte(cpu.registers.hl(), cpu.registers.c);
+pub fn ld_hl_c(cpu: &Cpu, bus: &mut impl Bus) -> u8 {
+    bus.write_byte(cpu.registers.hl(), cpu.registers.c);
     2
 }
 
-pub fn ld_hl_d(cpu: &Cpu, mmu: &mut Mmu) -> u8 {
-    mmu.write_byte(cpu.registers.hl(), cpu.registers.d);
+pub fn ld_hl_d(cpu: &Cpu, bus: &mut impl Bus) -> u8 {
+    bus.write_byte(cpu.registers.hl(), cpu.registers.d);
     2
 }
 
-pub fn ld_hl_e(cpu: &Cpu, mmu: &mut Mmu) -> u8 {
-    mmu.write_byte(cpu.registers.hl(), cpu.registers.e);
+pub fn ld_hl_e(cpu: &Cpu, bus: &mut impl Bus) -> u8 {
+    bus.write_byte(cpu.registers.hl(), cpu.registers.e);
     2
 }
 
-pub fn ld_hl_h(cpu: &Cpu, mmu: &mut Mmu) -> u8 {
-    mmu.write_byte(cpu.registers.hl(), cpu.registers.h);
+pub fn ld_hl_h(cpu: &Cpu, bus: &mut impl Bus) -> u8 {
+    bus.write_byte(cpu.registers.hl(), cpu.registers.h);
     2
 }
 
-pub fn ld_hl_l(cpu: &Cpu, mmu: &mut Mmu) -> u8 {
-    mmu.write_byte(cpu.registers.hl(), cpu.registers.l);
+pub fn ld_hl_l(cpu: &Cpu, bus: &mut impl Bus) -> u8 {
+    bus.write_byte(cpu.registers.hl(), cpu.registers.l);
     2
 }
 
-pub fn ld_hl_a(cpu: &Cpu, mmu: &mut Mmu) -> u8 {
-    mmu.write_byte(cpu.registers.hl(), cpu.registers.a);
+pub fn ld_hl_a(cpu: &Cpu, bus: &mut impl Bus) -> u8 {
+    bus.write_byte(cpu.registers.hl(), cpu.registers.a);
     2
 }
 
 /// LD (HL),u8 - Load immediate value into memory address HL
-pub fn ld_hl_u8(cpu: &mut Cpu, mmu: &mut Mmu) -> u8 {
-    let value = read_u8(cpu, mmu);
-    mmu.write_byte(cpu.registers.hl(), value);
+pub fn ld_hl_u8(cpu: &mut Cpu, bus: &mut impl Bus) -> u8 {
+    let value = read_u8(cpu, bus);
+    bus.write_byte(cpu.registers.hl(), value);
     3
 }
 
 /// LD A,(BC) - Load value from memory address BC into A
-pub fn ld_a_bc(cpu: &mut Cpu, mmu: &Mmu) -> u8 {
-    cpu.registers.a = mmu.read_byte(cpu.registers.bc());
+pub fn ld_a_bc(cpu: &mut Cpu, bus: &mut impl Bus) -> u8 {
+    cpu.registers.a = bus.read_byte(cpu.registers.bc());
     2
 }
 
 /// LD A,(DE) - Load value from memory address DE into A
-pub fn ld_a_de(cpu: &mut Cpu, mmu: &Mmu) -> u8 {
-    cpu.registers.a = mmu.read_byte(cpu.registers.de());
+pub fn ld_a_de(cpu: &mut Cpu, bus: &mut impl Bus) -> u8 {
+    cpu.registers.a = bus.read_byte(cpu.registers.de());
     2
 }
 
 /// LD (BC),A - Load A into memory address BC
-pub fn ld_bc_a(cpu: &Cpu, mmu: &mut Mmu) -> u8 {
-    mmu.write_byte(cpu.registers.bc(), cpu.registers.a);
+pub fn ld_bc_a(cpu: &Cpu, bus: &mut impl Bus) -> u8 {
+    bus.write_byte(cpu.registers.bc(), cpu.registers.a);
     2
 }
 
 /// LD (DE),A - Load A into memory address DE
-pub fn ld_de_a(cpu: &Cpu, mmu: &mut Mmu) -> u8 {
-    mmu.write_byte(cpu.registers.de(), cpu.registers.a);
+pub fn ld_de_a(cpu: &Cpu, bus: &mut impl Bus) -> u8 {
+    bus.write_byte(cpu.registers.de(), cpu.registers.a);
     2
 }
 
 /// LD A,(HL+) / LD A,(HLI) - Load from HL into A, increment HL
-pub fn ld_a_hli(cpu: &mut Cpu, mmu: &Mmu) -> u8 {
-    cpu.registers.a = mmu.read_byte(cpu.registers.hl());
+pub fn ld_a_hli(cpu: &mut Cpu, bus: &mut impl Bus) -> u8 {
+    cpu.registers.a = bus.read_byte(cpu.registers.hl());
     cpu.registers.set_hl(cpu.registers.hl().wrapping_add(1));
     2
 }
 
 /// LD (HL+),A / LD (HLI),A - Load A into HL, increment HL
-pub fn ld_hli_a(cpu: &mut Cpu, mmu: &mut Mmu) -> u8 {
-    mmu.write_byte(cpu.registers.hl(), cpu.registers.a);
+pub fn ld_hli_a(cpu: &mut Cpu, bus: &mut impl Bus) -> u8 {
+    bus.write_byte(cpu.registers.hl(), cpu.registers.a);
     cpu.registers.set_hl(cpu.registers.hl().wrapping_add(1));
     2
 }
 
 /// LD A,(HL-) / LD A,(HLD) - Load from HL into A, decrement HL
-pub fn ld_a_hld(cpu: &mut Cpu, mmu: &Mmu) -> u8 {
-    cpu.registers.a = mmu.read_byte(cpu.registers.hl());
+pub fn ld_a_hld(cpu: &mut Cpu, bus: &mut impl Bus) -> u8 {
+    cpu.registers.a = bus.read_byte(cpu.registers.hl());
     cpu.registers.set_hl(cpu.registers.hl().wrapping_sub(1));
     2
 }
 
 /// LD (HL-),A / LD (HLD),A - Load A into HL, decrement HL
-pub fn ld_hld_a(cpu: &mut Cpu, mmu: &mut Mmu) -> u8 {
-    mmu.write_byte(cpu.registers.hl(), cpu.registers.a);
+pub fn ld_hld_a(cpu: &mut Cpu, bus: &mut impl Bus) -> u8 {
+    bus.write_byte(cpu.registers.hl(), cpu.registers.a);
     cpu.registers.set_hl(cpu.registers.hl().wrapping_sub(1));
     2
 }
 
 /// LD A,(u16) - Load value from immediate 16-bit address into A
-pub fn ld_a_u16(cpu: &mut Cpu, mmu: &mut Mmu) -> u8 {
-    let address = read_u16(cpu, mmu);
-    cpu.registers.a = mmu.read_byte(address);
+pub fn ld_a_u16(cpu: &mut Cpu, bus: &mut impl Bus) -> u8 {
+    let address = read_u16(cpu, bus);
+    cpu.registers.a = bus.read_byte(address);
     4
 }
 
 /// LD (u16),A - Load A into immediate 16-bit address
-pub fn ld_u16_a(cpu: &mut Cpu, mmu: &mut Mmu) -> u8 {
-    let address = read_u16(cpu, mmu);
-    mmu.write_byte(address, cpu.registers.a);
+pub fn ld_u16_a(cpu: &mut Cpu, bus: &mut impl Bus) -> u8 {
+    let address = read_u16(cpu, bus);
+    bus.write_byte(address, cpu.registers.a);
     4
 }
 
 /// LDH (u8),A / LD ($FF00+u8),A - Load A into high memory (0xFF00 + u8)
-pub fn ldh_u8_a(cpu: &mut Cpu, mmu: &mut Mmu) -> u8 {
-    let offset = read_u8(cpu, mmu);
-    mmu.write_byte(0xFF00 + offset as u16, cpu.registers.a);
+pub fn ldh_u8_a(cpu: &mut Cpu, bus: &mut impl Bus) -> u8 {
+    let offset = read_u8(cpu, bus);
+    bus.write_byte(0xFF00 + offset as u16, cpu.registers.a);
     3
 }
 
 /// LDH A,(u8) / LD A,($FF00+u8) - Load from high memory into A
-pub fn ldh_a_u8(cpu: &mut Cpu, mmu: &mut Mmu) -> u8 {
-    let offset = read_u8(cpu, mmu);
-    cpu.registers.a = mmu.read_byte(0xFF00 + offset as u16);
+pub fn ldh_a_u8(cpu: &mut Cpu, bus: &mut impl Bus) -> u8 {
+    let offset = read_u8(cpu, bus);
+    cpu.registers.a = bus.read_byte(0xFF00 + offset as u16);
     3
 }
 
 /// LDH (C),A / LD ($FF00+C),A - Load A into high memory (0xFF00 + C)
-pub fn ldh_c_a(cpu: &Cpu, mmu: &mut Mmu) -> u8 {
-    mmu.write_byte(0xFF00 + cpu.registers.c as u16, cpu.registers.a);
+pub fn ldh_c_a(cpu: &Cpu, bus: &mut impl Bus) -> u8 {
+    bus.write_byte(0xFF00 + cpu.registers.c as u16, cpu.registers.a);
     2
 }
 
 /// LDH A,(C) / LD A,($FF00+C) - Load from high memory (0xFF00 + C) into A
-pub fn ldh_a_c(cpu: &mut Cpu, mmu: &Mmu) -> u8 {
-    cpu.registers.a = mmu.read_byte(0xFF00 + cpu.registers.c as u16);
+pub fn ldh_a_c(cpu: &mut Cpu, bus: &mut impl Bus) -> u8 {
+    cpu.registers.a = bus.read_byte(0xFF00 + cpu.registers.c as u16);
     2
 }
 
 // ===== 16-bit Load Instructions =====
 
 /// LD BC,u16 - Load 16-bit immediate into BC
-pub fn ld_bc_u16(cpu: &mut Cpu, mmu: &Mmu) -> u8 {
-    let value = read_u16(cpu, mmu);
+pub fn ld_bc_u16(cpu: &mut Cpu, bus: &mut impl Bus) -> u8 {
+    let value = read_u16(cpu, bus);
     cpu.registers.set_bc(value);
     3
 }
 
 /// LD DE,u16 - Load 16-bit immediate into DE
-pub fn ld_de_u16(cpu: &mut Cpu, mmu: &Mmu) -> u8 {
-    let value = read_u16(cpu, mmu);
+pub fn ld_de_u16(cpu: &mut Cpu, bus: &mut impl Bus) -> u8 {
+    let value = read_u16(cpu, bus);
     cpu.registers.set_de(value);
     3
 }
 
 /// LD HL,u16 - Load 16-bit immediate into HL
-pub fn ld_hl_u16(cpu: &mut Cpu, mmu: &Mmu) -> u8 {
-    let value = read_u16(cpu, mmu);
+pub fn ld_hl_u16(cpu: &mut Cpu, bus: &mut impl Bus) -> u8 {
+    let value = read_u16(cpu, bus);
     cpu.registers.set_hl(value);
     3
 }
 
 /// LD SP,u16 - Load 16-bit immediate into SP
-pub fn ld_sp_u16(cpu: &mut Cpu, mmu: &Mmu) -> u8 {
-    cpu.registers.sp = read_u16(cpu, mmu);
+pub fn ld_sp_u16(cpu: &mut Cpu, bus: &mut impl Bus) -> u8 {
+    cpu.registers.sp = read_u16(cpu, bus);
     3
 }
 
 /// LD (u16),SP - Load SP into memory at immediate 16-bit address
-pub fn ld_u16_sp(cpu: &mut Cpu, mmu: &mut Mmu) -> u8 {
-    let address = read_u16(cpu, mmu);
-    mmu.write_word(address, cpu.registers.sp);
+pub fn ld_u16_sp(cpu: &mut Cpu, bus: &mut impl Bus) -> u8 {
+    let address = read_u16(cpu, bus);
+    bus.write_word(address, cpu.registers.sp);
     5
 }
 
 /// LD SP,HL - Load HL into SP
-pub fn ld_sp_hl(cpu: &mut Cpu) -> u8 {
+pub fn ld_sp_hl(cpu: &mut Cpu, bus: &mut impl Bus) -> u8 {
+    bus.idle();
     cpu.registers.sp = cpu.registers.hl();
     2
 }
 
 /// LD HL,SP+i8 - Load SP + signed 8-bit immediate into HL
-pub fn ld_hl_sp_i8(cpu: &mut Cpu, mmu: &Mmu) -> u8 {
-    let offset = read_i8(cpu, mmu);
+pub fn ld_hl_sp_i8(cpu: &mut Cpu, bus: &mut impl Bus) -> u8 {
+    let offset = read_i8(cpu, bus);
     let sp = cpu.registers.sp;
     let result = sp.wrapping_add(offset as u16);
     
@@ -404,6 +452,7 @@ pub fn ld_hl_sp_i8(cpu: &mut Cpu, mmu: &Mmu) -> u8 {
     cpu.registers.set_flag_h(((sp as u8) & 0x0F) + (offset_u8 & 0x0F) > 0x0F);
     cpu.registers.set_flag_c(((sp as u8) as u16) + (offset_u8 as u16) > 0xFF);
     
+    bus.idle();
     cpu.registers.set_hl(result);
     3
 }
@@ -447,15 +496,16 @@ pub fn inc_a(cpu: &mut Cpu) -> u8 {
 }
 
 /// INC (HL) - Increment value at memory address HL
-pub fn inc_hl_mem(cpu: &mut Cpu, mmu: &mut Mmu) -> u8 {
+pub fn inc_hl_mem(cpu: &mut Cpu, bus: &mut impl Bus) -> u8 {
     let address = cpu.registers.hl();
-    let value = mmu.read_byte(address);
+    let value = bus.read_byte(address);
     let result = inc_u8(cpu, value);
-    mmu.write_byte(address, result);
+    bus.write_byte(address, result);
     3
 }
 
-/// This helper implements 8-bit increment with proper flag setting
+/// This helper implements 8-bit increment with proper flag setting.
+/// Only Z, N and H change - the carry flag is deliberately left untouched.
 fn inc_u8(cpu: &mut Cpu, value: u8) -> u8 {
     let result = value.wrapping_add(1);
     cpu.registers.set_flag_z(result == 0);
@@ -501,15 +551,16 @@ pub fn dec_a(cpu: &mut Cpu) -> u8 {
 }
 
 /// DEC (HL) - Decrement value at memory address HL
-pub fn dec_hl_mem(cpu: &mut Cpu, mmu: &mut Mmu) -> u8 {
+pub fn dec_hl_mem(cpu: &mut Cpu, bus: &mut impl Bus) -> u8 {
     let address = cpu.registers.hl();
-    let value = mmu.read_byte(address);
+    let value = bus.read_byte(address);
     let result = dec_u8(cpu, value);
-    mmu.write_byte(address, result);
+    bus.write_byte(address, result);
     3
 }
 
-/// This helper implements 8-bit decrement with proper flag setting
+/// This helper implements 8-bit decrement with proper flag setting.
+/// Only Z, N and H change - the carry flag is deliberately left untouched.
 fn dec_u8(cpu: &mut Cpu, value: u8) -> u8 {
     let result = value.wrapping_sub(1);
     cpu.registers.set_flag_z(result == 0);
@@ -526,15 +577,15 @@ pub fn add_a_r(cpu: &mut Cpu, reg: u8) -> u8 {
 }
 
 /// ADD A,(HL) - Add value at HL to A
-pub fn add_a_hl(cpu: &mut Cpu, mmu: &Mmu) -> u8 {
-    let value = mmu.read_byte(cpu.registers.hl());
+pub fn add_a_hl(cpu: &mut Cpu, bus: &mut impl Bus) -> u8 {
+    let value = bus.read_byte(cpu.registers.hl());
     add_a(cpu, value);
     2
 }
 
 /// ADD A,u8 - Add immediate to A
-pub fn add_a_u8(cpu: &mut Cpu, mmu: &Mmu) -> u8 {
-    let value = read_u8(cpu, mmu);
+pub fn add_a_u8(cpu: &mut Cpu, bus: &mut impl Bus) -> u8 {
+    let value = read_u8(cpu, bus);
     add_a(cpu, value);
     2
 }
@@ -560,15 +611,15 @@ pub fn adc_a_r(cpu: &mut Cpu, reg: u8) -> u8 {
 }
 
 /// ADC A,(HL) - Add value at HL + carry to A
-pub fn adc_a_hl(cpu: &mut Cpu, mmu: &Mmu) -> u8 {
-    let value = mmu.read_byte(cpu.registers.hl());
+pub fn adc_a_hl(cpu: &mut Cpu, bus: &mut impl Bus) -> u8 {
+    let value = bus.read_byte(cpu.registers.hl());
     adc_a(cpu, value);
     2
 }
 
 /// ADC A,u8 - Add immediate + carry to A
-pub fn adc_a_u8(cpu: &mut Cpu, mmu: &Mmu) -> u8 {
-    let value = read_u8(cpu, mmu);
+pub fn adc_a_u8(cpu: &mut Cpu, bus: &mut impl Bus) -> u8 {
+    let value = read_u8(cpu, bus);
     adc_a(cpu, value);
     2
 }
@@ -595,15 +646,15 @@ pub fn sub_a_r(cpu: &mut Cpu, reg: u8) -> u8 {
 }
 
 /// SUB A,(HL) - Subtract value at HL from A
-pub fn sub_a_hl(cpu: &mut Cpu, mmu: &Mmu) -> u8 {
-    let value = mmu.read_byte(cpu.registers.hl());
+pub fn sub_a_hl(cpu: &mut Cpu, bus: &mut impl Bus) -> u8 {
+    let value = bus.read_byte(cpu.registers.hl());
     sub_a(cpu, value);
     2
 }
 
 /// SUB A,u8 - Subtract immediate from A
-pub fn sub_a_u8(cpu: &mut Cpu, mmu: &Mmu) -> u8 {
-    let value = read_u8(cpu, mmu);
+pub fn sub_a_u8(cpu: &mut Cpu, bus: &mut impl Bus) -> u8 {
+    let value = read_u8(cpu, bus);
     sub_a(cpu, value);
     2
 }
@@ -629,15 +680,15 @@ pub fn sbc_a_r(cpu: &mut Cpu, reg: u8) -> u8 {
 }
 
 /// SBC A,(HL) - Subtract value at HL + carry from A
-pub fn sbc_a_hl(cpu: &mut Cpu, mmu: &Mmu) -> u8 {
-    let value = mmu.read_byte(cpu.registers.hl());
+pub fn sbc_a_hl(cpu: &mut Cpu, bus: &mut impl Bus) -> u8 {
+    let value = bus.read_byte(cpu.registers.hl());
     sbc_a(cpu, value);
     2
 }
 
 /// SBC A,u8 - Subtract immediate + carry from A
-pub fn sbc_a_u8(cpu: &mut Cpu, mmu: &Mmu) -> u8 {
-    let value = read_u8(cpu, mmu);
+pub fn sbc_a_u8(cpu: &mut Cpu, bus: &mut impl Bus) -> u8 {
+    let value = read_u8(cpu, bus);
     sbc_a(cpu, value);
     2
 }
@@ -664,15 +715,15 @@ pub fn and_a_r(cpu: &mut Cpu, reg: u8) -> u8 {
 }
 
 /// AND A,(HL) - Bitwise AND value at HL with A
-pub fn and_a_hl(cpu: &mut Cpu, mmu: &Mmu) -> u8 {
-    let value = mmu.read_byte(cpu.registers.hl());
+pub fn and_a_hl(cpu: &mut Cpu, bus: &mut impl Bus) -> u8 {
+    let value = bus.read_byte(cpu.registers.hl());
     and_a(cpu, value);
     2
 }
 
 /// AND A,u8 - Bitwise AND immediate with A
-pub fn and_a_u8(cpu: &mut Cpu, mmu: &Mmu) -> u8 {
-    let value = read_u8(cpu, mmu);
+pub fn and_a_u8(cpu: &mut Cpu, bus: &mut impl Bus) -> u8 {
+    let value = read_u8(cpu, bus);
     and_a(cpu, value);
     2
 }
@@ -694,15 +745,15 @@ pub fn xor_a_r(cpu: &mut Cpu, reg: u8) -> u8 {
 }
 
 /// XOR A,(HL) - Bitwise XOR value at HL with A
-pub fn xor_a_hl(cpu: &mut Cpu, mmu: &Mmu) -> u8 {
-    let value = mmu.read_byte(cpu.registers.hl());
+pub fn xor_a_hl(cpu: &mut Cpu, bus: &mut impl Bus) -> u8 {
+    let value = bus.read_byte(cpu.registers.hl());
     xor_a(cpu, value);
     2
 }
 
 /// XOR A,u8 - Bitwise XOR immediate with A
-pub fn xor_a_u8(cpu: &mut Cpu, mmu: &Mmu) -> u8 {
-    let value = read_u8(cpu, mmu);
+pub fn xor_a_u8(cpu: &mut Cpu, bus: &mut impl Bus) -> u8 {
+    let value = read_u8(cpu, bus);
     xor_a(cpu, value);
     2
 }
@@ -724,15 +775,15 @@ pub fn or_a_r(cpu: &mut Cpu, reg: u8) -> u8 {
 }
 
 /// OR A,(HL) - Bitwise OR value at HL with A
-pub fn or_a_hl(cpu: &mut Cpu, mmu: &Mmu) -> u8 {
-    let value = mmu.read_byte(cpu.registers.hl());
+pub fn or_a_hl(cpu: &mut Cpu, bus: &mut impl Bus) -> u8 {
+    let value = bus.read_byte(cpu.registers.hl());
     or_a(cpu, value);
     2
 }
 
 /// OR A,u8 - Bitwise OR immediate with A
-pub fn or_a_u8(cpu: &mut Cpu, mmu: &Mmu) -> u8 {
-    let value = read_u8(cpu, mmu);
+pub fn or_a_u8(cpu: &mut Cpu, bus: &mut impl Bus) -> u8 {
+    let value = read_u8(cpu, bus);
     or_a(cpu, value);
     2
 }
@@ -754,15 +805,15 @@ pub fn cp_a_r(cpu: &mut Cpu, reg: u8) -> u8 {
 }
 
 /// CP A,(HL) - Compare value at HL with A
-pub fn cp_a_hl(cpu: &mut Cpu, mmu: &Mmu) -> u8 {
-    let value = mmu.read_byte(cpu.registers.hl());
+pub fn cp_a_hl(cpu: &mut Cpu, bus: &mut impl Bus) -> u8 {
+    let value = bus.read_byte(cpu.registers.hl());
     cp_a(cpu, value);
     2
 }
 
 /// CP A,u8 - Compare immediate with A
-pub fn cp_a_u8(cpu: &mut Cpu, mmu: &Mmu) -> u8 {
-    let value = read_u8(cpu, mmu);
+pub fn cp_a_u8(cpu: &mut Cpu, bus: &mut impl Bus) -> u8 {
+    let value = read_u8(cpu, bus);
     cp_a(cpu, value);
     2
 }
@@ -779,70 +830,85 @@ fn cp_a(cpu: &mut Cpu, value: u8) {
 // ===== 16-bit Arithmetic Instructions =====
 
 /// INC rr - Increment 16-bit register
-pub fn inc_bc(cpu: &mut Cpu) -> u8 {
+/// 2 M-cycles: opcode fetch and an internal cycle for the 16-bit increment
+pub fn inc_bc(cpu: &mut Cpu, bus: &mut impl Bus) -> u8 {
+    bus.idle();
     cpu.registers.set_bc(cpu.registers.bc().wrapping_add(1));
     2
 }
 
-pub fn inc_de(cpu: &mut Cpu) -> u8 {
+pub fn inc_de(cpu: &mut Cpu, bus: &mut impl Bus) -> u8 {
+    bus.idle();
     cpu.registers.set_de(cpu.registers.de().wrapping_add(1));
     2
 }
 
-pub fn inc_hl(cpu: &mut Cpu) -> u8 {
+pub fn inc_hl(cpu: &mut Cpu, bus: &mut impl Bus) -> u8 {
+    bus.idle();
     cpu.registers.set_hl(cpu.registers.hl().wrapping_add(1));
     2
 }
 
-pub fn inc_sp(cpu: &mut Cpu) -> u8 {
+pub fn inc_sp(cpu: &mut Cpu, bus: &mut impl Bus) -> u8 {
+    bus.idle();
     cpu.registers.sp = cpu.registers.sp.wrapping_add(1);
     2
 }
 
 /// DEC rr - Decrement 16-bit register
-pub fn dec_bc(cpu: &mut Cpu) -> u8 {
+pub fn dec_bc(cpu: &mut Cpu, bus: &mut impl Bus) -> u8 {
+    bus.idle();
     cpu.registers.set_bc(cpu.registers.bc().wrapping_sub(1));
     2
 }
 
-pub fn dec_de(cpu: &mut Cpu) -> u8 {
+pub fn dec_de(cpu: &mut Cpu, bus: &mut impl Bus) -> u8 {
+    bus.idle();
     cpu.registers.set_de(cpu.registers.de().wrapping_sub(1));
     2
 }
 
-pub fn dec_hl(cpu: &mut Cpu) -> u8 {
+pub fn dec_hl(cpu: &mut Cpu, bus: &mut impl Bus) -> u8 {
+    bus.idle();
     cpu.registers.set_hl(cpu.registers.hl().wrapping_sub(1));
     2
 }
 
-pub fn dec_sp(cpu: &mut Cpu) -> u8 {
+pub fn dec_sp(cpu: &mut Cpu, bus: &mut impl Bus) -> u8 {
+    bus.idle();
     cpu.registers.sp = cpu.registers.sp.wrapping_sub(1);
     2
 }
 
 /// ADD HL,rr - Add 16-bit register to HL
-pub fn add_hl_bc(cpu: &mut Cpu) -> u8 {
+/// 2 M-cycles: opcode fetch and an internal cycle for the 16-bit addition
+pub fn add_hl_bc(cpu: &mut Cpu, bus: &mut impl Bus) -> u8 {
+    bus.idle();
     add_hl(cpu, cpu.registers.bc());
     2
 }
 
-pub fn add_hl_de(cpu: &mut Cpu) -> u8 {
+pub fn add_hl_de(cpu: &mut Cpu, bus: &mut impl Bus) -> u8 {
+    bus.idle();
     add_hl(cpu, cpu.registers.de());
     2
 }
 
-pub fn add_hl_hl(cpu: &mut Cpu) -> u8 {
+pub fn add_hl_hl(cpu: &mut Cpu, bus: &mut impl Bus) -> u8 {
+    bus.idle();
     let hl = cpu.registers.hl();
     add_hl(cpu, hl);
     2
 }
 
-pub fn add_hl_sp(cpu: &mut Cpu) -> u8 {
+pub fn add_hl_sp(cpu: &mut Cpu, bus: &mut impl Bus) -> u8 {
+    bus.idle();
     add_hl(cpu, cpu.registers.sp);
     2
 }
 
-/// This helper implements 16-bit ADD to HL
+/// This helper implements 16-bit ADD to HL. Z is left untouched even when the
+/// result is 0; N is cleared, H is the carry out of bit 11 and C out of bit 15.
 fn add_hl(cpu: &mut Cpu, value: u16) {
     let hl = cpu.registers.hl();
     let result = hl.wrapping_add(value);
@@ -855,8 +921,8 @@ fn add_hl(cpu: &mut Cpu, value: u16) {
 }
 
 /// ADD SP,i8 - Add signed 8-bit immediate to SP
-pub fn add_sp_i8(cpu: &mut Cpu, mmu: &Mmu) -> u8 {
-    let offset = read_i8(cpu, mmu);
+pub fn add_sp_i8(cpu: &mut Cpu, bus: &mut impl Bus) -> u8 {
+    let offset = read_i8(cpu, bus);
     let sp = cpu.registers.sp;
     
     // We set flags based on the addition using the UNSIGNED byte value for flag calculation
@@ -867,6 +933,9 @@ pub fn add_sp_i8(cpu: &mut Cpu, mmu: &Mmu) -> u8 {
     cpu.registers.set_flag_h(((sp as u8) & 0x0F) + (offset_u8 & 0x0F) > 0x0F);
     cpu.registers.set_flag_c(((sp as u8) as u16) + (offset_u8 as u16) > 0xFF);
     
+    // Two internal cycles: one for each byte of the 16-bit addition
+    bus.idle();
+    bus.idle();
     cpu.registers.sp = sp.wrapping_add(offset as u16);
     4
 }
@@ -973,6 +1042,7 @@ pub fn cpl(cpu: &mut Cpu) -> u8 {
 }
 
 /// SCF - Set carry flag
+/// N and H are cleared; Z is left untouched (a common bug is clearing it).
 pub fn scf(cpu: &mut Cpu) -> u8 {
     cpu.registers.set_flag_n(false);
     cpu.registers.set_flag_h(false);
@@ -981,6 +1051,7 @@ pub fn scf(cpu: &mut Cpu) -> u8 {
 }
 
 /// CCF - Complement carry flag
+/// N and H are cleared; Z is left untouched, like SCF.
 pub fn ccf(cpu: &mut Cpu) -> u8 {
     cpu.registers.set_flag_n(false);
     cpu.registers.set_flag_h(false);
@@ -991,15 +1062,19 @@ pub fn ccf(cpu: &mut Cpu) -> u8 {
 // ===== Jump Instructions =====
 
 /// JP u16 - Unconditional jump to immediate address
-pub fn jp_u16(cpu: &mut Cpu, mmu: &Mmu) -> u8 {
-    cpu.registers.pc = read_u16(cpu, mmu);
+pub fn jp_u16(cpu: &mut Cpu, bus: &mut impl Bus) -> u8 {
+    let address = read_u16(cpu, bus);
+    bus.idle();
+    cpu.registers.pc = address;
     4
 }
 
 /// JP cc,u16 - Conditional jump to immediate address
-pub fn jp_nz_u16(cpu: &mut Cpu, mmu: &Mmu) -> u8 {
-    let address = read_u16(cpu, mmu);
+/// A taken jump spends an internal cycle loading PC (4 M-cycles), otherwise 3
+pub fn jp_nz_u16(cpu: &mut Cpu, bus: &mut impl Bus) -> u8 {
+    let address = read_u16(cpu, bus);
     if !cpu.registers.flag_z() {
+        bus.idle();
         cpu.registers.pc = address;
         4
     } else {
@@ -1007,9 +1082,10 @@ pub fn jp_nz_u16(cpu: &mut Cpu, mmu: &Mmu) -> u8 {
     }
 }
 
-pub fn jp_z_u16(cpu: &mut Cpu, mmu: &Mmu) -> u8 {
-    let address = read_u16(cpu, mmu);
+pub fn jp_z_u16(cpu: &mut Cpu, bus: &mut impl Bus) -> u8 {
+    let address = read_u16(cpu, bus);
     if cpu.registers.flag_z() {
+        bus.idle();
         cpu.registers.pc = address;
         4
     } else {
@@ -1017,9 +1093,10 @@ pub fn jp_z_u16(cpu: &mut Cpu, mmu: &Mmu) -> u8 {
     }
 }
 
-pub fn jp_nc_u16(cpu: &mut Cpu, mmu: &Mmu) -> u8 {
-    let address = read_u16(cpu, mmu);
+pub fn jp_nc_u16(cpu: &mut Cpu, bus: &mut impl Bus) -> u8 {
+    let address = read_u16(cpu, bus);
     if !cpu.registers.flag_c() {
+        bus.idle();
         cpu.registers.pc = address;
         4
     } else {
@@ -1027,9 +1104,10 @@ pub fn jp_nc_u16(cpu: &mut Cpu, mmu: &Mmu) -> u8 {
     }
 }
 
-pub fn jp_c_u16(cpu: &mut Cpu, mmu: &Mmu) -> u8 {
-    let address = read_u16(cpu, mmu);
+pub fn jp_c_u16(cpu: &mut Cpu, bus: &mut impl Bus) -> u8 {
+    let address = read_u16(cpu, bus);
     if cpu.registers.flag_c() {
+        bus.idle();
         cpu.registers.pc = address;
         4
     } else {
@@ -1038,22 +1116,26 @@ pub fn jp_c_u16(cpu: &mut Cpu, mmu: &Mmu) -> u8 {
 }
 
 /// JP (HL) - Jump to address in HL
+/// Despite the parentheses in the mnemonic, this is a register move: PC = HL with
+/// no memory read, so it takes 1 M-cycle (and needs no MMU access at all).
 pub fn jp_hl(cpu: &mut Cpu) -> u8 {
     cpu.registers.pc = cpu.registers.hl();
     1
 }
 
 /// JR i8 - Relative jump by signed offset
-pub fn jr_i8(cpu: &mut Cpu, mmu: &Mmu) -> u8 {
-    let offset = read_i8(cpu, mmu);
+pub fn jr_i8(cpu: &mut Cpu, bus: &mut impl Bus) -> u8 {
+    let offset = read_i8(cpu, bus);
+    bus.idle();
     cpu.registers.pc = cpu.registers.pc.wrapping_add(offset as u16);
     3
 }
 
 /// JR cc,i8 - Conditional relative jump
-pub fn jr_nz_i8(cpu: &mut Cpu, mmu: &Mmu) -> u8 {
-    let offset = read_i8(cpu, mmu);
+pub fn jr_nz_i8(cpu: &mut Cpu, bus: &mut impl Bus) -> u8 {
+    let offset = read_i8(cpu, bus);
     if !cpu.registers.flag_z() {
+        bus.idle();
         cpu.registers.pc = cpu.registers.pc.wrapping_add(offset as u16);
         3
     } else {
@@ -1061,9 +1143,10 @@ pub fn jr_nz_i8(cpu: &mut Cpu, mmu: &Mmu) -> u8 {
     }
 }
 
-pub fn jr_z_i8(cpu: &mut Cpu, mmu: &Mmu) -> u8 {
-    let offset = read_i8(cpu, mmu);
+pub fn jr_z_i8(cpu: &mut Cpu, bus: &mut impl Bus) -> u8 {
+    let offset = read_i8(cpu, bus);
     if cpu.registers.flag_z() {
+        bus.idle();
         cpu.registers.pc = cpu.registers.pc.wrapping_add(offset as u16);
         3
     } else {
@@ -1071,9 +1154,10 @@ pub fn jr_z_i8(cpu: &mut Cpu, mmu: &Mmu) -> u8 {
     }
 }
 
-pub fn jr_nc_i8(cpu: &mut Cpu, mmu: &Mmu) -> u8 {
-    let offset = read_i8(cpu, mmu);
+pub fn jr_nc_i8(cpu: &mut Cpu, bus: &mut impl Bus) -> u8 {
+    let offset = read_i8(cpu, bus);
     if !cpu.registers.flag_c() {
+        bus.idle();
         cpu.registers.pc = cpu.registers.pc.wrapping_add(offset as u16);
         3
     } else {
@@ -1081,9 +1165,10 @@ pub fn jr_nc_i8(cpu: &mut Cpu, mmu: &Mmu) -> u8 {
     }
 }
 
-pub fn jr_c_i8(cpu: &mut Cpu, mmu: &Mmu) -> u8 {
-    let offset = read_i8(cpu, mmu);
+pub fn jr_c_i8(cpu: &mut Cpu, bus: &mut impl Bus) -> u8 {
+    let offset = read_i8(cpu, bus);
     if cpu.registers.flag_c() {
+        bus.idle();
         cpu.registers.pc = cpu.registers.pc.wrapping_add(offset as u16);
         3
     } else {
@@ -1094,18 +1179,18 @@ pub fn jr_c_i8(cpu: &mut Cpu, mmu: &Mmu) -> u8 {
 // ===== Call and Return Instructions =====
 
 /// CALL u16 - Unconditional call to address
-pub fn call_u16(cpu: &mut Cpu, mmu: &mut Mmu) -> u8 {
-    let address = read_u16(cpu, mmu);
-    push_u16(cpu, mmu, cpu.registers.pc);
+pub fn call_u16(cpu: &mut Cpu, bus: &mut impl Bus) -> u8 {
+    let address = read_u16(cpu, bus);
+    push_u16(cpu, bus, cpu.registers.pc);
     cpu.registers.pc = address;
     6
 }
 
 /// CALL cc,u16 - Conditional call
-pub fn call_nz_u16(cpu: &mut Cpu, mmu: &mut Mmu) -> u8 {
-    let address = read_u16(cpu, mmu);
+pub fn call_nz_u16(cpu: &mut Cpu, bus: &mut impl Bus) -> u8 {
+    let address = read_u16(cpu, bus);
     if !cpu.registers.flag_z() {
-        push_u16(cpu, mmu, cpu.registers.pc);
+        push_u16(cpu, bus, cpu.registers.pc);
         cpu.registers.pc = address;
         6
     } else {
@@ -1113,10 +1198,10 @@ pub fn call_nz_u16(cpu: &mut Cpu, mmu: &mut Mmu) -> u8 {
     }
 }
 
-pub fn call_z_u16(cpu: &mut Cpu, mmu: &mut Mmu) -> u8 {
-    let address = read_u16(cpu, mmu);
+pub fn call_z_u16(cpu: &mut Cpu, bus: &mut impl Bus) -> u8 {
+    let address = read_u16(cpu, bus);
     if cpu.registers.flag_z() {
-        push_u16(cpu, mmu, cpu.registers.pc);
+        push_u16(cpu, bus, cpu.registers.pc);
         cpu.registers.pc = address;
         6
     } else {
@@ -1124,10 +1209,10 @@ pub fn call_z_u16(cpu: &mut Cpu, mmu: &mut Mmu) -> u8 {
     }
 }
 
-pub fn call_nc_u16(cpu: &mut Cpu, mmu: &mut Mmu) -> u8 {
-    let address = read_u16(cpu, mmu);
+pub fn call_nc_u16(cpu: &mut Cpu, bus: &mut impl Bus) -> u8 {
+    let address = read_u16(cpu, bus);
     if !cpu.registers.flag_c() {
-        push_u16(cpu, mmu, cpu.registers.pc);
+        push_u16(cpu, bus, cpu.registers.pc);
         cpu.registers.pc = address;
         6
     } else {
@@ -1135,10 +1220,10 @@ pub fn call_nc_u16(cpu: &mut Cpu, mmu: &mut Mmu) -> u8 {
     }
 }
 
-pub fn call_c_u16(cpu: &mut Cpu, mmu: &mut Mmu) -> u8 {
-    let address = read_u16(cpu, mmu);
+pub fn call_c_u16(cpu: &mut Cpu, bus: &mut impl Bus) -> u8 {
+    let address = read_u16(cpu, bus);
     if cpu.registers.flag_c() {
-        push_u16(cpu, mmu, cpu.registers.pc);
+        push_u16(cpu, bus, cpu.registers.pc);
         cpu.registers.pc = address;
         6
     } else {
@@ -1147,128 +1232,146 @@ pub fn call_c_u16(cpu: &mut Cpu, mmu: &mut Mmu) -> u8 {
 }
 
 /// RET - Unconditional return from call
-pub fn ret(cpu: &mut Cpu, mmu: &Mmu) -> u8 {
-    cpu.registers.pc = pop_u16(cpu, mmu);
+pub fn ret(cpu: &mut Cpu, bus: &mut impl Bus) -> u8 {
+    cpu.registers.pc = pop_u16(cpu, bus);
+    bus.idle();
     4
 }
 
 /// RET cc - Conditional return
-pub fn ret_nz(cpu: &mut Cpu, mmu: &Mmu) -> u8 {
+/// The condition check takes an internal cycle (2 M-cycles when not taken); a taken
+/// return then pops PC and spends another internal cycle loading it (5 M-cycles)
+pub fn ret_nz(cpu: &mut Cpu, bus: &mut impl Bus) -> u8 {
+    bus.idle();
     if !cpu.registers.flag_z() {
-        cpu.registers.pc = pop_u16(cpu, mmu);
+        cpu.registers.pc = pop_u16(cpu, bus);
+        bus.idle();
         5
     } else {
         2
     }
 }
 
-pub fn ret_z(cpu: &mut Cpu, mmu: &Mmu) -> u8 {
+pub fn ret_z(cpu: &mut Cpu, bus: &mut impl Bus) -> u8 {
+    bus.idle();
     if cpu.registers.flag_z() {
-        cpu.registers.pc = pop_u16(cpu, mmu);
+        cpu.registers.pc = pop_u16(cpu, bus);
+        bus.idle();
         5
     } else {
         2
     }
 }
 
-pub fn ret_nc(cpu: &mut Cpu, mmu: &Mmu) -> u8 {
+pub fn ret_nc(cpu: &mut Cpu, bus: &mut impl Bus) -> u8 {
+    bus.idle();
     if !cpu.registers.flag_c() {
-        cpu.registers.pc = pop_u16(cpu, mmu);
+        cpu.registers.pc = pop_u16(cpu, bus);
+        bus.idle();
         5
     } else {
         2
     }
 }
 
-pub fn ret_c(cpu: &mut Cpu, mmu: &Mmu) -> u8 {
+pub fn ret_c(cpu: &mut Cpu, bus: &mut impl Bus) -> u8 {
+    bus.idle();
     if cpu.registers.flag_c() {
-        cpu.registers.pc = pop_u16(cpu, mmu);
+        cpu.registers.pc = pop_u16(cpu, bus);
+        bus.idle();
         5
     } else {
         2
     }
 }
 
-/// RETI - Return and enable interrupts
-pub fn reti(cpu: &mut Cpu, mmu: &Mmu) -> u8 {
-    cpu.registers.pc = pop_u16(cpu, mmu);
+/// RETI - Return and enable interrupts (immediately, unlike EI)
+pub fn reti(cpu: &mut Cpu, bus: &mut impl Bus) -> u8 {
+    cpu.registers.pc = pop_u16(cpu, bus);
+    bus.idle();
     cpu.ime = true;
     4
 }
 
 /// RST n - Call to fixed address
-pub fn rst_00(cpu: &mut Cpu, mmu: &mut Mmu) -> u8 { rst(cpu, mmu, 0x00); 4 }
-pub fn rst_08(cpu: &mut Cpu, mmu: &mut Mmu) -> u8 { rst(cpu, mmu, 0x08); 4 }
-pub fn rst_10(cpu: &mut Cpu, mmu: &mut Mmu) -> u8 { rst(cpu, mmu, 0x10); 4 }
-pub fn rst_18(cpu: &mut Cpu, mmu: &mut Mmu) -> u8 { rst(cpu, mmu, 0x18); 4 }
-pub fn rst_20(cpu: &mut Cpu, mmu: &mut Mmu) -> u8 { rst(cpu, mmu, 0x20); 4 }
-pub fn rst_28(cpu: &mut Cpu, mmu: &mut Mmu) -> u8 { rst(cpu, mmu, 0x28); 4 }
-pub fn rst_30(cpu: &mut Cpu, mmu: &mut Mmu) -> u8 { rst(cpu, mmu, 0x30); 4 }
-pub fn rst_38(cpu: &mut Cpu, mmu: &mut Mmu) -> u8 { rst(cpu, mmu, 0x38); 4 }
+pub fn rst_00(cpu: &mut Cpu, bus: &mut impl Bus) -> u8 { rst(cpu, bus, 0x00); 4 }
+pub fn rst_08(cpu: &mut Cpu, bus: &mut impl Bus) -> u8 { rst(cpu, bus, 0x08); 4 }
+pub fn rst_10(cpu: &mut Cpu, bus: &mut impl Bus) -> u8 { rst(cpu, bus, 0x10); 4 }
+pub fn rst_18(cpu: &mut Cpu, bus: &mut impl Bus) -> u8 { rst(cpu, bus, 0x18); 4 }
+pub fn rst_20(cpu: &mut Cpu, bus: &mut impl Bus) -> u8 { rst(cpu, bus, 0x20); 4 }
+pub fn rst_28(cpu: &mut Cpu, bus: &mut impl Bus) -> u8 { rst(cpu, bus, 0x28); 4 }
+pub fn rst_30(cpu: &mut Cpu, bus: &mut impl Bus) -> u8 { rst(cpu, bus, 0x30); 4 }
+pub fn rst_38(cpu: &mut Cpu, bus: &mut impl Bus) -> u8 { rst(cpu, bus, 0x38); 4 }
 
 /// This helper implements RST operation (restart/call to fixed address)
-fn rst(cpu: &mut Cpu, mmu: &mut Mmu, address: u8) {
-    push_u16(cpu, mmu, cpu.registers.pc);
+fn rst(cpu: &mut Cpu, bus: &mut impl Bus, address: u8) {
+    push_u16(cpu, bus, cpu.registers.pc);
     cpu.registers.pc = address as u16;
 }
 
 // ===== Stack Instructions =====
 
 /// PUSH rr - Push 16-bit register onto stack
-pub fn push_bc(cpu: &mut Cpu, mmu: &mut Mmu) -> u8 {
-    push_u16(cpu, mmu, cpu.registers.bc());
+/// 4 M-cycles: opcode fetch, one internal cycle (SP decrement), then the two writes
+pub fn push_bc(cpu: &mut Cpu, bus: &mut impl Bus) -> u8 {
+    push_u16(cpu, bus, cpu.registers.bc());
     4
 }
 
-pub fn push_de(cpu: &mut Cpu, mmu: &mut Mmu) -> u8 {
-    push_u16(cpu, mmu, cpu.registers.de());
+pub fn push_de(cpu: &mut Cpu, bus: &mut impl Bus) -> u8 {
+    push_u16(cpu, bus, cpu.registers.de());
     4
 }
 
-pub fn push_hl(cpu: &mut Cpu, mmu: &mut Mmu) -> u8 {
-    push_u16(cpu, mmu, cpu.registers.hl());
+pub fn push_hl(cpu: &mut Cpu, bus: &mut impl Bus) -> u8 {
+    push_u16(cpu, bus, cpu.registers.hl());
     4
 }
 
-pub fn push_af(cpu: &mut Cpu, mmu: &mut Mmu) -> u8 {
-    push_u16(cpu, mmu, cpu.registers.af());
+pub fn push_af(cpu: &mut Cpu, bus: &mut impl Bus) -> u8 {
+    push_u16(cpu, bus, cpu.registers.af());
     4
 }
 
 /// POP rr - Pop 16-bit value from stack into register
-pub fn pop_bc(cpu: &mut Cpu, mmu: &Mmu) -> u8 {
-    let value = pop_u16(cpu, mmu);
+/// 3 M-cycles: opcode fetch and the two reads
+pub fn pop_bc(cpu: &mut Cpu, bus: &mut impl Bus) -> u8 {
+    let value = pop_u16(cpu, bus);
     cpu.registers.set_bc(value);
     3
 }
 
-pub fn pop_de(cpu: &mut Cpu, mmu: &Mmu) -> u8 {
-    let value = pop_u16(cpu, mmu);
+pub fn pop_de(cpu: &mut Cpu, bus: &mut impl Bus) -> u8 {
+    let value = pop_u16(cpu, bus);
     cpu.registers.set_de(value);
     3
 }
 
-pub fn pop_hl(cpu: &mut Cpu, mmu: &Mmu) -> u8 {
-    let value = pop_u16(cpu, mmu);
+pub fn pop_hl(cpu: &mut Cpu, bus: &mut impl Bus) -> u8 {
+    let value = pop_u16(cpu, bus);
     cpu.registers.set_hl(value);
     3
 }
 
-pub fn pop_af(cpu: &mut Cpu, mmu: &Mmu) -> u8 {
-    let value = pop_u16(cpu, mmu);
+pub fn pop_af(cpu: &mut Cpu, bus: &mut impl Bus) -> u8 {
+    let value = pop_u16(cpu, bus);
     cpu.registers.set_af(value);
     3
 }
 
-/// This helper pushes 16-bit value onto stack
-fn push_u16(cpu: &mut Cpu, mmu: &mut Mmu, value: u16) {
-    cpu.registers.sp = cpu.registers.sp.wrapping_sub(2);
-    mmu.write_word(cpu.registers.sp, value);
+/// This helper pushes 16-bit value onto stack. Like the hardware, an internal
+/// cycle (the SP decrement) comes first, then the high byte is written (to SP-1)
+/// and the low byte second (to SP-2). PUSH, CALL and RST go through here, and it
+/// shares `Cpu::push_byte` with interrupt dispatch.
+fn push_u16(cpu: &mut Cpu, bus: &mut impl Bus, value: u16) {
+    bus.idle();
+    cpu.push_byte(bus, (value >> 8) as u8);
+    cpu.push_byte(bus, value as u8);
 }
 
 /// This helper pops 16-bit value from stack
-fn pop_u16(cpu: &mut Cpu, mmu: &Mmu) -> u16 {
-    let value = mmu.read_word(cpu.registers.sp);
+fn pop_u16(cpu: &mut Cpu, bus: &mut impl Bus) -> u16 {
+    let value = bus.read_word(cpu.registers.sp);
     cpu.registers.sp = cpu.registers.sp.wrapping_add(2);
     value
 }
@@ -1276,8 +1379,8 @@ fn pop_u16(cpu: &mut Cpu, mmu: &Mmu) -> u16 {
 // ===== CB-Prefixed Instructions =====
 
 /// This handles all CB-prefixed instructions (rotates, shifts, bit operations)
-pub fn execute_cb(cpu: &mut Cpu, mmu: &mut Mmu) -> u8 {
-    let opcode = read_u8(cpu, mmu);
+pub fn execute_cb(cpu: &mut Cpu, bus: &mut impl Bus) -> u8 {
+    let opcode = read_u8(cpu, bus);
     
     // We extract the operation type from bits 6-7, register from bits 0-2
     let op = (opcode >> 6) & 0x03;
@@ -1285,19 +1388,19 @@ pub fn execute_cb(cpu: &mut Cpu, mmu: &mut Mmu) -> u8 {
     let reg = opcode & 0x07;
     
     match op {
-        0 => execute_cb_rot_shift(cpu, mmu, bit, reg),
-        1 => execute_cb_bit(cpu, mmu, bit, reg),
-        2 => execute_cb_res(cpu, mmu, bit, reg),
-        3 => execute_cb_set(cpu, mmu, bit, reg),
+        0 => execute_cb_rot_shift(cpu, bus, bit, reg),
+        1 => execute_cb_bit(cpu, bus, bit, reg),
+        2 => execute_cb_res(cpu, bus, bit, reg),
+        3 => execute_cb_set(cpu, bus, bit, reg),
         _ => unreachable!(),
     }
 }
 
 /// This handles CB rotate and shift operations (RLC, RRC, RL, RR, SLA, SRA, SWAP, SRL)
-fn execute_cb_rot_shift(cpu: &mut Cpu, mmu: &mut Mmu, op: u8, reg: u8) -> u8 {
+fn execute_cb_rot_shift(cpu: &mut Cpu, bus: &mut impl Bus, op: u8, reg: u8) -> u8 {
     let (value, cycles) = if reg == 6 {
         // (HL) operations take 4 cycles
-        (mmu.read_byte(cpu.registers.hl()), 4)
+        (bus.read_byte(cpu.registers.hl()), 4)
     } else {
         // Register operations take 2 cycles - use CB register encoding
         (get_reg_cb(cpu, reg), 2)
@@ -1316,7 +1419,7 @@ fn execute_cb_rot_shift(cpu: &mut Cpu, mmu: &mut Mmu, op: u8, reg: u8) -> u8 {
     };
     
     if reg == 6 {
-        mmu.write_byte(cpu.registers.hl(), result);
+        bus.write_byte(cpu.registers.hl(), result);
     } else {
         // Use CB register encoding
         set_reg_cb(cpu, reg, result);
@@ -1415,9 +1518,9 @@ fn srl(cpu: &mut Cpu, value: u8) -> u8 {
 }
 
 /// BIT b,r - Test bit in register
-fn execute_cb_bit(cpu: &mut Cpu, mmu: &Mmu, bit: u8, reg: u8) -> u8 {
+fn execute_cb_bit(cpu: &mut Cpu, bus: &mut impl Bus, bit: u8, reg: u8) -> u8 {
     let value = if reg == 6 {
-        mmu.read_byte(cpu.registers.hl())
+        bus.read_byte(cpu.registers.hl())
     } else {
         get_reg_cb(cpu, reg)  // Use CB register encoding
     };
@@ -1431,13 +1534,13 @@ fn execute_cb_bit(cpu: &mut Cpu, mmu: &Mmu, bit: u8, reg: u8) -> u8 {
 }
 
 /// RES b,r - Reset (clear) bit in register
-fn execute_cb_res(cpu: &mut Cpu, mmu: &mut Mmu, bit: u8, reg: u8) -> u8 {
+fn execute_cb_res(cpu: &mut Cpu, bus: &mut impl Bus, bit: u8, reg: u8) -> u8 {
     let mask = !(1 << bit);
     
     if reg == 6 {
         let address = cpu.registers.hl();
-        let value = mmu.read_byte(address);
-        mmu.write_byte(address, value & mask);
+        let value = bus.read_byte(address);
+        bus.write_byte(address, value & mask);
         4
     } else {
         let value = get_reg_cb(cpu, reg);  // Use CB register encoding
@@ -1447,13 +1550,13 @@ fn execute_cb_res(cpu: &mut Cpu, mmu: &mut Mmu, bit: u8, reg: u8) -> u8 {
 }
 
 /// SET b,r - Set bit in register
-fn execute_cb_set(cpu: &mut Cpu, mmu: &mut Mmu, bit: u8, reg: u8) -> u8 {
+fn execute_cb_set(cpu: &mut Cpu, bus: &mut impl Bus, bit: u8, reg: u8) -> u8 {
     let mask = 1 << bit;
     
     if reg == 6 {
         let address = cpu.registers.hl();
-        let value = mmu.read_byte(address);
-        mmu.write_byte(address, value | mask);
+        let value = bus.read_byte(address);
+        bus.write_byte(address, value | mask);
         4
     } else {
         let value = get_reg_cb(cpu, reg);  // Use CB register encoding
@@ -1461,3 +1564,400 @@ fn execute_cb_set(cpu: &mut Cpu, mmu: &mut Mmu, bit: u8, reg: u8) -> u8 {
         2
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mmu::Mmu;
+
+    /// This is one M-cycle of bus activity as seen by `RecordingBus`
+    #[derive(Debug, PartialEq)]
+    enum Access {
+        Read(u16),
+        Write(u16, u8),
+        Idle,
+    }
+
+    /// This bus records every M-cycle an instruction spends on it, in order
+    struct RecordingBus {
+        mmu: Mmu,
+        accesses: Vec<Access>,
+    }
+
+    impl RecordingBus {
+        /// This returns a bus whose 32KB ROM has `code` at the 0x0100 entry point
+        fn with_code(code: &[u8]) -> Self {
+            let mut rom = vec![0; 0x8000];
+            rom[0x0100..0x0100 + code.len()].copy_from_slice(code);
+            RecordingBus { mmu: Mmu::new(rom), accesses: Vec::new() }
+        }
+    }
+
+    impl Bus for RecordingBus {
+        fn read_byte(&mut self, address: u16) -> u8 {
+            self.accesses.push(Access::Read(address));
+            self.mmu.read_byte(address)
+        }
+
+        fn write_byte(&mut self, address: u16, value: u8) {
+            self.accesses.push(Access::Write(address, value));
+            self.mmu.write_byte(address, value);
+        }
+
+        fn idle(&mut self) {
+            self.accesses.push(Access::Idle);
+        }
+
+        fn peek_byte(&self, address: u16) -> u8 {
+            self.mmu.peek_byte(address)
+        }
+
+        fn poke_byte(&mut self, address: u16, value: u8) {
+            self.mmu.poke_byte(address, value);
+        }
+
+        fn reset_div(&mut self) {
+            self.mmu.reset_div();
+        }
+
+        fn set_trace_pc(&mut self, _pc: u16) {}
+    }
+
+    /// This returns a CPU with F set to `flags` (Z=0x80, N=0x40, H=0x20, C=0x10)
+    fn cpu_with_flags(flags: u8) -> Cpu {
+        let mut cpu = Cpu::new();
+        cpu.registers.set_f(flags);
+        cpu
+    }
+
+    #[test]
+    fn inc_and_dec_keep_carry() {
+        for carry in [0x00, 0x10] {
+            let mut cpu = cpu_with_flags(carry);
+            cpu.registers.b = 0xFF;
+            assert_eq!(inc_b(&mut cpu), 1);
+            assert_eq!(cpu.registers.b, 0x00);
+            assert_eq!(cpu.registers.f, 0xA0 | carry); // Z, H
+
+            dec_b(&mut cpu);
+            assert_eq!(cpu.registers.b, 0xFF);
+            assert_eq!(cpu.registers.f, 0x60 | carry); // N, H
+
+            cpu.registers.b = 0x01;
+            dec_b(&mut cpu);
+            assert_eq!(cpu.registers.f, 0xC0 | carry); // Z, N
+        }
+    }
+
+    #[test]
+    fn inc_and_dec_hl_memory_keep_carry() {
+        for carry in [0x00, 0x10] {
+            let mut cpu = cpu_with_flags(carry);
+            let mut mmu = Mmu::new(vec![0; 0x8000]);
+            cpu.registers.set_hl(0xC000);
+            mmu.write_byte(0xC000, 0x0F);
+
+            assert_eq!(inc_hl_mem(&mut cpu, &mut mmu), 3);
+            assert_eq!(mmu.read_byte(0xC000), 0x10);
+            assert_eq!(cpu.registers.f, 0x20 | carry); // H
+
+            assert_eq!(dec_hl_mem(&mut cpu, &mut mmu), 3);
+            assert_eq!(mmu.read_byte(0xC000), 0x0F);
+            assert_eq!(cpu.registers.f, 0x60 | carry); // N, H
+        }
+    }
+
+    #[test]
+    fn jp_hl_jumps_in_one_cycle() {
+        let mut rom = vec![0; 0x8000];
+        rom[0x0100] = 0xE9; // JP (HL)
+        let mut mmu = Mmu::new(rom);
+        let mut cpu = Cpu::new();
+        cpu.registers.set_hl(0x1234);
+
+        // Only the opcode fetch costs a cycle: HL itself is never dereferenced
+        assert_eq!(cpu.tick(&mut mmu), Ok(1));
+        assert_eq!(cpu.registers.pc, 0x1234);
+    }
+
+    /// This builds a CPU at 0x0100 with `code` placed there in ROM
+    fn cpu_with_code(code: &[u8]) -> (Cpu, Mmu) {
+        let mut rom = vec![0; 0x8000];
+        rom[0x0100..0x0100 + code.len()].copy_from_slice(code);
+        let mut cpu = Cpu::new();
+        cpu.strictness = StrictnessMode::Lenient;
+        (cpu, Mmu::new(rom))
+    }
+
+    #[test]
+    fn stop_skips_its_second_byte_and_waits_for_a_button() {
+        let (mut cpu, mut mmu) = cpu_with_code(&[0x10, 0x00]);
+        cpu.tick(&mut mmu).expect("STOP failed");
+        assert_eq!(cpu.registers.pc, 0x0102);
+        assert!(cpu.stopped);
+        assert_eq!(mmu.read_byte(0xFF04), 0x00);
+
+        // Nothing happens until a button on a selected line goes low
+        cpu.tick(&mut mmu).expect("STOP wait failed");
+        assert_eq!(cpu.registers.pc, 0x0102);
+        mmu.set_joypad_state(0xEF);
+        cpu.tick(&mut mmu).expect("NOP failed");
+        assert!(!cpu.stopped);
+        assert_eq!(cpu.registers.pc, 0x0103);
+    }
+
+    #[test]
+    fn stop_with_an_interrupt_pending_is_one_byte_long() {
+        // The byte after STOP runs as an instruction: INC A
+        let (mut cpu, mut mmu) = cpu_with_code(&[0x10, 0x3C]);
+        mmu.write_byte(0xFFFF, 0x01);
+        mmu.write_byte(0xFF0F, 0x01);
+        cpu.tick(&mut mmu).expect("STOP failed");
+        assert_eq!(cpu.registers.pc, 0x0101);
+        assert!(cpu.stopped);
+    }
+
+    #[test]
+    fn stop_with_a_button_held_halts_or_does_nothing() {
+        // No interrupt pending: 2 bytes and HALT instead of STOP
+        let (mut cpu, mut mmu) = cpu_with_code(&[0x10, 0x00]);
+        mmu.set_joypad_state(0xEF);
+        cpu.tick(&mut mmu).expect("STOP failed");
+        assert_eq!(cpu.registers.pc, 0x0102);
+        assert!(cpu.halted);
+        assert!(!cpu.stopped);
+
+        // Interrupt pending too: 1 byte and nothing else
+        let (mut cpu, mut mmu) = cpu_with_code(&[0x10, 0x00]);
+        mmu.set_joypad_state(0xEF);
+        mmu.write_byte(0xFFFF, 0x01);
+        mmu.write_byte(0xFF0F, 0x01);
+        cpu.tick(&mut mmu).expect("STOP failed");
+        assert_eq!(cpu.registers.pc, 0x0101);
+        assert!(!cpu.halted);
+        assert!(!cpu.stopped);
+    }
+
+    #[test]
+    fn push_and_pop_take_four_and_three_cycles() {
+        // PUSH BC; POP DE
+        let (mut cpu, mut mmu) = cpu_with_code(&[0xC5, 0xD1]);
+        cpu.registers.sp = 0xDFF0;
+        cpu.registers.set_bc(0x1234);
+
+        assert_eq!(cpu.tick(&mut mmu).unwrap(), 4);
+        assert_eq!(cpu.registers.sp, 0xDFEE);
+        assert_eq!(mmu.read_byte(0xDFEF), 0x12); // high byte at SP-1
+        assert_eq!(mmu.read_byte(0xDFEE), 0x34);
+
+        assert_eq!(cpu.tick(&mut mmu).unwrap(), 3);
+        assert_eq!(cpu.registers.de(), 0x1234);
+        assert_eq!(cpu.registers.sp, 0xDFF0);
+    }
+
+    #[test]
+    fn interrupt_dispatch_pushes_like_push_u16() {
+        let (mut pushed, mut push_mmu) = cpu_with_code(&[]);
+        pushed.registers.sp = 0xDFF0;
+        push_u16(&mut pushed, &mut push_mmu, 0x1234);
+
+        let (mut cpu, mut mmu) = cpu_with_code(&[]);
+        cpu.registers.sp = 0xDFF0;
+        cpu.registers.pc = 0x1234;
+        cpu.ime = true;
+        mmu.write_byte(0xFFFF, crate::interrupts::INT_TIMER);
+        mmu.write_byte(0xFF0F, crate::interrupts::INT_TIMER);
+        assert_eq!(crate::interrupts::handle_interrupts(&mut cpu, &mut mmu), 5);
+
+        assert_eq!(cpu.registers.pc, 0x0050);
+        assert_eq!(cpu.registers.sp, pushed.registers.sp);
+        for address in 0xDFEE..=0xDFEF {
+            assert_eq!(mmu.read_byte(address), push_mmu.read_byte(address), "0x{:04X}", address);
+        }
+    }
+
+    #[test]
+    fn add_hl_sets_h_and_c_and_keeps_z() {
+        // (instruction, HL, operand, result, flags before, flags after)
+        type Case = (fn(&mut Cpu, &mut Mmu) -> u8, u16, u16, u16, u8, u8);
+        let cases: [Case; 4] = [
+            (add_hl_bc, 0x0FFF, 0x0001, 0x1000, 0xC0, 0xA0),
+            (add_hl_de, 0x8000, 0x8000, 0x0000, 0x40, 0x10),
+            (add_hl_hl, 0x8800, 0x8800, 0x1000, 0x40, 0x30),
+            (add_hl_sp, 0xF000, 0x1000, 0x0000, 0xF0, 0x90),
+        ];
+        for (add, hl, operand, result, before, after) in cases {
+            let mut cpu = cpu_with_flags(before);
+            cpu.registers.set_hl(hl);
+            cpu.registers.set_bc(operand);
+            cpu.registers.set_de(operand);
+            cpu.registers.sp = operand;
+
+            assert_eq!(add(&mut cpu, &mut Mmu::new(vec![0; 0x8000])), 2);
+            assert_eq!(cpu.registers.hl(), result);
+            assert_eq!(cpu.registers.f, after, "HL={:04X} + {:04X}", hl, operand);
+        }
+    }
+
+    #[test]
+    fn jp_hl_jumps_without_reading_memory() {
+        let mut bus = RecordingBus::with_code(&[0xE9]); // JP (HL)
+        let mut cpu = Cpu::new();
+        cpu.registers.set_hl(0x1234);
+
+        assert_eq!(cpu.tick(&mut bus), Ok(1));
+        assert_eq!(cpu.registers.pc, 0x1234);
+        // Only the opcode fetch touches the bus
+        assert_eq!(bus.accesses, [Access::Read(0x0100)]);
+    }
+
+    #[test]
+    fn push_and_pop_use_one_bus_access_or_idle_per_m_cycle() {
+        // PUSH BC; POP DE
+        let mut bus = RecordingBus::with_code(&[0xC5, 0xD1]);
+        let mut cpu = Cpu::new();
+        cpu.registers.sp = 0xDFF0;
+        cpu.registers.set_bc(0x1234);
+
+        assert_eq!(cpu.tick(&mut bus), Ok(4));
+        assert_eq!(bus.accesses, [
+            Access::Read(0x0100),
+            Access::Idle, // SP decrement
+            Access::Write(0xDFEF, 0x12),
+            Access::Write(0xDFEE, 0x34),
+        ]);
+
+        bus.accesses.clear();
+        assert_eq!(cpu.tick(&mut bus), Ok(3));
+        assert_eq!(bus.accesses, [Access::Read(0x0101), Access::Read(0xDFEE), Access::Read(0xDFEF)]);
+    }
+
+    #[test]
+    fn control_flow_internal_cycles_go_through_the_bus() {
+        // (code, M-cycles, number of idle cycles)
+        let cases: [(&[u8], u8, usize); 8] = [
+            (&[0xCD, 0x00, 0x02], 6, 1), // CALL $0200
+            (&[0xC9], 4, 1),             // RET
+            (&[0xC3, 0x00, 0x02], 4, 1), // JP $0200
+            (&[0x18, 0x10], 3, 1),       // JR +16
+            (&[0xC8], 5, 2),             // RET Z (taken)
+            (&[0xC0], 2, 1),             // RET NZ (not taken)
+            (&[0x09], 2, 1),             // ADD HL,BC
+            (&[0xE8, 0x01], 4, 2),       // ADD SP,1
+        ];
+        for (code, cycles, idles) in cases {
+            let mut bus = RecordingBus::with_code(code);
+            let mut cpu = cpu_with_flags(0x80); // Z set
+            cpu.registers.sp = 0xDFF0;
+
+            assert_eq!(cpu.tick(&mut bus), Ok(cycles), "{:02X?}", code);
+            // Every M-cycle shows up on the bus, either as an access or as an idle
+            assert_eq!(bus.accesses.len(), cycles as usize, "{:02X?}", code);
+            let idle_count = bus.accesses.iter().filter(|access| **access == Access::Idle).count();
+            assert_eq!(idle_count, idles, "{:02X?}", code);
+        }
+    }
+
+    #[test]
+    fn halt_bug_runs_the_next_byte_twice() {
+        // HALT; INC A; NOP with IME off and a timer interrupt pending
+        let (mut cpu, mut mmu) = cpu_with_code(&[0x76, 0x3C, 0x00]);
+        cpu.registers.a = 0;
+        mmu.write_byte(0xFFFF, crate::interrupts::INT_TIMER);
+        mmu.write_byte(0xFF0F, crate::interrupts::INT_TIMER);
+
+        cpu.tick(&mut mmu).expect("HALT failed");
+        assert!(!cpu.halted);
+        assert_eq!(cpu.registers.pc, 0x0101);
+
+        // INC A is read once without moving PC, then again normally
+        cpu.tick(&mut mmu).expect("INC A failed");
+        assert_eq!(cpu.registers.a, 1);
+        assert_eq!(cpu.registers.pc, 0x0101);
+        cpu.tick(&mut mmu).expect("INC A failed");
+        assert_eq!(cpu.registers.a, 2);
+        assert_eq!(cpu.registers.pc, 0x0102);
+    }
+
+    #[test]
+    fn halt_right_after_ei_with_an_interrupt_pending_returns_to_the_halt() {
+        // EI; HALT with a timer interrupt pending
+        let (mut cpu, mut mmu) = cpu_with_code(&[0xFB, 0x76]);
+        cpu.registers.sp = 0xDFF0;
+        mmu.write_byte(0xFFFF, crate::interrupts::INT_TIMER);
+        mmu.write_byte(0xFF0F, crate::interrupts::INT_TIMER);
+
+        cpu.tick(&mut mmu).expect("EI failed");
+        cpu.tick(&mut mmu).expect("HALT failed");
+        assert!(!cpu.halted);
+        assert!(cpu.ime);
+        crate::interrupts::handle_interrupts(&mut cpu, &mut mmu);
+        assert_eq!(cpu.registers.pc, 0x0050);
+        // The pushed return address is the HALT itself
+        assert_eq!(mmu.read_byte(0xDFEF), 0x01);
+        assert_eq!(mmu.read_byte(0xDFEE), 0x01);
+    }
+
+    #[test]
+    fn halt_without_a_pending_interrupt_halts() {
+        let (mut cpu, mut mmu) = cpu_with_code(&[0x76]);
+        mmu.write_byte(0xFFFF, crate::interrupts::INT_TIMER);
+        mmu.write_byte(0xFF0F, 0x00);
+        cpu.tick(&mut mmu).expect("HALT failed");
+        assert!(cpu.halted);
+        assert!(!cpu.halt_bug);
+    }
+
+    #[test]
+    fn stop_clears_div_during_dma() {
+        let mut mmu = Mmu::new(vec![0; 0x8000]);
+        mmu.write_byte(0xFF46, 0xC0);
+        mmu.tick_dma();
+        mmu.tick_dma();
+        assert!(mmu.dma_active());
+
+        let mut cpu = Cpu::new();
+        cpu.strictness = StrictnessMode::Lenient;
+        stop(&mut cpu, &mut mmu);
+        assert!(cpu.stopped);
+        assert_eq!(mmu.peek_byte(0xFF04), 0);
+        assert!(mmu.take_div_reset());
+    }
+
+    #[test]
+    fn scf_sets_carry_and_keeps_z() {
+        for z in [0x00, 0x80] {
+            let mut cpu = cpu_with_flags(z | 0x60);
+            assert_eq!(scf(&mut cpu), 1);
+            assert_eq!(cpu.registers.f, z | 0x10);
+        }
+    }
+
+    #[test]
+    fn ccf_flips_carry_and_keeps_z() {
+        for z in [0x00, 0x80] {
+            let mut cpu = cpu_with_flags(z | 0x70);
+            assert_eq!(ccf(&mut cpu), 1);
+            assert_eq!(cpu.registers.f, z);
+            ccf(&mut cpu);
+            assert_eq!(cpu.registers.f, z | 0x10);
+        }
+    }
+
+    #[test]
+    fn pop_af_drops_the_low_nibble_of_f() {
+        let mut mmu = Mmu::new(vec![0; 0x8000]);
+        mmu.write_byte(0xC000, 0xFF);
+        mmu.write_byte(0xC001, 0x12);
+
+        let mut cpu = Cpu::new();
+        cpu.registers.sp = 0xC000;
+        assert_eq!(pop_af(&mut cpu, &mut mmu), 3);
+        assert_eq!(cpu.registers.a, 0x12);
+        assert_eq!(cpu.registers.f, 0xF0);
+        assert_eq!(cpu.registers.sp, 0xC002);
+
+        cpu.registers.set_f(0x5A);
+        assert_eq!(cpu.registers.f, 0x50);
+    }
+}
@@ -8,10 +8,97 @@
 
 mod registers;
 mod instructions;
-mod opcodes;
+pub mod opcodes;
 
 pub use registers::Registers;
 
+use crate::mmu::Bus;
+
+use crate::savestate::CpuState;
+
+use std::collections::HashSet;
+use std::fmt;
+use std::str::FromStr;
+
+/// This controls how the CPU reacts to unimplemented or spec-violating conditions
+/// (illegal opcodes, instructions whose behavior isn't emulated accurately yet)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StrictnessMode {
+    /// Keep running silently, with illegal opcodes run as 1 M-cycle NOPs
+    Lenient,
+    /// Keep running past unimplemented behavior with a warning (once per kind of
+    /// condition), but stop on an illegal opcode with a `CpuError`
+    #[default]
+    Warn,
+    /// Stop with a recoverable `CpuError` on either
+    Strict,
+}
+
+impl FromStr for StrictnessMode {
+    type Err = String;
+    
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "lenient" => Ok(StrictnessMode::Lenient),
+            "warn" => Ok(StrictnessMode::Warn),
+            "strict" => Ok(StrictnessMode::Strict),
+            _ => Err(format!("Unknown strictness mode: {} (expected lenient, warn or strict)", s)),
+        }
+    }
+}
+
+/// Errors the CPU reports instead of panicking, so the caller can stop cleanly
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CpuError {
+    /// An undefined opcode was fetched at the given address
+    IllegalOpcode { opcode: u8, pc: u16 },
+    /// Execution reached behavior that isn't emulated accurately yet
+    Unimplemented { what: &'static str, pc: u16 },
+    /// An instruction was fetched from a region that can't hold code (see `fetch_region_warning`)
+    UnusualFetch { region: &'static str, pc: u16 },
+}
+
+impl fmt::Display for CpuError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CpuError::IllegalOpcode { opcode, pc } => {
+                write!(f, "Illegal opcode 0x{:02X} at 0x{:04X}", opcode, pc)
+            }
+            CpuError::Unimplemented { what, pc } => {
+                write!(f, "Unimplemented behavior at 0x{:04X}: {}", pc, what)
+            }
+            CpuError::UnusualFetch { region, pc } => {
+                write!(f, "Executing from {} at 0x{:04X}", region, pc)
+            }
+        }
+    }
+}
+
+impl CpuError {
+    /// This returns the address of the instruction that caused the error
+    pub fn pc(&self) -> u16 {
+        match *self {
+            CpuError::IllegalOpcode { pc, .. }
+            | CpuError::Unimplemented { pc, .. }
+            | CpuError::UnusualFetch { pc, .. } => pc,
+        }
+    }
+}
+
+impl std::error::Error for CpuError {}
+
+/// This returns the name of the region `pc` is in if code can't run from there:
+/// the unusable area 0xFEA0-0xFEFF (reads as 0xFF) and the I/O registers and IE
+/// (0xFF00-0xFF7F, 0xFFFF). ROM, RAM, VRAM, OAM and HRAM all return None, since
+/// games do run code from RAM and HRAM (e.g. the OAM DMA routine).
+pub fn fetch_region_warning(pc: u16) -> Option<&'static str> {
+    match pc {
+        0xFEA0..=0xFEFF => Some("unusable memory"),
+        0xFF00..=0xFF7F | 0xFFFF => Some("I/O registers"),
+        _ => None,
+    }
+}
+
 /// This struct represents the Game Boy's CPU state including all registers,
 /// timing information, and execution state like whether interrupts are enabled
 pub struct Cpu {
@@ -21,122 +108,268 @@ pub struct Cpu {
     /// The interrupt master enable flag (IME) which controls if interrupts work
     pub ime: bool,
     
+    /// Set by EI: IME turns on after the next instruction (DI cancels it)
+    pub ime_pending: bool,
+    
     /// Whether we're currently halted (waiting for an interrupt)
     pub halted: bool,
     
+    /// Set by HALT with IME=0 and an interrupt pending: the next opcode fetch
+    /// doesn't increment PC (the "halt bug")
+    pub halt_bug: bool,
+    
+    /// Whether we're in STOP mode (waiting for a button press)
+    pub stopped: bool,
+    
     /// Machine cycles (M-cycles) spent on last instruction - each is 4 clock cycles
     pub last_m_cycles: u8,
+    
+    /// How unimplemented or spec-violating conditions are handled
+    pub strictness: StrictnessMode,
+    
+    /// Fetch regions already warned about (Warn mode only reports each once)
+    warned: HashSet<&'static str>,
 }
 
 impl Cpu {
-    /// This creates a new CPU with all registers initialized to their power-on state.
-    /// At power on, the Game Boy's CPU starts with specific values in registers.
+    /// This creates a new CPU with all registers initialized to the state the boot
+    /// ROM leaves them in when it hands control to the cartridge at 0x0100.
     pub fn new() -> Self {
         Cpu {
             registers: Registers::new(),
             ime: false,
+            ime_pending: false,
             halted: false,
+            halt_bug: false,
+            stopped: false,
             last_m_cycles: 0,
+            strictness: StrictnessMode::default(),
+            warned: HashSet::new(),
+        }
+    }
+    
+    /// This creates a CPU in its power-on state (zeroed registers, PC=0x0000) for
+    /// running the boot ROM instead of starting at the post-boot handoff
+    pub fn power_on() -> Self {
+        Cpu {
+            registers: Registers::power_on(),
+            ..Cpu::new()
         }
     }
     
     /// This method executes one instruction - it fetches the opcode from memory,
     /// decodes what instruction it is, executes it, and returns how many cycles it took.
-    pub fn tick(&mut self, mmu: &mut crate::mmu::Mmu) -> u8 {
+    /// In Strict mode, unimplemented or illegal behavior is returned as an error.
+    pub fn tick(&mut self, bus: &mut impl Bus) -> Result<u8, CpuError> {
+        // In STOP mode we wait for a button press on a selected joypad line
+        if self.stopped {
+            if bus.peek_byte(0xFF00) & 0x0F == 0x0F {
+                return Ok(1);
+            }
+            self.stopped = false;
+        }
+        
         // If we're halted, we just wait and don't execute anything
         if self.halted {
-            return 1; // Return 1 M-cycle for waiting
+            return Ok(1); // Return 1 M-cycle for waiting
         }
         
-        // We fetch the next instruction byte from where PC points
-        let opcode = mmu.read_byte(self.registers.pc);
-        self.registers.pc = self.registers.pc.wrapping_add(1);
+        // An EI executed before this instruction takes effect once it completes
+        let enable_ime = self.ime_pending;
+        
+        // We fetch the next instruction byte from where PC points. Any address
+        // works, but some regions can only mean a runaway PC.
+        if let Some(region) = fetch_region_warning(self.registers.pc) {
+            self.unusual_fetch(region)?;
+        }
+        bus.set_trace_pc(self.registers.pc);
+        let opcode = bus.read_byte(self.registers.pc);
+        if self.halt_bug {
+            // The halt bug: PC stays put, so this byte is read again next
+            self.halt_bug = false;
+        } else {
+            self.registers.pc = self.registers.pc.wrapping_add(1);
+        }
         
         // We execute the instruction and get back how many cycles it took
-        let cycles = self.execute(opcode, mmu);
+        let cycles = self.execute(opcode, bus)?;
         self.last_m_cycles = cycles;
         
-        cycles
+        // The instruction may have been a DI, which cancels the pending enable
+        if enable_ime && self.ime_pending {
+            self.ime = true;
+            self.ime_pending = false;
+        }
+        
+        Ok(cycles)
+    }
+    
+    /// This pushes one byte onto the stack. PUSH, CALL and RST push through here,
+    /// and so does interrupt dispatch, which picks the interrupt between the two
+    /// bytes of the return address.
+    pub fn push_byte(&mut self, bus: &mut impl Bus, value: u8) {
+        self.registers.sp = self.registers.sp.wrapping_sub(1);
+        bus.write_byte(self.registers.sp, value);
+    }
+    
+    /// This returns the register dump plus interrupt and halt state for logging,
+    /// e.g. "AF=01B0 BC=0013 DE=00D8 HL=014D SP=FFFE PC=0100 [Z - H C] IME=0 HALT=0 STOP=0"
+    pub fn state_string(&self) -> String {
+        format!(
+            "{} IME={} HALT={} STOP={}",
+            self.registers.dump(),
+            self.ime as u8,
+            self.halted as u8,
+            self.stopped as u8
+        )
+    }
+    
+    /// This returns the registers and execution state for a save state. The
+    /// strictness mode is a setting rather than machine state, so it isn't saved.
+    pub fn save_state(&self) -> CpuState {
+        let r = &self.registers;
+        CpuState {
+            a: r.a,
+            f: r.f,
+            b: r.b,
+            c: r.c,
+            d: r.d,
+            e: r.e,
+            h: r.h,
+            l: r.l,
+            sp: r.sp,
+            pc: r.pc,
+            ime: self.ime,
+            ime_pending: self.ime_pending,
+            halted: self.halted,
+            halt_bug: self.halt_bug,
+            stopped: self.stopped,
+            last_m_cycles: self.last_m_cycles,
+        }
+    }
+
+    /// This restores what `save_state` returned
+    pub fn load_state(&mut self, state: &CpuState) {
+        let r = &mut self.registers;
+        [r.a, r.b, r.c, r.d, r.e, r.h, r.l] = [state.a, state.b, state.c, state.d, state.e, state.h, state.l];
+        // A hand-edited or corrupt state can't put bits in F's low nibble
+        r.set_f(state.f);
+        r.sp = state.sp;
+        r.pc = state.pc;
+        self.ime = state.ime;
+        self.ime_pending = state.ime_pending;
+        self.halted = state.halted;
+        self.halt_bug = state.halt_bug;
+        self.stopped = state.stopped;
+        self.last_m_cycles = state.last_m_cycles;
+    }
+    
+    /// This handles an illegal/undefined opcode according to the strictness mode.
+    /// Reaching one usually means an emulation bug sent the CPU into data, so only
+    /// Lenient mode keeps running (treating it as a 1 M-cycle NOP).
+    fn illegal_opcode(&mut self, opcode: u8) -> Result<u8, CpuError> {
+        let pc = self.registers.pc.wrapping_sub(1);
+        match self.strictness {
+            StrictnessMode::Strict | StrictnessMode::Warn => Err(CpuError::IllegalOpcode { opcode, pc }),
+            StrictnessMode::Lenient => Ok(1),
+        }
+    }
+    
+    /// This reports an instruction fetch from `region` according to the strictness
+    /// mode. The fetch itself goes ahead when we keep running (0xFF from unusable
+    /// memory decodes as RST 38). Warn mode prints each region only once.
+    fn unusual_fetch(&mut self, region: &'static str) -> Result<(), CpuError> {
+        let pc = self.registers.pc;
+        match self.strictness {
+            StrictnessMode::Strict => Err(CpuError::UnusualFetch { region, pc }),
+            StrictnessMode::Warn => {
+                if self.warned.insert(region) {
+                    eprintln!("Warning: executing from {} at 0x{:04X}", region, pc);
+                }
+                Ok(())
+            }
+            StrictnessMode::Lenient => Ok(()),
+        }
     }
     
     /// This executes a single instruction based on the opcode we fetched.
     /// Each opcode maps to a specific instruction the CPU can perform.
-    fn execute(&mut self, opcode: u8, mmu: &mut crate::mmu::Mmu) -> u8 {
+    fn execute(&mut self, opcode: u8, bus: &mut impl Bus) -> Result<u8, CpuError> {
         // We use a match statement to dispatch to the correct instruction implementation
         // based on the opcode value. Each instruction returns the number of M-cycles it took.
         use instructions::*;
         
-        match opcode {
+        let cycles = match opcode {
             // 0x0X - Misc/control
             0x00 => nop(self),
-            0x01 => ld_bc_u16(self, mmu),
-            0x02 => ld_bc_a(self, mmu),
-            0x03 => inc_bc(self),
+            0x01 => ld_bc_u16(self, bus),
+            0x02 => ld_bc_a(self, bus),
+            0x03 => inc_bc(self, bus),
             0x04 => inc_b(self),
             0x05 => dec_b(self),
-            0x06 => ld_b_u8(self, mmu),
+            0x06 => ld_b_u8(self, bus),
             0x07 => rlca(self),
-            0x08 => ld_u16_sp(self, mmu),
-            0x09 => add_hl_bc(self),
-            0x0A => ld_a_bc(self, mmu),
-            0x0B => dec_bc(self),
+            0x08 => ld_u16_sp(self, bus),
+            0x09 => add_hl_bc(self, bus),
+            0x0A => ld_a_bc(self, bus),
+            0x0B => dec_bc(self, bus),
             0x0C => inc_c(self),
             0x0D => dec_c(self),
-            0x0E => ld_c_u8(self, mmu),
+            0x0E => ld_c_u8(self, bus),
             0x0F => rrca(self),
             
             // 0x1X
-            0x10 => stop(self),
-            0x11 => ld_de_u16(self, mmu),
-            0x12 => ld_de_a(self, mmu),
-            0x13 => inc_de(self),
+            0x10 => stop(self, bus),
+            0x11 => ld_de_u16(self, bus),
+            0x12 => ld_de_a(self, bus),
+            0x13 => inc_de(self, bus),
             0x14 => inc_d(self),
             0x15 => dec_d(self),
-            0x16 => ld_d_u8(self, mmu),
+            0x16 => ld_d_u8(self, bus),
             0x17 => rla(self),
-            0x18 => jr_i8(self, mmu),
-            0x19 => add_hl_de(self),
-            0x1A => ld_a_de(self, mmu),
-            0x1B => dec_de(self),
+            0x18 => jr_i8(self, bus),
+            0x19 => add_hl_de(self, bus),
+            0x1A => ld_a_de(self, bus),
+            0x1B => dec_de(self, bus),
             0x1C => inc_e(self),
             0x1D => dec_e(self),
-            0x1E => ld_e_u8(self, mmu),
+            0x1E => ld_e_u8(self, bus),
             0x1F => rra(self),
             
             // 0x2X
-            0x20 => jr_nz_i8(self, mmu),
-            0x21 => ld_hl_u16(self, mmu),
-            0x22 => ld_hli_a(self, mmu),
-            0x23 => inc_hl(self),
+            0x20 => jr_nz_i8(self, bus),
+            0x21 => ld_hl_u16(self, bus),
+            0x22 => ld_hli_a(self, bus),
+            0x23 => inc_hl(self, bus),
             0x24 => inc_h(self),
             0x25 => dec_h(self),
-            0x26 => ld_h_u8(self, mmu),
+            0x26 => ld_h_u8(self, bus),
             0x27 => daa(self),
-            0x28 => jr_z_i8(self, mmu),
-            0x29 => add_hl_hl(self),
-            0x2A => ld_a_hli(self, mmu),
-            0x2B => dec_hl(self),
+            0x28 => jr_z_i8(self, bus),
+            0x29 => add_hl_hl(self, bus),
+            0x2A => ld_a_hli(self, bus),
+            0x2B => dec_hl(self, bus),
             0x2C => inc_l(self),
             0x2D => dec_l(self),
-            0x2E => ld_l_u8(self, mmu),
+            0x2E => ld_l_u8(self, bus),
             0x2F => cpl(self),
             
             // 0x3X
-            0x30 => jr_nc_i8(self, mmu),
-            0x31 => ld_sp_u16(self, mmu),
-            0x32 => ld_hld_a(self, mmu),
-            0x33 => inc_sp(self),
-            0x34 => inc_hl_mem(self, mmu),
-            0x35 => dec_hl_mem(self, mmu),
-            0x36 => ld_hl_u8(self, mmu),
+            0x30 => jr_nc_i8(self, bus),
+            0x31 => ld_sp_u16(self, bus),
+            0x32 => ld_hld_a(self, bus),
+            0x33 => inc_sp(self, bus),
+            0x34 => inc_hl_mem(self, bus),
+            0x35 => dec_hl_mem(self, bus),
+            0x36 => ld_hl_u8(self, bus),
             0x37 => scf(self),
-            0x38 => jr_c_i8(self, mmu),
-            0x39 => add_hl_sp(self),
-            0x3A => ld_a_hld(self, mmu),
-            0x3B => dec_sp(self),
+            0x38 => jr_c_i8(self, bus),
+            0x39 => add_hl_sp(self, bus),
+            0x3A => ld_a_hld(self, bus),
+            0x3B => dec_sp(self, bus),
             0x3C => inc_a(self),
             0x3D => dec_a(self),
-            0x3E => ld_a_u8(self, mmu),
+            0x3E => ld_a_u8(self, bus),
             0x3F => ccf(self),
             
             // 0x4X - LD r,r instructions (register to register loads)
@@ -146,7 +379,7 @@ impl Cpu {
             0x43 => ld_r_r(self, REG_B, REG_E),
             0x44 => ld_r_r(self, REG_B, REG_H),
             0x45 => ld_r_r(self, REG_B, REG_L),
-            0x46 => ld_b_hl(self, mmu),
+            0x46 => ld_b_hl(self, bus),
             0x47 => ld_r_r(self, REG_B, REG_A),
             0x48 => ld_r_r(self, REG_C, REG_B),
             0x49 => ld_r_r(self, REG_C, REG_C),
@@ -154,7 +387,7 @@ impl Cpu {
             0x4B => ld_r_r(self, REG_C, REG_E),
             0x4C => ld_r_r(self, REG_C, REG_H),
             0x4D => ld_r_r(self, REG_C, REG_L),
-            0x4E => ld_c_hl(self, mmu),
+            0x4E => ld_c_hl(self, bus),
             0x4F => ld_r_r(self, REG_C, REG_A),
             
             // 0x5X
@@ -164,7 +397,7 @@ impl Cpu {
             0x53 => ld_r_r(self, REG_D, REG_E),
             0x54 => ld_r_r(self, REG_D, REG_H),
             0x55 => ld_r_r(self, REG_D, REG_L),
-            0x56 => ld_d_hl(self, mmu),
+            0x56 => ld_d_hl(self, bus),
             0x57 => ld_r_r(self, REG_D, REG_A),
             0x58 => ld_r_r(self, REG_E, REG_B),
             0x59 => ld_r_r(self, REG_E, REG_C),
@@ -172,7 +405,7 @@ impl Cpu {
             0x5B => ld_r_r(self, REG_E, REG_E),
             0x5C => ld_r_r(self, REG_E, REG_H),
             0x5D => ld_r_r(self, REG_E, REG_L),
-            0x5E => ld_e_hl(self, mmu),
+            0x5E => ld_e_hl(self, bus),
             0x5F => ld_r_r(self, REG_E, REG_A),
             
             // 0x6X
@@ -182,7 +415,7 @@ impl Cpu {
             0x63 => ld_r_r(self, REG_H, REG_E),
             0x64 => ld_r_r(self, REG_H, REG_H),
             0x65 => ld_r_r(self, REG_H, REG_L),
-            0x66 => ld_h_hl(self, mmu),
+            0x66 => ld_h_hl(self, bus),
             0x67 => ld_r_r(self, REG_H, REG_A),
             0x68 => ld_r_r(self, REG_L, REG_B),
             0x69 => ld_r_r(self, REG_L, REG_C),
@@ -190,25 +423,25 @@ impl Cpu {
             0x6B => ld_r_r(self, REG_L, REG_E),
             0x6C => ld_r_r(self, REG_L, REG_H),
             0x6D => ld_r_r(self, REG_L, REG_L),
-            0x6E => ld_l_hl(self, mmu),
+            0x6E => ld_l_hl(self, bus),
             0x6F => ld_r_r(self, REG_L, REG_A),
             
             // 0x7X
-            0x70 => ld_hl_b(self, mmu),
-            0x71 => ld_hl_c(self, mmu),
-            0x72 => ld_hl_d(self, mmu),
-            0x73 => ld_hl_e(self, mmu),
-            0x74 => ld_hl_h(self, mmu),
-            0x75 => ld_hl_l(self, mmu),
-            0x76 => halt(self),
-            0x77 => ld_hl_a(self, mmu),
+            0x70 => ld_hl_b(self, bus),
+            0x71 => ld_hl_c(self, bus),
+            0x72 => ld_hl_d(self, bus),
+            0x73 => ld_hl_e(self, bus),
+            0x74 => ld_hl_h(self, bus),
+            0x75 => ld_hl_l(self, bus),
+            0x76 => halt(self, bus),
+            0x77 => ld_hl_a(self, bus),
             0x78 => ld_r_r(self, REG_A, REG_B),
             0x79 => ld_r_r(self, REG_A, REG_C),
             0x7A => ld_r_r(self, REG_A, REG_D),
             0x7B => ld_r_r(self, REG_A, REG_E),
             0x7C => ld_r_r(self, REG_A, REG_H),
             0x7D => ld_r_r(self, REG_A, REG_L),
-            0x7E => ld_a_hl(self, mmu),
+            0x7E => ld_a_hl(self, bus),
             0x7F => ld_r_r(self, REG_A, REG_A),
             
             // 0x8X - ADD/ADC instructions
@@ -218,7 +451,7 @@ impl Cpu {
             0x83 => add_a_r(self, REG_E),
             0x84 => add_a_r(self, REG_H),
             0x85 => add_a_r(self, REG_L),
-            0x86 => add_a_hl(self, mmu),
+            0x86 => add_a_hl(self, bus),
             0x87 => add_a_r(self, REG_A),
             0x88 => adc_a_r(self, REG_B),
             0x89 => adc_a_r(self, REG_C),
@@ -226,7 +459,7 @@ impl Cpu {
             0x8B => adc_a_r(self, REG_E),
             0x8C => adc_a_r(self, REG_H),
             0x8D => adc_a_r(self, REG_L),
-            0x8E => adc_a_hl(self, mmu),
+            0x8E => adc_a_hl(self, bus),
             0x8F => adc_a_r(self, REG_A),
             
             // 0x9X - SUB/SBC instructions
@@ -236,7 +469,7 @@ impl Cpu {
             0x93 => sub_a_r(self, REG_E),
             0x94 => sub_a_r(self, REG_H),
             0x95 => sub_a_r(self, REG_L),
-            0x96 => sub_a_hl(self, mmu),
+            0x96 => sub_a_hl(self, bus),
             0x97 => sub_a_r(self, REG_A),
             0x98 => sbc_a_r(self, REG_B),
             0x99 => sbc_a_r(self, REG_C),
@@ -244,7 +477,7 @@ impl Cpu {
             0x9B => sbc_a_r(self, REG_E),
             0x9C => sbc_a_r(self, REG_H),
             0x9D => sbc_a_r(self, REG_L),
-            0x9E => sbc_a_hl(self, mmu),
+            0x9E => sbc_a_hl(self, bus),
             0x9F => sbc_a_r(self, REG_A),
             
             // 0xAX - AND/XOR instructions
@@ -254,7 +487,7 @@ impl Cpu {
             0xA3 => and_a_r(self, REG_E),
             0xA4 => and_a_r(self, REG_H),
             0xA5 => and_a_r(self, REG_L),
-            0xA6 => and_a_hl(self, mmu),
+            0xA6 => and_a_hl(self, bus),
             0xA7 => and_a_r(self, REG_A),
             0xA8 => xor_a_r(self, REG_B),
             0xA9 => xor_a_r(self, REG_C),
@@ -262,7 +495,7 @@ impl Cpu {
             0xAB => xor_a_r(self, REG_E),
             0xAC => xor_a_r(self, REG_H),
             0xAD => xor_a_r(self, REG_L),
-            0xAE => xor_a_hl(self, mmu),
+            0xAE => xor_a_hl(self, bus),
             0xAF => xor_a_r(self, REG_A),
             
             // 0xBX - OR/CP instructions
@@ -272,7 +505,7 @@ impl Cpu {
             0xB3 => or_a_r(self, REG_E),
             0xB4 => or_a_r(self, REG_H),
             0xB5 => or_a_r(self, REG_L),
-            0xB6 => or_a_hl(self, mmu),
+            0xB6 => or_a_hl(self, bus),
             0xB7 => or_a_r(self, REG_A),
             0xB8 => cp_a_r(self, REG_B),
             0xB9 => cp_a_r(self, REG_C),
@@ -280,81 +513,83 @@ impl Cpu {
             0xBB => cp_a_r(self, REG_E),
             0xBC => cp_a_r(self, REG_H),
             0xBD => cp_a_r(self, REG_L),
-            0xBE => cp_a_hl(self, mmu),
+            0xBE => cp_a_hl(self, bus),
             0xBF => cp_a_r(self, REG_A),
             
             // 0xCX - Control flow and stack operations
-            0xC0 => ret_nz(self, mmu),
-            0xC1 => pop_bc(self, mmu),
-            0xC2 => jp_nz_u16(self, mmu),
-            0xC3 => jp_u16(self, mmu),
-            0xC4 => call_nz_u16(self, mmu),
-            0xC5 => push_bc(self, mmu),
-            0xC6 => add_a_u8(self, mmu),
-            0xC7 => rst_00(self, mmu),
-            0xC8 => ret_z(self, mmu),
-            0xC9 => ret(self, mmu),
-            0xCA => jp_z_u16(self, mmu),
-            0xCB => execute_cb(self, mmu), // CB-prefixed instructions
-            0xCC => call_z_u16(self, mmu),
-            0xCD => call_u16(self, mmu),
-            0xCE => adc_a_u8(self, mmu),
-            0xCF => rst_08(self, mmu),
+            0xC0 => ret_nz(self, bus),
+            0xC1 => pop_bc(self, bus),
+            0xC2 => jp_nz_u16(self, bus),
+            0xC3 => jp_u16(self, bus),
+            0xC4 => call_nz_u16(self, bus),
+            0xC5 => push_bc(self, bus),
+            0xC6 => add_a_u8(self, bus),
+            0xC7 => rst_00(self, bus),
+            0xC8 => ret_z(self, bus),
+            0xC9 => ret(self, bus),
+            0xCA => jp_z_u16(self, bus),
+            0xCB => execute_cb(self, bus), // CB-prefixed instructions
+            0xCC => call_z_u16(self, bus),
+            0xCD => call_u16(self, bus),
+            0xCE => adc_a_u8(self, bus),
+            0xCF => rst_08(self, bus),
             
             // 0xDX
-            0xD0 => ret_nc(self, mmu),
-            0xD1 => pop_de(self, mmu),
-            0xD2 => jp_nc_u16(self, mmu),
-            0xD3 => illegal_opcode(opcode),
-            0xD4 => call_nc_u16(self, mmu),
-            0xD5 => push_de(self, mmu),
-            0xD6 => sub_a_u8(self, mmu),
-            0xD7 => rst_10(self, mmu),
-            0xD8 => ret_c(self, mmu),
-            0xD9 => reti(self, mmu),
-            0xDA => jp_c_u16(self, mmu),
-            0xDB => illegal_opcode(opcode),
-            0xDC => call_c_u16(self, mmu),
-            0xDD => illegal_opcode(opcode),
-            0xDE => sbc_a_u8(self, mmu),
-            0xDF => rst_18(self, mmu),
+            0xD0 => ret_nc(self, bus),
+            0xD1 => pop_de(self, bus),
+            0xD2 => jp_nc_u16(self, bus),
+            0xD3 => self.illegal_opcode(opcode)?,
+            0xD4 => call_nc_u16(self, bus),
+            0xD5 => push_de(self, bus),
+            0xD6 => sub_a_u8(self, bus),
+            0xD7 => rst_10(self, bus),
+            0xD8 => ret_c(self, bus),
+            0xD9 => reti(self, bus),
+            0xDA => jp_c_u16(self, bus),
+            0xDB => self.illegal_opcode(opcode)?,
+            0xDC => call_c_u16(self, bus),
+            0xDD => self.illegal_opcode(opcode)?,
+            0xDE => sbc_a_u8(self, bus),
+            0xDF => rst_18(self, bus),
             
             // 0xEX
-            0xE0 => ldh_u8_a(self, mmu),
-            0xE1 => pop_hl(self, mmu),
-            0xE2 => ldh_c_a(self, mmu),
-            0xE3 => illegal_opcode(opcode),
-            0xE4 => illegal_opcode(opcode),
-            0xE5 => push_hl(self, mmu),
-            0xE6 => and_a_u8(self, mmu),
-            0xE7 => rst_20(self, mmu),
-            0xE8 => add_sp_i8(self, mmu),
+            0xE0 => ldh_u8_a(self, bus),
+            0xE1 => pop_hl(self, bus),
+            0xE2 => ldh_c_a(self, bus),
+            0xE3 => self.illegal_opcode(opcode)?,
+            0xE4 => self.illegal_opcode(opcode)?,
+            0xE5 => push_hl(self, bus),
+            0xE6 => and_a_u8(self, bus),
+            0xE7 => rst_20(self, bus),
+            0xE8 => add_sp_i8(self, bus),
             0xE9 => jp_hl(self),
-            0xEA => ld_u16_a(self, mmu),
-            0xEB => illegal_opcode(opcode),
-            0xEC => illegal_opcode(opcode),
-            0xED => illegal_opcode(opcode),
-            0xEE => xor_a_u8(self, mmu),
-            0xEF => rst_28(self, mmu),
+            0xEA => ld_u16_a(self, bus),
+            0xEB => self.illegal_opcode(opcode)?,
+            0xEC => self.illegal_opcode(opcode)?,
+            0xED => self.illegal_opcode(opcode)?,
+            0xEE => xor_a_u8(self, bus),
+            0xEF => rst_28(self, bus),
             
             // 0xFX
-            0xF0 => ldh_a_u8(self, mmu),
-            0xF1 => pop_af(self, mmu),
-            0xF2 => ldh_a_c(self, mmu),
+            0xF0 => ldh_a_u8(self, bus),
+            0xF1 => pop_af(self, bus),
+            0xF2 => ldh_a_c(self, bus),
             0xF3 => di(self),
-            0xF4 => illegal_opcode(opcode),
-            0xF5 => push_af(self, mmu),
-            0xF6 => or_a_u8(self, mmu),
-            0xF7 => rst_30(self, mmu),
-            0xF8 => ld_hl_sp_i8(self, mmu),
-            0xF9 => ld_sp_hl(self),
-            0xFA => ld_a_u16(self, mmu),
+            0xF4 => self.illegal_opcode(opcode)?,
+            0xF5 => push_af(self, bus),
+            0xF6 => or_a_u8(self, bus),
+            0xF7 => rst_30(self, bus),
+            0xF8 => ld_hl_sp_i8(self, bus),
+            0xF9 => ld_sp_hl(self, bus),
+            0xFA => ld_a_u16(self, bus),
             0xFB => ei(self),
-            0xFC => illegal_opcode(opcode),
-            0xFD => illegal_opcode(opcode),
-            0xFE => cp_a_u8(self, mmu),
-            0xFF => rst_38(self, mmu),
-        }
+            0xFC => self.illegal_opcode(opcode)?,
+            0xFD => self.illegal_opcode(opcode)?,
+            0xFE => cp_a_u8(self, bus),
+            0xFF => rst_38(self, bus),
+        };
+        
+        Ok(cycles)
     }
 }
 
@@ -363,3 +598,137 @@ impl Default for Cpu {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mmu::Mmu;
+
+    /// This builds a CPU at 0x0100 with `code` placed there in ROM
+    fn cpu_with_code(code: &[u8]) -> (Cpu, Mmu) {
+        let mut rom = vec![0; 0x8000];
+        rom[0x0100..0x0100 + code.len()].copy_from_slice(code);
+        (Cpu::new(), Mmu::new(rom))
+    }
+
+    #[test]
+    fn strict_mode_returns_an_error_for_an_illegal_opcode() {
+        let (mut cpu, mut mmu) = cpu_with_code(&[0xD3]);
+        cpu.strictness = StrictnessMode::Strict;
+        assert_eq!(cpu.tick(&mut mmu), Err(CpuError::IllegalOpcode { opcode: 0xD3, pc: 0x0100 }));
+    }
+
+    #[test]
+    fn lenient_mode_runs_an_illegal_opcode_as_a_nop() {
+        // Illegal 0xD3, then LD A,$42
+        let (mut cpu, mut mmu) = cpu_with_code(&[0xD3, 0x3E, 0x42]);
+        cpu.strictness = StrictnessMode::Lenient;
+        assert_eq!(cpu.tick(&mut mmu), Ok(1));
+        cpu.tick(&mut mmu).expect("LD A,n failed");
+        assert_eq!(cpu.registers.a, 0x42);
+        assert_eq!(cpu.registers.pc, 0x0103);
+    }
+
+    #[test]
+    fn every_illegal_opcode_stops_the_default_warn_mode() {
+        for opcode in [0xD3, 0xDB, 0xDD, 0xE3, 0xE4, 0xEB, 0xEC, 0xED, 0xF4, 0xFC, 0xFD] {
+            let (mut cpu, mut mmu) = cpu_with_code(&[opcode]);
+            assert_eq!(cpu.strictness, StrictnessMode::Warn);
+            let error = cpu.tick(&mut mmu).expect_err("illegal opcode ran");
+            assert_eq!(error, CpuError::IllegalOpcode { opcode, pc: 0x0100 });
+            assert_eq!(error.pc(), 0x0100);
+            assert_eq!(error.to_string(), format!("Illegal opcode 0x{:02X} at 0x0100", opcode));
+        }
+    }
+
+    #[test]
+    fn state_string_adds_ime_halt_and_stop() {
+        let mut cpu = Cpu::new();
+        cpu.registers.set_af(0x01B0);
+        cpu.ime = true;
+        cpu.halted = true;
+        assert!(cpu.state_string().starts_with("AF=01B0 "));
+        assert!(cpu.state_string().ends_with("[Z - H C] IME=1 HALT=1 STOP=0"));
+    }
+
+    #[test]
+    fn ei_enables_interrupts_after_the_next_instruction() {
+        // EI; NOP; EI; DI
+        let (mut cpu, mut mmu) = cpu_with_code(&[0xFB, 0x00, 0xFB, 0xF3]);
+        cpu.tick(&mut mmu).expect("EI failed");
+        assert!(!cpu.ime);
+        assert!(cpu.ime_pending);
+
+        cpu.tick(&mut mmu).expect("NOP failed");
+        assert!(cpu.ime);
+        assert!(!cpu.ime_pending);
+
+        // DI right after EI cancels the pending enable
+        cpu.ime = false;
+        cpu.tick(&mut mmu).expect("EI failed");
+        cpu.tick(&mut mmu).expect("DI failed");
+        assert!(!cpu.ime);
+        assert!(!cpu.ime_pending);
+        assert_eq!(cpu.registers.pc, 0x0104);
+    }
+
+    #[test]
+    fn reti_enables_interrupts_immediately() {
+        // RETI, returning to 0x0150
+        let (mut cpu, mut mmu) = cpu_with_code(&[0xD9]);
+        cpu.registers.sp = 0xFFFC;
+        mmu.write_byte(0xFFFC, 0x50);
+        mmu.write_byte(0xFFFD, 0x01);
+
+        cpu.tick(&mut mmu).expect("RETI failed");
+        assert!(cpu.ime);
+        assert!(!cpu.ime_pending);
+        assert_eq!(cpu.registers.pc, 0x0150);
+    }
+
+    #[test]
+    fn strict_mode_runs_ei() {
+        let (mut cpu, mut mmu) = cpu_with_code(&[0xFB]);
+        cpu.strictness = StrictnessMode::Strict;
+        assert_eq!(cpu.tick(&mut mmu), Ok(1));
+        assert!(cpu.ime_pending);
+    }
+
+    #[test]
+    fn fetch_regions_are_checked_by_address() {
+        for pc in [0x0100, 0x7FFF, 0xC000, 0xFE9F, 0xFF80, 0xFFFE] {
+            assert_eq!(fetch_region_warning(pc), None, "PC {:04X}", pc);
+        }
+        for pc in [0xFEA0, 0xFEFF] {
+            assert_eq!(fetch_region_warning(pc), Some("unusable memory"), "PC {:04X}", pc);
+        }
+        for pc in [0xFF00, 0xFF7F, 0xFFFF] {
+            assert_eq!(fetch_region_warning(pc), Some("I/O registers"), "PC {:04X}", pc);
+        }
+    }
+
+    #[test]
+    fn unusual_fetch_follows_the_strictness_mode() {
+        let (mut cpu, mut mmu) = cpu_with_code(&[]);
+        cpu.strictness = StrictnessMode::Strict;
+        cpu.registers.pc = 0xFEA0;
+        assert_eq!(cpu.tick(&mut mmu), Err(CpuError::UnusualFetch { region: "unusable memory", pc: 0xFEA0 }));
+        cpu.registers.pc = 0xFF00;
+        assert_eq!(cpu.tick(&mut mmu), Err(CpuError::UnusualFetch { region: "I/O registers", pc: 0xFF00 }));
+
+        // Code in HRAM is fine even in strict mode
+        mmu.write_byte(0xFF80, 0x00);
+        cpu.registers.pc = 0xFF80;
+        cpu.tick(&mut mmu).expect("NOP in HRAM failed");
+        assert_eq!(cpu.registers.pc, 0xFF81);
+
+        // Warn and lenient mode run the 0xFF the unusable area reads as (RST 38)
+        for strictness in [StrictnessMode::Warn, StrictnessMode::Lenient] {
+            let (mut cpu, mut mmu) = cpu_with_code(&[]);
+            cpu.strictness = strictness;
+            cpu.registers.pc = 0xFEA0;
+            cpu.tick(&mut mmu).expect("unusable fetch stopped the CPU");
+            assert_eq!(cpu.registers.pc, 0x0038);
+        }
+    }
+}
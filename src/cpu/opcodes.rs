@@ -1,10 +1,257 @@
 // REMINDER: Read AGENTS.md file before continuing development
 //
-// CPU Opcodes - Opcode mapping and dispatch
+// CPU Opcodes - Opcode mapping and disassembly
 //
-// This file will contain the opcode map that tells us which instruction
-// each opcode byte represents. The Game Boy has 256 base opcodes (0x00-0xFF)
-// plus 256 CB-prefixed opcodes (0xCB 0x00 through 0xCB 0xFF).
+// This file contains the opcode map that tells us which instruction each opcode
+// byte represents. The Game Boy has 256 base opcodes (0x00-0xFF) plus 256
+// CB-prefixed opcodes (0xCB 0x00 through 0xCB 0xFF).
+//
+// Opcodes are decoded from their bit fields the same way the CPU groups them:
+// x = bits 7-6, y = bits 5-3, z = bits 2-0, with y split into p = bits 5-4 and
+// q = bit 3. Mnemonics follow the usual assembler syntax with `$` hex operands.
+
+use crate::mmu::Mmu;
+
+/// 8-bit register operands, indexed by the y or z field
+const R: [&str; 8] = ["B", "C", "D", "E", "H", "L", "(HL)", "A"];
+/// 16-bit register pairs for loads and arithmetic, indexed by p
+const RP: [&str; 4] = ["BC", "DE", "HL", "SP"];
+/// 16-bit register pairs for PUSH and POP, indexed by p
+const RP2: [&str; 4] = ["BC", "DE", "HL", "AF"];
+/// Branch conditions, indexed by y (0-3)
+const CC: [&str; 4] = ["NZ", "Z", "NC", "C"];
+/// 8-bit ALU operations, indexed by y
+const ALU: [&str; 8] = ["ADD A,", "ADC A,", "SUB ", "SBC A,", "AND ", "XOR ", "OR ", "CP "];
+/// Accumulator/flag operations (opcodes 0x07-0x3F step 8), indexed by y
+const ACC: [&str; 8] = ["RLCA", "RRCA", "RLA", "RRA", "DAA", "CPL", "SCF", "CCF"];
+/// CB-prefixed rotates and shifts, indexed by y
+const ROT: [&str; 8] = ["RLC", "RRC", "RL", "RR", "SLA", "SRA", "SWAP", "SRL"];
+
+/// Size of a switchable ROM bank
+const ROM_BANK_SIZE: usize = 0x4000;
+
+/// One decoded instruction
+pub struct Instruction {
+    /// Mnemonic with operands, e.g. "LD A,($C000)"
+    pub text: String,
+    /// Length in bytes, including the opcode (and the CB prefix)
+    pub length: u16,
+}
+
+/// This decodes the instruction at the start of `bytes`, which was read from
+/// `address` (needed to resolve JR targets). Bytes past the end of the slice
+/// are treated as missing: the instruction is shown as a `DB` of what's left.
+pub fn decode(bytes: &[u8], address: u16) -> Instruction {
+    let opcode = match bytes.first() {
+        Some(&opcode) => opcode,
+        None => return Instruction { text: "DB".to_string(), length: 0 },
+    };
+    let length = instruction_length(bytes);
+    if bytes.len() < length as usize {
+        let listed: Vec<String> = bytes.iter().map(|b| format!("${:02X}", b)).collect();
+        return Instruction { text: format!("DB {}", listed.join(",")), length: bytes.len() as u16 };
+    }
+
+    let n = bytes.get(1).copied().unwrap_or(0);
+    let nn = u16::from_le_bytes([n, bytes.get(2).copied().unwrap_or(0)]);
+    let e = n as i8;
+
+    let x = opcode >> 6;
+    let y = ((opcode >> 3) & 0x07) as usize;
+    let z = opcode & 0x07;
+    let p = y >> 1;
+    let q = y & 1;
+
+    let text = match (x, z) {
+        (0, 0) => match y {
+            0 => "NOP".to_string(),
+            1 => format!("LD (${:04X}),SP", nn),
+            2 => "STOP".to_string(),
+            3 => format!("JR ${:04X}", jr_target(address, e)),
+            _ => format!("JR {},${:04X}", CC[y - 4], jr_target(address, e)),
+        },
+        (0, 1) if q == 0 => format!("LD {},${:04X}", RP[p], nn),
+        (0, 1) => format!("ADD HL,{}", RP[p]),
+        (0, 2) => {
+            let memory = ["(BC)", "(DE)", "(HL+)", "(HL-)"][p];
+            if q == 0 {
+                format!("LD {},A", memory)
+            } else {
+                format!("LD A,{}", memory)
+            }
+        }
+        (0, 3) => format!("{} {}", if q == 0 { "INC" } else { "DEC" }, RP[p]),
+        (0, 4) => format!("INC {}", R[y]),
+        (0, 5) => format!("DEC {}", R[y]),
+        (0, 6) => format!("LD {},${:02X}", R[y], n),
+        (0, 7) => ACC[y].to_string(),
+        (1, 6) if y == 6 => "HALT".to_string(),
+        (1, _) => format!("LD {},{}", R[y], R[z as usize]),
+        (2, _) => format!("{}{}", ALU[y], R[z as usize]),
+        (3, 0) => match y {
+            0..=3 => format!("RET {}", CC[y]),
+            4 => format!("LDH (${:02X}),A", n),
+            5 => format!("ADD SP,{}", e),
+            6 => format!("LDH A,(${:02X})", n),
+            _ => format!("LD HL,SP{:+}", e),
+        },
+        (3, 1) if q == 0 => format!("POP {}", RP2[p]),
+        (3, 1) => ["RET", "RETI", "JP HL", "LD SP,HL"][p].to_string(),
+        (3, 2) => match y {
+            0..=3 => format!("JP {},${:04X}", CC[y], nn),
+            4 => "LD (C),A".to_string(),
+            5 => format!("LD (${:04X}),A", nn),
+            6 => "LD A,(C)".to_string(),
+            _ => format!("LD A,(${:04X})", nn),
+        },
+        (3, 3) => match y {
+            0 => format!("JP ${:04X}", nn),
+            1 => decode_cb(n),
+            6 => "DI".to_string(),
+            7 => "EI".to_string(),
+            _ => illegal(opcode),
+        },
+        (3, 4) if y < 4 => format!("CALL {},${:04X}", CC[y], nn),
+        (3, 5) if q == 0 => format!("PUSH {}", RP2[p]),
+        (3, 5) if p == 0 => format!("CALL ${:04X}", nn),
+        (3, 6) => format!("{}${:02X}", ALU[y], n),
+        (3, 7) => format!("RST ${:02X}", y * 8),
+        _ => illegal(opcode),
+    };
+
+    Instruction { text, length }
+}
+
+/// This disassembles the instruction at `pc` as the CPU would fetch it right now
+/// (with the current ROM bank mapped) and returns its text and length in bytes.
+/// Memory is read with `peek_byte`, so it works during OAM DMA and isn't traced.
+pub fn disassemble(mmu: &Mmu, pc: u16) -> (String, u8) {
+    let bytes: Vec<u8> = (0..3).map(|i| mmu.peek_byte(pc.wrapping_add(i))).collect();
+    let instruction = decode(&bytes, pc);
+    (instruction.text, instruction.length as u8)
+}
+
+/// This returns the length in bytes of the instruction starting at `bytes[0]`
+fn instruction_length(bytes: &[u8]) -> u16 {
+    match bytes[0] {
+        // STOP is followed by a (normally 0x00) second byte, and CB by its opcode
+        0x10 | 0xCB => 2,
+        // 8-bit immediates: LD r,n / JR / LDH / ALU A,n / ADD SP,e / LD HL,SP+e
+        0x06 | 0x0E | 0x16 | 0x1E | 0x26 | 0x2E | 0x36 | 0x3E => 2,
+        0x18 | 0x20 | 0x28 | 0x30 | 0x38 => 2,
+        0xE0 | 0xF0 | 0xE8 | 0xF8 => 2,
+        0xC6 | 0xCE | 0xD6 | 0xDE | 0xE6 | 0xEE | 0xF6 | 0xFE => 2,
+        // 16-bit immediates: LD rr,nn / LD (nn),SP / JP / CALL / LD (nn),A / LD A,(nn)
+        0x01 | 0x11 | 0x21 | 0x31 | 0x08 => 3,
+        0xC2 | 0xC3 | 0xCA | 0xD2 | 0xDA => 3,
+        0xC4 | 0xCC | 0xCD | 0xD4 | 0xDC => 3,
+        0xEA | 0xFA => 3,
+        _ => 1,
+    }
+}
+
+/// This decodes the opcode byte that follows a 0xCB prefix
+fn decode_cb(opcode: u8) -> String {
+    let y = ((opcode >> 3) & 0x07) as usize;
+    let r = R[(opcode & 0x07) as usize];
+    match opcode >> 6 {
+        0 => format!("{} {}", ROT[y], r),
+        1 => format!("BIT {},{}", y, r),
+        2 => format!("RES {},{}", y, r),
+        _ => format!("SET {},{}", y, r),
+    }
+}
+
+/// This returns the destination of a JR at `address` with offset `e`
+fn jr_target(address: u16, e: i8) -> u16 {
+    address.wrapping_add(2).wrapping_add(e as u16)
+}
+
+/// This shows an opcode that doesn't exist on the DMG as a data byte
+fn illegal(opcode: u8) -> String {
+    format!("DB ${:02X}", opcode)
+}
+
+/// This disassembles the ROM from `start` to `end` (inclusive) as seen by the CPU
+/// with ROM bank `bank` mapped at 0x4000-0x7FFF. The range must stay inside one
+/// area (bank 0 at 0x0000-0x3FFF or the switchable bank at 0x4000-0x7FFF).
+/// Each line shows the bank and address, the raw bytes and the instruction,
+/// e.g. "00:0150  C3 50 01  JP $0150". The last instruction is listed in full even
+/// if it runs past `end`.
+pub fn disassemble_range(rom: &[u8], bank: usize, start: u16, end: u16) -> Result<Vec<String>, String> {
+    if start > end {
+        return Err(format!("Start address ${:04X} is after end address ${:04X}", start, end));
+    }
+    if end > 0x7FFF || (start < 0x4000) != (end < 0x4000) {
+        return Err("The range must lie within 0000-3FFF (bank 0) or 4000-7FFF (--bank)".to_string());
+    }
+
+    // Bank 0 is always at 0x0000; the requested bank only applies to 0x4000-0x7FFF
+    let (bank, area_start) = if start < 0x4000 { (0, 0x0000) } else { (bank, 0x4000) };
+    let bank_offset = bank * ROM_BANK_SIZE;
+    if bank_offset >= rom.len() {
+        return Err(format!("ROM has no bank {} ({} bytes)", bank, rom.len()));
+    }
+    let bank_end = (bank_offset + ROM_BANK_SIZE).min(rom.len());
+    let bank_bytes = &rom[bank_offset..bank_end];
+
+    let mut lines = Vec::new();
+    let mut address = start;
+    while address <= end {
+        let offset = (address - area_start) as usize;
+        if offset >= bank_bytes.len() {
+            break;
+        }
+
+        let instruction = decode(&bank_bytes[offset..], address);
+        let raw: Vec<String> = bank_bytes[offset..offset + instruction.length as usize]
+            .iter()
+            .map(|b| format!("{:02X}", b))
+            .collect();
+        lines.push(format!("{:02X}:{:04X}  {:<8}  {}", bank, address, raw.join(" "), instruction.text));
+
+        address = match address.checked_add(instruction.length) {
+            Some(next) => next,
+            None => break,
+        };
+    }
+
+    Ok(lines)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn disassemble_decodes_operands_and_lengths() {
+        let cases: [(&[u8], &str, u8); 10] = [
+            (&[0x00], "NOP", 1),
+            (&[0x21, 0x34, 0x12], "LD HL,$1234", 3),
+            (&[0x20, 0x05], "JR NZ,$C007", 2),
+            (&[0x18, 0xFE], "JR $C000", 2),
+            (&[0xE0, 0x40], "LDH ($40),A", 2),
+            (&[0xF8, 0xFE], "LD HL,SP-2", 2),
+            (&[0xFA, 0x00, 0xC0], "LD A,($C000)", 3),
+            (&[0xCB, 0x7C], "BIT 7,H", 2),
+            (&[0xCB, 0x36], "SWAP (HL)", 2),
+            (&[0xD3], "DB $D3", 1),
+        ];
+        for (bytes, text, length) in cases {
+            let mut mmu = Mmu::new(vec![0; 0x8000]);
+            for (i, &byte) in bytes.iter().enumerate() {
+                mmu.write_byte(0xC000 + i as u16, byte);
+            }
+            assert_eq!(disassemble(&mmu, 0xC000), (text.to_string(), length), "bytes {:02X?}", bytes);
+        }
+    }
 
-// TODO: Implement complete opcode map
-// TODO: Implement CB-prefixed opcode handling
+    #[test]
+    fn disassemble_range_lists_bank_address_and_bytes() {
+        let mut rom = vec![0; 0x8000];
+        rom[0x0150..0x0154].copy_from_slice(&[0xC3, 0x50, 0x01, 0x00]);
+        let lines = disassemble_range(&rom, 1, 0x0150, 0x0153).expect("range rejected");
+        assert_eq!(lines, ["00:0150  C3 50 01  JP $0150", "00:0153  00        NOP"]);
+        assert!(disassemble_range(&rom, 1, 0x3FFF, 0x4000).is_err());
+    }
+}
@@ -17,7 +17,8 @@ pub struct Registers {
     /// Bit 6: Subtraction flag (N) - set when last operation was subtraction
     /// Bit 5: Half-carry flag (H) - set when lower nibble overflowed
     /// Bit 4: Carry flag (C) - set when result overflowed/underflowed
-    /// Bits 0-3: Always zero
+    /// Bits 0-3: Always zero. Whole-register writes go through `set_f` (or
+    /// `set_af`), which keep them that way; the flag setters only touch bits 4-7.
     pub f: u8,
     
     /// General purpose registers
@@ -40,6 +41,8 @@ const FLAG_ZERO: u8 = 0b1000_0000;
 const FLAG_SUBTRACT: u8 = 0b0100_0000;
 const FLAG_HALF_CARRY: u8 = 0b0010_0000;
 const FLAG_CARRY: u8 = 0b0001_0000;
+/// Bits of F that exist in hardware; the low nibble always reads 0
+const FLAG_MASK: u8 = 0xF0;
 
 impl Registers {
     /// This creates new registers with the power-on state that the Game Boy
@@ -59,6 +62,23 @@ impl Registers {
         }
     }
     
+    /// This creates registers in the state the CPU powers on with: everything
+    /// zero, so execution starts at 0x0000 in the boot ROM (which sets SP itself)
+    pub fn power_on() -> Self {
+        Registers {
+            a: 0,
+            f: 0,
+            b: 0,
+            c: 0,
+            d: 0,
+            e: 0,
+            h: 0,
+            l: 0,
+            pc: 0x0000,
+            sp: 0x0000,
+        }
+    }
+    
     // These methods get/set 16-bit register pairs which we need often
     
     /// This gets the AF register pair (A in high byte, F in low byte)
@@ -69,7 +89,14 @@ impl Registers {
     /// This sets the AF register pair from a 16-bit value
     pub fn set_af(&mut self, value: u16) {
         self.a = (value >> 8) as u8;
-        self.f = (value & 0xF0) as u8;  // Lower 4 bits always zero
+        self.set_f(value as u8);
+    }
+    
+    /// This sets the whole F register. It's the one place F is written as a
+    /// byte (POP AF, save states, the debug console), so the low 4 bits, which
+    /// don't exist in hardware, are always dropped here.
+    pub fn set_f(&mut self, value: u8) {
+        self.f = value & FLAG_MASK;
     }
     
     /// This gets the BC register pair
@@ -164,8 +191,78 @@ impl Registers {
     }
 }
 
+impl Registers {
+    /// This returns the registers as "AF=xxxx BC=xxxx DE=xxxx HL=xxxx SP=xxxx PC=xxxx [Z N H C]"
+    /// where each flag shows its letter when set and '-' when clear
+    pub fn dump(&self) -> String {
+        self.to_string()
+    }
+    
+    /// This returns the registers in Gameboy Doctor's log format
+    /// ("A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0100")
+    pub fn doctor_string(&self) -> String {
+        format!(
+            "A:{:02X} F:{:02X} B:{:02X} C:{:02X} D:{:02X} E:{:02X} H:{:02X} L:{:02X} SP:{:04X} PC:{:04X}",
+            self.a, self.f, self.b, self.c, self.d, self.e, self.h, self.l, self.sp, self.pc
+        )
+    }
+}
+
+impl std::fmt::Display for Registers {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // We render each flag as its letter when set, '-' when clear
+        let flag = |set: bool, letter: char| if set { letter } else { '-' };
+        write!(
+            f,
+            "AF={:04X} BC={:04X} DE={:04X} HL={:04X} SP={:04X} PC={:04X} [{} {} {} {}]",
+            self.af(), self.bc(), self.de(), self.hl(), self.sp, self.pc,
+            flag(self.flag_z(), 'Z'),
+            flag(self.flag_n(), 'N'),
+            flag(self.flag_h(), 'H'),
+            flag(self.flag_c(), 'C')
+        )
+    }
+}
+
 impl Default for Registers {
     fn default() -> Self {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// This returns registers with every pair set to a distinct value
+    fn known_registers() -> Registers {
+        let mut registers = Registers::new();
+        registers.set_af(0x12A0);
+        registers.set_bc(0x3456);
+        registers.set_de(0x789A);
+        registers.set_hl(0xBCDE);
+        registers.sp = 0xDFF0;
+        registers.pc = 0x0150;
+        registers
+    }
+
+    #[test]
+    fn dump_shows_pairs_and_flag_letters() {
+        let mut registers = known_registers();
+        assert_eq!(
+            registers.dump(),
+            "AF=12A0 BC=3456 DE=789A HL=BCDE SP=DFF0 PC=0150 [Z - H -]"
+        );
+
+        registers.set_af(0x1250);
+        assert!(registers.dump().ends_with("[- N - C]"));
+    }
+
+    #[test]
+    fn doctor_string_matches_the_gameboy_doctor_format() {
+        assert_eq!(
+            known_registers().doctor_string(),
+            "A:12 F:A0 B:34 C:56 D:78 E:9A H:BC L:DE SP:DFF0 PC:0150"
+        );
+    }
+}
@@ -0,0 +1,394 @@
+// REMINDER: Read AGENTS.md file before continuing development
+//
+// Trace Comparison - Checking execution against a reference log
+//
+// This file implements `--compare-trace`: a reference trace from a known-good
+// emulator is read line by line while the CPU runs, and the state before each
+// instruction is compared with the next line. The run stops at the first
+// mismatch and reports the line, the expected and actual state, and which
+// fields differ. Two line formats are accepted (and can be mixed):
+//
+//   Gameboy Doctor  A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0100 PCMEM:00,C3,13,02
+//   JSON lines      {"a":1,"f":176,"b":0,"c":19,"d":0,"e":216,"h":1,"l":77,"sp":65534,"pc":256}
+//
+// PCMEM (or a JSON "pcmem" array) is optional; it's only compared when present.
+// JSON numbers may also be hex strings like "0x0100".
+
+use std::fmt;
+use std::io::BufRead;
+
+use crate::cpu::Cpu;
+use crate::mmu::Mmu;
+
+/// Register names in the order `TraceState::registers` stores them
+const REGISTER_NAMES: [&str; 8] = ["A", "F", "B", "C", "D", "E", "H", "L"];
+
+/// The CPU state before one instruction, as a trace line records it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TraceState {
+    /// A, F, B, C, D, E, H, L
+    pub registers: [u8; 8],
+    pub sp: u16,
+    pub pc: u16,
+    /// The 4 bytes at PC, if the trace records them
+    pub pcmem: Option<[u8; 4]>,
+}
+
+impl TraceState {
+    /// This captures the state the CPU is about to execute from, including PCMEM
+    pub fn capture(cpu: &Cpu, mmu: &Mmu) -> Self {
+        let r = &cpu.registers;
+        let pc = r.pc;
+        TraceState {
+            registers: [r.a, r.f, r.b, r.c, r.d, r.e, r.h, r.l],
+            sp: r.sp,
+            pc,
+            pcmem: Some([0, 1, 2, 3].map(|offset| mmu.peek_byte(pc.wrapping_add(offset)))),
+        }
+    }
+
+    /// This returns the names of the fields that differ from `expected`. PCMEM
+    /// only counts when both sides have it.
+    pub fn differences(&self, expected: &TraceState) -> Vec<&'static str> {
+        let mut fields: Vec<&'static str> = REGISTER_NAMES
+            .iter()
+            .zip(self.registers.iter().zip(expected.registers.iter()))
+            .filter(|(_, (actual, expected))| actual != expected)
+            .map(|(&name, _)| name)
+            .collect();
+        if self.sp != expected.sp {
+            fields.push("SP");
+        }
+        if self.pc != expected.pc {
+            fields.push("PC");
+        }
+        if let (Some(actual), Some(expected)) = (self.pcmem, expected.pcmem)
+            && actual != expected
+        {
+            fields.push("PCMEM");
+        }
+        fields
+    }
+}
+
+impl fmt::Display for TraceState {
+    /// This shows the state as a Gameboy Doctor line
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (name, value) in REGISTER_NAMES.iter().zip(self.registers.iter()) {
+            write!(f, "{}:{:02X} ", name, value)?;
+        }
+        write!(f, "SP:{:04X} PC:{:04X}", self.sp, self.pc)?;
+        if let Some(pcmem) = self.pcmem {
+            write!(f, " PCMEM:{:02X},{:02X},{:02X},{:02X}", pcmem[0], pcmem[1], pcmem[2], pcmem[3])?;
+        }
+        Ok(())
+    }
+}
+
+/// This parses a reference line in either format (JSON lines start with `{`)
+pub fn parse_line(line: &str) -> Result<TraceState, String> {
+    let line = line.trim();
+    if line.starts_with('{') {
+        parse_json_line(line)
+    } else {
+        parse_doctor_line(line)
+    }
+}
+
+/// This parses a Gameboy Doctor line: `NAME:hex` fields separated by spaces
+pub fn parse_doctor_line(line: &str) -> Result<TraceState, String> {
+    let mut fields = Fields::default();
+    for field in line.split_whitespace() {
+        let (name, value) = field
+            .split_once(':')
+            .ok_or_else(|| format!("Expected NAME:value, found {}", field))?;
+        if name.eq_ignore_ascii_case("PCMEM") {
+            fields.pcmem = Some(parse_pcmem(value.split(','), parse_hex)?);
+        } else {
+            fields.set(name, parse_hex(value)?)?;
+        }
+    }
+    fields.finish()
+}
+
+/// This parses a flat JSON object with lowercase register keys. Numbers are
+/// decimal or hex strings; "pcmem" is an array of 4 bytes.
+pub fn parse_json_line(line: &str) -> Result<TraceState, String> {
+    let body = line
+        .trim()
+        .strip_prefix('{')
+        .and_then(|rest| rest.strip_suffix('}'))
+        .ok_or("Expected a JSON object on one line")?;
+
+    let mut fields = Fields::default();
+    let mut rest = body.trim();
+    while !rest.is_empty() {
+        let (key, after_key) = rest
+            .split_once(':')
+            .ok_or_else(|| format!("Expected \"key\":value in {}", rest))?;
+        let key = key.trim().trim_matches('"');
+        let after_key = after_key.trim_start();
+
+        // Arrays contain commas, so they end at the closing bracket instead
+        let (value, remainder) = if after_key.starts_with('[') {
+            let end = after_key.find(']').ok_or("Unterminated array")?;
+            (&after_key[..=end], &after_key[end + 1..])
+        } else {
+            after_key.split_once(',').map_or((after_key, ""), |(value, rest)| (value, rest))
+        };
+        rest = remainder.trim_start().trim_start_matches(',').trim_start();
+
+        if key == "pcmem" {
+            let items = value.trim().trim_start_matches('[').trim_end_matches(']').split(',');
+            fields.pcmem = Some(parse_pcmem(items, parse_json_number)?);
+        } else {
+            fields.set(key, parse_json_number(value)?)?;
+        }
+    }
+    fields.finish()
+}
+
+/// This parses a hex number from a Doctor field
+fn parse_hex(value: &str) -> Result<u16, String> {
+    u16::from_str_radix(value.trim(), 16).map_err(|_| format!("Invalid hex value: {}", value))
+}
+
+/// This parses a JSON number, or a string holding a `0x` hex number
+fn parse_json_number(value: &str) -> Result<u16, String> {
+    let value = value.trim().trim_matches('"');
+    match value.strip_prefix("0x").or_else(|| value.strip_prefix("0X")) {
+        Some(hex) => u16::from_str_radix(hex, 16),
+        None => value.parse(),
+    }
+    .map_err(|_| format!("Invalid number: {}", value))
+}
+
+/// This parses the 4 PCMEM bytes
+fn parse_pcmem<'a>(
+    items: impl Iterator<Item = &'a str>,
+    parse: impl Fn(&str) -> Result<u16, String>,
+) -> Result<[u8; 4], String> {
+    let bytes = items
+        .map(|item| parse(item).and_then(|value| u8::try_from(value).map_err(|_| format!("PCMEM byte out of range: {}", item))))
+        .collect::<Result<Vec<u8>, String>>()?;
+    bytes
+        .try_into()
+        .map_err(|bytes: Vec<u8>| format!("PCMEM needs 4 bytes, found {}", bytes.len()))
+}
+
+/// Fields collected from one line, checked for completeness at the end
+#[derive(Default)]
+struct Fields {
+    registers: [Option<u8>; 8],
+    sp: Option<u16>,
+    pc: Option<u16>,
+    pcmem: Option<[u8; 4]>,
+}
+
+impl Fields {
+    /// This stores one register field; names are case-insensitive
+    fn set(&mut self, name: &str, value: u16) -> Result<(), String> {
+        let name = name.to_ascii_uppercase();
+        match name.as_str() {
+            "SP" => self.sp = Some(value),
+            "PC" => self.pc = Some(value),
+            _ => {
+                let index = REGISTER_NAMES
+                    .iter()
+                    .position(|&register| register == name)
+                    .ok_or_else(|| format!("Unknown field: {}", name))?;
+                let byte = u8::try_from(value).map_err(|_| format!("{} doesn't fit in a byte: {}", name, value))?;
+                self.registers[index] = Some(byte);
+            }
+        }
+        Ok(())
+    }
+
+    /// This builds the state, failing if a register is missing
+    fn finish(self) -> Result<TraceState, String> {
+        let mut registers = [0; 8];
+        for (index, value) in self.registers.iter().enumerate() {
+            registers[index] = value.ok_or_else(|| format!("Missing {}", REGISTER_NAMES[index]))?;
+        }
+        Ok(TraceState {
+            registers,
+            sp: self.sp.ok_or("Missing SP")?,
+            pc: self.pc.ok_or("Missing PC")?,
+            pcmem: self.pcmem,
+        })
+    }
+}
+
+/// The first point where execution stopped matching the reference
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+    /// Reference line number (1-based)
+    pub line: usize,
+    /// Instructions that matched before this one
+    pub instruction: usize,
+    pub expected: TraceState,
+    pub actual: TraceState,
+}
+
+/// How a comparison ended
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CompareEnd {
+    /// Every reference line matched
+    Completed { instructions: usize },
+    Diverged(Divergence),
+    /// A reference line couldn't be read or parsed
+    Invalid { line: usize, error: String },
+}
+
+impl CompareEnd {
+    /// This returns whether the run matched the whole reference
+    pub fn is_success(&self) -> bool {
+        matches!(self, CompareEnd::Completed { .. })
+    }
+}
+
+impl fmt::Display for CompareEnd {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CompareEnd::Completed { instructions } => {
+                writeln!(f, "Trace matches the reference ({} instructions)", instructions)
+            }
+            CompareEnd::Diverged(divergence) => {
+                writeln!(
+                    f,
+                    "Trace diverges at reference line {} (after {} matching instructions)",
+                    divergence.line, divergence.instruction
+                )?;
+                writeln!(f, "  expected: {}", divergence.expected)?;
+                writeln!(f, "  actual:   {}", divergence.actual)?;
+                writeln!(f, "  differs:  {}", divergence.actual.differences(&divergence.expected).join(", "))
+            }
+            CompareEnd::Invalid { line, error } => {
+                writeln!(f, "Reference trace line {} is invalid: {}", line, error)
+            }
+        }
+    }
+}
+
+/// This struct reads a reference trace and checks the CPU state against it one
+/// instruction at a time
+pub struct TraceComparer {
+    reader: Box<dyn BufRead>,
+    /// Lines read so far
+    line: usize,
+    /// Instructions that matched so far
+    matched: usize,
+}
+
+impl TraceComparer {
+    /// This creates a comparer reading the reference from `reader`
+    pub fn new(reader: Box<dyn BufRead>) -> Self {
+        TraceComparer {
+            reader,
+            line: 0,
+            matched: 0,
+        }
+    }
+
+    /// This compares the state before the next instruction with the next
+    /// reference line (blank lines are skipped). Returns None while everything
+    /// matches, or how the comparison ended.
+    pub fn check(&mut self, actual: &TraceState) -> Option<CompareEnd> {
+        let mut text = String::new();
+        loop {
+            text.clear();
+            self.line += 1;
+            match self.reader.read_line(&mut text) {
+                Ok(0) => return Some(CompareEnd::Completed { instructions: self.matched }),
+                Ok(_) if text.trim().is_empty() => continue,
+                Ok(_) => break,
+                Err(e) => return Some(CompareEnd::Invalid { line: self.line, error: e.to_string() }),
+            }
+        }
+
+        let expected = match parse_line(&text) {
+            Ok(expected) => expected,
+            Err(error) => return Some(CompareEnd::Invalid { line: self.line, error }),
+        };
+        if !actual.differences(&expected).is_empty() {
+            return Some(CompareEnd::Diverged(Divergence {
+                line: self.line,
+                instruction: self.matched,
+                expected,
+                actual: *actual,
+            }));
+        }
+        self.matched += 1;
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    /// This returns a Doctor line for PC with A set to `a`
+    fn doctor_line(a: u8, pc: u16) -> String {
+        format!(
+            "A:{:02X} F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:{:04X} PCMEM:00,C3,37,06",
+            a, pc
+        )
+    }
+
+    /// This builds a comparer over the given reference lines
+    fn comparer(lines: &[String]) -> TraceComparer {
+        TraceComparer::new(Box::new(Cursor::new(lines.join("\n"))))
+    }
+
+    #[test]
+    fn doctor_and_json_lines_parse_to_the_same_state() {
+        let doctor = parse_line(&doctor_line(0x01, 0x0100)).unwrap();
+        let json = parse_line(
+            r#"{"a": 1, "f": "0xB0", "b": 0, "c": 19, "d": 0, "e": 216, "h": 1, "l": 77, "sp": 65534, "pc": "0x0100", "pcmem": [0, 195, 55, 6]}"#,
+        )
+        .unwrap();
+        assert_eq!(doctor, json);
+        assert_eq!(doctor.pcmem, Some([0x00, 0xC3, 0x37, 0x06]));
+        assert!(parse_line("A:01 F:B0").is_err());
+    }
+
+    #[test]
+    fn comparer_reports_the_first_difference() {
+        let reference = [doctor_line(0x01, 0x0100), String::new(), doctor_line(0x01, 0x0101), doctor_line(0x02, 0x0102)];
+        let mut comparer = comparer(&reference);
+        let state = |pc| parse_line(&doctor_line(0x01, pc)).unwrap();
+
+        assert_eq!(comparer.check(&state(0x0100)), None);
+        // The blank line is skipped
+        assert_eq!(comparer.check(&state(0x0101)), None);
+        let end = comparer.check(&state(0x0102)).expect("divergence not found");
+        match &end {
+            CompareEnd::Diverged(divergence) => {
+                assert_eq!((divergence.line, divergence.instruction), (4, 2));
+                assert_eq!(divergence.actual.differences(&divergence.expected), ["A"]);
+            }
+            other => panic!("expected a divergence, got {:?}", other),
+        }
+        assert!(!end.is_success());
+    }
+
+    #[test]
+    fn comparer_completes_at_the_end_of_the_reference() {
+        let mut comparer = comparer(&[doctor_line(0x01, 0x0100), doctor_line(0x01, 0x0101)]);
+        let state = |pc| parse_line(&doctor_line(0x01, pc)).unwrap();
+        assert_eq!(comparer.check(&state(0x0100)), None);
+        assert_eq!(comparer.check(&state(0x0101)), None);
+
+        let end = comparer.check(&state(0x0102));
+        assert_eq!(end, Some(CompareEnd::Completed { instructions: 2 }));
+        assert!(end.unwrap().is_success());
+    }
+
+    #[test]
+    fn comparer_stops_on_an_invalid_line() {
+        let mut comparer = comparer(&["A:01 nonsense".to_string()]);
+        let state = parse_line(&doctor_line(0x01, 0x0100)).unwrap();
+        assert!(matches!(comparer.check(&state), Some(CompareEnd::Invalid { line: 1, .. })));
+    }
+}
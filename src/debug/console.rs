@@ -0,0 +1,354 @@
+// REMINDER: Read AGENTS.md file before continuing development
+//
+// Debug Console - Interactive commands on a paused machine
+//
+// This file implements a small command prompt on stdin that the frontend opens
+// with a hotkey. Emulation stops while it's open, and the commands inspect or
+// change the machine so interrupt handlers or specific states can be tried out:
+//
+//   set <reg>=<value>   set a register (A F B C D E H L AF BC DE HL SP PC)
+//   poke <addr> <value> write a byte through the CPU bus (Mmu::write_byte)
+//   peek <addr>         read a byte through the CPU bus
+//   state               print the machine state
+//   trace on|off        start/stop recording CPU bus accesses
+//   trace [n]           print the last n recorded accesses (default 16)
+//   freeze timer|ppu    stop the timer or the PPU while the CPU keeps running
+//   unfreeze timer|ppu  let it run again
+//   continue            close the console and resume (also `c` or end of input)
+//
+// Numbers are hex, with or without a `0x` or `$` prefix.
+
+use std::fmt;
+use std::io::{self, BufRead, Write};
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+
+use crate::gameboy::GameBoy;
+use crate::mmu::trace::DEFAULT_TRACE_CAPACITY;
+
+/// Accesses `trace` prints when no count is given
+const DEFAULT_TRACE_LINES: usize = 16;
+
+/// A CPU register (or register pair) that `set` can change
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Register {
+    A,
+    F,
+    B,
+    C,
+    D,
+    E,
+    H,
+    L,
+    AF,
+    BC,
+    DE,
+    HL,
+    SP,
+    PC,
+}
+
+impl Register {
+    /// This returns whether the register holds 16 bits
+    fn is_pair(self) -> bool {
+        matches!(
+            self,
+            Register::AF | Register::BC | Register::DE | Register::HL | Register::SP | Register::PC
+        )
+    }
+}
+
+impl FromStr for Register {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_uppercase().as_str() {
+            "A" => Ok(Register::A),
+            "F" => Ok(Register::F),
+            "B" => Ok(Register::B),
+            "C" => Ok(Register::C),
+            "D" => Ok(Register::D),
+            "E" => Ok(Register::E),
+            "H" => Ok(Register::H),
+            "L" => Ok(Register::L),
+            "AF" => Ok(Register::AF),
+            "BC" => Ok(Register::BC),
+            "DE" => Ok(Register::DE),
+            "HL" => Ok(Register::HL),
+            "SP" => Ok(Register::SP),
+            "PC" => Ok(Register::PC),
+            other => Err(format!("Unknown register: {}", other)),
+        }
+    }
+}
+
+impl fmt::Display for Register {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+/// A component `freeze` can stop on its own
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Component {
+    Timer,
+    Ppu,
+}
+
+impl FromStr for Component {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "timer" => Ok(Component::Timer),
+            "ppu" => Ok(Component::Ppu),
+            other => Err(format!("Unknown component: {} (timer or ppu)", other)),
+        }
+    }
+}
+
+impl fmt::Display for Component {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Component::Timer => write!(f, "Timer"),
+            Component::Ppu => write!(f, "PPU"),
+        }
+    }
+}
+
+/// One console command
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    Set(Register, u16),
+    Poke(u16, u8),
+    Peek(u16),
+    State,
+    TraceOn,
+    TraceOff,
+    ShowTrace(usize),
+    /// Freeze (true) or unfreeze (false) a component
+    Freeze(Component, bool),
+    Continue,
+}
+
+/// This parses a hex number, with or without a `0x` or `$` prefix
+pub fn parse_hex(value: &str) -> Result<u16, String> {
+    let value = value.trim();
+    let digits = value
+        .strip_prefix("0x")
+        .or_else(|| value.strip_prefix("0X"))
+        .or_else(|| value.strip_prefix("$"))
+        .unwrap_or(value);
+    u16::from_str_radix(digits, 16).map_err(|_| format!("Invalid hex number: {}", value))
+}
+
+/// This parses a hex number that has to fit in a byte
+fn parse_byte(value: &str) -> Result<u8, String> {
+    let number = parse_hex(value)?;
+    u8::try_from(number).map_err(|_| format!("Value {} doesn't fit in a byte", value.trim()))
+}
+
+/// This parses a comma-separated list of inclusive hex address ranges like
+/// "0150-0160,C000-C0FF". A single address is a range of one.
+pub fn parse_address_ranges(text: &str) -> Result<Vec<RangeInclusive<u16>>, String> {
+    text.split(',')
+        .map(|range| {
+            let (start, end) = range.split_once('-').unwrap_or((range, range));
+            let (start, end) = (parse_hex(start)?, parse_hex(end)?);
+            if start > end {
+                return Err(format!("Address range {} ends before it starts", range.trim()));
+            }
+            Ok(start..=end)
+        })
+        .collect()
+}
+
+/// This parses one console line, e.g. "set DE=0xBEEF" or "poke C000 42"
+pub fn parse_command(line: &str) -> Result<Command, String> {
+    let line = line.trim();
+    let (name, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+    let args: Vec<&str> = rest.split_whitespace().collect();
+
+    match name.to_ascii_lowercase().as_str() {
+        "set" => {
+            // The assignment may be written with or without spaces around '='
+            let assignment = args.concat();
+            let (register, value) = assignment
+                .split_once('=')
+                .ok_or("Usage: set <reg>=<value>")?;
+            let register: Register = register.parse()?;
+            let value = if register.is_pair() {
+                parse_hex(value)?
+            } else {
+                parse_byte(value)? as u16
+            };
+            Ok(Command::Set(register, value))
+        }
+        "poke" => match args[..] {
+            [address, value] => Ok(Command::Poke(parse_hex(address)?, parse_byte(value)?)),
+            _ => Err("Usage: poke <addr> <value>".to_string()),
+        },
+        "peek" => match args[..] {
+            [address] => Ok(Command::Peek(parse_hex(address)?)),
+            _ => Err("Usage: peek <addr>".to_string()),
+        },
+        "state" => Ok(Command::State),
+        "trace" => match args[..] {
+            ["on"] => Ok(Command::TraceOn),
+            ["off"] => Ok(Command::TraceOff),
+            [] => Ok(Command::ShowTrace(DEFAULT_TRACE_LINES)),
+            [count] => count
+                .parse()
+                .map(Command::ShowTrace)
+                .map_err(|_| format!("Invalid access count: {}", count)),
+            _ => Err("Usage: trace on|off|[n]".to_string()),
+        },
+        "freeze" | "unfreeze" => match args[..] {
+            [component] => Ok(Command::Freeze(component.parse()?, name.eq_ignore_ascii_case("freeze"))),
+            _ => Err(format!("Usage: {} timer|ppu", name.to_ascii_lowercase())),
+        },
+        "continue" | "c" => Ok(Command::Continue),
+        other => Err(format!(
+            "Unknown command: {} (set, poke, peek, state, trace, freeze, unfreeze, continue)",
+            other
+        )),
+    }
+}
+
+/// This runs a command against the machine and returns what to print
+pub fn execute(command: Command, gb: &mut GameBoy) -> String {
+    let registers = &mut gb.cpu.registers;
+    match command {
+        Command::Set(register, value) => {
+            match register {
+                Register::A => registers.a = value as u8,
+                // The low nibble of F doesn't exist on hardware and always reads 0
+                Register::F => registers.set_f(value as u8),
+                Register::B => registers.b = value as u8,
+                Register::C => registers.c = value as u8,
+                Register::D => registers.d = value as u8,
+                Register::E => registers.e = value as u8,
+                Register::H => registers.h = value as u8,
+                Register::L => registers.l = value as u8,
+                Register::AF => registers.set_af(value),
+                Register::BC => registers.set_bc(value),
+                Register::DE => registers.set_de(value),
+                Register::HL => registers.set_hl(value),
+                Register::SP => registers.sp = value,
+                Register::PC => registers.pc = value,
+            }
+            format!("{}\n", registers.dump())
+        }
+        Command::Poke(address, value) => {
+            gb.mmu.write_byte(address, value);
+            format!("[{:04X}] = {:02X}\n", address, gb.mmu.read_byte(address))
+        }
+        Command::Peek(address) => format!("[{:04X}] = {:02X}\n", address, gb.mmu.read_byte(address)),
+        Command::State => super::format_state(&gb.cpu, &gb.mmu, &gb.ppu, gb.cycles),
+        Command::TraceOn => {
+            gb.mmu.enable_access_trace(DEFAULT_TRACE_CAPACITY);
+            format!("Recording the last {} memory accesses\n", DEFAULT_TRACE_CAPACITY)
+        }
+        Command::TraceOff => {
+            gb.mmu.disable_access_trace();
+            "Memory access trace off\n".to_string()
+        }
+        Command::ShowTrace(count) => {
+            if !gb.mmu.access_trace_enabled() {
+                return "Memory access trace is off (trace on)\n".to_string();
+            }
+            let accesses = gb.mmu.recent_accesses();
+            let start = accesses.len().saturating_sub(count);
+            accesses[start..].iter().map(|access| format!("{}\n", access)).collect()
+        }
+        Command::Freeze(component, frozen) => {
+            match component {
+                Component::Timer => gb.timer.frozen = frozen,
+                Component::Ppu => gb.ppu.frozen = frozen,
+            }
+            format!("{} {}\n", component, if frozen { "frozen" } else { "running" })
+        }
+        Command::Continue => String::new(),
+    }
+}
+
+/// This reads and runs commands from stdin until `continue` or end of input.
+/// Emulation doesn't advance while the console is open.
+pub fn run(gb: &mut GameBoy) {
+    println!("Debug console (set <reg>=<value>, poke <addr> <value>, peek <addr>, state, trace on|off|[n], freeze|unfreeze timer|ppu, continue)");
+    let stdin = io::stdin();
+    let mut line = String::new();
+    loop {
+        print!("debug> ");
+        io::stdout().flush().ok();
+
+        line.clear();
+        match stdin.lock().read_line(&mut line) {
+            Ok(0) | Err(_) => break,
+            Ok(_) => {}
+        }
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        match parse_command(&line) {
+            Ok(Command::Continue) => break,
+            Ok(command) => print!("{}", execute(command, gb)),
+            Err(e) => println!("{}", e),
+        }
+    }
+    println!("Resuming");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn set_parses_registers_case_insensitively() {
+        assert_eq!(parse_command("set DE=0xBEEF"), Ok(Command::Set(Register::DE, 0xBEEF)));
+        assert_eq!(parse_command("SET hl = $1234"), Ok(Command::Set(Register::HL, 0x1234)));
+        assert_eq!(parse_command("set a=42"), Ok(Command::Set(Register::A, 0x42)));
+        // An 8-bit register can't take a 16-bit value
+        assert!(parse_command("set A=0x100").is_err());
+        assert!(parse_command("set XY=1").is_err());
+        assert!(parse_command("set DE").is_err());
+    }
+
+    #[test]
+    fn poke_parses_an_address_and_a_byte() {
+        assert_eq!(parse_command("poke C000 42"), Ok(Command::Poke(0xC000, 0x42)));
+        assert_eq!(parse_command("poke 0xFF80 $FF"), Ok(Command::Poke(0xFF80, 0xFF)));
+        assert!(parse_command("poke C000 100").is_err());
+        assert!(parse_command("poke C000").is_err());
+    }
+
+    #[test]
+    fn set_and_poke_change_the_machine() {
+        let mut gb = GameBoy::new(vec![0; 0x8000]);
+        execute(parse_command("set DE=0xBEEF").unwrap(), &mut gb);
+        assert_eq!(gb.cpu.registers.de(), 0xBEEF);
+        execute(parse_command("set F=0xFF").unwrap(), &mut gb);
+        assert_eq!(gb.cpu.registers.f, 0xF0);
+
+        let output = execute(parse_command("poke C000 42").unwrap(), &mut gb);
+        assert_eq!(gb.mmu.read_byte(0xC000), 0x42);
+        assert_eq!(output, "[C000] = 42\n");
+    }
+
+    #[test]
+    fn trace_parses_on_off_and_a_count() {
+        assert_eq!(parse_command("trace on"), Ok(Command::TraceOn));
+        assert_eq!(parse_command("trace off"), Ok(Command::TraceOff));
+        assert_eq!(parse_command("trace"), Ok(Command::ShowTrace(DEFAULT_TRACE_LINES)));
+        assert_eq!(parse_command("trace 4"), Ok(Command::ShowTrace(4)));
+        assert!(parse_command("trace some").is_err());
+    }
+
+    #[test]
+    fn address_ranges_parse_comma_separated_hex() {
+        assert_eq!(parse_address_ranges("0150-0160,C000"), Ok(vec![0x0150..=0x0160, 0xC000..=0xC000]));
+        assert!(parse_address_ranges("0160-0150").is_err());
+        assert!(parse_address_ranges("0150-zz").is_err());
+    }
+}
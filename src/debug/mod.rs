@@ -0,0 +1,261 @@
+// REMINDER: Read AGENTS.md file before continuing development
+//
+// Debug Module - Machine state inspection
+//
+// This module formats the emulator's state into human-readable text. It's used
+// for post-mortem dumps when the emulator exits (or crashes) so we can see exactly
+// where the CPU was and how the hardware was configured without a full save-state.
+
+pub mod compare;
+pub mod console;
+
+use crate::cpu::Cpu;
+use crate::mmu::{BankSwitch, Mmu, RomWrite, OAM_SPRITE_COUNT};
+use crate::ppu::Ppu;
+
+/// Key I/O registers included in a state dump, with their names
+const DUMP_IO_REGISTERS: [(&str, u16); 14] = [
+    ("P1", 0xFF00),
+    ("DIV", 0xFF04),
+    ("TIMA", 0xFF05),
+    ("TMA", 0xFF06),
+    ("TAC", 0xFF07),
+    ("IF", 0xFF0F),
+    ("LCDC", 0xFF40),
+    ("STAT", 0xFF41),
+    ("SCY", 0xFF42),
+    ("SCX", 0xFF43),
+    ("LY", 0xFF44),
+    ("LYC", 0xFF45),
+    ("BGP", 0xFF47),
+    ("IE", 0xFFFF),
+];
+
+/// This formats the whole machine state (registers, key I/O registers, banking,
+/// PPU mode/LY and the total M-cycle count) as a multi-line report.
+/// Memory is read without bus conflicts so an active DMA doesn't hide values.
+pub fn format_state(cpu: &Cpu, mmu: &Mmu, ppu: &Ppu, cycles: u64) -> String {
+    let mut out = String::new();
+
+    out.push_str("=== Machine State ===\n");
+    out.push_str(&format!("CPU: {}\n", cpu.state_string()));
+
+    // We show the bytes at PC so the next instruction can be identified
+    let pc = cpu.registers.pc;
+    out.push_str(&format!(
+        "PCMEM: {:02X},{:02X},{:02X},{:02X}\n",
+        mmu.peek_byte(pc),
+        mmu.peek_byte(pc.wrapping_add(1)),
+        mmu.peek_byte(pc.wrapping_add(2)),
+        mmu.peek_byte(pc.wrapping_add(3))
+    ));
+
+    let io: Vec<String> = DUMP_IO_REGISTERS
+        .iter()
+        .map(|(name, address)| format!("{}:{:02X}", name, mmu.peek_byte(*address)))
+        .collect();
+    out.push_str(&format!("I/O: {}\n", io.join(" ")));
+
+    out.push_str(&format!(
+        "Banking: ROM:{:02X} RAM:{:02X} RAM enabled:{} Mode:{}\n",
+        mmu.rom_bank(), mmu.ram_bank(), mmu.ram_enabled() as u8, mmu.banking_mode() as u8
+    ));
+    out.push_str(&format!("PPU: Mode:{} LY:{}\n", ppu.mode(), ppu.ly()));
+    out.push_str(&format!("Cycles: {} M-cycles\n", cycles));
+
+    out
+}
+
+/// Bytes shown on each side of a faulting address
+const FAULT_CONTEXT_BYTES: u16 = 8;
+
+/// This formats the bytes around `address` with the byte at `address` in
+/// brackets, e.g. "0x01F8: 00 3E 01 ... [D3] 00 ...", so a CPU error report
+/// shows what the CPU ran into and how it got there
+pub fn format_bytes_around(mmu: &Mmu, address: u16) -> String {
+    let start = address.wrapping_sub(FAULT_CONTEXT_BYTES);
+    let bytes: Vec<String> = (0..=FAULT_CONTEXT_BYTES * 2)
+        .map(|offset| {
+            let current = start.wrapping_add(offset);
+            let value = mmu.peek_byte(current);
+            if current == address {
+                format!("[{:02X}]", value)
+            } else {
+                format!("{:02X}", value)
+            }
+        })
+        .collect();
+    format!("0x{:04X}: {}", start, bytes.join(" "))
+}
+
+/// This formats one palette change for the `--log-palette` log, with the shade
+/// each color index maps to (color 0 first), e.g. "Frame 42: BGP = E4 (0 1 2 3)"
+pub fn format_palette_change(frame: u64, bgp: u8) -> String {
+    format!(
+        "Frame {}: BGP = {:02X} ({} {} {} {})",
+        frame,
+        bgp,
+        bgp & 0x03,
+        (bgp >> 2) & 0x03,
+        (bgp >> 4) & 0x03,
+        (bgp >> 6) & 0x03
+    )
+}
+
+/// This formats one bank switch for the `--rom-bank-log` timeline with the PC of
+/// the instruction that wrote the MBC register and the M-cycle count after it,
+/// e.g. "[   123456] PC=0x0B2C ROM bank 01 -> 05"
+pub fn format_bank_switch(cycles: u64, pc: u16, switch: &BankSwitch) -> String {
+    format!(
+        "[{:>9}] PC=0x{:04X} {} {:02X} -> {:02X}",
+        cycles, pc, switch.register, switch.from, switch.to
+    )
+}
+
+/// This formats a `--log-rom-writes` warning for a write to the ROM area that no
+/// banking register decodes, e.g.
+/// "Warning: [   123456] PC=0x0B2C wrote 0x05 to ROM at 0x6000, not a banking register"
+pub fn format_rom_write(cycles: u64, pc: u16, write: &RomWrite) -> String {
+    format!(
+        "Warning: [{:>9}] PC=0x{:04X} wrote 0x{:02X} to ROM at 0x{:04X}, not a banking register",
+        cycles, pc, write.value, write.address
+    )
+}
+
+/// This formats all 40 sprites in OAM, one line each with the raw bytes and the
+/// decoded flags (priority, flips, palette), e.g.
+/// "#00 Y=20 X=18 tile=42 flags=B0 [behind-BG X-flip OBP1]". Sprites parked off
+/// screen (y = 0 or y >= 160) are marked "hidden".
+pub fn format_oam(mmu: &Mmu) -> String {
+    let mut out = String::new();
+    for index in 0..OAM_SPRITE_COUNT {
+        let sprite = mmu.oam_entry(index);
+
+        let mut attributes = Vec::new();
+        if sprite.behind_background() {
+            attributes.push("behind-BG");
+        }
+        if sprite.y_flip() {
+            attributes.push("Y-flip");
+        }
+        if sprite.x_flip() {
+            attributes.push("X-flip");
+        }
+        attributes.push(if sprite.use_obp1() { "OBP1" } else { "OBP0" });
+
+        out.push_str(&format!(
+            "#{:02} Y={:02X} X={:02X} tile={:02X} flags={:02X} [{}]{}\n",
+            index,
+            sprite.y,
+            sprite.x,
+            sprite.tile,
+            sprite.flags,
+            attributes.join(" "),
+            if sprite.y == 0 || sprite.y >= 160 { " hidden" } else { "" }
+        ));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mmu::BankRegister;
+
+    #[test]
+    fn format_state_reports_registers_io_banking_and_ppu() {
+        let mut cpu = Cpu::new();
+        cpu.registers.a = 0x12;
+        cpu.registers.f = 0x90;
+        cpu.registers.sp = 0xDFF0;
+        cpu.registers.pc = 0xC000;
+        cpu.ime = true;
+
+        let mut mmu = Mmu::new(vec![0; 0x8000]);
+        for (offset, byte) in [0x3E, 0x42, 0x18, 0xFE].iter().enumerate() {
+            mmu.write_byte(0xC000 + offset as u16, *byte);
+        }
+        mmu.write_byte(0xFF06, 0xAB);
+        mmu.write_byte(0xFFFF, 0x05);
+
+        let dump = format_state(&cpu, &mmu, &Ppu::new(), 1234);
+        let lines: Vec<&str> = dump.lines().collect();
+        assert_eq!(lines[0], "=== Machine State ===");
+        assert!(lines[1].starts_with("CPU: AF=1290 "));
+        assert!(lines[1].ends_with("SP=DFF0 PC=C000 [Z - - C] IME=1 HALT=0 STOP=0"));
+        assert_eq!(lines[2], "PCMEM: 3E,42,18,FE");
+        assert!(lines[3].contains("TMA:AB"));
+        assert!(lines[3].ends_with("IE:05"));
+        assert_eq!(lines[4], "Banking: ROM:01 RAM:00 RAM enabled:0 Mode:0");
+        assert!(lines[5].starts_with("PPU: Mode:"));
+        assert_eq!(lines[6], "Cycles: 1234 M-cycles");
+    }
+
+    #[test]
+    fn palette_log_lists_each_change_with_its_frame() {
+        let mut mmu = Mmu::new(vec![0; 0x8000]);
+        mmu.log_palette = true;
+
+        let mut log = Vec::new();
+        let frames: [&[u8]; 3] = [&[0xE4, 0xE4, 0x1B], &[0x1B], &[0xFC]];
+        for (frame, writes) in frames.iter().enumerate() {
+            for &bgp in writes.iter() {
+                mmu.write_byte(0xFF47, bgp);
+            }
+            for bgp in mmu.take_bgp_changes() {
+                log.push(format_palette_change(frame as u64 + 1, bgp));
+            }
+        }
+
+        // Repeated values aren't changes, so frame 2 logs nothing
+        assert_eq!(log, [
+            "Frame 1: BGP = E4 (0 1 2 3)",
+            "Frame 1: BGP = 1B (3 2 1 0)",
+            "Frame 3: BGP = FC (0 3 3 3)",
+        ]);
+    }
+
+    #[test]
+    fn bank_switch_lines_show_cycles_pc_and_banks() {
+        let switch = BankSwitch { register: BankRegister::Rom, from: 1, to: 5 };
+        assert_eq!(format_bank_switch(123456, 0x0B2C, &switch), "[   123456] PC=0x0B2C ROM bank 01 -> 05");
+        let switch = BankSwitch { register: BankRegister::Mode, from: 0, to: 1 };
+        assert_eq!(format_bank_switch(7, 0x4000, &switch), "[        7] PC=0x4000 Banking mode 00 -> 01");
+    }
+
+    #[test]
+    fn bytes_around_a_fault_bracket_the_faulting_byte() {
+        let mut rom = vec![0; 0x8000];
+        rom[0x0200] = 0xD3;
+        rom[0x01F8] = 0x3E;
+        let mmu = Mmu::new(rom);
+        assert_eq!(
+            format_bytes_around(&mmu, 0x0200),
+            "0x01F8: 3E 00 00 00 00 00 00 00 [D3] 00 00 00 00 00 00 00 00"
+        );
+    }
+
+    #[test]
+    fn format_oam_decodes_flags() {
+        let mut mmu = Mmu::new(vec![0; 0x8000]);
+        for (address, value) in [(0xFE00, 0x20), (0xFE01, 0x18), (0xFE02, 0x42), (0xFE03, 0xB0), (0xFE07, 0x40)] {
+            mmu.write_byte(address, value);
+        }
+
+        let oam = format_oam(&mmu);
+        let lines: Vec<&str> = oam.lines().collect();
+        assert_eq!(lines.len(), 40);
+        assert_eq!(lines[0], "#00 Y=20 X=18 tile=42 flags=B0 [behind-BG X-flip OBP1]");
+        assert_eq!(lines[1], "#01 Y=00 X=00 tile=00 flags=40 [Y-flip OBP0] hidden");
+        assert_eq!(lines[39], "#39 Y=00 X=00 tile=00 flags=00 [OBP0] hidden");
+    }
+
+    #[test]
+    fn format_rom_write_names_the_pc_and_address() {
+        let write = RomWrite { address: 0x6000, value: 0x05 };
+        assert_eq!(
+            format_rom_write(123456, 0x0B2C, &write),
+            "Warning: [   123456] PC=0x0B2C wrote 0x05 to ROM at 0x6000, not a banking register"
+        );
+    }
+}
@@ -0,0 +1,147 @@
+// REMINDER: Read AGENTS.md file before continuing development
+//
+// Background Debug Window
+//
+// This file draws the whole 256x256 background map (`Ppu::render_full_background`)
+// in a second SDL window, with the part the screen shows (the 160x144 viewport at
+// SCX/SCY) outlined. The viewport wraps around the map edges like the real
+// scrolling does, so the outline can be split across the sides. Scroll registers
+// are read once per frame, so mid-frame scroll changes (raster effects) aren't shown.
+
+use sdl2::pixels::PixelFormatEnum;
+use sdl2::render::{Canvas, Texture, TextureCreator};
+use sdl2::video::{Window, WindowContext};
+use sdl2::Sdl;
+
+/// Width and height of the background map in pixels (32 tiles of 8 pixels)
+pub const BACKGROUND_SIZE: usize = 256;
+/// Scale the background window opens at (512x512)
+const BACKGROUND_SCALE: u32 = 2;
+/// Color of the viewport outline
+const VIEWPORT_COLOR: u32 = 0xFF0000;
+
+/// This expands a 256x256 background of shades (0-3) into colors and outlines the
+/// 160x144 viewport whose top-left corner is at (`scx`, `scy`), wrapping at the edges
+pub fn background_view(background: &[u8], colors: &[u32; 4], scx: u8, scy: u8) -> Vec<u32> {
+    let mut view: Vec<u32> = background
+        .iter()
+        .map(|&shade| colors[(shade & 0x03) as usize])
+        .collect();
+
+    let (left, top) = (scx as usize, scy as usize);
+    let (right, bottom) = (left + super::SCREEN_WIDTH as usize - 1, top + super::SCREEN_HEIGHT as usize - 1);
+    for x in left..=right {
+        let x = x % BACKGROUND_SIZE;
+        view[top * BACKGROUND_SIZE + x] = VIEWPORT_COLOR;
+        view[(bottom % BACKGROUND_SIZE) * BACKGROUND_SIZE + x] = VIEWPORT_COLOR;
+    }
+    for y in top..=bottom {
+        let y = y % BACKGROUND_SIZE;
+        view[y * BACKGROUND_SIZE + left] = VIEWPORT_COLOR;
+        view[y * BACKGROUND_SIZE + right % BACKGROUND_SIZE] = VIEWPORT_COLOR;
+    }
+    view
+}
+
+/// This struct is the second window that shows the background map
+pub struct BackgroundWindow<'a> {
+    // The texture is declared first so it's destroyed before its renderer
+    texture: Texture<'a>,
+    texture_creator: TextureCreator<WindowContext>,
+    canvas: Canvas<Window>,
+}
+
+impl<'a> BackgroundWindow<'a> {
+    /// This opens the background window next to the main one
+    pub fn new(sdl_context: &Sdl) -> Result<Self, String> {
+        let video_subsystem = sdl_context.video()?;
+        let size = BACKGROUND_SIZE as u32 * BACKGROUND_SCALE;
+        let window = video_subsystem
+            .window("Rustiboa-SNT - Background", size, size)
+            .build()
+            .map_err(|e| e.to_string())?;
+        let canvas = window.into_canvas().accelerated().build().map_err(|e| e.to_string())?;
+
+        let texture_creator: TextureCreator<WindowContext> = canvas.texture_creator();
+        let texture = unsafe {
+            // SAFETY: We're storing the texture_creator in the struct so the texture lifetime is valid
+            std::mem::transmute::<Texture<'_>, Texture<'_>>(
+                texture_creator
+                    .create_texture_streaming(PixelFormatEnum::RGB24, BACKGROUND_SIZE as u32, BACKGROUND_SIZE as u32)
+                    .map_err(|e| e.to_string())?,
+            )
+        };
+
+        Ok(BackgroundWindow {
+            texture,
+            texture_creator,
+            canvas,
+        })
+    }
+
+    /// This returns the SDL id of the window, to tell its events apart
+    pub fn id(&self) -> u32 {
+        self.canvas.window().id()
+    }
+
+    /// This draws the background (from `Ppu::render_full_background`) with the
+    /// viewport outline at (`scx`, `scy`) in the given palette colors
+    pub fn render(&mut self, background: &[u8], colors: &[u32; 4], scx: u8, scy: u8) -> Result<(), String> {
+        let view = background_view(background, colors, scx, scy);
+        self.texture.with_lock(None, |buffer: &mut [u8], pitch: usize| {
+            for (y, row) in view.chunks(BACKGROUND_SIZE).enumerate() {
+                for (x, &color) in row.iter().enumerate() {
+                    let offset = y * pitch + x * 3;
+                    buffer[offset] = (color >> 16) as u8;
+                    buffer[offset + 1] = (color >> 8) as u8;
+                    buffer[offset + 2] = color as u8;
+                }
+            }
+        })?;
+
+        self.canvas.clear();
+        self.canvas.copy(&self.texture, None, None)?;
+        self.canvas.present();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COLORS: [u32; 4] = [0xFFFFFF, 0xAAAAAA, 0x555555, 0x000000];
+
+    /// This returns the view pixel at (x, y)
+    fn at(view: &[u32], x: usize, y: usize) -> u32 {
+        view[y * BACKGROUND_SIZE + x]
+    }
+
+    #[test]
+    fn background_view_outlines_the_viewport() {
+        let background = vec![3; BACKGROUND_SIZE * BACKGROUND_SIZE];
+        let view = background_view(&background, &COLORS, 10, 20);
+        // Corners of the 160x144 viewport at (10, 20)
+        for (x, y) in [(10, 20), (169, 20), (10, 163), (169, 163)] {
+            assert_eq!(at(&view, x, y), VIEWPORT_COLOR, "({}, {})", x, y);
+        }
+        // Inside and outside the outline the map keeps its palette color
+        assert_eq!(at(&view, 11, 21), COLORS[3]);
+        assert_eq!(at(&view, 170, 20), COLORS[3]);
+        assert_eq!(at(&view, 9, 20), COLORS[3]);
+        assert_eq!(view.iter().filter(|&&color| color == VIEWPORT_COLOR).count(), 2 * 160 + 2 * 142);
+    }
+
+    #[test]
+    fn background_view_wraps_the_viewport_at_the_map_edges() {
+        let background = vec![0; BACKGROUND_SIZE * BACKGROUND_SIZE];
+        let view = background_view(&background, &COLORS, 200, 250);
+        // The left and top edges stay put, the right and bottom ones wrap around
+        assert_eq!(at(&view, 200, 250), VIEWPORT_COLOR);
+        assert_eq!(at(&view, (200 + 159) % 256, 250), VIEWPORT_COLOR);
+        assert_eq!(at(&view, 200, (250 + 143) % 256), VIEWPORT_COLOR);
+        assert_eq!(at(&view, 255, 250), VIEWPORT_COLOR);
+        assert_eq!(at(&view, 0, 250), VIEWPORT_COLOR);
+        assert_eq!(at(&view, 150, 100), COLORS[0]);
+    }
+}
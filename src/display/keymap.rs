@@ -0,0 +1,180 @@
+// REMINDER: Read AGENTS.md file before continuing development
+//
+// Key Bindings
+//
+// This file maps keyboard keys to Game Boy buttons. The default map is the one
+// the emulator always had (arrow keys, Z/X, Enter/Right Shift); a map can be
+// changed at runtime with `bind` or loaded from a small config file with one
+// `button = key` line per button, e.g.
+//
+//   # WASD layout
+//   up = W
+//   left = A
+//   down = S
+//   right = D
+//   a = Space
+//   b = LShift, J
+//
+// Key names are SDL key names. A button listed in the file loses its default keys;
+// buttons that aren't listed keep them. The core only knows buttons (see
+// `rustiboa_snt::input::Button`); `Keyboard` turns held keys into held buttons.
+
+use sdl2::keyboard::Keycode;
+use std::collections::HashSet;
+
+use rustiboa_snt::input::{Button, Input};
+
+/// This struct maps keyboard keys to the buttons they press. A key presses at
+/// most one button; a button can have several keys.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyMap {
+    bindings: Vec<(Keycode, Button)>,
+}
+
+impl Default for KeyMap {
+    /// Arrow keys = D-pad, Z/X = A/B, Enter/Right Shift = Start/Select
+    fn default() -> Self {
+        KeyMap {
+            bindings: vec![
+                (Keycode::Right, Button::Right),
+                (Keycode::Left, Button::Left),
+                (Keycode::Up, Button::Up),
+                (Keycode::Down, Button::Down),
+                (Keycode::Z, Button::A),
+                (Keycode::X, Button::B),
+                (Keycode::Return, Button::Start),
+                (Keycode::RShift, Button::Select),
+            ],
+        }
+    }
+}
+
+impl KeyMap {
+    /// This returns the button a key presses, if it's bound
+    pub fn button(&self, key: Keycode) -> Option<Button> {
+        self.bindings
+            .iter()
+            .find(|&&(bound, _)| bound == key)
+            .map(|&(_, button)| button)
+    }
+
+    /// This makes `key` press `button`, replacing whatever the key pressed before.
+    /// The button's other keys still work.
+    pub fn bind(&mut self, key: Keycode, button: Button) {
+        self.bindings.retain(|&(bound, _)| bound != key);
+        self.bindings.push((key, button));
+    }
+
+    /// This removes every key bound to `button`
+    pub fn unbind(&mut self, button: Button) {
+        self.bindings.retain(|&(_, bound)| bound != button);
+    }
+
+    /// This parses a config file's text (see the top of this file), starting
+    /// from the default map. Errors name the offending line.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut map = KeyMap::default();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (button, keys) = line
+                .split_once('=')
+                .ok_or_else(|| format!("Key map line {}: expected <button> = <key>", number + 1))?;
+            let button: Button = button
+                .parse()
+                .map_err(|e| format!("Key map line {}: {}", number + 1, e))?;
+            map.unbind(button);
+            for name in keys.split(',') {
+                let key = Keycode::from_name(name.trim())
+                    .ok_or_else(|| format!("Key map line {}: unknown key {}", number + 1, name.trim()))?;
+                map.bind(key, button);
+            }
+        }
+        Ok(map)
+    }
+
+    /// This reads and parses a key map config file
+    pub fn load(path: &str) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read key map {}: {}", path, e))?;
+        KeyMap::parse(&text)
+    }
+}
+
+/// This struct tracks the keys held down and reports the buttons they press to
+/// the core's `Input`
+pub struct Keyboard {
+    /// Keys currently pressed
+    keys_pressed: HashSet<Keycode>,
+    /// Which key presses which button
+    keymap: KeyMap,
+}
+
+impl Keyboard {
+    /// This creates a keyboard with no keys pressed and the given key map
+    pub fn new(keymap: KeyMap) -> Self {
+        Keyboard {
+            keys_pressed: HashSet::new(),
+            keymap,
+        }
+    }
+
+    /// This handles an SDL2 key press event
+    pub fn key_down(&mut self, key: Keycode, input: &mut Input) {
+        self.keys_pressed.insert(key);
+        self.commit(input);
+    }
+
+    /// This handles an SDL2 key release event
+    pub fn key_up(&mut self, key: Keycode, input: &mut Input) {
+        self.keys_pressed.remove(&key);
+        self.commit(input);
+    }
+
+    /// This hands the buttons of every held key to the input state. A button
+    /// with two keys stays pressed until both are released.
+    fn commit(&self, input: &mut Input) {
+        input.set_pressed(self.keys_pressed.iter().filter_map(|&key| self.keymap.button(key)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustiboa_snt::input::BUTTON_A;
+
+    #[test]
+    fn space_presses_a_once_rebound() {
+        let mut keymap = KeyMap::default();
+        keymap.unbind(Button::A);
+        keymap.bind(Keycode::Space, Button::A);
+        let mut keyboard = Keyboard::new(keymap);
+        let mut input = Input::new();
+
+        keyboard.key_down(Keycode::Space, &mut input);
+        assert_eq!(input.read_joypad() & BUTTON_A, 0);
+        keyboard.key_up(Keycode::Space, &mut input);
+        assert_eq!(input.read_joypad() & BUTTON_A, BUTTON_A);
+
+        // Z no longer presses A
+        keyboard.key_down(Keycode::Z, &mut input);
+        assert_eq!(input.read_joypad() & BUTTON_A, BUTTON_A);
+    }
+
+    #[test]
+    fn button_with_two_keys_stays_pressed_until_both_are_released() {
+        let mut keymap = KeyMap::default();
+        keymap.bind(Keycode::Space, Button::A);
+        let mut keyboard = Keyboard::new(keymap);
+        let mut input = Input::new();
+
+        keyboard.key_down(Keycode::Z, &mut input);
+        keyboard.key_down(Keycode::Space, &mut input);
+        keyboard.key_up(Keycode::Z, &mut input);
+        assert_eq!(input.read_joypad() & BUTTON_A, 0);
+        keyboard.key_up(Keycode::Space, &mut input);
+        assert_eq!(input.read_joypad() & BUTTON_A, BUTTON_A);
+    }
+}
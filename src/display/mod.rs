@@ -4,48 +4,220 @@
 //
 // This module handles creating an SDL2 window and rendering the Game Boy's
 // framebuffer to it. The Game Boy screen is 160x144 pixels with 4 shades of gray.
+// keymap.rs turns SDL key events into Game Boy button presses, screenshots are
+// written as PNG files through the small encoder in png.rs, and background.rs
+// holds the optional second window showing the background map.
+
+mod background;
+mod keymap;
+mod png;
+
+pub use background::BackgroundWindow;
+pub use keymap::{KeyMap, Keyboard};
 
 use sdl2::pixels::PixelFormatEnum;
 use sdl2::rect::Rect;
 use sdl2::render::{Canvas, Texture, TextureCreator};
-use sdl2::video::{Window, WindowContext};
+use sdl2::video::{FullscreenType, Window, WindowContext};
 use sdl2::Sdl;
 
+use rustiboa_snt::ppu::{LineTiming, DOTS_PER_LINE};
+
 const SCREEN_WIDTH: u32 = 160;
 const SCREEN_HEIGHT: u32 = 144;
-const SCALE_FACTOR: u32 = 4; // Scale up for visibility
-
-/// Game Boy color palette (4 shades of gray/green)
-const PALETTE: [u32; 4] = [
-    0xE0F8D0, // Lightest (white/off-white)
-    0x88C070, // Light gray/green
-    0x346856, // Dark gray/green
-    0x081820, // Darkest (black/dark blue)
+/// Default window scale when `--scale` isn't given
+pub const DEFAULT_SCALE: u32 = 4;
+/// Scale value that asks for the largest integer scale fitting the screen
+pub const AUTO_SCALE: u32 = 0;
+/// Pixels left free around the window (taskbars, title bars) when auto-scaling
+const AUTO_SCALE_MARGIN: u32 = 96;
+
+/// Width in pixels of the PPU timing strip drawn at the right edge of each line
+const TIMING_STRIP_WIDTH: usize = 38;
+/// Dots of a scanline each pixel of the timing strip stands for (456 / 38)
+const DOTS_PER_STRIP_PIXEL: u16 = DOTS_PER_LINE / TIMING_STRIP_WIDTH as u16;
+/// Timing strip colors for mode 2 (OAM search), mode 3 (pixel transfer) and mode 0 (HBlank)
+const TIMING_COLORS: [u32; 3] = [0xE04040, 0x4060E0, 0x40C040];
+
+/// This returns the colors of one line's timing strip: each pixel covers
+/// `DOTS_PER_STRIP_PIXEL` dots and shows the mode the line was in at its first
+/// dot, so the green part on the right is the HBlank budget left on that line
+pub fn timing_strip(timing: &LineTiming) -> [u32; TIMING_STRIP_WIDTH] {
+    let transfer_end = timing.oam_search + timing.pixel_transfer;
+    std::array::from_fn(|i| {
+        let dot = i as u16 * DOTS_PER_STRIP_PIXEL;
+        if dot < timing.oam_search {
+            TIMING_COLORS[0]
+        } else if dot < transfer_end {
+            TIMING_COLORS[1]
+        } else {
+            TIMING_COLORS[2]
+        }
+    })
+}
+
+/// A set of 4 RGB colors for the shades 0 (lightest) to 3 (darkest)
+pub struct Palette {
+    pub name: &'static str,
+    pub colors: [u32; 4],
+}
+
+/// Built-in palettes, cycled at runtime with the backtick key. The first is the default.
+pub const PALETTES: [Palette; 3] = [
+    Palette {
+        name: "dmg-green",
+        colors: [
+            0xE0F8D0, // Lightest (white/off-white)
+            0x88C070, // Light gray/green
+            0x346856, // Dark gray/green
+            0x081820, // Darkest (black/dark blue)
+        ],
+    },
+    Palette {
+        name: "grayscale",
+        colors: [0xFFFFFF, 0xAAAAAA, 0x555555, 0x000000],
+    },
+    Palette {
+        name: "pocket",
+        colors: [0xC4CFA1, 0x8B956D, 0x4D533C, 0x1F1F1F],
+    },
 ];
 
+/// This expands a framebuffer of shades (0-3) into RGB bytes through a palette's colors
+pub fn frame_rgb(framebuffer: &[u8; 160 * 144], colors: &[u32; 4]) -> Vec<u8> {
+    framebuffer
+        .iter()
+        .flat_map(|&shade| {
+            let color = colors[(shade & 0x03) as usize];
+            [(color >> 16) as u8, (color >> 8) as u8, color as u8]
+        })
+        .collect()
+}
+
+/// This encodes a framebuffer as a 160x144 PNG image in a palette's colors
+pub fn screenshot_png(framebuffer: &[u8; 160 * 144], colors: &[u32; 4]) -> Vec<u8> {
+    png::encode_rgb(SCREEN_WIDTH, SCREEN_HEIGHT, &frame_rgb(framebuffer, colors))
+}
+
+/// This returns a screenshot path next to the ROM that includes the current time
+/// in milliseconds, e.g. game.gb -> game-1760612345123.png, so screenshots never
+/// overwrite each other
+pub fn screenshot_path(rom_path: &str) -> std::path::PathBuf {
+    let rom = std::path::Path::new(rom_path);
+    let stem = rom.file_stem().and_then(|stem| stem.to_str()).unwrap_or("screenshot");
+    let millis = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|time| time.as_millis())
+        .unwrap_or(0);
+    rom.with_file_name(format!("{}-{}.png", stem, millis))
+}
+
+/// This returns the position in `PALETTES` of the palette with the given name
+/// (case-insensitive)
+pub fn palette_index(name: &str) -> Result<usize, String> {
+    PALETTES
+        .iter()
+        .position(|palette| palette.name.eq_ignore_ascii_case(name))
+        .ok_or_else(|| {
+            let names: Vec<&str> = PALETTES.iter().map(|palette| palette.name).collect();
+            format!("Unknown palette: {} (expected {})", name, names.join(", "))
+        })
+}
+
 /// This struct manages the SDL2 display system including the window,
 /// canvas for drawing, and texture that holds the Game Boy's framebuffer
 pub struct Display<'a> {
     canvas: Canvas<Window>,
     texture_creator: TextureCreator<WindowContext>,
     texture: Texture<'a>,
+    /// Integer window scale in use (auto mode is resolved before this is stored)
+    scale: u32,
+    /// Whether the frame is drawn 1:1 and scaling is left to SDL/the compositor
+    native_res: bool,
+    /// Index of the active palette in `PALETTES`
+    palette: usize,
+    /// Whether the PPU timing strip is drawn over the right edge of the frame
+    timing_overlay: bool,
+    /// Whether the window covers the desktop (F11); the frame is then letterboxed
+    fullscreen: bool,
+}
+
+/// This parses a `--scale` value: a whole number, where 0 or "auto" asks for
+/// auto-fit to the display
+pub fn parse_scale(value: &str) -> Result<u32, String> {
+    match value {
+        "auto" => Ok(AUTO_SCALE),
+        _ => value.parse().map_err(|_| format!("Invalid scale: {}", value)),
+    }
+}
+
+/// This picks the largest integer scale at which the 160x144 screen still fits
+/// inside a display of the given size minus `AUTO_SCALE_MARGIN`. Tiny displays
+/// still get a scale of 1.
+pub fn auto_scale(display_width: u32, display_height: u32) -> u32 {
+    let usable_width = display_width.saturating_sub(AUTO_SCALE_MARGIN);
+    let usable_height = display_height.saturating_sub(AUTO_SCALE_MARGIN);
+    (usable_width / SCREEN_WIDTH)
+        .min(usable_height / SCREEN_HEIGHT)
+        .max(1)
+}
+
+/// This returns the rectangle the 160x144 frame is drawn into: scaled up by
+/// `scale`, or exactly 160x144 with `native_res` whatever the scale is
+pub fn frame_rect(scale: u32, native_res: bool) -> Rect {
+    let scale = if native_res { 1 } else { scale };
+    Rect::new(0, 0, SCREEN_WIDTH * scale, SCREEN_HEIGHT * scale)
+}
+
+/// This returns the rectangle the frame is drawn into on an output of the given
+/// size in fullscreen: the largest integer scale that fits (at least 1), centered,
+/// with the rest of the output left as black bars
+pub fn letterbox_rect(output_width: u32, output_height: u32) -> Rect {
+    let scale = (output_width / SCREEN_WIDTH).min(output_height / SCREEN_HEIGHT).max(1);
+    let (width, height) = (SCREEN_WIDTH * scale, SCREEN_HEIGHT * scale);
+    let x = (output_width as i32 - width as i32) / 2;
+    let y = (output_height as i32 - height as i32) / 2;
+    Rect::new(x, y, width, height)
+}
+
+/// This returns the palette index after `index` (or before it when `forward` is
+/// false), wrapping around at both ends of a list of `count` palettes
+pub fn cycle_palette_index(index: usize, count: usize, forward: bool) -> usize {
+    if forward {
+        (index + 1) % count
+    } else {
+        (index + count - 1) % count
+    }
 }
 
 impl<'a> Display<'a> {
     /// This creates a new SDL2 window and initializes the rendering pipeline.
-    /// The window is scaled up from 160x144 to make it more visible.
-    pub fn new(sdl_context: &Sdl) -> Result<Self, String> {
+    /// The window is scaled up from 160x144 by `scale`; `AUTO_SCALE` (0) picks the
+    /// largest scale that fits the primary display. With `native_res` the window
+    /// starts at 160x144 (resizable, high-DPI aware) and the frame is copied 1:1
+    /// into a 160x144 logical size, so the window manager or SDL does the scaling.
+    pub fn new(sdl_context: &Sdl, scale: u32, native_res: bool) -> Result<Self, String> {
         let video_subsystem = sdl_context.video()?;
         
-        let window = video_subsystem
-            .window(
-                "Rustiboa-SNT - Game Boy Emulator",
-                SCREEN_WIDTH * SCALE_FACTOR,
-                SCREEN_HEIGHT * SCALE_FACTOR,
-            )
-            .position_centered()
-            .build()
-            .map_err(|e| e.to_string())?;
+        let scale = if native_res {
+            1
+        } else if scale == AUTO_SCALE {
+            let bounds = video_subsystem.display_bounds(0)?;
+            auto_scale(bounds.width(), bounds.height())
+        } else {
+            scale
+        };
+        
+        let mut window_builder = video_subsystem.window(
+            "Rustiboa-SNT - Game Boy Emulator",
+            SCREEN_WIDTH * scale,
+            SCREEN_HEIGHT * scale,
+        );
+        window_builder.position_centered();
+        if native_res {
+            window_builder.resizable().allow_highdpi();
+        }
+        let window = window_builder.build().map_err(|e| e.to_string())?;
         
         let mut canvas = window
             .into_canvas()
@@ -53,6 +225,12 @@ impl<'a> Display<'a> {
             .present_vsync()
             .build()
             .map_err(|e| e.to_string())?;
+        if native_res {
+            // Whatever size the window ends up, the frame maps onto it as 160x144
+            canvas
+                .set_logical_size(SCREEN_WIDTH, SCREEN_HEIGHT)
+                .map_err(|e| e.to_string())?;
+        }
         
         canvas.set_draw_color(sdl2::pixels::Color::RGB(0xE0, 0xF8, 0xD0));
         canvas.clear();
@@ -72,19 +250,38 @@ impl<'a> Display<'a> {
             canvas,
             texture_creator,
             texture,
+            scale,
+            native_res,
+            fullscreen: false,
+            palette: 0,
+            timing_overlay: false,
         })
     }
     
     /// This renders the Game Boy's framebuffer to the SDL2 window.
     /// Each pixel in the framebuffer is a value 0-3 representing one of four gray shades.
-    pub fn render(&mut self, framebuffer: &[u8; 160 * 144]) -> Result<(), String> {
+    /// With the timing overlay on, each line ends in its strip from `line_timings`.
+    pub fn render(
+        &mut self,
+        framebuffer: &[u8; 160 * 144],
+        line_timings: &[LineTiming; 144],
+    ) -> Result<(), String> {
+        let colors = PALETTES[self.palette].colors;
+        let timing_overlay = self.timing_overlay;
+        let strip_start = SCREEN_WIDTH as usize - TIMING_STRIP_WIDTH;
+        
         // We update the texture with pixel data from the framebuffer
         self.texture.with_lock(None, |buffer: &mut [u8], pitch: usize| {
-            for y in 0..SCREEN_HEIGHT as usize {
+            for (y, timing) in line_timings.iter().enumerate() {
+                let strip = timing_strip(timing);
                 for x in 0..SCREEN_WIDTH as usize {
                     let fb_index = y * SCREEN_WIDTH as usize + x;
                     let color_index = framebuffer[fb_index] & 0x03; // Mask to 0-3
-                    let color = PALETTE[color_index as usize];
+                    let color = if timing_overlay && x >= strip_start {
+                        strip[x - strip_start]
+                    } else {
+                        colors[color_index as usize]
+                    };
                     
                     let offset = y * pitch + x * 3;
                     buffer[offset] = ((color >> 16) & 0xFF) as u8;     // R
@@ -94,20 +291,153 @@ impl<'a> Display<'a> {
             }
         })?;
         
-        // We clear the canvas and draw the texture scaled up
+        // We clear the canvas and draw the texture scaled up. In fullscreen the
+        // frame is centered at an integer scale, except with `native_res`, where
+        // the logical size already keeps the aspect ratio.
+        let target = if self.fullscreen && !self.native_res {
+            let (width, height) = self.canvas.output_size()?;
+            letterbox_rect(width, height)
+        } else {
+            frame_rect(self.scale, self.native_res)
+        };
         self.canvas.clear();
-        self.canvas.copy(
-            &self.texture,
-            None,
-            Some(Rect::new(
-                0,
-                0,
-                SCREEN_WIDTH * SCALE_FACTOR,
-                SCREEN_HEIGHT * SCALE_FACTOR,
-            )),
-        )?;
+        self.canvas.copy(&self.texture, None, Some(target))?;
         self.canvas.present();
         
         Ok(())
     }
+    
+    /// This returns the integer window scale in use
+    pub fn scale(&self) -> u32 {
+        self.scale
+    }
+    
+    /// This returns the active palette
+    pub fn palette(&self) -> &'static Palette {
+        &PALETTES[self.palette]
+    }
+    
+    /// This switches to the built-in palette with the given name (see `palette_index`).
+    /// It applies from the next render.
+    pub fn set_palette(&mut self, name: &str) -> Result<&'static Palette, String> {
+        self.palette = palette_index(name)?;
+        Ok(&PALETTES[self.palette])
+    }
+    
+    /// This switches to the next built-in palette (or the previous one when
+    /// `forward` is false) and returns the new palette. It applies from the next render.
+    pub fn cycle_palette(&mut self, forward: bool) -> &'static Palette {
+        self.palette = cycle_palette_index(self.palette, PALETTES.len(), forward);
+        &PALETTES[self.palette]
+    }
+    
+    /// This writes a framebuffer to a PNG file in the active palette's colors, as
+    /// the game shows it (without the timing overlay)
+    pub fn screenshot(&self, path: &std::path::Path, framebuffer: &[u8; 160 * 144]) -> Result<(), String> {
+        std::fs::write(path, screenshot_png(framebuffer, &PALETTES[self.palette].colors))
+            .map_err(|e| format!("Failed to write screenshot {}: {}", path.display(), e))
+    }
+    
+    /// This switches the window between windowed and fullscreen-desktop and
+    /// returns whether it is now fullscreen. Leaving fullscreen restores the
+    /// window at its scale.
+    pub fn toggle_fullscreen(&mut self) -> Result<bool, String> {
+        let fullscreen = !self.fullscreen;
+        let mode = if fullscreen { FullscreenType::Desktop } else { FullscreenType::Off };
+        self.canvas.window_mut().set_fullscreen(mode)?;
+        self.fullscreen = fullscreen;
+        
+        // The letterbox bars are black; windowed, the frame covers the whole canvas
+        self.canvas.set_draw_color(if fullscreen {
+            sdl2::pixels::Color::RGB(0, 0, 0)
+        } else {
+            sdl2::pixels::Color::RGB(0xE0, 0xF8, 0xD0)
+        });
+        Ok(fullscreen)
+    }
+    
+    /// This turns the PPU timing overlay on or off and returns whether it is now on
+    pub fn toggle_timing_overlay(&mut self) -> bool {
+        self.timing_overlay = !self.timing_overlay;
+        self.timing_overlay
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_scale_accepts_numbers_and_auto() {
+        assert_eq!(parse_scale("3"), Ok(3));
+        assert_eq!(parse_scale("0"), Ok(AUTO_SCALE));
+        assert_eq!(parse_scale("auto"), Ok(AUTO_SCALE));
+        assert!(parse_scale("big").is_err());
+    }
+
+    #[test]
+    fn auto_scale_picks_the_largest_multiple_that_fits() {
+        // 1920x1080 leaves 1824x984: 11x wide but only 6x tall
+        assert_eq!(auto_scale(1920, 1080), 6);
+        assert_eq!(auto_scale(1366, 768), 4);
+        assert_eq!(auto_scale(3840, 2160), 14);
+        // Displays smaller than the screen still get 1x
+        assert_eq!(auto_scale(200, 150), 1);
+    }
+
+    #[test]
+    fn palette_cycling_wraps_at_both_ends() {
+        let count = PALETTES.len();
+        assert_eq!(cycle_palette_index(0, count, true), 1);
+        assert_eq!(cycle_palette_index(count - 1, count, true), 0);
+        assert_eq!(cycle_palette_index(0, count, false), count - 1);
+        assert_eq!(cycle_palette_index(1, count, false), 0);
+
+        // A full cycle either way comes back to the start
+        let mut index = 0;
+        for _ in 0..count {
+            index = cycle_palette_index(index, count, true);
+        }
+        assert_eq!(index, 0);
+        assert_eq!(cycle_palette_index(0, 1, true), 0);
+        assert_eq!(cycle_palette_index(0, 1, false), 0);
+    }
+
+    #[test]
+    fn palette_index_finds_palettes_by_name() {
+        assert_eq!(palette_index("dmg-green"), Ok(0));
+        assert_eq!(palette_index("grayscale"), Ok(1));
+        assert_eq!(palette_index("Pocket"), Ok(2));
+        let error = palette_index("sepia").unwrap_err();
+        assert!(error.contains("dmg-green, grayscale, pocket"), "{}", error);
+    }
+
+    #[test]
+    fn native_res_draws_the_frame_1_to_1_at_any_scale() {
+        let native = Rect::new(0, 0, SCREEN_WIDTH, SCREEN_HEIGHT);
+        for scale in [1, 3, 6, AUTO_SCALE] {
+            assert_eq!(frame_rect(scale, true), native);
+        }
+        assert_eq!(frame_rect(3, false), Rect::new(0, 0, 480, 432));
+    }
+
+    #[test]
+    fn letterbox_centers_the_largest_integer_scale() {
+        // 1920x1080: 7x (1120x1008) with 400-pixel bars left and right, 36 above and below
+        assert_eq!(letterbox_rect(1920, 1080), Rect::new(400, 36, 1120, 1008));
+        // An exact fit leaves no bars
+        assert_eq!(letterbox_rect(640, 576), Rect::new(0, 0, 640, 576));
+        // Outputs smaller than the screen still get 1x, cropped around the center
+        assert_eq!(letterbox_rect(100, 100), Rect::new(-30, -22, 160, 144));
+    }
+
+    #[test]
+    fn timing_strip_colors_each_mode_by_its_dots() {
+        let timing = LineTiming { oam_search: 80, pixel_transfer: 168, hblank: 208 };
+        let strip = timing_strip(&timing);
+        // 12 dots per pixel: pixels 0-6 start in mode 2, 7-20 in mode 3, the rest in HBlank
+        assert!(strip[..7].iter().all(|&color| color == TIMING_COLORS[0]));
+        assert!(strip[7..21].iter().all(|&color| color == TIMING_COLORS[1]));
+        assert!(strip[21..].iter().all(|&color| color == TIMING_COLORS[2]));
+    }
 }
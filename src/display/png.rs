@@ -0,0 +1,142 @@
+// REMINDER: Read AGENTS.md file before continuing development
+//
+// PNG Encoder - Minimal writer for screenshots
+//
+// This file writes 8-bit RGB images as PNG files without an image library. The
+// image data is stored uncompressed (zlib "stored" deflate blocks), which keeps the
+// encoder short; a 160x144 screenshot comes out at about 68KB. Chunks are
+// length-prefixed and end in a CRC-32, the zlib stream ends in an Adler-32.
+
+/// Bytes every PNG file starts with
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+
+/// Largest payload of one stored deflate block
+const MAX_STORED_BLOCK: usize = 0xFFFF;
+
+/// This encodes an RGB image (3 bytes per pixel, row by row, no padding) as a PNG
+pub fn encode_rgb(width: u32, height: u32, rgb: &[u8]) -> Vec<u8> {
+    let mut png = PNG_SIGNATURE.to_vec();
+
+    // IHDR: size, 8 bits per channel, color type 2 (RGB), default compression,
+    // filter and no interlacing
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&width.to_be_bytes());
+    header.extend_from_slice(&height.to_be_bytes());
+    header.extend_from_slice(&[8, 2, 0, 0, 0]);
+    write_chunk(&mut png, b"IHDR", &header);
+
+    // Each row starts with its filter type; we use 0 (none) throughout
+    let row_len = width as usize * 3;
+    let mut raw = Vec::with_capacity((row_len + 1) * height as usize);
+    for row in rgb.chunks(row_len) {
+        raw.push(0);
+        raw.extend_from_slice(row);
+    }
+    write_chunk(&mut png, b"IDAT", &zlib_stored(&raw));
+
+    write_chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+/// This appends one chunk: length, type, data and the CRC of type and data
+fn write_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    let crc = crc32(&png[start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+/// This wraps data (never empty: every PNG row has a filter byte) in a zlib
+/// stream of uncompressed deflate blocks
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    // CMF/FLG: deflate with a 32KB window, no preset dictionary, check bits valid
+    let mut out = vec![0x78, 0x01];
+    let blocks = data.len().div_ceil(MAX_STORED_BLOCK);
+    for (index, block) in data.chunks(MAX_STORED_BLOCK).enumerate() {
+        // Block header: BFINAL on the last block, BTYPE 00 (stored), then LEN and NLEN
+        out.push((index + 1 == blocks) as u8);
+        let len = block.len() as u16;
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&(!len).to_le_bytes());
+        out.extend_from_slice(block);
+    }
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+/// This computes the CRC-32 (ISO-HDLC polynomial, as used by PNG) of `data`
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+/// This computes the Adler-32 checksum that ends a zlib stream
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in data {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// This reads the payloads back out of a zlib stream of stored blocks
+    fn unstored(zlib: &[u8]) -> Vec<u8> {
+        let mut data = Vec::new();
+        let mut position = 2;
+        loop {
+            let last = zlib[position] & 1 != 0;
+            let len = u16::from_le_bytes([zlib[position + 1], zlib[position + 2]]) as usize;
+            let nlen = u16::from_le_bytes([zlib[position + 3], zlib[position + 4]]) as usize;
+            assert_eq!(len ^ 0xFFFF, nlen);
+            data.extend_from_slice(&zlib[position + 5..position + 5 + len]);
+            position += 5 + len;
+            if last {
+                break;
+            }
+        }
+        assert_eq!(&zlib[position..], &adler32(&data).to_be_bytes());
+        data
+    }
+
+    #[test]
+    fn checksums_match_reference_values() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        assert_eq!(crc32(b"IEND"), 0xAE42_6082);
+        assert_eq!(adler32(b"Wikipedia"), 0x11E6_0398);
+    }
+
+    #[test]
+    fn encode_rgb_writes_header_rows_and_end() {
+        // 2x1: a red and a blue pixel
+        let png = encode_rgb(2, 1, &[255, 0, 0, 0, 0, 255]);
+        assert_eq!(png[..8], PNG_SIGNATURE);
+        assert_eq!(&png[8..16], &[0, 0, 0, 13, b'I', b'H', b'D', b'R']);
+        assert_eq!(&png[16..29], &[0, 0, 0, 2, 0, 0, 0, 1, 8, 2, 0, 0, 0]);
+        assert_eq!(&png[png.len() - 12..], &[0, 0, 0, 0, b'I', b'E', b'N', b'D', 0xAE, 0x42, 0x60, 0x82]);
+
+        let idat_len = u32::from_be_bytes(png[33..37].try_into().unwrap()) as usize;
+        assert_eq!(&png[37..41], b"IDAT");
+        assert_eq!(unstored(&png[41..41 + idat_len]), [0, 255, 0, 0, 0, 0, 255]);
+    }
+
+    #[test]
+    fn large_images_span_several_stored_blocks() {
+        let data: Vec<u8> = (0..MAX_STORED_BLOCK * 2 + 10).map(|i| i as u8).collect();
+        let zlib = zlib_stored(&data);
+        assert_eq!(zlib[2], 0);
+        assert_eq!(unstored(&zlib), data);
+    }
+}
@@ -0,0 +1,779 @@
+// REMINDER: Read AGENTS.md file before continuing development
+//
+// GameBoy - The whole emulated machine
+//
+// This module ties the CPU, MMU, PPU and timer together into one struct so the
+// machine is always created the same way. Every run starts from the state the DMG
+// boot ROM hands over at 0x0100 (registers AND I/O registers), whether or not
+// Gameboy Doctor logging is enabled, and one call steps all components in sync.
+// With a boot ROM (`with_boot_rom`) it starts from power-on instead and runs the
+// boot sequence first.
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::cartridge::{self, Cartridge};
+use crate::cpu::{Cpu, CpuError};
+use crate::interrupts;
+use crate::mmu::{Bus, Mmu};
+use crate::ppu::Ppu;
+use crate::savestate::{SaveState, StateFormat, StateReader, StateWriter};
+use crate::timer::Timer;
+
+/// What happened during one `GameBoy::step`
+pub struct Step {
+    /// M-cycles used by the instruction plus any interrupt dispatch
+    pub cycles: u8,
+    /// Whether the PPU finished a frame during this step
+    pub frame_ready: bool,
+}
+
+/// Errors from setting up a machine, so embedders can tell what went wrong
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EmuError {
+    /// The ROM file couldn't be read or isn't a cartridge
+    Rom(String),
+    /// The battery save next to the ROM couldn't be read
+    BatterySave(String),
+}
+
+impl fmt::Display for EmuError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EmuError::Rom(message) | EmuError::BatterySave(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for EmuError {}
+
+/// This struct owns every emulated component and the total M-cycle count
+pub struct GameBoy {
+    pub cpu: Cpu,
+    pub mmu: Mmu,
+    pub ppu: Ppu,
+    pub timer: Timer,
+    /// Total M-cycles run since power-on
+    pub cycles: u64,
+}
+
+impl GameBoy {
+    /// This creates a machine for the given ROM in the post-boot handoff state
+    pub fn new(rom: Vec<u8>) -> Self {
+        GameBoy {
+            cpu: Cpu::new(),
+            mmu: Mmu::new(rom),
+            ppu: Ppu::new(),
+            timer: Timer::new(),
+            cycles: 0,
+        }
+    }
+
+    /// This creates a machine at power-on that runs `boot` (the 256-byte DMG boot
+    /// ROM) from 0x0000 before handing over to the cartridge at 0x0100
+    pub fn with_boot_rom(rom: Vec<u8>, boot: Vec<u8>) -> Self {
+        GameBoy {
+            cpu: Cpu::power_on(),
+            mmu: Mmu::with_boot_rom(rom, boot),
+            ppu: Ppu::new(),
+            timer: Timer::new(),
+            cycles: 0,
+        }
+    }
+    
+    /// This loads a ROM file and returns a machine ready to run it in the
+    /// post-boot state: the header picks the MBC and the cartridge RAM size, and
+    /// for cartridges with a battery the RAM is restored from `<rom>.sav` if
+    /// one exists.
+    pub fn from_rom_path<P: AsRef<Path>>(path: P) -> Result<Self, EmuError> {
+        let cartridge = Cartridge::load(&path).map_err(EmuError::Rom)?;
+        let mut gb = GameBoy::new(cartridge.rom);
+        if gb.mmu.has_battery() {
+            gb.load_ram(cartridge::battery_save_path(&path))
+                .map_err(EmuError::BatterySave)?;
+        }
+        Ok(gb)
+    }
+
+    /// This returns the game title from the cartridge header
+    pub fn title(&self) -> String {
+        cartridge::header_title(self.mmu.rom())
+    }
+
+    /// This restores the cartridge RAM from a battery save file. Returns false
+    /// (leaving the RAM untouched) when the file doesn't exist yet.
+    pub fn load_ram<P: AsRef<Path>>(&mut self, path: P) -> Result<bool, String> {
+        match fs::read(&path) {
+            Ok(data) => {
+                self.mmu.load_battery_save(&data);
+                Ok(true)
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(format!("Failed to read battery save {}: {}", path.as_ref().display(), e)),
+        }
+    }
+
+    /// This writes the cartridge RAM (and the RTC, if the cartridge has one) to a
+    /// battery save file
+    pub fn save_ram<P: AsRef<Path>>(&self, path: P) -> Result<(), String> {
+        fs::write(&path, self.mmu.battery_save())
+            .map_err(|e| format!("Failed to write battery save {}: {}", path.as_ref().display(), e))
+    }
+    
+    /// This enables Gameboy Doctor mode (LY reads as 0x90). It only changes how
+    /// LY reads, never the starting state, so logged and normal runs stay identical.
+    pub fn set_doctor_mode(&mut self, enabled: bool) {
+        self.mmu.doctor_mode = enabled;
+    }
+
+    /// This returns whether Gameboy Doctor mode is on. It can be toggled at any
+    /// time; once it's off, LY reads follow the PPU again from the next read.
+    pub fn doctor_mode(&self) -> bool {
+        self.mmu.doctor_mode
+    }
+
+    /// This requests an interrupt (one of the `interrupts::INT_*` bits) by setting
+    /// its IF bit, as if the hardware had raised it. Tests and tools use this to
+    /// exercise interrupt handlers without waiting for the real trigger; it's
+    /// serviced on the next `step` if IME and the matching IE bit allow it.
+    pub fn request_interrupt(&mut self, interrupt: u8) {
+        interrupts::request_interrupt(&mut self.mmu, interrupt);
+    }
+
+    /// This returns the PPU's current mode as STAT reports it (0 = HBlank,
+    /// 1 = VBlank, 2 = OAM search, 3 = pixel transfer)
+    pub fn ppu_mode(&self) -> u8 {
+        self.ppu.mode()
+    }
+
+    /// This returns the scanline the PPU is on (0-153). Unlike reading LY through
+    /// the MMU, it isn't affected by Gameboy Doctor mode.
+    pub fn ppu_ly(&self) -> u8 {
+        self.ppu.ly()
+    }
+
+    /// This runs one CPU instruction, services interrupts, and advances the timer,
+    /// OAM DMA, serial port and PPU by the same number of cycles, one M-cycle at a
+    /// time. The components move forward before each memory access, so an access
+    /// sees the machine as it is on that M-cycle of the instruction.
+    pub fn step(&mut self) -> Result<Step, CpuError> {
+        let mut bus = ClockedBus {
+            mmu: &mut self.mmu,
+            ppu: &mut self.ppu,
+            timer: &mut self.timer,
+            cycles: &mut self.cycles,
+            stopped: self.cpu.stopped,
+            ticks: 0,
+            frame_ready: false,
+        };
+        let m_cycles = self.cpu.tick(&mut bus)?;
+
+        // Cycles the instruction didn't spend on the bus (HALT and STOP) follow
+        // its accesses
+        bus.stopped = self.cpu.stopped;
+        bus.tick_until(m_cycles);
+
+        // Check and handle any pending interrupts AFTER instruction execution
+        // This ensures instructions that modify IF get their interrupts serviced immediately
+        let int_cycles = interrupts::handle_interrupts(&mut self.cpu, &mut bus);
+        let total_cycles = m_cycles + int_cycles;
+        bus.tick_until(total_cycles);
+
+        Ok(Step {
+            cycles: total_cycles,
+            frame_ready: bus.frame_ready,
+        })
+    }
+
+    /// This advances everything but the CPU by one M-cycle: the timer, OAM DMA,
+    /// the serial port and 4 PPU dots. `step` does the same before each memory
+    /// access of an instruction. Returns whether the PPU finished a frame.
+    pub fn tick_m_cycle(&mut self) -> bool {
+        tick_components(&mut self.mmu, &mut self.ppu, &mut self.timer, &mut self.cycles, self.cpu.stopped)
+    }
+
+    /// This snapshots the whole machine (CPU, memory and banking, PPU, timer and
+    /// the cycle count) into a save state for `load_state`, in the default format
+    pub fn save_state(&self) -> Vec<u8> {
+        self.save_state_as(StateFormat::default())
+    }
+
+    /// This snapshots the machine like `save_state`, in the given format. JSON
+    /// states list every CPU register and the cycle count as readable fields.
+    pub fn save_state_as(&self, format: StateFormat) -> Vec<u8> {
+        self.snapshot().encode(format)
+    }
+
+    /// This restores a state from `save_state` or `save_state_as` (the format is
+    /// detected from the data). States from another emulator build or for another
+    /// ROM are rejected; on any error the machine is left exactly as it was.
+    pub fn load_state(&mut self, bytes: &[u8]) -> Result<(), String> {
+        let state = SaveState::decode(bytes, self.mmu.rom())?;
+        let backup = self.snapshot();
+        let result = self.restore(&state);
+        if result.is_err() {
+            // Our own snapshot always reads back, so this can't fail
+            self.restore(&backup).expect("Failed to restore the machine state");
+        }
+        result
+    }
+
+    /// This captures the machine as a save state
+    fn snapshot(&self) -> SaveState {
+        let mut machine = StateWriter::default();
+        self.mmu.save_state(&mut machine);
+        self.ppu.save_state(&mut machine);
+        self.timer.save_state(&mut machine);
+        SaveState::new(self.mmu.rom(), self.cpu.save_state(), self.cycles, machine.finish())
+    }
+
+    /// This puts the machine into a save state's contents
+    fn restore(&mut self, state: &SaveState) -> Result<(), String> {
+        let mut machine = StateReader::new(&state.machine);
+        self.mmu.load_state(&mut machine)?;
+        self.ppu.load_state(&mut machine)?;
+        self.timer.load_state(&mut machine)?;
+        machine.finish()?;
+        self.cpu.load_state(&state.cpu);
+        self.cycles = state.cycles;
+        Ok(())
+    }
+}
+
+/// This advances the timer, OAM DMA, serial port and PPU by one M-cycle and
+/// counts it. Returns whether the PPU finished a frame.
+fn tick_components(mmu: &mut Mmu, ppu: &mut Ppu, timer: &mut Timer, cycles: &mut u64, stopped: bool) -> bool {
+    *cycles += 1;
+
+    // STOP halts the system clock: the timer (DIV included), DMA, serial and
+    // PPU stay frozen until a button press wakes the CPU. Joypad input is
+    // still committed by the frontend, which is what ends STOP.
+    if stopped {
+        return false;
+    }
+
+    timer.tick(1, mmu);
+    mmu.tick_dma();
+    mmu.tick_serial();
+
+    // Each M-cycle = 4 PPU dots
+    let mut frame_ready = false;
+    for _ in 0..4 {
+        frame_ready |= ppu.tick(mmu);
+    }
+    frame_ready
+}
+
+/// This is the bus the CPU uses inside `GameBoy::step`: every access first moves
+/// the rest of the machine forward by the M-cycle it takes
+struct ClockedBus<'a> {
+    mmu: &'a mut Mmu,
+    ppu: &'a mut Ppu,
+    timer: &'a mut Timer,
+    cycles: &'a mut u64,
+    /// Whether the CPU is in STOP (the components don't move)
+    stopped: bool,
+    /// M-cycles run so far in this step
+    ticks: u8,
+    /// Whether the PPU finished a frame during this step
+    frame_ready: bool,
+}
+
+impl ClockedBus<'_> {
+    /// This runs one M-cycle of the components
+    fn tick(&mut self) {
+        self.ticks += 1;
+        self.frame_ready |= tick_components(self.mmu, self.ppu, self.timer, self.cycles, self.stopped);
+    }
+
+    /// This runs M-cycles until `total` have passed in this step
+    fn tick_until(&mut self, total: u8) {
+        while self.ticks < total {
+            self.tick();
+        }
+    }
+}
+
+impl Bus for ClockedBus<'_> {
+    fn read_byte(&mut self, address: u16) -> u8 {
+        self.tick();
+        self.mmu.read_byte(address)
+    }
+
+    fn write_byte(&mut self, address: u16, value: u8) {
+        self.tick();
+        self.mmu.write_byte(address, value);
+    }
+
+    fn idle(&mut self) {
+        self.tick();
+    }
+
+    fn peek_byte(&self, address: u16) -> u8 {
+        self.mmu.peek_byte(address)
+    }
+
+    fn poke_byte(&mut self, address: u16, value: u8) {
+        self.mmu.poke_byte(address, value);
+    }
+
+    fn reset_div(&mut self) {
+        self.mmu.reset_div();
+    }
+
+    fn set_trace_pc(&mut self, pc: u16) {
+        self.mmu.set_trace_pc(pc);
+    }
+}
+
+#[cfg(test)]
+impl GameBoy {
+    /// This returns a machine whose 32KB ROM has `code` at the 0x0100 entry point
+    pub(crate) fn with_code(code: &[u8]) -> Self {
+        let mut rom = vec![0; 0x8000];
+        rom[0x0100..0x0100 + code.len()].copy_from_slice(code);
+        GameBoy::new(rom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn post_boot_state_is_the_same_with_doctor_mode() {
+        let plain = GameBoy::new(vec![0; 0x8000]);
+        let mut logged = GameBoy::new(vec![0; 0x8000]);
+        logged.set_doctor_mode(true);
+
+        assert_eq!(plain.cpu.registers.doctor_string(), logged.cpu.registers.doctor_string());
+        // Everything but LY, which doctor mode pins to 0x90
+        for address in (0xFF00..=0xFF7F).chain([0xFFFF]).filter(|&a| a != 0xFF44) {
+            assert_eq!(plain.mmu.read_byte(address), logged.mmu.read_byte(address), "0x{:04X}", address);
+        }
+    }
+
+    #[test]
+    fn post_boot_state_matches_the_dmg_handoff() {
+        let gb = GameBoy::new(vec![0; 0x8000]);
+        assert_eq!(
+            gb.cpu.registers.dump(),
+            "AF=01B0 BC=0013 DE=00D8 HL=014D SP=FFFE PC=0100 [Z - H C]"
+        );
+        for (address, value) in [
+            (0xFF00, 0xCF), // P1: both select lines low, nothing pressed
+            (0xFF04, 0xAB), // DIV
+            (0xFF07, 0xF8), // TAC
+            (0xFF0F, 0xE1), // IF
+            (0xFF26, 0xF1), // NR52
+            (0xFF40, 0x91), // LCDC
+            (0xFF47, 0xFC), // BGP
+            (0xFFFF, 0x00), // IE
+        ] {
+            assert_eq!(gb.mmu.read_byte(address), value, "0x{:04X}", address);
+        }
+    }
+
+    #[test]
+    fn dma_takes_160_m_cycles_while_the_cpu_runs() {
+        let mut gb = GameBoy::new(vec![0; 0x8000]);
+        for i in 0..0xA0 {
+            gb.mmu.write_byte(0xC000 + i, i as u8 ^ 0xFF);
+        }
+        // Running from HRAM, the only memory the CPU can reach during DMA
+        let program = [
+            0x3E, 0xC0, // FF80: LD A,$C0
+            0xE0, 0x46, // FF82: LDH ($46),A
+            0x04,       // FF84: INC B
+            0x18, 0xFD, // FF85: JR $FF84
+        ];
+        for (i, byte) in program.iter().enumerate() {
+            gb.mmu.write_byte(0xFF80 + i as u16, *byte);
+        }
+        gb.cpu.registers.pc = 0xFF80;
+        gb.cpu.registers.b = 0;
+
+        gb.step().expect("LD A,n failed");
+        gb.step().expect("LDH (n),A failed");
+        let start = gb.cycles;
+        // The first M-cycle after the write is the startup delay
+        assert!(!gb.mmu.dma_active());
+
+        // Then 160 bytes at one per M-cycle
+        while gb.cycles - start < 160 {
+            gb.step().expect("step failed");
+        }
+        assert_eq!(gb.cycles - start, 160);
+        assert!(gb.mmu.dma_active());
+        gb.step().expect("INC B failed");
+        assert_eq!(gb.cycles - start, 161);
+        assert!(!gb.mmu.dma_active());
+
+        for i in 0..0xA0 {
+            assert_eq!(gb.mmu.peek_byte(0xFE00 + i), i as u8 ^ 0xFF);
+        }
+        // INC B + JR is 4 M-cycles: the loop ran 40 times during the transfer,
+        // plus the INC B that finished it
+        assert_eq!(gb.cpu.registers.b, 41);
+    }
+
+    #[test]
+    fn requested_vblank_is_serviced_at_0x40() {
+        let mut gb = GameBoy::new(vec![0; 0x8000]); // NOPs from 0x0100
+        gb.cpu.ime = true;
+        gb.mmu.write_byte(0xFF0F, 0x00);
+        gb.mmu.write_byte(0xFFFF, interrupts::INT_VBLANK);
+
+        gb.request_interrupt(interrupts::INT_VBLANK);
+        assert_eq!(gb.mmu.read_byte(0xFF0F) & 0x1F, interrupts::INT_VBLANK);
+
+        // The NOP at 0x0100 runs, then the interrupt is dispatched
+        gb.step().unwrap();
+        assert_eq!(gb.cpu.registers.pc, 0x0040);
+        assert!(!gb.cpu.ime);
+        assert_eq!(gb.mmu.read_byte(0xFF0F) & interrupts::INT_VBLANK, 0);
+        assert_eq!(gb.mmu.read_word(gb.cpu.registers.sp), 0x0101);
+    }
+
+    #[test]
+    fn doctor_mode_pins_ly_until_turned_off() {
+        let mut plain = GameBoy::with_code(&[0x18, 0xFE]); // JR $0100
+        let mut gb = GameBoy::with_code(&[0x18, 0xFE]);
+        gb.set_doctor_mode(true);
+        assert!(gb.doctor_mode());
+        // Run both into the middle of the frame, past line 0x20
+        while plain.mmu.read_byte(0xFF44) < 0x20 {
+            plain.step().expect("step failed");
+            gb.step().expect("step failed");
+        }
+        assert_eq!(gb.mmu.read_byte(0xFF44), 0x90);
+
+        gb.set_doctor_mode(false);
+        assert!(!gb.doctor_mode());
+        assert_eq!(gb.mmu.read_byte(0xFF44), plain.mmu.read_byte(0xFF44));
+    }
+
+    #[test]
+    fn tick_m_cycle_advances_ppu_dma_and_timer() {
+        let mut gb = GameBoy::new(vec![0; 0x8000]);
+
+        // A scanline is 456 dots, or 114 M-cycles
+        let ly = gb.ppu.ly();
+        while gb.ppu.ly() == ly {
+            gb.tick_m_cycle();
+        }
+        let ly = gb.ppu.ly();
+        let start = gb.cycles;
+        while gb.ppu.ly() == ly {
+            gb.tick_m_cycle();
+        }
+        assert_eq!(gb.cycles - start, 114);
+
+        // TIMA every 4 M-cycles, counted from the DIV reset
+        gb.mmu.write_byte(0xFF04, 0x00);
+        gb.mmu.write_byte(0xFF07, 0x05);
+        gb.mmu.write_byte(0xFF05, 0x00);
+        for _ in 0..3 {
+            gb.tick_m_cycle();
+        }
+        assert_eq!(gb.mmu.peek_byte(0xFF05), 0x00);
+        gb.tick_m_cycle();
+        assert_eq!(gb.mmu.peek_byte(0xFF05), 0x01);
+
+        // After a one-M-cycle startup delay, each M-cycle copies one byte
+        gb.mmu.write_byte(0xC000, 0x11);
+        gb.mmu.write_byte(0xC001, 0x22);
+        gb.mmu.write_byte(0xFF46, 0xC0);
+        gb.tick_m_cycle();
+        assert_eq!(gb.mmu.peek_byte(0xFE00), 0x00);
+        gb.tick_m_cycle();
+        assert_eq!(gb.mmu.peek_byte(0xFE00), 0x11);
+        assert_eq!(gb.mmu.peek_byte(0xFE01), 0x00);
+        gb.tick_m_cycle();
+        assert_eq!(gb.mmu.peek_byte(0xFE01), 0x22);
+    }
+
+    #[test]
+    fn timer_sees_the_internal_cycle_before_a_stack_read() {
+        // RET Z spends an internal cycle on its condition check before popping.
+        // With SP at TIMA, the low byte read lands on the M-cycle TIMA ticks.
+        let mut gb = GameBoy::with_code(&[0xC8]);
+        gb.cpu.registers.f = 0x80;
+        gb.cpu.registers.sp = 0xFF05;
+        gb.mmu.write_byte(0xFF04, 0x00);
+        gb.mmu.write_byte(0xFF07, 0x05);
+        gb.mmu.write_byte(0xFF05, 0x10);
+        gb.mmu.write_byte(0xFF06, 0x20);
+        // This M-cycle resets the divider chain, so TIMA ticks 3 M-cycles later
+        gb.tick_m_cycle();
+
+        assert_eq!(gb.step().expect("RET Z failed").cycles, 5);
+        // Fetch, idle, then the low byte read sees the freshly incremented TIMA
+        assert_eq!(gb.cpu.registers.pc, 0x2011);
+    }
+
+    #[test]
+    fn halt_with_ime_wakes_into_the_timer_handler() {
+        // EI; HALT
+        let mut gb = GameBoy::with_code(&[0xFB, 0x76]);
+        gb.mmu.write_byte(0xFFFF, interrupts::INT_TIMER);
+        gb.mmu.write_byte(0xFF0F, 0x00);
+        gb.mmu.write_byte(0xFF06, 0x00);
+        gb.mmu.write_byte(0xFF05, 0xF0);
+        gb.mmu.write_byte(0xFF07, 0x05); // TIMA every 4 M-cycles
+
+        gb.step().expect("EI failed");
+        gb.step().expect("HALT failed");
+        assert!(gb.cpu.halted);
+        while gb.cpu.halted {
+            gb.step().expect("step failed");
+            assert!(gb.cycles < 1000, "the timer never woke the CPU");
+        }
+
+        // The step that wakes the CPU also services the interrupt
+        assert_eq!(gb.cpu.registers.pc, 0x0050);
+        assert!(!gb.cpu.ime);
+        assert_eq!(gb.mmu.peek_byte(0xFF0F) & interrupts::INT_TIMER, 0);
+        let sp = gb.cpu.registers.sp;
+        assert_eq!(gb.mmu.peek_byte(sp) as u16 | (gb.mmu.peek_byte(sp + 1) as u16) << 8, 0x0102);
+    }
+
+    #[test]
+    fn stop_freezes_the_clock_until_a_selected_button_is_pressed() {
+        // STOP; INC B; JR $0103
+        let mut gb = GameBoy::with_code(&[0x10, 0x00, 0x04, 0x18, 0xFE]);
+        gb.mmu.write_byte(0xFF00, 0x10); // Action buttons selected
+        assert_ne!(gb.mmu.peek_byte(0xFF04), 0);
+
+        gb.step().expect("STOP failed");
+        assert!(gb.cpu.stopped);
+        assert_eq!(gb.cpu.registers.pc, 0x0102);
+        assert_eq!(gb.mmu.peek_byte(0xFF04), 0);
+
+        // The clock is stopped too, so DIV and LY stay put
+        let ly = gb.ppu.ly();
+        for _ in 0..200 {
+            gb.step().expect("step failed");
+        }
+        assert_eq!(gb.cpu.registers.pc, 0x0102);
+        assert_eq!(gb.mmu.peek_byte(0xFF04), 0);
+        assert_eq!(gb.ppu.ly(), ly);
+
+        // A d-pad press doesn't pull a line low while only the buttons are selected
+        gb.mmu.set_joypad_state(0xFE); // Right pressed
+        gb.step().expect("step failed");
+        assert!(gb.cpu.stopped);
+
+        gb.mmu.set_joypad_state(0xEF); // A pressed
+        gb.step().expect("INC B failed");
+        assert!(!gb.cpu.stopped);
+        assert_eq!(gb.cpu.registers.b, 0x01);
+        assert_eq!(gb.cpu.registers.pc, 0x0103);
+    }
+
+    #[test]
+    fn boot_rom_machine_starts_at_power_on() {
+        let gb = GameBoy::with_boot_rom(vec![0; 0x8000], vec![0; crate::mmu::BOOT_ROM_SIZE]);
+        assert_eq!(gb.cpu.registers.pc, 0x0000);
+        assert_eq!(gb.cpu.registers.sp, 0x0000);
+        assert_eq!(gb.cpu.registers.a, 0x00);
+        // The boot ROM sets up the LCD itself
+        assert_eq!(gb.mmu.peek_byte(0xFF40), 0x00);
+    }
+
+    /// This returns a machine a few hundred instructions into a small loop, with
+    /// something in WRAM, OAM and the registers
+    fn running_machine() -> GameBoy {
+        // LD HL,$C000; loop: INC A; LD (HL+),A; JR loop
+        let mut gb = GameBoy::with_code(&[0x21, 0x00, 0xC0, 0x3C, 0x22, 0x18, 0xFC]);
+        gb.mmu.write_byte(0xFE10, 0x77);
+        for _ in 0..300 {
+            gb.step().expect("step failed");
+        }
+        gb
+    }
+
+    #[test]
+    fn load_state_returns_to_the_saved_point() {
+        let mut gb = running_machine();
+        let state = gb.save_state();
+        let (pc, a, cycles, ly) = (gb.cpu.registers.pc, gb.cpu.registers.a, gb.cycles, gb.mmu.peek_byte(0xFF44));
+
+        for _ in 0..500 {
+            gb.step().expect("step failed");
+        }
+        assert_ne!(gb.cycles, cycles);
+
+        gb.load_state(&state).expect("load failed");
+        assert_eq!((gb.cpu.registers.pc, gb.cpu.registers.a, gb.cycles, gb.mmu.peek_byte(0xFF44)), (pc, a, cycles, ly));
+        assert_eq!(gb.mmu.peek_byte(0xFE10), 0x77);
+        assert_eq!(gb.save_state(), state);
+    }
+
+    #[test]
+    fn rejected_state_leaves_machine_untouched() {
+        let mut gb = running_machine();
+        let before = gb.save_state();
+        let other = GameBoy::new(vec![1; 0x8000]);
+        assert!(gb.load_state(&other.save_state()).is_err());
+        assert!(gb.load_state(b"not a state").is_err());
+        assert!(gb.load_state(&before[..before.len() - 1]).is_err());
+        assert_eq!(gb.save_state(), before);
+    }
+
+    /// This returns the path of a ROM in tests/fixtures
+    fn fixture(name: &str) -> std::path::PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures").join(name)
+    }
+
+    #[test]
+    fn from_rom_path_loads_a_ready_machine() {
+        // fixture.gb: title "FIXTURE", ROM only; NOP; JP $0150
+        let mut gb = GameBoy::from_rom_path(fixture("fixture.gb")).expect("fixture failed to load");
+        assert_eq!(gb.title(), "FIXTURE");
+        assert_eq!(gb.cpu.registers.pc, 0x0100);
+
+        gb.step().expect("NOP failed");
+        gb.step().expect("JP failed");
+        assert_eq!(gb.cpu.registers.pc, 0x0150);
+    }
+
+    #[test]
+    fn from_rom_path_reports_a_missing_rom() {
+        let result = GameBoy::from_rom_path(fixture("missing.gb"));
+        assert!(matches!(result, Err(EmuError::Rom(_))));
+    }
+
+    #[test]
+    fn di_right_after_ei_cancels_the_enable() {
+        // EI; DI; NOP; NOP with a VBlank interrupt already pending
+        let mut gb = GameBoy::with_code(&[0xFB, 0xF3, 0x00, 0x00]);
+        gb.mmu.write_byte(0xFFFF, interrupts::INT_VBLANK);
+        gb.request_interrupt(interrupts::INT_VBLANK);
+
+        for pc in [0x0101, 0x0102, 0x0103, 0x0104] {
+            gb.step().expect("step failed");
+            assert!(!gb.cpu.ime);
+            assert_eq!(gb.cpu.registers.pc, pc);
+        }
+        assert!(!gb.cpu.ime_pending);
+        assert_eq!(gb.mmu.peek_byte(0xFF0F) & interrupts::INT_VBLANK, interrupts::INT_VBLANK);
+    }
+
+    #[test]
+    fn button_press_wakes_halt_into_the_joypad_handler() {
+        // LD A,$10; LDH ($00),A (buttons selected); EI; HALT
+        let mut gb = GameBoy::with_code(&[0x3E, 0x10, 0xE0, 0x00, 0xFB, 0x76]);
+        gb.mmu.write_byte(0xFFFF, interrupts::INT_JOYPAD);
+        gb.mmu.write_byte(0xFF0F, 0x00);
+        while !gb.cpu.halted {
+            gb.step().expect("step failed");
+        }
+        for _ in 0..100 {
+            gb.step().expect("step failed");
+        }
+        assert!(gb.cpu.halted);
+
+        let mut input = crate::input::Input::new();
+        input.press(crate::input::Button::A);
+        gb.mmu.set_joypad_state(input.read_joypad());
+        assert_eq!(gb.mmu.peek_byte(0xFF0F) & interrupts::INT_JOYPAD, interrupts::INT_JOYPAD);
+
+        gb.step().expect("step failed");
+        assert!(!gb.cpu.halted);
+        assert_eq!(gb.cpu.registers.pc, 0x0060);
+        assert_eq!(gb.mmu.peek_byte(0xFF0F) & interrupts::INT_JOYPAD, 0);
+    }
+
+    #[test]
+    fn unselected_button_press_does_not_request_the_joypad_interrupt() {
+        let mut gb = GameBoy::with_code(&[0x00]);
+        gb.mmu.write_byte(0xFF00, 0x20); // d-pad selected
+        gb.mmu.write_byte(0xFF0F, 0x00);
+        gb.mmu.set_joypad_state(0xEF); // A pressed
+        assert_eq!(gb.mmu.peek_byte(0xFF0F) & interrupts::INT_JOYPAD, 0);
+    }
+
+    #[test]
+    fn ie_overwritten_by_the_pc_push_cancels_dispatch() {
+        // With SP = 0x0000 the high byte of PC (0x01) lands on IE, which drops
+        // the timer bit before the interrupt is picked
+        let mut gb = GameBoy::with_code(&[0x00, 0x00]);
+        gb.cpu.registers.sp = 0x0000;
+        gb.cpu.ime = true;
+        gb.mmu.write_byte(0xFFFF, interrupts::INT_TIMER);
+        gb.mmu.write_byte(0xFF0F, interrupts::INT_TIMER);
+
+        gb.step().expect("step failed");
+        assert_eq!(gb.cpu.registers.pc, 0x0000);
+        assert_eq!(gb.mmu.peek_byte(0xFFFF), 0x01);
+        assert_eq!(gb.mmu.peek_byte(0xFF0F) & interrupts::INT_TIMER, interrupts::INT_TIMER);
+    }
+
+    #[test]
+    fn ppu_mode_and_ly_follow_the_frame() {
+        // JR -2 keeps the CPU busy while the PPU runs
+        let mut gb = GameBoy::with_code(&[0x18, 0xFE]);
+        let mut modes = [false; 4];
+        while gb.ppu_ly() < 144 {
+            assert_ne!(gb.ppu_mode(), 1, "VBlank on line {}", gb.ppu_ly());
+            assert_eq!(gb.ppu_ly(), gb.mmu.peek_byte(0xFF44));
+            modes[gb.ppu_mode() as usize] = true;
+            gb.step().expect("step failed");
+        }
+        assert_eq!(modes, [true, false, true, true]);
+        assert_eq!(gb.ppu_mode(), 1);
+    }
+
+    #[test]
+    fn save_state_round_trips_through_both_formats() {
+        let gb = running_machine();
+        for format in [StateFormat::Bincode, StateFormat::Json] {
+            let state = gb.save_state_as(format);
+            let mut restored = GameBoy::new(gb.mmu.rom().to_vec());
+            restored.load_state(&state).expect("load failed");
+
+            assert_eq!(restored.cpu.save_state(), gb.cpu.save_state());
+            assert_eq!(restored.cycles, gb.cycles);
+            assert_eq!(restored.mmu.peek_byte(0xC010), gb.mmu.peek_byte(0xC010));
+            assert_eq!(restored.mmu.peek_byte(0xFE10), 0x77);
+            assert_eq!(restored.ppu_ly(), gb.ppu_ly());
+            assert_eq!(restored.save_state(), gb.save_state());
+        }
+    }
+
+    #[test]
+    fn json_state_loads_edited_fields() {
+        let mut gb = running_machine();
+        let mut json: serde_json::Value = serde_json::from_slice(&gb.save_state_as(StateFormat::Json)).unwrap();
+        json["cpu"]["pc"] = 0x0200.into();
+        json["cpu"]["b"] = 0x42.into();
+        json["cpu"]["f"] = 0xFF.into();
+        json["cycles"] = 1234.into();
+
+        gb.load_state(&serde_json::to_vec(&json).unwrap()).expect("load failed");
+        assert_eq!(gb.cpu.registers.pc, 0x0200);
+        assert_eq!(gb.cpu.registers.b, 0x42);
+        assert_eq!(gb.cpu.registers.f, 0xF0);
+        assert_eq!(gb.cycles, 1234);
+    }
+
+    #[test]
+    fn json_state_rejects_out_of_range_values() {
+        let mut gb = running_machine();
+        let before = gb.save_state();
+        let json: serde_json::Value = serde_json::from_slice(&gb.save_state_as(StateFormat::Json)).unwrap();
+
+        // A register that doesn't fit its width, and machine bytes that aren't hex
+        let mut register = json.clone();
+        register["cpu"]["a"] = 0x100.into();
+        let mut machine = json.clone();
+        machine["machine"] = "zz".into();
+        for edited in [register, machine] {
+            assert!(gb.load_state(&serde_json::to_vec(&edited).unwrap()).is_err());
+        }
+        assert_eq!(gb.save_state(), before);
+    }
+}
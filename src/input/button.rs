@@ -0,0 +1,62 @@
+// REMINDER: Read AGENTS.md file before continuing development
+//
+// Joypad Buttons
+//
+// This file names the eight Game Boy buttons and their bits in the joypad state.
+// Frontends decide which keys (or gamepad buttons) press which button and report
+// presses to `Input` in these terms, so the core never sees a key code.
+
+use std::str::FromStr;
+
+use super::{BUTTON_A, BUTTON_B, BUTTON_SELECT, BUTTON_START};
+
+/// A Game Boy button
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Button {
+    Right,
+    Left,
+    Up,
+    Down,
+    A,
+    B,
+    Select,
+    Start,
+}
+
+impl Button {
+    /// This returns the button's bit in the joypad state (active-low, d-pad in the
+    /// low nibble, buttons in the high nibble)
+    pub fn mask(self) -> u8 {
+        match self {
+            Button::Right => 0x01,
+            Button::Left => 0x02,
+            Button::Up => 0x04,
+            Button::Down => 0x08,
+            Button::A => BUTTON_A,
+            Button::B => BUTTON_B,
+            Button::Select => BUTTON_SELECT,
+            Button::Start => BUTTON_START,
+        }
+    }
+}
+
+impl FromStr for Button {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "right" => Ok(Button::Right),
+            "left" => Ok(Button::Left),
+            "up" => Ok(Button::Up),
+            "down" => Ok(Button::Down),
+            "a" => Ok(Button::A),
+            "b" => Ok(Button::B),
+            "select" => Ok(Button::Select),
+            "start" => Ok(Button::Start),
+            other => Err(format!(
+                "Unknown button: {} (up, down, left, right, a, b, start or select)",
+                other
+            )),
+        }
+    }
+}
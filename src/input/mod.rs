@@ -5,85 +5,137 @@
 // This module handles Game Boy joypad input. The joypad has 8 buttons mapped
 // to I/O register 0xFF00: D-pad (Up, Down, Left, Right) and buttons (A, B, Start, Select).
 // The register uses a matrix system where you select button or d-pad mode.
+//
+// The state kept here holds both groups in one active-low byte, laid out the way
+// the register reports them: the low nibble is the d-pad (Right, Left, Up, Down
+// on bits 0-3) and the high nibble the buttons (A, B, Select, Start). The MMU
+// picks the nibble the game's select bits ask for when 0xFF00 is read.
+//
+// Input arrives as `Button` presses; mapping keys or gamepads to buttons is up to
+// the frontend, which keeps this module free of SDL.
+
+mod button;
+
+pub use button::Button;
 
-use sdl2::keyboard::Keycode;
-use std::collections::HashSet;
+/// Joypad state bit for the A button (active-low, high nibble = buttons)
+pub const BUTTON_A: u8 = 0x10;
+/// Joypad state bit for the B button
+pub const BUTTON_B: u8 = 0x20;
+/// Joypad state bit for the Select button
+pub const BUTTON_SELECT: u8 = 0x40;
+/// Joypad state bit for the Start button
+pub const BUTTON_START: u8 = 0x80;
+
+/// Default auto-fire period in frames (2 frames pressed, 2 released = 15 presses/s)
+pub const DEFAULT_AUTOFIRE_PERIOD: u32 = 4;
 
 /// This struct tracks which buttons are currently pressed and manages
 /// the joypad state register that the Game Boy reads
 pub struct Input {
-    /// Keys currently pressed (from SDL2)
-    keys_pressed: HashSet<Keycode>,
+    /// Buttons physically held, as joypad state bits (active-high, before `--swap-ab`)
+    pressed: u8,
     
     /// Joypad register state (0xFF00)
     joypad_state: u8,
+    
+    /// Buttons (BUTTON_A/BUTTON_B bits) that auto-fire while held
+    autofire_buttons: u8,
+    /// Whether auto-fire is currently on (the frontend can toggle it)
+    autofire_enabled: bool,
+    /// Frames per auto-fire press/release cycle (pressed for the first half)
+    autofire_period: u32,
+    /// Frames committed so far, which drives the auto-fire pattern
+    frame: u64,
+    
+    /// Whether the keys for A and B are exchanged (`--swap-ab`)
+    swap_ab: bool,
 }
 
 impl Input {
-    /// This creates a new input handler with no keys pressed
+    /// This creates a new input handler with no buttons pressed
     pub fn new() -> Self {
         Input {
-            keys_pressed: HashSet::new(),
+            pressed: 0,
             joypad_state: 0xFF, // All bits high = no buttons pressed
+            autofire_buttons: 0,
+            autofire_enabled: false,
+            autofire_period: DEFAULT_AUTOFIRE_PERIOD,
+            frame: 0,
+            swap_ab: false,
         }
     }
     
-    /// This handles an SDL2 key press event
-    pub fn key_down(&mut self, keycode: Keycode) {
-        self.keys_pressed.insert(keycode);
+    /// This turns auto-fire on for the given buttons (BUTTON_A/BUTTON_B bits).
+    /// Holding one of them then presses and releases it every `period` frames.
+    pub fn set_autofire(&mut self, buttons: u8, period: u32) {
+        self.autofire_buttons = buttons;
+        self.autofire_period = period.max(2);
+        self.autofire_enabled = buttons != 0;
+    }
+    
+    /// This turns auto-fire on or off and returns whether it's now on. It stays
+    /// off until `set_autofire` has given it buttons to fire.
+    pub fn toggle_autofire(&mut self) -> bool {
+        self.autofire_enabled = !self.autofire_enabled && self.autofire_buttons != 0;
+        self.autofire_enabled
+    }
+    
+    /// This exchanges which keys press A and B. Buttons already held are
+    /// re-resolved, so toggling it mid-press doesn't leave a button stuck.
+    pub fn set_swap_ab(&mut self, swap: bool) {
+        self.swap_ab = swap;
+        self.update_joypad_state();
+    }
+    
+    /// This presses a button
+    pub fn press(&mut self, button: Button) {
+        self.pressed |= button.mask();
+        self.update_joypad_state();
+    }
+    
+    /// This releases a button
+    pub fn release(&mut self, button: Button) {
+        self.pressed &= !button.mask();
         self.update_joypad_state();
     }
     
-    /// This handles an SDL2 key release event
-    pub fn key_up(&mut self, keycode: Keycode) {
-        self.keys_pressed.remove(&keycode);
+    /// This replaces the set of held buttons, e.g. with the buttons of every key
+    /// a frontend sees held down (a button may appear more than once)
+    pub fn set_pressed(&mut self, buttons: impl IntoIterator<Item = Button>) {
+        self.pressed = buttons.into_iter().fold(0, |pressed, button| pressed | button.mask());
         self.update_joypad_state();
     }
     
-    /// This updates the internal joypad state based on currently pressed keys.
+    /// This updates the internal joypad state based on the held buttons.
     /// The Game Boy joypad register uses active-low logic (0 = pressed).
     fn update_joypad_state(&mut self) {
-        // TODO: Implement proper joypad matrix and register handling
-        // For now we just store basic state
-        self.joypad_state = 0xFF;
-        
-        // Map SDL keys to Game Boy buttons
-        // Arrow keys = D-pad, Z/X = A/B, Enter/Shift = Start/Select
-        // When a button is pressed, clear its bit (active low)
-        
-        if self.keys_pressed.contains(&Keycode::Right) {
-            self.joypad_state &= !0x01;
-        }
-        if self.keys_pressed.contains(&Keycode::Left) {
-            self.joypad_state &= !0x02;
-        }
-        if self.keys_pressed.contains(&Keycode::Up) {
-            self.joypad_state &= !0x04;
-        }
-        if self.keys_pressed.contains(&Keycode::Down) {
-            self.joypad_state &= !0x08;
-        }
-        if self.keys_pressed.contains(&Keycode::Z) {
-            // A button
-            self.joypad_state &= !0x10;
-        }
-        if self.keys_pressed.contains(&Keycode::X) {
-            // B button
-            self.joypad_state &= !0x20;
-        }
-        if self.keys_pressed.contains(&Keycode::Return) {
-            // Start
-            self.joypad_state &= !0x40;
-        }
-        if self.keys_pressed.contains(&Keycode::RShift) {
-            // Select
-            self.joypad_state &= !0x80;
+        // With --swap-ab, a held A reads as B and the other way round
+        let mut pressed = self.pressed;
+        if self.swap_ab {
+            let a_b = pressed & (BUTTON_A | BUTTON_B);
+            if a_b == BUTTON_A || a_b == BUTTON_B {
+                pressed ^= BUTTON_A | BUTTON_B;
+            }
         }
+        self.joypad_state = !pressed;
+    }
+    
+    /// This advances the auto-fire pattern by one frame. The main loop calls it
+    /// once per frame, right before committing the joypad state.
+    pub fn tick_frame(&mut self) {
+        self.frame += 1;
     }
     
-    /// This returns the current joypad register value for the MMU to read
+    /// This returns the current joypad state (active-low) for the MMU to expose at 0xFF00.
+    /// Held auto-fire buttons read as released during the second half of each period.
     pub fn read_joypad(&self) -> u8 {
-        self.joypad_state
+        let period = self.autofire_period as u64;
+        if self.autofire_enabled && self.frame % period >= period / 2 {
+            self.joypad_state | self.autofire_buttons
+        } else {
+            self.joypad_state
+        }
     }
 }
 
@@ -92,3 +144,93 @@ impl Default for Input {
         Self::new()
     }
 }
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn autofire_alternates_every_frame_with_a_two_frame_period() {
+        let mut input = Input::new();
+        input.set_autofire(BUTTON_A, 2);
+        input.press(Button::A);
+        input.press(Button::B);
+
+        for frame in 0..6 {
+            let joypad = input.read_joypad();
+            // A is pressed (bit low) on even frames and released on odd ones
+            assert_eq!(joypad & BUTTON_A == 0, frame % 2 == 0, "A on frame {}", frame);
+            // B isn't an auto-fire button, so it stays held
+            assert_eq!(joypad & BUTTON_B, 0, "B on frame {}", frame);
+            input.tick_frame();
+        }
+    }
+
+    #[test]
+    fn autofire_toggle_does_nothing_without_buttons() {
+        let mut input = Input::new();
+        assert!(!input.toggle_autofire());
+        input.press(Button::A);
+
+        for _ in 0..8 {
+            assert_eq!(input.read_joypad() & BUTTON_A, 0);
+            input.tick_frame();
+        }
+    }
+
+    #[test]
+    fn swap_ab_exchanges_the_a_and_b_buttons() {
+        let mut input = Input::new();
+        input.set_swap_ab(true);
+
+        input.press(Button::A);
+        assert_eq!(input.read_joypad() & (BUTTON_A | BUTTON_B), BUTTON_A);
+        input.release(Button::A);
+        input.press(Button::B);
+        assert_eq!(input.read_joypad() & (BUTTON_A | BUTTON_B), BUTTON_B);
+
+        // Turning it off re-resolves the held button
+        input.set_swap_ab(false);
+        assert_eq!(input.read_joypad() & (BUTTON_A | BUTTON_B), BUTTON_A);
+        // The other buttons aren't affected
+        input.press(Button::Start);
+        assert_eq!(input.read_joypad() & BUTTON_START, 0);
+    }
+
+    #[test]
+    fn swap_ab_composes_with_autofire() {
+        let mut input = Input::new();
+        input.set_swap_ab(true);
+        input.set_autofire(BUTTON_A, 2);
+        // With the swap, a held B reads as A, which is the auto-fire button
+        input.press(Button::B);
+
+        assert_eq!(input.read_joypad() & BUTTON_A, 0);
+        input.tick_frame();
+        assert_eq!(input.read_joypad() & BUTTON_A, BUTTON_A);
+    }
+
+    #[test]
+    fn joypad_register_reads_the_selected_group() {
+        let mut input = Input::new();
+        input.press(Button::Left);
+        input.press(Button::Start);
+        let mut mmu = crate::mmu::Mmu::new(vec![0; 0x8000]);
+
+        // P14 low (0x20 written) selects the d-pad: Left is bit 1
+        mmu.write_byte(0xFF00, 0x20);
+        mmu.set_joypad_state(input.read_joypad());
+        assert_eq!(mmu.read_byte(0xFF00), 0xED);
+
+        // P15 low (0x10 written) selects the buttons: Start is bit 3
+        mmu.write_byte(0xFF00, 0x10);
+        assert_eq!(mmu.read_byte(0xFF00), 0xD7);
+
+        // Committing new input keeps the game's select bits
+        input.release(Button::Start);
+        mmu.set_joypad_state(input.read_joypad());
+        assert_eq!(mmu.read_byte(0xFF00), 0xDF);
+    }
+
+}
@@ -8,7 +8,7 @@
 // When an interrupt fires, the CPU jumps to a specific handler address.
 
 use crate::cpu::Cpu;
-use crate::mmu::Mmu;
+use crate::mmu::{Bus, Mmu};
 
 /// Interrupt bit positions in IE and IF registers
 pub const INT_VBLANK: u8 = 0x01;  // Bit 0: VBlank interrupt
@@ -17,6 +17,11 @@ pub const INT_TIMER: u8 = 0x04;    // Bit 2: Timer interrupt
 pub const INT_SERIAL: u8 = 0x08;   // Bit 3: Serial interrupt
 pub const INT_JOYPAD: u8 = 0x10;   // Bit 4: Joypad interrupt
 
+/// Bits of IE and IF that belong to an interrupt source. IE stores all 8 bits on
+/// DMG (games can keep data in bits 5-7) and IF reads bits 5-7 as 1, so both are
+/// masked before deciding whether anything is pending.
+const INT_MASK: u8 = 0x1F;
+
 /// Interrupt handler addresses in memory
 const INT_VBLANK_ADDR: u16 = 0x0040;
 const INT_LCD_STAT_ADDR: u16 = 0x0048;
@@ -25,17 +30,21 @@ const INT_SERIAL_ADDR: u16 = 0x0058;
 const INT_JOYPAD_ADDR: u16 = 0x0060;
 
 /// This checks if any enabled interrupts are pending and services the highest priority one.
-/// Returns the number of cycles taken (20 if interrupt serviced, 0 otherwise).
+/// Returns the number of M-cycles taken (5 if an interrupt was serviced, 0 otherwise),
+/// which are spent on the bus as they happen.
 /// Priority order: VBlank > LCD STAT > Timer > Serial > Joypad
-pub fn handle_interrupts(cpu: &mut Cpu, mmu: &mut Mmu) -> u8 {
+pub fn handle_interrupts(cpu: &mut Cpu, bus: &mut impl Bus) -> u8 {
     // We read the enabled interrupts (IE) and pending interrupts (IF)
-    let ie = mmu.read_byte(0xFFFF); // Interrupt Enable register
-    let if_reg = mmu.read_byte(0xFF0F); // Interrupt Flag register
+    let ie = bus.peek_byte(0xFFFF); // Interrupt Enable register
+    let if_reg = bus.peek_byte(0xFF0F); // Interrupt Flag register
     
-    // We find which interrupts are both enabled and pending
-    let triggered = ie & if_reg;
+    // We find which interrupts are both enabled and pending (bits 5-7 never
+    // trigger anything, even when set in both registers)
+    let triggered = ie & if_reg & INT_MASK;
     
-    // If the CPU is halted, any triggered interrupt wakes it up (even if IME is off)
+    // If the CPU is halted, any triggered interrupt wakes it up (even if IME is off).
+    // With IME on, the same call goes on to service it, so the halted CPU leaves
+    // HALT straight into the handler with the address after HALT on the stack.
     if cpu.halted && triggered != 0 {
         cpu.halted = false;
     }
@@ -50,40 +59,89 @@ pub fn handle_interrupts(cpu: &mut Cpu, mmu: &mut Mmu) -> u8 {
         return 0;
     }
     
-    // We disable IME so nested interrupts don't occur
+    // We disable IME so nested interrupts don't occur (dropping any EI still pending)
     cpu.ime = false;
+    cpu.ime_pending = false;
+    
+    // Servicing an interrupt takes 5 M-cycles: 2 wait cycles, 2 for the PC push,
+    // and 1 to jump to the handler
+    bus.idle();
+    bus.idle();
+    
+    // We push the high byte of PC first. Only then does the CPU look at IE and IF
+    // again to pick the interrupt, so a push that lands on IE (SP = 0x0000) can
+    // change which interrupt is serviced, or cancel it.
+    let pc = cpu.registers.pc;
+    cpu.push_byte(bus, (pc >> 8) as u8);
+    let ie = bus.peek_byte(0xFFFF);
+    let if_reg = bus.peek_byte(0xFF0F);
+    let dispatched = highest_priority(ie & if_reg & INT_MASK);
+    cpu.push_byte(bus, pc as u8);
     
-    // We check each interrupt in priority order and service the first one found
-    let (int_bit, handler_addr) = if triggered & INT_VBLANK != 0 {
-        (INT_VBLANK, INT_VBLANK_ADDR)
+    match dispatched {
+        Some((int_bit, handler_addr)) => {
+            // We clear this interrupt's pending flag and jump to its handler
+            bus.poke_byte(0xFF0F, if_reg & !int_bit);
+            cpu.registers.pc = handler_addr;
+        }
+        None => {
+            // Nothing is left to service: the dispatch is canceled and jumps to
+            // 0x0000 with IF untouched ("IE push" quirk)
+            cpu.registers.pc = 0x0000;
+        }
+    }
+    bus.idle();
+    5
+}
+
+/// This returns the bit and handler address of the highest priority interrupt in
+/// `triggered`, checking them in priority order
+fn highest_priority(triggered: u8) -> Option<(u8, u16)> {
+    if triggered & INT_VBLANK != 0 {
+        Some((INT_VBLANK, INT_VBLANK_ADDR))
     } else if triggered & INT_LCD_STAT != 0 {
-        (INT_LCD_STAT, INT_LCD_STAT_ADDR)
+        Some((INT_LCD_STAT, INT_LCD_STAT_ADDR))
     } else if triggered & INT_TIMER != 0 {
-        (INT_TIMER, INT_TIMER_ADDR)
+        Some((INT_TIMER, INT_TIMER_ADDR))
     } else if triggered & INT_SERIAL != 0 {
-        (INT_SERIAL, INT_SERIAL_ADDR)
+        Some((INT_SERIAL, INT_SERIAL_ADDR))
     } else if triggered & INT_JOYPAD != 0 {
-        (INT_JOYPAD, INT_JOYPAD_ADDR)
+        Some((INT_JOYPAD, INT_JOYPAD_ADDR))
     } else {
-        return 0;
-    };
-    
-    // We clear this interrupt's pending flag
-    mmu.write_byte(0xFF0F, if_reg & !int_bit);
-    
-    // We push the current PC onto the stack (like a CALL instruction)
-    cpu.registers.sp = cpu.registers.sp.wrapping_sub(2);
-    mmu.write_word(cpu.registers.sp, cpu.registers.pc);
-    
-    // We jump to the interrupt handler
-    cpu.registers.pc = handler_addr;
-    
-    // Servicing an interrupt takes 20 cycles (5 M-cycles)
-    20
+        None
+    }
 }
 
 /// This requests an interrupt by setting the corresponding bit in IF
 pub fn request_interrupt(mmu: &mut Mmu, interrupt: u8) {
-    let if_reg = mmu.read_byte(0xFF0F);
-    mmu.write_byte(0xFF0F, if_reg | interrupt);
+    let if_reg = mmu.peek_byte(0xFF0F);
+    mmu.poke_byte(0xFF0F, if_reg | interrupt);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ie_bits_5_to_7_never_dispatch() {
+        let mut mmu = Mmu::new(vec![0; 0x8000]);
+        let mut cpu = Cpu::new();
+        cpu.ime = true;
+        cpu.halted = true;
+        cpu.registers.pc = 0x1234;
+        mmu.write_byte(0xFFFF, 0xE0);
+        mmu.write_byte(0xFF0F, 0xE0);
+
+        assert_eq!(handle_interrupts(&mut cpu, &mut mmu), 0);
+        assert!(cpu.ime);
+        assert!(cpu.halted);
+        assert_eq!(cpu.registers.pc, 0x1234);
+
+        // Bit 2 (timer) dispatches with the upper bits still set
+        mmu.write_byte(0xFFFF, 0xE4);
+        mmu.write_byte(0xFF0F, 0xE4);
+        assert_eq!(handle_interrupts(&mut cpu, &mut mmu), 5);
+        assert_eq!(cpu.registers.pc, INT_TIMER_ADDR);
+        assert_eq!(mmu.peek_byte(0xFFFF), 0xE4);
+    }
 }
@@ -0,0 +1,36 @@
+// REMINDER: Read AGENTS.md file before continuing development
+//
+// Rustiboa-SNT - Emulator core library
+//
+// This crate root holds everything that emulates the Game Boy (CPU, MMU, PPU,
+// timer, cartridge, input state, save states and the frame runner) so it can be
+// embedded in other programs or driven from integration tests. The SDL window
+// lives in the binary (main.rs and the display module), so nothing here opens a
+// window; `Runner` runs frames headless.
+
+pub mod cartridge;
+pub mod cpu;
+pub mod debug;
+pub mod gameboy;
+pub mod input;
+pub mod interrupts;
+pub mod mmu;
+pub mod ppu;
+pub mod runner;
+pub mod savestate;
+pub mod snapshot;
+pub mod timer;
+pub mod timing;
+
+pub use cartridge::Cartridge;
+pub use cpu::Cpu;
+pub use gameboy::{EmuError, GameBoy};
+pub use input::Input;
+pub use mmu::Mmu;
+pub use ppu::Ppu;
+pub use runner::Runner;
+pub use timer::Timer;
+
+/// The emulator facade: a whole machine built from a ROM image
+/// (`Emulator::new(rom)`), advanced with `step` one instruction at a time
+pub type Emulator = GameBoy;
@@ -9,57 +9,401 @@
 // Allow dead code during development as we're building the framework
 #![allow(dead_code)]
 
-mod cpu;
-mod mmu;
-mod ppu;
+// The emulator core is the rustiboa_snt library (lib.rs); only the SDL window
+// is part of the binary
 mod display;
-mod cartridge;
-mod input;
-mod interrupts;
-mod timer;
 
 use std::env;
 use std::process;
 use std::fs::File;
-use std::io::Write;
 
-use cpu::Cpu;
-use mmu::Mmu;
-use ppu::Ppu;
+use rustiboa_snt::{cartridge, cpu, debug, gameboy, input, mmu, savestate, snapshot};
+use rustiboa_snt::cpu::StrictnessMode;
 use display::Display;
-use input::Input;
-use cartridge::Cartridge;
-use timer::Timer;
+use rustiboa_snt::cartridge::Cartridge;
+use rustiboa_snt::runner::Runner;
+use rustiboa_snt::timing::{self, FpsCounter, FrameLimiter, FrameStats};
 
-fn main() {
-    // We parse command line arguments to get the ROM file path and optional log file
-    let args: Vec<String> = env::args().collect();
-    
-    if args.len() < 2 {
-        eprintln!("Usage: {} <rom-file.gb> [--log <logfile>]", args[0]);
-        eprintln!("\nRustiboa-SNT - A DMG (original Game Boy) emulator");
-        eprintln!("Provide a .gb ROM file to run");
-        eprintln!("Optional: --log <logfile> to enable CPU state logging for Gameboy Doctor");
-        process::exit(1);
+/// Keys the window handles before the auto-fire and OAM dump keys, so they can't use them
+const HOTKEYS: [sdl2::keyboard::Keycode; 11] = {
+    use sdl2::keyboard::Keycode;
+    [Keycode::F1, Keycode::F2, Keycode::F4, Keycode::F5, Keycode::F9, Keycode::F11, Keycode::F12, Keycode::P, Keycode::Tab, Keycode::Backquote, Keycode::Period]
+};
+
+/// Where the final machine state is written when `--dump-state-on-exit` is given
+enum DumpTarget {
+    Stdout,
+    File(String),
+}
+
+/// Command line options for a run of the emulator
+struct Options {
+    rom_path: String,
+    /// DMG boot ROM to run from power-on before the cartridge (`--boot <file>`)
+    boot_path: Option<String>,
+    /// Gameboy Doctor log file (`--log <logfile>`)
+    log_path: Option<String>,
+    /// PC ranges the Gameboy Doctor log is limited to (`--trace-filter <start>-<end>`)
+    trace_filter: Vec<std::ops::RangeInclusive<u16>>,
+    /// Reference trace to check execution against (`--compare-trace <file>`)
+    compare_trace: Option<String>,
+    /// Post-mortem state dump destination (`--dump-state-on-exit [file]`)
+    dump_state_on_exit: Option<DumpTarget>,
+    /// Stop after this many frames (`--max-frames <n>`)
+    max_frames: Option<u64>,
+    /// How unimplemented/illegal CPU behavior is handled (`--strictness <mode>`)
+    strictness: StrictnessMode,
+    /// Window scale (`--scale <n|auto>`), where 0 means auto-fit to the display
+    scale: u32,
+    /// Create a 160x144 window and leave scaling to the window manager (`--native-res`)
+    native_res: bool,
+    /// Print the FPS every second, and frame time percentiles and a histogram on exit (`--frame-stats`)
+    frame_stats: bool,
+    /// Run without a window or SDL, as fast as possible (`--headless`)
+    headless: bool,
+    /// Open the background map debug window at startup (`--debug-bg`)
+    debug_bg: bool,
+    /// Run headless for a fixed number of frames and report the emulation speed (`--benchmark`)
+    benchmark: bool,
+    /// Write the last frame as a packed reference file (`--save-frame <file>`)
+    save_frame: Option<String>,
+    /// Compare the last frame against a reference file (`--expect-frame <file>`)
+    expect_frame: Option<String>,
+    /// Built-in palette the display starts with (`--palette <name>`)
+    palette: String,
+    /// Log every BGP palette change with its frame number (`--log-palette`)
+    log_palette: bool,
+    /// Warn about writes to ROM that aren't banking commands (`--log-rom-writes`)
+    log_rom_writes: bool,
+    /// Buttons that auto-fire while held (`--autofire a,b`), as input::BUTTON_* bits
+    autofire_buttons: u8,
+    /// Auto-fire press/release period in frames (`--autofire-period <frames>`)
+    autofire_period: u32,
+    /// Key that turns auto-fire on and off (`--autofire-key <key>`)
+    autofire_key: sdl2::keyboard::Keycode,
+    /// Exchange the keys for A and B (`--swap-ab`)
+    swap_ab: bool,
+    /// Key that prints the OAM sprite table (`--oam-dump-key <key>`)
+    oam_dump_key: sdl2::keyboard::Keycode,
+    /// Battery RAM save file instead of `<rom>.sav` (`--sram-file <path>`)
+    sram_path: Option<String>,
+    /// Key bindings loaded from a config file (`--keymap <file>`)
+    keymap: Option<display::KeyMap>,
+    /// Bank switch timeline file (`--rom-bank-log <file>`)
+    bank_log_path: Option<String>,
+    /// Model OAM DMA bus conflicts per bus (`--accurate-dma`)
+    accurate_dma: bool,
+    /// Complete serial transfers instantly (`--fast-serial`)
+    fast_serial: bool,
+    /// Address to wait for a link cable peer on (`--link-listen <addr>`)
+    link_listen: Option<String>,
+    /// Address of a link cable peer to connect to (`--link-connect <addr>`)
+    link_connect: Option<String>,
+    /// Speed multiplier while Tab is held, 0 for uncapped (`--turbo-speed <factor>`)
+    turbo_speed: f32,
+    /// How F5 writes save states (`--state-format <bincode|json>`)
+    state_format: savestate::StateFormat,
+    /// Print a static disassembly of this address range and exit (`--disassemble <start> <end>`)
+    disassemble: Option<(u16, u16)>,
+    /// ROM bank shown at 0x4000-0x7FFF when disassembling (`--bank <n>`)
+    bank: usize,
+}
+
+impl Options {
+    /// This returns where battery-backed cartridge RAM is loaded from and saved
+    /// to: the `--sram-file` path, or `<rom>.sav` next to the ROM
+    fn battery_path(&self) -> std::path::PathBuf {
+        match self.sram_path {
+            Some(ref path) => std::path::PathBuf::from(path),
+            None => cartridge::battery_save_path(&self.rom_path),
+        }
     }
+}
+
+/// This parses the command line arguments. The ROM path comes first, followed by
+/// optional flags in any order.
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let rom_path = args.get(1).ok_or("Missing ROM file")?.clone();
+    let mut options = Options {
+        rom_path,
+        boot_path: None,
+        log_path: None,
+        trace_filter: Vec::new(),
+        compare_trace: None,
+        dump_state_on_exit: None,
+        max_frames: None,
+        strictness: StrictnessMode::default(),
+        scale: display::DEFAULT_SCALE,
+        native_res: false,
+        frame_stats: false,
+        headless: false,
+        benchmark: false,
+        debug_bg: false,
+        save_frame: None,
+        expect_frame: None,
+        palette: display::PALETTES[0].name.to_string(),
+        log_palette: false,
+        log_rom_writes: false,
+        autofire_buttons: 0,
+        autofire_period: input::DEFAULT_AUTOFIRE_PERIOD,
+        autofire_key: sdl2::keyboard::Keycode::T,
+        swap_ab: false,
+        oam_dump_key: sdl2::keyboard::Keycode::O,
+        keymap: None,
+        sram_path: None,
+        bank_log_path: None,
+        accurate_dma: false,
+        fast_serial: false,
+        link_listen: None,
+        link_connect: None,
+        turbo_speed: timing::DEFAULT_TURBO_SPEED,
+        state_format: savestate::StateFormat::default(),
+        disassemble: None,
+        bank: 1,
+    };
     
-    let rom_path = &args[1];
-    
-    // Check for --log flag to enable CPU state logging for Gameboy Doctor
-    let mut log_file: Option<File> = None;
-    if args.len() >= 4 && args[2] == "--log" {
-        match File::create(&args[3]) {
-            Ok(file) => {
-                log_file = Some(file);
-                eprintln!("CPU logging enabled: {}", args[3]);
+    let mut i = 2;
+    while i < args.len() {
+        match args[i].as_str() {
+            "--log" => {
+                i += 1;
+                let path = args.get(i).ok_or("--log requires a file path")?;
+                options.log_path = Some(path.clone());
             }
-            Err(e) => {
-                eprintln!("Failed to create log file: {}", e);
-                process::exit(1);
+            "--trace-filter" => {
+                // Ranges can be comma-separated or given with several --trace-filter flags
+                i += 1;
+                let ranges = args.get(i).ok_or("--trace-filter requires an address range like 0150-0160")?;
+                options.trace_filter.extend(debug::console::parse_address_ranges(ranges)?);
+            }
+            "--dump-state-on-exit" => {
+                // The file is optional: without one we dump to stdout
+                match args.get(i + 1) {
+                    Some(path) if !path.starts_with("--") => {
+                        i += 1;
+                        options.dump_state_on_exit = Some(DumpTarget::File(path.clone()));
+                    }
+                    _ => options.dump_state_on_exit = Some(DumpTarget::Stdout),
+                }
+            }
+            "--max-frames" => {
+                i += 1;
+                let value = args.get(i).ok_or("--max-frames requires a number")?;
+                let frames = value
+                    .parse()
+                    .map_err(|_| format!("Invalid frame count: {}", value))?;
+                options.max_frames = Some(frames);
+            }
+            "--strictness" => {
+                i += 1;
+                let mode = args.get(i).ok_or("--strictness requires lenient, warn or strict")?;
+                options.strictness = mode.parse()?;
+            }
+            "--scale" => {
+                i += 1;
+                let value = args.get(i).ok_or("--scale requires a number or auto")?;
+                options.scale = display::parse_scale(value)?;
+            }
+            "--frame-stats" => options.frame_stats = true,
+            "--headless" => options.headless = true,
+            "--benchmark" => options.benchmark = true,
+            "--debug-bg" => options.debug_bg = true,
+            "--native-res" => options.native_res = true,
+            "--log-palette" => options.log_palette = true,
+            "--palette" => {
+                i += 1;
+                let name = args.get(i).ok_or("--palette requires a palette name like grayscale")?;
+                display::palette_index(name)?;
+                options.palette = name.clone();
+            }
+            "--log-rom-writes" => options.log_rom_writes = true,
+            "--accurate-dma" => options.accurate_dma = true,
+            "--fast-serial" => options.fast_serial = true,
+            "--link-listen" => {
+                i += 1;
+                let address = args.get(i).ok_or("--link-listen requires an address like 0.0.0.0:5000")?;
+                options.link_listen = Some(address.clone());
+            }
+            "--link-connect" => {
+                i += 1;
+                let address = args.get(i).ok_or("--link-connect requires an address like 192.168.1.2:5000")?;
+                options.link_connect = Some(address.clone());
             }
+            "--state-format" => {
+                i += 1;
+                let format = args.get(i).ok_or("--state-format requires bincode or json")?;
+                options.state_format = format.parse()?;
+            }
+            "--turbo-speed" => {
+                i += 1;
+                let value = args.get(i).ok_or("--turbo-speed requires a speed factor like 4")?;
+                options.turbo_speed = value
+                    .parse()
+                    .ok()
+                    .filter(|&speed: &f32| speed == 0.0 || (speed.is_finite() && speed >= 1.0))
+                    .ok_or_else(|| format!("Invalid turbo speed: {} (1 or more, or 0 for uncapped)", value))?;
+            }
+            "--rom-bank-log" => {
+                i += 1;
+                let path = args.get(i).ok_or("--rom-bank-log requires a file path")?;
+                options.bank_log_path = Some(path.clone());
+            }
+            "--autofire" => {
+                i += 1;
+                let buttons = args.get(i).ok_or("--autofire requires a button list like a,b")?;
+                for button in buttons.split(',') {
+                    options.autofire_buttons |= match button.parse() {
+                        Ok(button @ (input::Button::A | input::Button::B)) => button.mask(),
+                        _ => return Err(format!("Unknown auto-fire button: {} (expected a or b)", button.trim())),
+                    };
+                }
+            }
+            "--autofire-period" => {
+                i += 1;
+                let value = args.get(i).ok_or("--autofire-period requires a number of frames")?;
+                options.autofire_period = value
+                    .parse()
+                    .ok()
+                    .filter(|&frames| frames >= 2)
+                    .ok_or_else(|| format!("Invalid auto-fire period: {} (at least 2 frames)", value))?;
+            }
+            "--autofire-key" => {
+                i += 1;
+                let name = args.get(i).ok_or("--autofire-key requires a key name like T or F3")?;
+                let key = sdl2::keyboard::Keycode::from_name(name)
+                    .ok_or_else(|| format!("Unknown key: {}", name))?;
+                if HOTKEYS.contains(&key) {
+                    return Err(format!("{} is already a hotkey, pick another key for --autofire-key like T or F3", name));
+                }
+                options.autofire_key = key;
+            }
+            "--swap-ab" => options.swap_ab = true,
+            "--oam-dump-key" => {
+                i += 1;
+                let name = args.get(i).ok_or("--oam-dump-key requires a key name like O or F3")?;
+                let key = sdl2::keyboard::Keycode::from_name(name)
+                    .ok_or_else(|| format!("Unknown key: {}", name))?;
+                if HOTKEYS.contains(&key) {
+                    return Err(format!("{} is already a hotkey, pick another key for --oam-dump-key like O or F3", name));
+                }
+                options.oam_dump_key = key;
+            }
+            "--sram-file" => {
+                i += 1;
+                let path = args.get(i).ok_or("--sram-file requires a file path")?;
+                options.sram_path = Some(path.clone());
+            }
+            "--keymap" => {
+                i += 1;
+                let path = args.get(i).ok_or("--keymap requires a file path")?;
+                options.keymap = Some(display::KeyMap::load(path)?);
+            }
+            "--disassemble" => {
+                let start = args.get(i + 1).ok_or("--disassemble requires a start and end address")?;
+                let end = args.get(i + 2).ok_or("--disassemble requires a start and end address")?;
+                options.disassemble = Some((debug::console::parse_hex(start)?, debug::console::parse_hex(end)?));
+                i += 2;
+            }
+            "--bank" => {
+                i += 1;
+                let value = args.get(i).ok_or("--bank requires a ROM bank number")?;
+                options.bank = value
+                    .parse()
+                    .map_err(|_| format!("Invalid ROM bank: {}", value))?;
+            }
+            "--compare-trace" => {
+                i += 1;
+                let path = args.get(i).ok_or("--compare-trace requires a reference trace file")?;
+                options.compare_trace = Some(path.clone());
+            }
+            "--boot" => {
+                i += 1;
+                let path = args.get(i).ok_or("--boot requires a boot ROM file")?;
+                options.boot_path = Some(path.clone());
+            }
+            "--save-frame" => {
+                i += 1;
+                let path = args.get(i).ok_or("--save-frame requires a file path")?;
+                options.save_frame = Some(path.clone());
+            }
+            "--expect-frame" => {
+                i += 1;
+                let path = args.get(i).ok_or("--expect-frame requires a file path")?;
+                options.expect_frame = Some(path.clone());
+            }
+            other => return Err(format!("Unknown option: {}", other)),
         }
+        i += 1;
     }
     
+    // The auto-fire key is handled first, so sharing it would hide the OAM dump
+    if options.autofire_key == options.oam_dump_key {
+        return Err(format!("{} is both the auto-fire and the OAM dump key, pick another with --oam-dump-key", options.oam_dump_key));
+    }
+    // Both keys are handled before button input, so a key that presses a button
+    // would stop working. The key map can come after these flags, so this is
+    // checked once everything is parsed.
+    let keymap = options.keymap.clone().unwrap_or_default();
+    for (flag, key) in [("--autofire-key", options.autofire_key), ("--oam-dump-key", options.oam_dump_key)] {
+        if let Some(button) = keymap.button(key) {
+            return Err(format!("{} presses {:?}, pick another key for {}", key, button, flag));
+        }
+    }
+    
+    Ok(options)
+}
+
+fn main() {
+    // We parse command line arguments to get the ROM file path and optional flags
+    let args: Vec<String> = env::args().collect();
+    
+    let options = match parse_args(&args) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            eprintln!("Usage: {} <rom-file.gb> [--boot <file>] [--log <logfile>] [--trace-filter <start>-<end>] [--compare-trace <file>] [--dump-state-on-exit [file]] [--max-frames <n>] [--strictness <mode>] [--scale <n|auto>] [--native-res] [--debug-bg] [--frame-stats] [--headless] [--benchmark] [--save-frame <file>] [--expect-frame <file>] [--palette <name>] [--log-palette] [--autofire a,b] [--autofire-period <frames>] [--autofire-key <key>] [--swap-ab] [--oam-dump-key <key>] [--keymap <file>] [--sram-file <path>] [--rom-bank-log <file>] [--log-rom-writes] [--accurate-dma] [--fast-serial] [--link-listen <addr>] [--link-connect <addr>] [--turbo-speed <factor>] [--state-format <bincode|json>] [--disassemble <start> <end> [--bank <n>]]", args[0]);
+            eprintln!("\nRustiboa-SNT - A DMG (original Game Boy) emulator");
+            eprintln!("Provide a .gb ROM file to run");
+            eprintln!("Optional: --boot <file> to run a 256-byte DMG boot ROM (Nintendo logo scroll) before the game");
+            eprintln!("Optional: --log <logfile> to enable CPU state logging for Gameboy Doctor");
+            eprintln!("Optional: --trace-filter <start>-<end>[,...] to only log instructions whose PC is in the given hex ranges");
+            eprintln!("Optional: --compare-trace <file> to check each instruction against a reference trace (Gameboy Doctor or JSON lines) and stop at the first difference");
+            eprintln!("Optional: --dump-state-on-exit [file] to write the final machine state (stdout by default)");
+            eprintln!("Optional: --max-frames <n> to exit after n frames");
+            eprintln!("Optional: --strictness <lenient|warn|strict> to ignore, warn about, or stop on unimplemented CPU behavior (default: warn); only lenient runs illegal opcodes as NOPs");
+            eprintln!("Optional: --scale <n|auto> to set the window scale; 0 or auto fits the screen (default: 4)");
+            eprintln!("Optional: --native-res to open a 160x144 window and let the window manager scale it (overrides --scale)");
+            eprintln!("Optional: --debug-bg to open a second window showing the 256x256 background map with the screen outlined (F4 toggles it)");
+            eprintln!("Optional: --frame-stats to print the FPS every second, and frame time percentiles and a histogram on exit");
+            eprintln!("Optional: --headless to run without a window (no SDL) as fast as possible, e.g. test ROMs in CI with --max-frames");
+            eprintln!("Optional: --benchmark to run headless for --max-frames frames (default: 3600) and report the speed as a multiple of real time");
+            eprintln!("Optional: --save-frame <file> to write the last frame as a reference file");
+            eprintln!("Optional: --expect-frame <file> to compare the last frame against a reference (exit code 1 on mismatch)");
+            eprintln!("Optional: --palette <dmg-green|grayscale|pocket> to pick the starting color palette (default: dmg-green)");
+            eprintln!("Optional: --log-palette to log each BGP palette change with its frame number");
+            eprintln!("Optional: --autofire a,b to auto-fire the listed buttons while held (T toggles auto-fire)");
+            eprintln!("Optional: --autofire-period <frames> to set the auto-fire press/release period (default: 4)");
+            eprintln!("Optional: --autofire-key <key> to toggle auto-fire with another key (default: T)");
+            eprintln!("Optional: --swap-ab to swap the keys for the A and B buttons");
+            eprintln!("Optional: --oam-dump-key <key> to pick the key that prints the OAM sprite table (default: O)");
+            eprintln!("Optional: --keymap <file> to load key bindings from a config file with button = key lines (e.g. a = Space)");
+            eprintln!("Optional: --sram-file <path> to load and save battery RAM at this path instead of next to the ROM (<rom>.sav)");
+            eprintln!("Optional: --rom-bank-log <file> to log every ROM/RAM bank switch with its PC and cycle count");
+            eprintln!("Optional: --log-rom-writes to warn about writes to ROM that aren't commands for the cartridge's bank controller");
+            eprintln!("Optional: --accurate-dma to model OAM DMA bus conflicts per bus instead of blocking everything but HRAM");
+            eprintln!("Optional: --fast-serial to complete serial transfers instantly instead of at 8192 bits/s (test ROM automation)");
+            eprintln!("Optional: --link-listen <addr> to wait for another emulator to connect a link cable at this address (e.g. 0.0.0.0:5000)");
+            eprintln!("Optional: --link-connect <addr> to connect a link cable to an emulator started with --link-listen (e.g. 192.168.1.2:5000)");
+            eprintln!("Optional: --turbo-speed <factor> to set how fast Tab fast-forwards, 0 for uncapped (default: 4)");
+            eprintln!("Optional: --state-format <bincode|json> to write save states as compact bincode (default) or readable JSON");
+            eprintln!("Optional: --disassemble <start> <end> to print a disassembly of a ROM address range (hex) and exit");
+            eprintln!("Optional: --bank <n> to pick the ROM bank disassembled at 4000-7FFF (default: 1)");
+            process::exit(1);
+        }
+    };
+    
+    let rom_path = &options.rom_path;
+    
     println!("Rustiboa-SNT - Game Boy Emulator");
     println!("Loading ROM: {}", rom_path);
     
@@ -75,151 +419,488 @@ fn main() {
     println!("Cartridge loaded: {}", cartridge.title);
     println!("ROM size: {} bytes", cartridge.rom.len());
     
-    // We initialize all emulator components
-    let mut mmu = Mmu::new(cartridge.rom.clone());
-    let mut cpu = Cpu::new();
-    let mut ppu = Ppu::new();
-    let mut input = Input::new();
-    let mut timer = Timer::new();
-    
-    // For Gameboy Doctor compatibility: initialize CPU state as if boot ROM finished
-    if log_file.is_some() {
-        mmu.doctor_mode = true;  // Enable special LY register handling
-        cpu.registers.a = 0x01;
-        cpu.registers.f = 0xB0;
-        cpu.registers.b = 0x00;
-        cpu.registers.c = 0x13;
-        cpu.registers.d = 0x00;
-        cpu.registers.e = 0xD8;
-        cpu.registers.h = 0x01;
-        cpu.registers.l = 0x4D;
-        cpu.registers.sp = 0xFFFE;
-        cpu.registers.pc = 0x0100;
+    // Static disassembly mode: print the listing without running anything
+    if let Some((start, end)) = options.disassemble {
+        match cpu::opcodes::disassemble_range(&cartridge.rom, options.bank, start, end) {
+            Ok(lines) => {
+                for line in lines {
+                    println!("{}", line);
+                }
+                return;
+            }
+            Err(e) => {
+                eprintln!("Failed to disassemble: {}", e);
+                process::exit(1);
+            }
+        }
+    }
+    
+    // We initialize the machine in the post-boot handoff state, or at power-on
+    // when a boot ROM is given, wrapped in the runner
+    let mut runner = match options.boot_path {
+        Some(ref path) => {
+            let boot = match std::fs::read(path) {
+                Ok(boot) => boot,
+                Err(e) => {
+                    eprintln!("Failed to load boot ROM: {}", e);
+                    process::exit(1);
+                }
+            };
+            if boot.len() != mmu::BOOT_ROM_SIZE {
+                eprintln!("Failed to load boot ROM: expected {} bytes, got {}", mmu::BOOT_ROM_SIZE, boot.len());
+                process::exit(1);
+            }
+            println!("Boot ROM loaded: {}", path);
+            Runner::with_boot_rom(cartridge.rom.clone(), boot)
+        }
+        None => Runner::new(cartridge.rom.clone()),
+    };
+    if options.autofire_buttons != 0 {
+        runner.input.set_autofire(options.autofire_buttons, options.autofire_period);
+    }
+    runner.input.set_swap_ab(options.swap_ab);
+    
+    // Battery-backed cartridge RAM is kept next to the ROM as <rom>.sav, unless
+    // --sram-file points somewhere else (e.g. when the ROM directory is read-only)
+    let battery_path = options.battery_path();
+    if runner.gb.mmu.has_battery() {
+        match runner.gb.load_ram(&battery_path) {
+            Ok(true) => println!("Battery save loaded: {}", battery_path.display()),
+            Ok(false) => {}
+            Err(e) => eprintln!("{}", e),
+        }
+    }
+    runner.gb.cpu.strictness = options.strictness;
+    runner.gb.mmu.log_palette = options.log_palette;
+    runner.gb.mmu.log_rom_writes = options.log_rom_writes;
+    runner.gb.mmu.accurate_dma = options.accurate_dma;
+    runner.gb.mmu.fast_serial = options.fast_serial;
+    
+    // Link cable to another running emulator over TCP; listening waits for the peer
+    let link = if let Some(ref address) = options.link_listen {
+        println!("Waiting for a link cable peer on {}", address);
+        Some(mmu::serial::TcpLink::listen(address.as_str()))
+    } else {
+        options.link_connect.as_ref().map(|address| mmu::serial::TcpLink::connect(address.as_str()))
+    };
+    match link {
+        Some(Ok(link)) => {
+            println!("Link cable connected");
+            runner.gb.mmu.set_serial_link(Some(Box::new(link)));
+        }
+        Some(Err(e)) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+        None => {}
+    }
+    runner.set_max_frames(options.max_frames);
+    
+    // Check for --log flag to enable CPU state logging for Gameboy Doctor
+    // (this also makes LY read as 0x90; the starting state is the same either way)
+    if let Some(ref log_path) = options.log_path {
+        match File::create(log_path) {
+            Ok(file) => {
+                runner.set_doctor_log(Box::new(file));
+                runner.set_trace_filter(options.trace_filter.clone());
+                eprintln!("CPU logging enabled: {}", log_path);
+            }
+            Err(e) => {
+                eprintln!("Failed to create log file: {}", e);
+                process::exit(1);
+            }
+        }
+    }
+    
+    // Reference trace to validate the CPU against, stopping at the first divergence
+    if let Some(ref path) = options.compare_trace {
+        match File::open(path) {
+            Ok(file) => {
+                runner.set_compare_trace(Box::new(std::io::BufReader::new(file)));
+                eprintln!("Comparing against reference trace: {}", path);
+            }
+            Err(e) => {
+                eprintln!("Failed to open reference trace: {}", e);
+                process::exit(1);
+            }
+        }
+    }
+    
+    // Bank switch timeline for reverse engineering a game's memory layout
+    if let Some(ref path) = options.bank_log_path {
+        match File::create(path) {
+            Ok(file) => runner.set_bank_log(Box::new(file)),
+            Err(e) => {
+                eprintln!("Failed to create bank log file: {}", e);
+                process::exit(1);
+            }
+        }
+    }
+    
+    let frame_stats = if options.benchmark {
+        // Headless and unpaced for a fixed number of frames, timed against the wall clock
+        runner.set_max_frames(Some(options.max_frames.unwrap_or(timing::DEFAULT_BENCHMARK_FRAMES)));
+        println!("Running benchmark");
+        let start_cycles = runner.gb.cycles;
+        let start = std::time::Instant::now();
+        if let Err(e) = runner.run() {
+            report_cpu_error(&runner.gb, &e);
+        }
+        let benchmark = timing::Benchmark {
+            frames: runner.frame_count(),
+            instructions: runner.instruction_count(),
+            t_cycles: (runner.gb.cycles - start_cycles) * 4,
+            elapsed: start.elapsed(),
+        };
+        print!("{}", benchmark.report());
+        None
+    } else if options.headless {
+        // No window and no pacing: frames run as fast as possible until the frame
+        // limit, with serial output still printed as it arrives
+        println!("Running headless");
+        if let Err(e) = run_headless(&mut runner) {
+            report_cpu_error(&runner.gb, &e);
+        }
+        None
+    } else {
+        Some(run_with_display(&mut runner, &options))
+    };
+    
+    println!("\nEmulator stopped");
+    
+    let gb = &runner.gb;
+    
+    if gb.mmu.has_battery() {
+        match gb.save_ram(&battery_path) {
+            Ok(()) => println!("Battery save written: {}", battery_path.display()),
+            Err(e) => eprintln!("{}", e),
+        }
+    }
+    
+    if options.frame_stats && let Some(frame_stats) = frame_stats {
+        print!("{}", frame_stats.report());
+    }
+    
+    // Report how the trace comparison ended; a divergence fails the run
+    let compare_failed = match runner.compare_end() {
+        Some(end) => {
+            print!("{}", end);
+            !end.is_success()
+        }
+        None => false,
+    };
+    
+    // Write the post-mortem state dump if requested
+    if let Some(target) = options.dump_state_on_exit {
+        let dump = debug::format_state(&gb.cpu, &gb.mmu, &gb.ppu, gb.cycles);
+        match target {
+            DumpTarget::Stdout => print!("{}", dump),
+            DumpTarget::File(path) => {
+                if let Err(e) = std::fs::write(&path, dump) {
+                    eprintln!("Failed to write state dump to {}: {}", path, e);
+                }
+            }
+        }
+    }
+    
+    // Write the last frame as a reference file if requested
+    if let Some(path) = options.save_frame {
+        match snapshot::save(&path, &gb.ppu.framebuffer) {
+            Ok(()) => println!("Saved reference frame to {}", path),
+            Err(e) => eprintln!("Failed to save reference frame to {}: {}", path, e),
+        }
+    }
+    
+    // Compare the last frame against the reference; a missing fixture skips the check
+    if let Some(path) = options.expect_frame {
+        match snapshot::load(&path) {
+            Ok(Some(expected)) => {
+                let diff = snapshot::compare(&gb.ppu.framebuffer, &expected);
+                print!("{}", diff.report());
+                if !diff.is_match() {
+                    process::exit(1);
+                }
+            }
+            Ok(None) => println!("Reference frame {} not found, skipping comparison", path),
+            Err(e) => {
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+        }
     }
     
+    if compare_failed {
+        process::exit(1);
+    }
+}
+
+/// This opens the SDL window and runs the emulator in real time until the window
+/// is closed or the run ends, handling hotkeys and joypad input between frames.
+/// Returns the frame time statistics of the run.
+fn run_with_display(runner: &mut Runner, options: &Options) -> FrameStats {
     // We initialize SDL2 for display and input handling
     let sdl = sdl2::init().unwrap();
-    let mut display = Display::new(&sdl).expect("Failed to create display");
+    let mut display = Display::new(&sdl, options.scale, options.native_res).expect("Failed to create display");
+    // The name was checked when parsing the options
+    display.set_palette(&options.palette).expect("Invalid palette");
     let mut event_pump = sdl.event_pump().unwrap();
+    let mut keyboard = display::Keyboard::new(options.keymap.clone().unwrap_or_default());
+    let mut background_window = None;
+    if options.debug_bg {
+        background_window = open_background_window(&sdl);
+    }
+    
+    // Save states (F5/F9) go next to the ROM, e.g. game.gb -> game.state
+    let state_path = std::path::Path::new(&options.rom_path).with_extension("state");
     
     println!("Emulator initialized!");
-    println!("Controls: Arrow keys = D-pad, Z = A, X = B, Enter = Start, Shift = Select");
+    println!("Controls: Arrow keys = D-pad, Z = A, X = B, Enter = Start, Shift = Select, {} = Toggle auto-fire, Tab (hold) = Fast-forward, P = Pause, . = Advance one frame (paused), ` = Cycle palette, F1 = Debug console, F2 = PPU timing overlay, F4 = Background window, F11 = Fullscreen, F5 = Save state, F9 = Load state, F12 = Screenshot, {} = Dump OAM", options.autofire_key, options.oam_dump_key);
     
-    let mut vram_write_count = 0u64;
-    let start_time = std::time::Instant::now();
-    let mut last_pc = 0u16;
-    let mut pc_stuck_count = 0u32;
+    // The limiter paces frames to real time
+    let mut frame_limiter = FrameLimiter::new();
+    let mut frame_stats = FrameStats::new();
+    let mut fps_counter = FpsCounter::new();
+    // Set by the frame-advance key while paused: the next iteration runs one frame
+    let mut advance = false;
     
-    // Main emulation loop: we run CPU cycles and PPU in sync
+    // Main loop: the runner emulates one frame, then we present it, pace to real
+    // time and handle input events before the next one
     'running: loop {
-        // Handle input events
+        let frame = if advance {
+            advance = false;
+            runner.advance_frame()
+        } else {
+            runner.step_frame()
+        };
+        let frame = match frame {
+            Ok(frame) => frame,
+            Err(e) => {
+                // Strict mode stopped on a CPU error: we report where and exit cleanly
+                report_cpu_error(&runner.gb, &e);
+                break 'running;
+            }
+        };
+        
+        // When a frame is complete, we render it to the screen. While paused we keep
+        // presenting the last frame so the window (and overlay toggles) stay drawn.
+        if frame.frame_ready || runner.is_paused() {
+            if let Err(e) = display.render(&runner.gb.ppu.framebuffer, &runner.gb.ppu.line_timings) {
+                eprintln!("Render error: {}", e);
+            }
+            if let Some(ref mut window) = background_window {
+                let gb = &runner.gb;
+                let background = gb.ppu.render_full_background(&gb.mmu);
+                let (scx, scy) = (gb.mmu.peek_byte(0xFF43), gb.mmu.peek_byte(0xFF42));
+                if let Err(e) = window.render(&background, &display.palette().colors, scx, scy) {
+                    eprintln!("Render error: {}", e);
+                }
+            }
+        }
+        print_serial_output(runner);
+        if frame.finished {
+            break 'running;
+        }
+        
+        frame_limiter.set_speed(runner.speed());
+        frame_stats.record(frame_limiter.wait_for_next_frame());
+        if options.frame_stats && let Some(fps) = fps_counter.frame() {
+            println!("FPS: {:.1}", fps);
+        }
+        
         for event in event_pump.poll_iter() {
             use sdl2::event::Event;
+            use sdl2::event::WindowEvent;
+            use sdl2::keyboard::{Keycode, Mod};
             match event {
                 Event::Quit {..} => break 'running,
+                Event::Window { window_id, win_event: WindowEvent::Close, .. } => {
+                    // With two windows open SDL sends Close instead of Quit: closing the
+                    // background window just drops it, closing the main one quits
+                    if background_window.as_ref().is_some_and(|window| window.id() == window_id) {
+                        background_window = None;
+                    } else {
+                        break 'running;
+                    }
+                }
+                Event::KeyDown { keycode: Some(Keycode::Backquote), keymod, repeat: false, .. } => {
+                    // Backtick cycles forward through the built-in palettes, Shift+backtick back
+                    let forward = !keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD);
+                    println!("Palette: {}", display.cycle_palette(forward).name);
+                }
+                Event::KeyDown { keycode: Some(Keycode::F1), repeat: false, .. } => {
+                    // F1 stops emulation and opens the debug console in the terminal
+                    debug::console::run(&mut runner.gb);
+                }
+                Event::KeyDown { keycode: Some(Keycode::F2), repeat: false, .. } => {
+                    // F2 shows how each line's 456 dots split between modes 2, 3 and 0
+                    let on = display.toggle_timing_overlay();
+                    println!("PPU timing overlay: {}", if on { "on" } else { "off" });
+                }
+                Event::KeyDown { keycode: Some(Keycode::F4), repeat: false, .. } => {
+                    // F4 opens or closes the background map window
+                    background_window = match background_window {
+                        Some(_) => None,
+                        None => open_background_window(&sdl),
+                    };
+                }
+                Event::KeyDown { keycode: Some(Keycode::F11), repeat: false, .. } => {
+                    // F11 fills the screen, keeping the frame integer-scaled and centered
+                    if let Err(e) = display.toggle_fullscreen() {
+                        eprintln!("Failed to toggle fullscreen: {}", e);
+                    }
+                }
+                Event::KeyDown { keycode: Some(Keycode::F5), repeat: false, .. } => {
+                    match std::fs::write(&state_path, runner.gb.save_state_as(options.state_format)) {
+                        Ok(()) => println!("State saved: {}", state_path.display()),
+                        Err(e) => eprintln!("Failed to save state: {}", e),
+                    }
+                }
+                Event::KeyDown { keycode: Some(Keycode::F9), repeat: false, .. } => {
+                    // Either format loads; a state from another build or ROM is refused
+                    // and the game keeps running
+                    match std::fs::read(&state_path).map_err(|e| e.to_string()).and_then(|bytes| runner.gb.load_state(&bytes)) {
+                        Ok(()) => println!("State loaded: {}", state_path.display()),
+                        Err(e) => eprintln!("Failed to load state: {}", e),
+                    }
+                }
+                Event::KeyDown { keycode: Some(Keycode::Tab), repeat: false, .. } => {
+                    // Tab fast-forwards while held; releasing it returns to real time
+                    runner.set_speed(options.turbo_speed);
+                }
+                Event::KeyUp { keycode: Some(Keycode::Tab), .. } => {
+                    runner.set_speed(1.0);
+                }
+                Event::KeyDown { keycode: Some(Keycode::P), repeat: false, .. } => {
+                    let paused = runner.toggle_pause();
+                    println!("{}", if paused { "Paused" } else { "Resumed" });
+                }
+                Event::KeyDown { keycode: Some(Keycode::Period), .. } if runner.is_paused() => {
+                    // Period runs one frame while paused (holding it repeats)
+                    advance = true;
+                }
+                Event::KeyDown { keycode: Some(Keycode::F12), repeat: false, .. } => {
+                    // F12 saves the current frame next to the ROM, named by the time
+                    let path = display::screenshot_path(&options.rom_path);
+                    match display.screenshot(&path, &runner.gb.ppu.framebuffer) {
+                        Ok(()) => println!("Screenshot saved: {}", path.display()),
+                        Err(e) => eprintln!("{}", e),
+                    }
+                }
+                Event::KeyDown { keycode: Some(key), repeat: false, .. } if key == options.autofire_key => {
+                    // Auto-fire only has buttons to fire when --autofire listed some
+                    if options.autofire_buttons == 0 {
+                        println!("Auto-fire has no buttons (start with --autofire a,b)");
+                    } else {
+                        let on = runner.input.toggle_autofire();
+                        println!("Auto-fire {}", if on { "on" } else { "off" });
+                    }
+                }
+                Event::KeyDown { keycode: Some(key), repeat: false, .. } if key == options.oam_dump_key => {
+                    // Print every OAM entry to inspect the sprites the game has set up
+                    println!("OAM at frame {}:", runner.frame_count());
+                    print!("{}", debug::format_oam(&runner.gb.mmu));
+                }
                 Event::KeyDown { keycode: Some(key), .. } => {
-                    input.key_down(key);
+                    keyboard.key_down(key, &mut runner.input);
                 }
                 Event::KeyUp { keycode: Some(key), .. } => {
-                    input.key_up(key);
+                    keyboard.key_up(key, &mut runner.input);
                 }
                 _ => {}
             }
         }
         
-        // Log CPU state for Gameboy Doctor (before executing next instruction)
-        // Format: A:00 F:11 B:22 C:33 D:44 E:55 H:66 L:77 SP:8888 PC:9999 PCMEM:AA,BB,CC,DD
-        if let Some(ref mut file) = log_file {
-            if !cpu.halted {
-                let pc = cpu.registers.pc;
-                let pcmem0 = mmu.read_byte(pc);
-                let pcmem1 = mmu.read_byte(pc.wrapping_add(1));
-                let pcmem2 = mmu.read_byte(pc.wrapping_add(2));
-                let pcmem3 = mmu.read_byte(pc.wrapping_add(3));
-                
-                writeln!(file, "A:{:02X} F:{:02X} B:{:02X} C:{:02X} D:{:02X} E:{:02X} H:{:02X} L:{:02X} SP:{:04X} PC:{:04X} PCMEM:{:02X},{:02X},{:02X},{:02X}",
-                    cpu.registers.a, cpu.registers.f,
-                    cpu.registers.b, cpu.registers.c,
-                    cpu.registers.d, cpu.registers.e,
-                    cpu.registers.h, cpu.registers.l,
-                    cpu.registers.sp, pc,
-                    pcmem0, pcmem1, pcmem2, pcmem3
-                ).unwrap();
-            }
+        // Commit the joypad state to the MMU, which exposes it through 0xFF00. While
+        // paused, keys are still tracked but only reach the game with the next frame
+        // that runs, so buttons can be held down before advancing.
+        if !runner.is_paused() || advance {
+            runner.commit_input();
         }
-        
-        // Track if PC is stuck in a loop
-        let current_pc = cpu.registers.pc;
-        if current_pc == last_pc {
-            pc_stuck_count += 1;
-            if pc_stuck_count % 1000000 == 0 {
-                eprintln!("Warning: PC stuck at 0x{:04X} for {} iterations", current_pc, pc_stuck_count);
-            }
-        } else {
-            if pc_stuck_count > 10000 {
-                eprintln!("PC was stuck at 0x{:04X} for {} iterations, now at 0x{:04X}", last_pc, pc_stuck_count, current_pc);
-            }
-            pc_stuck_count = 0;
-            last_pc = current_pc;
-        }
-        
-        // Run one CPU instruction (this returns M-cycles used)
-        let m_cycles = cpu.tick(&mut mmu);
-        
-        // Check and handle any pending interrupts AFTER instruction execution
-        // This ensures instructions that modify IF get their interrupts serviced immediately
-        let int_cycles = interrupts::handle_interrupts(&mut cpu, &mut mmu);
-        let total_cycles = m_cycles + int_cycles;
-        
-        // Update timer based on cycles executed
-        timer.tick(total_cycles, &mut mmu);
-        
-        // Run OAM DMA for each M-cycle if active
-        for _ in 0..total_cycles {
-            mmu.tick_dma();
+    }
+    
+    frame_stats
+}
+
+/// This runs frames as fast as possible until the frame limit (like `Runner::run`),
+/// printing serial output after each frame
+fn run_headless(runner: &mut Runner) -> Result<(), cpu::CpuError> {
+    loop {
+        let frame = runner.step_frame()?;
+        print_serial_output(runner);
+        if frame.finished {
+            return Ok(());
         }
-        
-        // Run PPU for corresponding T-cycles (4 T-cycles = 1 M-cycle)
-        // Each M-cycle from CPU = 4 PPU dots
-        for _ in 0..(total_cycles * 4) {
-            let frame_ready = ppu.tick(&mut mmu);
-            
-            // When a frame is complete, we render it to the screen
-            if frame_ready {
-                // Check VRAM and framebuffer content
-                vram_write_count += 1;
-                
-                // Print serial output if any (Blargg test results)
-                if !mmu.serial_output.is_empty() {
-                    println!("{}", mmu.serial_output);
-                    // Clear to avoid reprinting
-                    mmu.serial_output.clear();
-                }
-                
-                // if vram_write_count <= 10 || vram_write_count % 60 == 0 {
-                //     let elapsed = start_time.elapsed().as_secs_f32();
-                //     let vram_has_data = mmu.read_byte(0x8000) != 0 || mmu.read_byte(0x9800) != 0;
-                //     let fb_has_data = ppu.framebuffer.iter().any(|&p| p != 0);
-                //     // Check tile 0x7F data (at 0x87F0)
-                //     let tile_7f_data = mmu.read_byte(0x87F0);
-                //     eprintln!("[{:.1}s] Frame {}, VRAM[0x8000]={:02X}, VRAM[0x9800]={:02X}, Tile 0x7F={:02X}, FB has data: {}", 
-                //              elapsed, vram_write_count, mmu.read_byte(0x8000), mmu.read_byte(0x9800), tile_7f_data, fb_has_data);
-                // }
-                if let Err(e) = display.render(&ppu.framebuffer) {
-                    eprintln!("Render error: {}", e);
-                }
-            }
+        runner.commit_input();
+    }
+}
+
+/// This prints what the game sent over the serial port since the last call
+/// (Blargg test results)
+fn print_serial_output(runner: &mut Runner) {
+    let output = runner.take_serial_output();
+    if !output.is_empty() {
+        println!("{}", output);
+    }
+}
+
+/// This opens the background map debug window, reporting (and skipping) failures
+fn open_background_window(sdl: &sdl2::Sdl) -> Option<display::BackgroundWindow<'static>> {
+    match display::BackgroundWindow::new(sdl) {
+        Ok(window) => Some(window),
+        Err(e) => {
+            eprintln!("Failed to open background window: {}", e);
+            None
         }
-        
-        // Update joypad state in MMU (write to 0xFF00 register)
-        let joypad_state = input.read_joypad();
-        mmu.write_byte(0xFF00, joypad_state);
-        
-        // Small delay to prevent running at unlimited speed (temporary)
-        // TODO: Implement proper frame timing with VSync
-        std::thread::sleep(std::time::Duration::from_micros(1));
     }
-    
-    println!("\nEmulator stopped");
 }
 
+/// This reports a CPU error that stopped the run (strict mode) with the bytes
+/// around the faulting PC and the machine state
+fn report_cpu_error(gb: &gameboy::GameBoy, e: &cpu::CpuError) {
+    eprintln!("CPU error: {}", e);
+    eprintln!("{}", debug::format_bytes_around(&gb.mmu, e.pc()));
+    eprint!("{}", debug::format_state(&gb.cpu, &gb.mmu, &gb.ppu, gb.cycles));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn sram_file_overrides_the_battery_save_path() {
+        let options = parse_args(&args(&["rustiboa-snt", "roms/game.gb"])).expect("parse failed");
+        assert_eq!(options.battery_path(), std::path::PathBuf::from("roms/game.sav"));
+
+        let options = parse_args(&args(&["rustiboa-snt", "roms/game.gb", "--sram-file", "/tmp/saves/game.sav"]))
+            .expect("parse failed");
+        assert_eq!(options.battery_path(), std::path::PathBuf::from("/tmp/saves/game.sav"));
+
+        assert!(parse_args(&args(&["rustiboa-snt", "roms/game.gb", "--sram-file"])).is_err());
+    }
+
+    #[test]
+    fn palette_option_checks_the_name() {
+        let options = parse_args(&args(&["rustiboa-snt", "game.gb"])).expect("parse failed");
+        assert_eq!(options.palette, "dmg-green");
+
+        let options = parse_args(&args(&["rustiboa-snt", "game.gb", "--palette", "grayscale"])).expect("parse failed");
+        assert_eq!(options.palette, "grayscale");
+
+        assert!(parse_args(&args(&["rustiboa-snt", "game.gb", "--palette", "sepia"])).is_err());
+        assert!(parse_args(&args(&["rustiboa-snt", "game.gb", "--palette"])).is_err());
+    }
+
+    #[test]
+    fn autofire_key_refuses_hotkeys_and_taken_keys() {
+        let options = parse_args(&args(&["rustiboa-snt", "game.gb", "--autofire-key", "Y"])).expect("parse failed");
+        assert_eq!(options.autofire_key, sdl2::keyboard::Keycode::Y);
+
+        assert!(parse_args(&args(&["rustiboa-snt", "game.gb", "--autofire-key", "Tab"])).is_err());
+        assert!(parse_args(&args(&["rustiboa-snt", "game.gb", "--autofire-key", "O"])).is_err());
+        assert!(parse_args(&args(&["rustiboa-snt", "game.gb", "--autofire-key", "Z"])).is_err());
+    }
+}
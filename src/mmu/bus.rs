@@ -0,0 +1,81 @@
+// REMINDER: Read AGENTS.md file before continuing development
+//
+// CPU Bus - Memory as the CPU sees it, one M-cycle per access
+//
+// This file holds the trait the CPU reaches memory through. Every `read_byte`,
+// `write_byte` and `idle` is one M-cycle on the real bus, so an implementation
+// can move the rest of the machine forward between the accesses of a single
+// instruction (see `GameBoy::step`, which ticks the timer, OAM DMA, serial port
+// and PPU before each access). `Mmu` implements it without any clock, for tools
+// and tests that run the CPU on bare memory.
+//
+// `peek_byte`/`poke_byte` and `reset_div` don't take a bus cycle: the CPU uses
+// them for its own checks of IE, IF and P1 (interrupt dispatch, HALT and STOP)
+// and STOP's DIV reset, which happen inside the CPU rather than on the memory bus.
+
+use super::Mmu;
+
+/// This trait is the CPU's memory bus
+pub trait Bus {
+    /// This reads a byte as the CPU sees it, taking one M-cycle
+    fn read_byte(&mut self, address: u16) -> u8;
+
+    /// This writes a byte as the CPU sees it, taking one M-cycle
+    fn write_byte(&mut self, address: u16, value: u8);
+
+    /// This spends one M-cycle without a memory access (internal CPU work)
+    fn idle(&mut self);
+
+    /// This reads a byte without a bus cycle or DMA conflicts
+    fn peek_byte(&self, address: u16) -> u8;
+
+    /// This writes a byte without a bus cycle or DMA conflicts
+    fn poke_byte(&mut self, address: u16, value: u8);
+
+    /// This resets DIV and the timer's counters without a bus cycle (STOP)
+    fn reset_div(&mut self);
+
+    /// This tells the access trace which instruction the following accesses belong to
+    fn set_trace_pc(&mut self, pc: u16);
+
+    /// This reads a 16-bit word (little-endian: low byte first), taking 2 M-cycles
+    fn read_word(&mut self, address: u16) -> u16 {
+        let low = self.read_byte(address) as u16;
+        let high = self.read_byte(address.wrapping_add(1)) as u16;
+        (high << 8) | low
+    }
+
+    /// This writes a 16-bit word (little-endian: low byte first), taking 2 M-cycles
+    fn write_word(&mut self, address: u16, value: u16) {
+        self.write_byte(address, value as u8);
+        self.write_byte(address.wrapping_add(1), (value >> 8) as u8);
+    }
+}
+
+impl Bus for Mmu {
+    fn read_byte(&mut self, address: u16) -> u8 {
+        Mmu::read_byte(self, address)
+    }
+
+    fn write_byte(&mut self, address: u16, value: u8) {
+        Mmu::write_byte(self, address, value)
+    }
+
+    fn idle(&mut self) {}
+
+    fn peek_byte(&self, address: u16) -> u8 {
+        Mmu::peek_byte(self, address)
+    }
+
+    fn poke_byte(&mut self, address: u16, value: u8) {
+        Mmu::poke_byte(self, address, value)
+    }
+
+    fn reset_div(&mut self) {
+        Mmu::reset_div(self)
+    }
+
+    fn set_trace_pc(&mut self, pc: u16) {
+        Mmu::set_trace_pc(self, pc)
+    }
+}
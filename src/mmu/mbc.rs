@@ -0,0 +1,338 @@
+// REMINDER: Read AGENTS.md file before continuing development
+//
+// Memory Bank Controllers (MBC)
+//
+// This file holds what the MMU needs to know about the cartridge's bank
+// controller: which MBC the header's cartridge type byte (0x0147) names, how much
+// external RAM the RAM size byte (0x0149) asks for, and the MBC3 real-time clock
+// registers. The banking itself lives in the MMU's read and write paths, which
+// dispatch on `Mbc`.
+//
+// The RTC follows wall-clock time: the live registers are brought up to date
+// (from the Unix time they were last updated at) whenever the game latches or
+// writes them. Battery saves of RTC cartridges end in the 48-byte footer other
+// emulators use: the live and latched registers as five 32-bit values each, then
+// the 64-bit Unix time they were saved at, so the clock keeps running while the
+// emulator is closed.
+
+use crate::savestate::{StateReader, StateWriter};
+
+/// Size of one switchable ROM bank (0x4000-0x7FFF)
+pub const ROM_BANK_SIZE: usize = 0x4000;
+
+/// Size of one external RAM bank (0xA000-0xBFFF)
+pub const RAM_BANK_SIZE: usize = 0x2000;
+
+/// The bank controller a cartridge uses
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mbc {
+    /// MBC1: 5-bit ROM bank plus a 2-bit register that selects the RAM bank or
+    /// the upper ROM bits, depending on the banking mode
+    Mbc1,
+    /// MBC2: 4-bit ROM bank and 512 half-bytes of built-in RAM. Address bit 8
+    /// decides which register a write to 0x0000-0x3FFF goes to.
+    Mbc2,
+    /// MBC3: 7-bit ROM bank, RAM banks 0-3 and the real-time clock registers
+    Mbc3,
+    /// MBC5: 9-bit ROM bank (bank 0 selectable at 0x4000-0x7FFF) and RAM banks 0-15
+    Mbc5,
+}
+
+impl Mbc {
+    /// This picks the controller for a cartridge type byte (0x0147). Types without
+    /// their own controller yet fall back to MBC1, which also behaves correctly for
+    /// 32KB ROM-only cartridges since they never write the bank registers.
+    pub fn from_cartridge_type(cartridge_type: u8) -> Self {
+        match cartridge_type {
+            0x05 | 0x06 => Mbc::Mbc2,
+            0x0F..=0x13 => Mbc::Mbc3,
+            // 0x1C-0x1E are the rumble variants
+            0x19..=0x1E => Mbc::Mbc5,
+            _ => Mbc::Mbc1,
+        }
+    }
+}
+
+/// This returns whether a cartridge type byte (0x0147) names a cartridge without
+/// a bank controller (ROM only, optionally with RAM), whose ROM area ignores writes
+pub fn is_rom_only(cartridge_type: u8) -> bool {
+    matches!(cartridge_type, 0x00 | 0x08 | 0x09)
+}
+
+impl Mbc {
+    /// This returns whether a write to `address` (0x0000-0x7FFF) reaches one of the
+    /// controller's registers. MBC2 only decodes 0x0000-0x3FFF and MBC5 has
+    /// nothing at 0x6000-0x7FFF; MBC1 and MBC3 use the whole range.
+    pub fn has_register_at(self, address: u16) -> bool {
+        match self {
+            Mbc::Mbc1 | Mbc::Mbc3 => true,
+            Mbc::Mbc2 => address <= 0x3FFF,
+            Mbc::Mbc5 => address <= 0x5FFF,
+        }
+    }
+}
+
+/// This returns whether a cartridge type byte (0x0147) names an MBC3 with the
+/// real-time clock
+pub fn has_rtc(cartridge_type: u8) -> bool {
+    matches!(cartridge_type, 0x0F | 0x10)
+}
+
+/// This returns whether a cartridge type byte (0x0147) includes a battery that
+/// keeps the cartridge RAM between sessions
+pub fn has_battery(cartridge_type: u8) -> bool {
+    matches!(cartridge_type, 0x03 | 0x06 | 0x09 | 0x0D | 0x0F | 0x10 | 0x13 | 0x1B | 0x1E)
+}
+
+/// Size of MBC2's built-in RAM (512 x 4 bits, one nibble per byte), which
+/// repeats across 0xA000-0xBFFF
+pub const MBC2_RAM_SIZE: usize = 0x200;
+
+/// This returns the external RAM size in bytes for a RAM size byte (0x0149)
+pub fn ram_size(code: u8) -> usize {
+    match code {
+        0x01 => 2 * 1024,
+        0x02 => 8 * 1024,
+        0x03 => 32 * 1024,  // 4 banks of 8KB
+        0x04 => 128 * 1024, // 16 banks of 8KB
+        0x05 => 64 * 1024,  // 8 banks of 8KB
+        _ => 0,
+    }
+}
+
+/// First MBC3 RAM bank register value that selects an RTC register (0x08 = seconds)
+pub const RTC_SECONDS: u8 = 0x08;
+/// Last RTC register (0x0C = day counter high bit, halt and day carry flags)
+pub const RTC_DAY_HIGH: u8 = 0x0C;
+
+/// Bits that exist in each RTC register: seconds and minutes (0-59), hours
+/// (0-23), day counter low byte, and day high (bit 0 = day bit 8, bit 6 = halt,
+/// bit 7 = day counter carry)
+const RTC_REGISTER_MASKS: [u8; 5] = [0x3F, 0x3F, 0x1F, 0xFF, 0xC1];
+/// Day high register flags
+const RTC_HALT: u8 = 0x40;
+const RTC_DAY_CARRY: u8 = 0x80;
+/// Size of the RTC footer at the end of a battery save
+pub const RTC_SAVE_SIZE: usize = 48;
+
+/// This returns the current Unix time in seconds
+fn unix_time() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|time| time.as_secs())
+        .unwrap_or(0)
+}
+
+/// This struct holds the MBC3 real-time clock registers (seconds, minutes,
+/// hours, day low, day high). The game reads a latched copy: writing 0x00 then
+/// 0x01 to 0x6000-0x7FFF copies the live registers into it. The live registers
+/// follow wall-clock time unless the halt flag is set.
+#[derive(Debug, Clone, Default)]
+pub struct Rtc {
+    registers: [u8; 5],
+    latched: [u8; 5],
+    /// The last latch write was 0x00, so a 0x01 latches
+    latch_armed: bool,
+    /// Unix time the live registers were last brought up to date (0 = the clock
+    /// hasn't been looked at yet, so nothing has elapsed)
+    last_update: u64,
+}
+
+impl Rtc {
+    /// This advances the live registers by `seconds` (unless halted). Counting
+    /// past day 511 wraps the day counter and sets the carry flag, which stays
+    /// set until the game clears it.
+    pub fn advance(&mut self, seconds: u64) {
+        let [second, minute, hour, day_low, day_high] = self.registers;
+        if day_high & RTC_HALT != 0 {
+            return;
+        }
+        let day = (day_high as u64 & 0x01) << 8 | day_low as u64;
+        let total = second as u64 + minute as u64 * 60 + hour as u64 * 3600 + day * 86400 + seconds;
+
+        let days = total / 86400;
+        let mut flags = day_high & (RTC_HALT | RTC_DAY_CARRY);
+        if days > 0x1FF {
+            flags |= RTC_DAY_CARRY;
+        }
+        let days = days & 0x1FF;
+        self.registers = [
+            (total % 60) as u8,
+            (total / 60 % 60) as u8,
+            (total / 3600 % 24) as u8,
+            days as u8,
+            flags | (days >> 8) as u8,
+        ];
+    }
+
+    /// This applies the wall-clock time that passed since the last update
+    fn update(&mut self) {
+        let now = unix_time();
+        if self.last_update != 0 && now > self.last_update {
+            self.advance(now - self.last_update);
+        }
+        self.last_update = now;
+    }
+
+    /// This returns the live registers followed by the latched ones and the time
+    /// they were saved, as the footer of a battery save
+    pub fn to_save_bytes(&self) -> [u8; RTC_SAVE_SIZE] {
+        let mut clock = self.clone();
+        clock.update();
+        let mut bytes = [0; RTC_SAVE_SIZE];
+        for (i, &value) in clock.registers.iter().chain(clock.latched.iter()).enumerate() {
+            bytes[i * 4] = value;
+        }
+        bytes[40..].copy_from_slice(&clock.last_update.to_le_bytes());
+        bytes
+    }
+
+    /// This restores the clock from a battery save footer. The time that passed
+    /// since it was saved is applied the next time the game looks at the clock.
+    pub fn load_save_bytes(&mut self, bytes: &[u8; RTC_SAVE_SIZE]) {
+        for i in 0..5 {
+            self.registers[i] = bytes[i * 4] & RTC_REGISTER_MASKS[i];
+            self.latched[i] = bytes[20 + i * 4] & RTC_REGISTER_MASKS[i];
+        }
+        let mut time = [0; 8];
+        time.copy_from_slice(&bytes[40..]);
+        self.last_update = u64::from_le_bytes(time);
+    }
+
+    /// This reads the latched value of RTC register `select` (0x08-0x0C)
+    pub fn read(&self, select: u8) -> u8 {
+        self.latched[(select - RTC_SECONDS) as usize]
+    }
+
+    /// This writes the live RTC register `select` (0x08-0x0C). Time that passed
+    /// before the write is applied first, so it isn't lost (or added to the new value).
+    pub fn write(&mut self, select: u8, value: u8) {
+        self.update();
+        let index = (select - RTC_SECONDS) as usize;
+        self.registers[index] = value & RTC_REGISTER_MASKS[index];
+    }
+
+    /// This handles a write to the latch register (0x6000-0x7FFF)
+    pub fn write_latch(&mut self, value: u8) {
+        if self.latch_armed && value == 0x01 {
+            self.update();
+            self.latched = self.registers;
+        }
+        self.latch_armed = value == 0x00;
+    }
+
+    /// This writes the live and latched registers to a save state
+    pub fn save_state(&self, state: &mut StateWriter) {
+        state.bytes(&self.registers);
+        state.bytes(&self.latched);
+        state.bool(self.latch_armed);
+        state.u64(self.last_update);
+    }
+
+    /// This restores what `save_state` wrote
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), String> {
+        state.bytes(&mut self.registers)?;
+        state.bytes(&mut self.latched)?;
+        self.latch_armed = state.bool()?;
+        self.last_update = state.u64()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// This returns a clock set to `registers` that never looks at wall time
+    /// until a test advances it by hand
+    fn rtc_at(registers: [u8; 5]) -> Rtc {
+        Rtc { registers, ..Rtc::default() }
+    }
+
+    /// This latches the live registers and returns what the game reads
+    fn latched(rtc: &mut Rtc) -> [u8; 5] {
+        rtc.write_latch(0x00);
+        rtc.write_latch(0x01);
+        [0x08, 0x09, 0x0A, 0x0B, 0x0C].map(|select| rtc.read(select))
+    }
+
+    #[test]
+    fn latch_copies_the_live_registers_on_0_then_1() {
+        let mut rtc = rtc_at([5, 4, 3, 2, 0]);
+        // A 0x01 without a 0x00 before it doesn't latch
+        rtc.write_latch(0x01);
+        assert_eq!(rtc.read(0x08), 0);
+        assert_eq!(latched(&mut rtc), [5, 4, 3, 2, 0]);
+
+        // The latched copy holds still while the live clock runs
+        rtc.advance(10);
+        assert_eq!(rtc.read(0x08), 5);
+        assert_eq!(latched(&mut rtc)[0], 15);
+    }
+
+    #[test]
+    fn advance_carries_into_minutes_hours_and_days() {
+        let mut rtc = rtc_at([59, 59, 23, 0xFF, 0]);
+        rtc.advance(1);
+        assert_eq!(rtc.registers, [0, 0, 0, 0x00, 0x01]);
+    }
+
+    #[test]
+    fn counting_past_day_511_wraps_and_sets_the_sticky_carry() {
+        let mut rtc = rtc_at([59, 59, 23, 0xFF, 0x01]);
+        rtc.advance(1);
+        assert_eq!(rtc.registers, [0, 0, 0, 0, RTC_DAY_CARRY]);
+
+        // The carry stays set as the clock keeps running, until the game clears it
+        rtc.advance(86400);
+        assert_eq!(rtc.registers, [0, 0, 0, 1, RTC_DAY_CARRY]);
+        rtc.registers[4] = 0;
+        rtc.advance(1);
+        assert_eq!(rtc.registers[4], 0);
+    }
+
+    #[test]
+    fn halt_stops_the_clock() {
+        let mut rtc = rtc_at([10, 0, 0, 0, RTC_HALT]);
+        rtc.advance(3600);
+        assert_eq!(rtc.registers, [10, 0, 0, 0, RTC_HALT]);
+    }
+
+    #[test]
+    fn writes_are_masked_to_the_bits_that_exist() {
+        let mut rtc = Rtc::default();
+        for select in RTC_SECONDS..=RTC_DAY_HIGH {
+            rtc.write(select, 0xFF);
+        }
+        assert_eq!(rtc.registers, RTC_REGISTER_MASKS);
+    }
+
+    #[test]
+    fn save_footer_round_trips_the_registers_and_time() {
+        let mut rtc = rtc_at([1, 2, 3, 4, RTC_HALT | 0x01]);
+        rtc.latched = [5, 6, 7, 8, 0];
+        let footer = rtc.to_save_bytes();
+        assert_eq!(footer.len(), RTC_SAVE_SIZE);
+        assert_eq!(&footer[..8], &[1, 0, 0, 0, 2, 0, 0, 0]);
+
+        let mut loaded = Rtc::default();
+        loaded.load_save_bytes(&footer);
+        assert_eq!(loaded.registers, rtc.registers);
+        assert_eq!(loaded.latched, rtc.latched);
+        assert!(loaded.last_update >= 1);
+        assert_eq!(loaded.to_save_bytes()[..40], footer[..40]);
+    }
+
+    #[test]
+    fn time_saved_in_the_footer_passes_while_closed() {
+        let mut rtc = rtc_at([0, 0, 0, 0, 0]);
+        let mut footer = rtc.to_save_bytes();
+        // Saved an hour ago
+        let saved = u64::from_le_bytes(footer[40..].try_into().unwrap()) - 3600;
+        footer[40..].copy_from_slice(&saved.to_le_bytes());
+
+        rtc.load_save_bytes(&footer);
+        let [_, minutes, hours, ..] = latched(&mut rtc);
+        assert_eq!((hours, minutes), (1, 0));
+    }
+}
@@ -9,13 +9,188 @@
 // 0x8000-0x9FFF: Video RAM (VRAM)
 // 0xA000-0xBFFF: External RAM (from cartridge, if present)
 // 0xC000-0xDFFF: Work RAM (WRAM)
-// 0xE000-0xFDFF: Echo RAM (mirror of WRAM)
+// 0xE000-0xFDFF: Echo RAM (mirror of WRAM 0xC000-0xDDFF; 0xDE00-0xDFFF has no mirror)
 // 0xFE00-0xFE9F: Object Attribute Memory (OAM, sprite info)
 // 0xFEA0-0xFEFF: Unusable
 // 0xFF00-0xFF7F: I/O Registers
 // 0xFF80-0xFFFE: High RAM (HRAM)
 // 0xFFFF: Interrupt Enable register
 
+mod bus;
+pub mod mbc;
+pub mod serial;
+pub mod trace;
+
+pub use bus::Bus;
+
+use crate::savestate::{StateReader, StateWriter};
+
+use std::cell::RefCell;
+
+use mbc::{Mbc, Rtc, MBC2_RAM_SIZE, RAM_BANK_SIZE, ROM_BANK_SIZE, RTC_DAY_HIGH, RTC_SECONDS};
+use serial::{LinkMessage, SerialLink};
+use trace::{AccessKind, AccessTrace, MemoryAccess};
+
+/// Value the CPU reads from memory it can't reach while OAM DMA is running
+const DMA_CONFLICT_VALUE: u8 = 0xFF;
+
+/// Which memory bus an address sits on. OAM DMA occupies one bus for the whole
+/// transfer, so the CPU only sees conflicts on that bus.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MemoryBus {
+    /// Cartridge ROM, cartridge RAM and WRAM (0x0000-0x7FFF, 0xA000-0xFDFF)
+    External,
+    /// VRAM (0x8000-0x9FFF)
+    Video,
+    /// OAM (0xFE00-0xFE9F), which the DMA is writing
+    Oam,
+    /// I/O, HRAM and IE - never touched by the DMA
+    Internal,
+}
+
+/// This returns the bus the given address is reached through
+fn memory_bus(address: u16) -> MemoryBus {
+    match address {
+        0x8000..=0x9FFF => MemoryBus::Video,
+        0x0000..=0xFDFF => MemoryBus::External,
+        0xFE00..=0xFE9F => MemoryBus::Oam,
+        _ => MemoryBus::Internal,
+    }
+}
+
+/// Bytes copied by one OAM DMA transfer, one per M-cycle (so it lasts 160 M-cycles)
+pub const OAM_DMA_LENGTH: u8 = 160;
+
+/// M-cycles per serial bit with the internal clock (8192 Hz = 128 M-cycles)
+const SERIAL_CYCLES_PER_BIT: u16 = 128;
+
+/// M-cycles a linked internal-clock transfer waits for the peer's byte after its
+/// 8 bits are done (about a second) before completing as if no cable were there
+const SERIAL_REPLY_TIMEOUT: u32 = 1 << 20;
+
+/// Which MBC banking register a logged bank switch changed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BankRegister {
+    /// Switchable ROM bank (0x4000-0x7FFF)
+    Rom,
+    /// RAM bank, or the upper ROM bank bits on MBC1
+    Ram,
+    /// MBC1 banking mode (0 = ROM banking, 1 = RAM banking)
+    Mode,
+}
+
+impl std::fmt::Display for BankRegister {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BankRegister::Rom => write!(f, "ROM bank"),
+            BankRegister::Ram => write!(f, "RAM bank"),
+            BankRegister::Mode => write!(f, "Banking mode"),
+        }
+    }
+}
+
+/// One bank switch recorded for `--rom-bank-log`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BankSwitch {
+    pub register: BankRegister,
+    pub from: u16,
+    pub to: u16,
+}
+
+/// A write to the ROM area that no banking register decodes, recorded for
+/// `--log-rom-writes` (usually a game bug or a wild pointer)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RomWrite {
+    pub address: u16,
+    pub value: u8,
+}
+
+/// Number of sprites in OAM (4 bytes each)
+pub const OAM_SPRITE_COUNT: usize = 40;
+
+/// One sprite's four OAM bytes, decoded. Y and X are stored as on hardware, offset
+/// by 16 and 8, so a sprite at screen (0, 0) has y = 16 and x = 8.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OamEntry {
+    pub y: u8,
+    pub x: u8,
+    pub tile: u8,
+    pub flags: u8,
+}
+
+impl OamEntry {
+    /// This returns whether background colors 1-3 are drawn over the sprite (flag bit 7)
+    pub fn behind_background(&self) -> bool {
+        self.flags & 0x80 != 0
+    }
+
+    /// This returns whether the sprite is flipped vertically (flag bit 6)
+    pub fn y_flip(&self) -> bool {
+        self.flags & 0x40 != 0
+    }
+
+    /// This returns whether the sprite is flipped horizontally (flag bit 5)
+    pub fn x_flip(&self) -> bool {
+        self.flags & 0x20 != 0
+    }
+
+    /// This returns whether the sprite uses OBP1 instead of OBP0 (flag bit 4)
+    pub fn use_obp1(&self) -> bool {
+        self.flags & 0x10 != 0
+    }
+}
+
+/// Size of the DMG boot ROM, mapped over 0x0000-0x00FF until 0xFF50 is written
+pub const BOOT_ROM_SIZE: usize = 0x100;
+
+/// I/O register values left by the DMG boot ROM when it hands control to the
+/// cartridge at 0x0100 (Pan Docs "Power Up Sequence"). Registers not listed start at 0.
+/// STAT keeps 0x81 (mode 1) since the PPU rewrites the mode bits on its first tick.
+const POST_BOOT_IO: [(u16, u8); 30] = [
+    (0xFF01, 0x00), // SB
+    (0xFF02, 0x7E), // SC
+    (0xFF04, 0xAB), // DIV
+    (0xFF05, 0x00), // TIMA
+    (0xFF06, 0x00), // TMA
+    (0xFF07, 0xF8), // TAC
+    (0xFF0F, 0xE1), // IF
+    (0xFF10, 0x80), // NR10
+    (0xFF11, 0xBF), // NR11
+    (0xFF12, 0xF3), // NR12
+    (0xFF13, 0xFF), // NR13
+    (0xFF14, 0xBF), // NR14
+    (0xFF16, 0x3F), // NR21
+    (0xFF18, 0xFF), // NR23
+    (0xFF19, 0xBF), // NR24
+    (0xFF1A, 0x7F), // NR30
+    (0xFF1B, 0xFF), // NR31
+    (0xFF1C, 0x9F), // NR32
+    (0xFF1D, 0xFF), // NR33
+    (0xFF1E, 0xBF), // NR34
+    (0xFF20, 0xFF), // NR41
+    (0xFF23, 0xBF), // NR44
+    (0xFF24, 0x77), // NR50
+    (0xFF25, 0xF3), // NR51
+    (0xFF26, 0xF1), // NR52: APU on, channel 1 still flagged active
+    (0xFF40, 0x91), // LCDC: LCD on, BG on, BG tile map 9800
+    (0xFF41, 0x81), // STAT: Mode 1
+    (0xFF46, 0xFF), // DMA
+    (0xFF47, 0xFC), // BGP
+    (0xFF50, 0x01), // Boot ROM disabled
+];
+
+/// Bits of each sound register (0xFF10-0xFF2F) that always read as 1: write-only
+/// fields (lengths, frequency low bytes, trigger bits) and unused bits/registers
+/// (Pan Docs "Audio Registers"). Reads OR the stored value with these.
+const AUDIO_READ_MASK: [u8; 0x20] = [
+    0x80, 0x3F, 0x00, 0xFF, 0xBF, // NR10-NR14
+    0xFF, 0x3F, 0x00, 0xFF, 0xBF, // (unused), NR21-NR24
+    0x7F, 0xFF, 0x9F, 0xFF, 0xBF, // NR30-NR34
+    0xFF, 0xFF, 0x00, 0x00, 0xBF, // (unused), NR41-NR44
+    0x00, 0x00, 0x70, // NR50, NR51, NR52
+    0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, // 0xFF27-0xFF2F (unused)
+];
+
 /// This struct represents the Game Boy's Memory Management Unit which maps all
 /// memory addresses to their corresponding regions (ROM, RAM, VRAM, I/O, etc.)
 pub struct Mmu {
@@ -31,8 +206,11 @@ pub struct Mmu {
     /// Video RAM (8KB at 0x8000-0x9FFF)
     vram: [u8; 0x2000],
     
-    /// External/Cartridge RAM (8KB+ depending on MBC, at 0xA000-0xBFFF)
-    eram: [u8; 0x2000],
+    /// External/Cartridge RAM (at 0xA000-0xBFFF), sized from the header's RAM size
+    /// byte but never smaller than one 8KB bank. Without a save it starts zeroed and
+    /// reads as 0x00 once enabled (0xFF only while disabled), which games checking
+    /// a "save exists" magic value rely on.
+    eram: Vec<u8>,
     
     /// Work RAM (8KB at 0xC000-0xDFFF)
     wram: [u8; 0x2000],
@@ -46,18 +224,24 @@ pub struct Mmu {
     /// High RAM (127 bytes at 0xFF80-0xFFFE)
     hram: [u8; 0x7F],
     
-    /// Interrupt Enable register (at 0xFFFF)
+    /// Interrupt Enable register (at 0xFFFF). All 8 bits are stored and read back;
+    /// only bits 0-4 enable interrupts.
     ie: u8,
     
-    // MBC1 banking state
+    // Cartridge banking state
+    /// Bank controller named by the cartridge header
+    mbc: Mbc,
     /// Whether RAM is enabled for read/write
     ram_enabled: bool,
-    /// Currently selected ROM bank (1-31)
-    rom_bank: u8,
-    /// Currently selected RAM bank or upper ROM bits (0-3)
+    /// Currently selected ROM bank (MBC1: 1-31, MBC2: 1-15, MBC3: 1-127, MBC5: 0-511)
+    rom_bank: u16,
+    /// Currently selected RAM bank (MBC1: 0-3, also the upper ROM bits;
+    /// MBC3: 0-3, or 0x08-0x0C for an RTC register; MBC5: 0-15)
     ram_bank: u8,
-    /// Banking mode: false = ROM mode, true = RAM mode
+    /// MBC1 banking mode: false = ROM mode, true = RAM mode
     banking_mode: bool,
+    /// MBC3 real-time clock registers
+    rtc: Rtc,
     
     // OAM DMA state
     /// Whether a DMA transfer is currently active
@@ -66,180 +250,353 @@ pub struct Mmu {
     dma_source: u16,
     /// Current progress in the DMA transfer (0-160 bytes)
     dma_progress: u8,
+    /// Source address of a transfer waiting out its one-M-cycle startup delay,
+    /// and the delay M-cycles left. Until it starts, the previous transfer (if
+    /// any) stays in effect.
+    dma_starting: Option<(u16, u8)>,
+    /// Byte currently driven on the DMA source bus (the last one copied)
+    dma_bus_byte: u8,
+    /// Model DMA bus conflicts per bus (`--accurate-dma`): CPU reads on the DMA
+    /// source bus return the byte being transferred, OAM is blocked, and the other
+    /// bus, I/O and HRAM work normally. When off, every read outside HRAM returns
+    /// 0xFF during DMA.
+    pub accurate_dma: bool,
+    
+    /// Recent CPU bus accesses, when tracing is on. `read_byte` takes `&self`,
+    /// so the trace lives in a RefCell.
+    access_trace: Option<RefCell<AccessTrace>>,
     
     // Serial port output for test ROM results
     /// Accumulated serial port output (test ROMs print results here)
     pub serial_output: String,
+    /// Finish internal-clock transfers the moment they start instead of shifting
+    /// one bit every 128 M-cycles (`--fast-serial`, for test ROM automation)
+    pub fast_serial: bool,
+    /// Bits still to shift in the current internal-clock transfer (0 = idle)
+    serial_bits_left: u8,
+    /// M-cycles until the next bit is shifted
+    serial_counter: u16,
+    /// Link cable to another machine (see serial.rs); None = nothing plugged in
+    serial_link: Option<Box<dyn SerialLink>>,
+    /// The peer's byte for the current linked transfer, if it came before our 8 bits were done
+    serial_reply: Option<u8>,
+    /// M-cycles left to wait for the peer's byte once our 8 bits are done (0 = not waiting)
+    serial_reply_wait: u32,
+    
+    /// Set when the CPU writes DIV, so the timer can reset its internal counters
+    div_reset_pending: bool,
+    
+    /// The internal LCD STAT interrupt line: the OR of every STAT source that is
+    /// both enabled and active. The interrupt is only requested when it goes from
+    /// low to high, so a source turning on while another holds the line is blocked.
+    stat_line: bool,
+    
+    /// Record BGP (0xFF47) changes for palette animation debugging (`--log-palette`)
+    pub log_palette: bool,
+    /// BGP values written since the last `take_bgp_changes`, only when the value changed
+    bgp_changes: Vec<u8>,
+    
+    /// Record MBC bank switches for reverse engineering (`--rom-bank-log`)
+    pub log_banking: bool,
+    /// Bank switches since the last `take_bank_switches` (writes that re-select
+    /// the current bank aren't recorded, which keeps tight loops from flooding the log)
+    bank_switches: Vec<BankSwitch>,
+    
+    /// Record writes to 0x0000-0x7FFF that aren't banking commands (`--log-rom-writes`)
+    pub log_rom_writes: bool,
+    /// Such writes since the last `take_rom_writes`
+    rom_writes: Vec<RomWrite>,
     
     /// Gameboy Doctor mode: always return 0x90 for LY register
     pub doctor_mode: bool,
-}impl Mmu {
+    
+    // Joypad matrix state
+    /// Select lines (bits 4-5) last written to 0xFF00 by the game
+    joypad_select: u8,
+    /// Live button state committed by the input handler (active-low).
+    /// Low nibble = d-pad (Right, Left, Up, Down), high nibble = buttons (A, B, Select, Start)
+    joypad_state: u8,
+}
+
+impl Mmu {
     /// This creates a new MMU with all memory regions initialized.
     /// The rom parameter is the cartridge data loaded from a .gb file.
     pub fn new(rom: Vec<u8>) -> Self {
+        // The header tells us the bank controller and how much RAM it has
+        let mbc = Mbc::from_cartridge_type(rom.get(0x0147).copied().unwrap_or(0));
+        let ram_size = mbc::ram_size(rom.get(0x0149).copied().unwrap_or(0)).max(RAM_BANK_SIZE);
+        
         let mut mmu = Mmu {
-            boot_rom: None,  // TODO: optionally load boot ROM
-            boot_rom_enabled: false,  // Start with boot ROM disabled for now
+            // No boot ROM: we start from the state it hands over (see `with_boot_rom`)
+            boot_rom: None,
+            boot_rom_enabled: false,
             rom,
             vram: [0; 0x2000],
-            eram: [0; 0x2000],
+            eram: vec![0; ram_size],
             wram: [0; 0x2000],
             oam: [0; 0xA0],
             io_registers: [0; 0x80],
             hram: [0; 0x7F],
             ie: 0,
-            // MBC1 starts with ROM bank 1 selected for 0x4000-0x7FFF
+            // The MBC starts with ROM bank 1 selected for 0x4000-0x7FFF
+            mbc,
             ram_enabled: false,
             rom_bank: 1,
             ram_bank: 0,
             banking_mode: false,
+            rtc: Rtc::default(),
             // OAM DMA starts inactive
             dma_active: false,
             dma_source: 0,
             dma_progress: 0,
+            dma_starting: None,
+            dma_bus_byte: 0xFF,
+            accurate_dma: false,
+            access_trace: None,
             // Serial port output starts empty
             serial_output: String::new(),
+            fast_serial: false,
+            serial_bits_left: 0,
+            serial_counter: 0,
+            serial_link: None,
+            serial_reply: None,
+            serial_reply_wait: 0,
+            div_reset_pending: false,
+            stat_line: false,
+            log_palette: false,
+            bgp_changes: Vec::new(),
+            log_banking: false,
+            bank_switches: Vec::new(),
+            log_rom_writes: false,
+            rom_writes: Vec::new(),
             // Gameboy Doctor mode starts disabled
             doctor_mode: false,
+            // No buttons pressed (the select lines are set by the post-boot I/O state)
+            joypad_select: 0x30,
+            joypad_state: 0xFF,
         };
         
-        // Initialize I/O registers to post-boot state
-        mmu.write_byte(0xFF40, 0x91);  // LCDC: LCD on, BG on, BG tile map 9800
-        mmu.write_byte(0xFF41, 0x81);  // STAT: Mode 1 (as per DMG boot state)
-        mmu.write_byte(0xFF47, 0xFC);  // BGP: Background palette
+        // Initialize I/O registers to the state the DMG boot ROM hands over
+        mmu.apply_post_boot_io();
         
         mmu
     }
     
-    /// This reads a byte from memory at the given address. We check which
-    /// region the address falls into and return the appropriate byte.
+    /// This creates an MMU that starts with `boot` (the 256-byte DMG boot ROM)
+    /// mapped over 0x0000-0x00FF, for running the boot sequence from power-on.
+    /// I/O registers start cleared (LCD off, APU off) since setting them up is
+    /// the boot ROM's job. Writing a non-zero value to 0xFF50 unmaps the boot ROM,
+    /// after which those addresses read from the cartridge again.
+    pub fn with_boot_rom(rom: Vec<u8>, boot: Vec<u8>) -> Self {
+        let mut mmu = Mmu::new(rom);
+        mmu.io_registers = [0; 0x80];
+        mmu.joypad_select = 0x30;
+        mmu.boot_rom = Some(boot);
+        mmu.boot_rom_enabled = true;
+        mmu
+    }
+    
+    /// This sets every I/O register to its DMG post-boot value (see `POST_BOOT_IO`).
+    /// Values are stored directly so write side effects (DIV reset, DMA start,
+    /// read-only NR52 bits) don't get in the way.
+    pub fn apply_post_boot_io(&mut self) {
+        for &(address, value) in POST_BOOT_IO.iter() {
+            self.io_registers[(address - 0xFF00) as usize] = value;
+        }
+        // The boot ROM leaves both select lines low, so P1 reads back 0xCF
+        self.joypad_select = 0x00;
+    }
+    
+    /// This reads a byte from memory as the CPU sees it. While OAM DMA is running
+    /// the CPU can only reach HRAM (which is why DMA wait routines live there),
+    /// so every other region returns the bus conflict value instead.
     pub fn read_byte(&self, address: u16) -> u8 {
-        match address {
-            // Boot ROM or ROM Bank 0
-            0x0000..=0x00FF => {
-                if self.boot_rom_enabled && self.boot_rom.is_some() {
-                    self.boot_rom.as_ref().unwrap()[address as usize]
+        let value = self.bus_read(address);
+        if let Some(trace) = &self.access_trace {
+            trace.borrow_mut().record(address, value, AccessKind::Read);
+        }
+        value
+    }
+    
+    /// This is `read_byte` without the access trace
+    fn bus_read(&self, address: u16) -> u8 {
+        if self.dma_blocks(address) {
+            if !self.accurate_dma {
+                return DMA_CONFLICT_VALUE;
+            }
+            let bus = memory_bus(address);
+            if bus == self.dma_source_bus() {
+                // The DMA owns this bus, so we see whatever byte it's moving
+                return self.dma_bus_byte;
+            } else if bus == MemoryBus::Oam {
+                // OAM is being written by the DMA and can't be read
+                return DMA_CONFLICT_VALUE;
+            }
+        }
+        self.peek_byte(address)
+    }
+    
+    /// This reads a byte from memory at the given address without any bus conflicts.
+    /// We dispatch on the top nibble of the address first (one jump table instead of
+    /// a chain of range checks), since this runs for every fetch and memory operand.
+    /// Other hardware (PPU, timer, interrupt controller) and debug tools use this.
+    pub fn peek_byte(&self, address: u16) -> u8 {
+        match address >> 12 {
+            // ROM Bank 0 (or the boot ROM overlay at 0x0000-0x00FF)
+            0x0..=0x3 => {
+                if address < 0x0100 {
+                    match &self.boot_rom {
+                        Some(boot) if self.boot_rom_enabled => {
+                            boot.get(address as usize).copied().unwrap_or(0xFF)
+                        }
+                        _ => self.rom.get(address as usize).copied().unwrap_or(0xFF),
+                    }
                 } else {
-                    self.rom.get(address as usize).copied().unwrap_or(0xFF)
+                    // ROM Bank 0 (or higher banks in MBC1's RAM banking mode)
+                    let bank = match self.mbc {
+                        // In RAM banking mode, upper 2 bits can be applied to bank 0 access
+                        Mbc::Mbc1 if self.banking_mode => (self.ram_bank << 5) as usize,
+                        _ => 0,
+                    };
+                    let addr = (bank * ROM_BANK_SIZE) + (address as usize);
+                    self.rom.get(addr).copied().unwrap_or(0xFF)
                 }
             }
-            0x0100..=0x3FFF => {
-                // ROM Bank 0 (or higher banks in RAM banking mode)
-                let bank = if self.banking_mode {
-                    // In RAM banking mode, upper 2 bits can be applied to bank 0 access
-                    (self.ram_bank << 5) as usize
-                } else {
-                    0
+            // ROM Bank 1-N (switchable via the MBC). MBC1-3 never select bank 0
+            // here (their bank registers turn 0 into 1); MBC5 can.
+            0x4..=0x7 => {
+                let bank = match self.mbc {
+                    // Combine 5-bit ROM bank with 2-bit RAM bank (used as upper ROM bits)
+                    Mbc::Mbc1 => self.rom_bank as usize | ((self.ram_bank as usize) << 5),
+                    Mbc::Mbc2 | Mbc::Mbc3 | Mbc::Mbc5 => self.rom_bank as usize,
                 };
-                let addr = (bank * 0x4000) + (address as usize);
-                self.rom.get(addr).copied().unwrap_or(0xFF)
-            }
-            // ROM Bank 1-N (switchable via MBC1)
-            0x4000..=0x7FFF => {
-                // Combine 5-bit ROM bank with 2-bit RAM bank (used as upper ROM bits)
-                let bank = (self.rom_bank | (self.ram_bank << 5)) as usize;
-                // Bank 0 is not allowed for this region, treat as bank 1
-                let effective_bank = if bank == 0 { 1 } else { bank };
-                let addr = (effective_bank * 0x4000) + ((address - 0x4000) as usize);
+                let addr = (bank * ROM_BANK_SIZE) + ((address - 0x4000) as usize);
                 self.rom.get(addr).copied().unwrap_or(0xFF)
             }
             // Video RAM
-            0x8000..=0x9FFF => {
+            0x8 | 0x9 => {
                 self.vram[(address - 0x8000) as usize]
             }
-            // External RAM (MBC1 controlled)
-            0xA000..=0xBFFF => {
+            // External RAM (or an MBC3 RTC register)
+            0xA | 0xB => {
                 if !self.ram_enabled {
                     return 0xFF;
                 }
-                let bank = if self.banking_mode { self.ram_bank } else { 0 };
-                let addr = ((bank as usize) * 0x2000) + ((address - 0xA000) as usize);
+                if self.rtc_selected() {
+                    return self.rtc.read(self.ram_bank);
+                }
                 // Clamp to available RAM
-                if addr < self.eram.len() {
-                    self.eram[addr]
-                } else {
-                    0xFF
+                match self.eram_index(address) {
+                    // MBC2 RAM is 4 bits wide; the upper nibble reads as 1s
+                    Some(index) if self.mbc == Mbc::Mbc2 => self.eram[index] | 0xF0,
+                    Some(index) => self.eram[index],
+                    None => 0xFF,
                 }
             }
-            // Work RAM
-            0xC000..=0xDFFF => {
-                self.wram[(address - 0xC000) as usize]
-            }
-            // Echo RAM (mirror of WRAM)
-            0xE000..=0xFDFF => {
-                self.wram[(address - 0xE000) as usize]
-            }
-            // Object Attribute Memory
-            0xFE00..=0xFE9F => {
-                self.oam[(address - 0xFE00) as usize]
+            // Work RAM, and the start of Echo RAM (mirror of WRAM)
+            0xC..=0xE => {
+                self.wram[(address & 0x1FFF) as usize]
             }
-            // Unusable memory
-            0xFEA0..=0xFEFF => 0xFF,
-            // I/O Registers
-            0xFF00..=0xFF7F => {
-                // Special handling for LY register in Gameboy Doctor mode
-                if self.doctor_mode && address == 0xFF44 {
-                    0x90
-                } else {
-                    self.io_registers[(address - 0xFF00) as usize]
+            // 0xF000-0xFFFF holds the rest of Echo RAM and every small region
+            _ => match address {
+                // Echo RAM (mirror of WRAM)
+                0xF000..=0xFDFF => {
+                    self.wram[(address & 0x1FFF) as usize]
                 }
-            }
-            // High RAM
-            0xFF80..=0xFFFE => {
-                self.hram[(address - 0xFF80) as usize]
-            }
-            // Interrupt Enable register
-            0xFFFF => self.ie,
+                // Object Attribute Memory
+                0xFE00..=0xFE9F => {
+                    self.oam[(address - 0xFE00) as usize]
+                }
+                // Unusable memory
+                0xFEA0..=0xFEFF => 0xFF,
+                // I/O Registers
+                0xFF00..=0xFF7F => {
+                    if address == 0xFF00 {
+                        // Joypad register is composed from the select lines and live input
+                        self.read_joypad()
+                    } else if self.doctor_mode && address == 0xFF44 {
+                        // Special handling for LY register in Gameboy Doctor mode
+                        0x90
+                    } else if address == 0xFF41 {
+                        // STAT - bit 7 is unused and reads as 1. The mode and
+                        // coincidence bits are kept live by the PPU every dot.
+                        self.io_registers[0x41] | 0x80
+                    } else if (0xFF10..=0xFF2F).contains(&address) {
+                        // Sound registers read back with their write-only bits set
+                        self.io_registers[(address - 0xFF00) as usize]
+                            | AUDIO_READ_MASK[(address - 0xFF10) as usize]
+                    } else {
+                        self.io_registers[(address - 0xFF00) as usize]
+                    }
+                }
+                // High RAM
+                0xFF80..=0xFFFE => {
+                    self.hram[(address - 0xFF80) as usize]
+                }
+                // Interrupt Enable register
+                _ => self.ie,
+            },
         }
     }
     
-    /// This writes a byte to memory at the given address. Some regions
-    /// are read-only (like ROM) and writes to them may trigger special behavior.
+    /// This writes a byte to memory as the CPU sees it. Writes outside HRAM are
+    /// dropped while OAM DMA is running, matching the read-side bus conflict.
     pub fn write_byte(&mut self, address: u16, value: u8) {
-        match address {
-            // MBC1: RAM Enable (0x0000-0x1FFF)
-            0x0000..=0x1FFF => {
-                // Writing 0x0A to this range enables RAM, anything else disables it
-                self.ram_enabled = (value & 0x0F) == 0x0A;
-            }
-            // MBC1: ROM Bank Number (0x2000-0x3FFF)
-            0x2000..=0x3FFF => {
-                // Lower 5 bits select ROM bank (1-31)
-                let bank = value & 0x1F;
-                // Bank 0 is treated as bank 1
-                self.rom_bank = if bank == 0 { 1 } else { bank };
-            }
-            // MBC1: RAM Bank Number or Upper ROM Bank bits (0x4000-0x5FFF)
-            0x4000..=0x5FFF => {
-                // Lower 2 bits - used as RAM bank or upper ROM bank bits
-                self.ram_bank = value & 0x03;
+        if let Some(trace) = &self.access_trace {
+            trace.borrow_mut().record(address, value, AccessKind::Write);
+        }
+        if self.dma_blocks(address) {
+            // With accurate DMA, only the bus the transfer is using (and OAM) is blocked,
+            // so I/O writes like IF, IE, LCDC or a DMA restart through 0xFF46 go through
+            let bus = memory_bus(address);
+            if !self.accurate_dma || bus == self.dma_source_bus() || bus == MemoryBus::Oam {
+                return;
             }
-            // MBC1: Banking Mode Select (0x6000-0x7FFF)
-            0x6000..=0x7FFF => {
-                // 0 = ROM banking mode (default), 1 = RAM banking mode
-                self.banking_mode = (value & 0x01) == 0x01;
+        }
+        self.poke_byte(address, value);
+    }
+    
+    /// This writes a byte to memory at the given address without any bus conflicts.
+    /// Some regions are read-only (like ROM) and writes to them may trigger special behavior.
+    pub fn poke_byte(&mut self, address: u16, value: u8) {
+        match address {
+            // MBC registers (writes to ROM never change it)
+            0x0000..=0x7FFF => {
+                if self.log_rom_writes && !self.is_banking_write(address) {
+                    self.rom_writes.push(RomWrite { address, value });
+                }
+                match self.mbc {
+                    Mbc::Mbc1 => self.write_mbc1(address, value),
+                    Mbc::Mbc2 => self.write_mbc2(address, value),
+                    Mbc::Mbc3 => self.write_mbc3(address, value),
+                    Mbc::Mbc5 => self.write_mbc5(address, value),
+                }
             }
             // Video RAM
             0x8000..=0x9FFF => {
                 self.vram[(address - 0x8000) as usize] = value;
             }
-            // External RAM (MBC1 controlled)
+            // External RAM (or an MBC3 RTC register)
             0xA000..=0xBFFF => {
                 if !self.ram_enabled {
                     return;
                 }
-                let bank = if self.banking_mode { self.ram_bank } else { 0 };
-                let addr = ((bank as usize) * 0x2000) + ((address - 0xA000) as usize);
+                if self.rtc_selected() {
+                    self.rtc.write(self.ram_bank, value);
+                    return;
+                }
                 // Only write if within RAM bounds
-                if addr < self.eram.len() {
-                    self.eram[addr] = value;
+                if let Some(index) = self.eram_index(address) {
+                    // MBC2 RAM only stores the low nibble
+                    self.eram[index] = if self.mbc == Mbc::Mbc2 { value & 0x0F } else { value };
                 }
             }
             // Work RAM
             0xC000..=0xDFFF => {
                 self.wram[(address - 0xC000) as usize] = value;
             }
-            // Echo RAM (writes go to WRAM)
+            // Echo RAM (writes go to WRAM). It ends at 0xFDFF -> wram[0x1DFF]; the last
+            // 512 bytes of WRAM aren't mirrored because 0xFE00 already belongs to OAM
             0xE000..=0xFDFF => {
                 self.wram[(address - 0xE000) as usize] = value;
             }
@@ -252,38 +609,95 @@ pub struct Mmu {
             // I/O Registers
             0xFF00..=0xFF7F => {
                 // Special handling for certain registers
-                if address == 0xFF01 {
+                if address == 0xFF00 {
+                    // Joypad (P1) - only the select lines (bits 4-5) are writable.
+                    // Selecting a group with a button held pulls its line low too.
+                    let lines = self.read_joypad();
+                    self.joypad_select = value & 0x30;
+                    self.request_joypad_interrupt(lines);
+                } else if address == 0xFF01 {
                     // Serial Data (SB) - Blargg tests write ASCII characters here
                     // We accumulate them in serial_output for test result reading
                     self.io_registers[0x01] = value;
-                    if value >= 0x20 && value <= 0x7E {
+                    if (0x20..=0x7E).contains(&value) {
                         // Only accumulate printable ASCII characters
                         self.serial_output.push(value as char);
                     }
                 } else if address == 0xFF02 {
-                    // Serial Control (SC) - writing 0x81 triggers a transfer
-                    // For test ROMs, we just acknowledge the write
-                    self.io_registers[0x02] = value;
-                    // Clear transfer flag after "transfer" completes instantly
-                    if value & 0x80 != 0 {
-                        self.io_registers[0x02] = value & 0x7F;
+                    // Serial Control (SC) - writing 0x81 starts an internal-clock transfer.
+                    // With the external clock (bit 0 clear) and no link peer, no clock
+                    // pulses ever arrive, so the transfer stays pending forever.
+                    self.io_registers[0x02] = value | 0x7E;
+                    self.serial_reply = None;
+                    self.serial_reply_wait = 0;
+                    if value & 0x81 == 0x81 && let Some(link) = self.serial_link.as_mut() {
+                        // Linked: the peer gets our byte now and answers with its own
+                        link.send(LinkMessage::Transfer(self.io_registers[0x01]));
+                    }
+                    if value & 0x81 == 0x81 && self.fast_serial && self.serial_link.is_some() {
+                        // No bit timing: the transfer completes once the peer answers
+                        self.serial_bits_left = 0;
+                        self.serial_reply_wait = SERIAL_REPLY_TIMEOUT;
+                    } else if value & 0x81 == 0x81 && self.fast_serial {
+                        // The whole byte shifts out at once: SB fills with the 1s
+                        // of the floating line and the transfer completes now
+                        self.io_registers[0x01] = 0xFF;
+                        self.io_registers[0x02] &= 0x7F;
+                        self.serial_bits_left = 0;
+                        crate::interrupts::request_interrupt(self, crate::interrupts::INT_SERIAL);
+                    } else if value & 0x81 == 0x81 {
+                        self.serial_bits_left = 8;
+                        self.serial_counter = SERIAL_CYCLES_PER_BIT;
+                    } else {
+                        self.serial_bits_left = 0;
                     }
                 } else if address == 0xFF04 {
-                    // Writing ANY value to DIV (0xFF04) resets it to 0
-                    self.io_registers[(address - 0xFF00) as usize] = 0;
+                    // Writing ANY value to DIV (0xFF04) resets it
+                    self.reset_div();
+                } else if address == 0xFF41 {
+                    // STAT - the mode (bits 0-1) and coincidence flag (bit 2) are
+                    // read-only, only the interrupt source selects can be written
+                    self.io_registers[0x41] = (value & 0x78) | (self.io_registers[0x41] & 0x07);
+                    self.update_stat_line();
+                } else if address == 0xFF44 {
+                    // LY is read-only: only the PPU changes it (`set_ly`)
+                } else if address == 0xFF45 {
+                    // LYC - the LY==LYC comparison is redone right away, so a game
+                    // setting LYC to the current line sees the flag (and interrupt) now
+                    self.io_registers[0x45] = value;
+                    self.update_lyc_coincidence();
+                } else if address == 0xFF26 {
+                    // NR52 (sound on/off) - only bit 7 (APU power) is writable.
+                    // Bits 4-6 are unused and read as 1, bits 0-3 are read-only channel status.
+                    if value & 0x80 == 0 {
+                        // Powering off clears every sound register (NR10-NR51)
+                        self.io_registers[0x10..=0x25].fill(0);
+                        self.io_registers[0x26] = 0x70;
+                    } else {
+                        self.io_registers[0x26] = 0xF0 | (self.io_registers[0x26] & 0x0F);
+                    }
+                } else if (0xFF10..=0xFF25).contains(&address) && self.io_registers[0x26] & 0x80 == 0 {
+                    // Sound registers ignore writes while the APU is powered off
                 } else if address == 0xFF46 {
                     // Writing to 0xFF46 (DMA register) starts OAM DMA transfer
                     // The value written is the source address divided by 0x100
                     // Transfer copies 160 bytes from source to OAM (0xFE00-0xFE9F)
-                    self.dma_source = (value as u16) << 8;  // Convert to full address
-                    self.dma_active = true;
-                    self.dma_progress = 0;
+                    // after a one-M-cycle startup delay (see tick_dma)
+                    self.dma_starting = Some(((value as u16) << 8, 1));  // Convert to full address
                     self.io_registers[(address - 0xFF00) as usize] = value;
+                } else if address == 0xFF47 {
+                    // BGP (background palette) - optionally record changes for debugging
+                    if self.log_palette && value != self.io_registers[0x47] {
+                        self.bgp_changes.push(value);
+                    }
+                    self.io_registers[0x47] = value;
                 } else if address == 0xFF50 && value != 0 {
                     // Writing to 0xFF50 disables boot ROM
                     self.boot_rom_enabled = false;
                     self.io_registers[(address - 0xFF00) as usize] = value;
                 } else {
+                    // Plain read/write registers, including SCY/SCX (0xFF42/43) and
+                    // WY/WX (0xFF4A/4B), which the PPU reads live while fetching
                     self.io_registers[(address - 0xFF00) as usize] = value;
                 }
             }
@@ -311,28 +725,52 @@ pub struct Mmu {
         self.write_byte(address.wrapping_add(1), (value >> 8) as u8);
     }
     
-    /// This advances OAM DMA by one M-cycle if a transfer is active.
+    /// This returns true when an active OAM DMA transfer keeps the CPU from
+    /// reaching the given address (everything except HRAM at 0xFF80-0xFFFE)
+    fn dma_blocks(&self, address: u16) -> bool {
+        self.dma_active && !(0xFF80..=0xFFFE).contains(&address)
+    }
+    
+    /// This advances OAM DMA by one M-cycle.
     /// OAM DMA transfers one byte per M-cycle from source to OAM.
-    /// The transfer takes 160 M-cycles total (160 bytes: 0xFE00-0xFE9F).
+    /// The first M-cycle after the 0xFF46 write is a startup delay: nothing is
+    /// copied for the new transfer and the CPU isn't blocked by it yet, while a
+    /// previous transfer keeps running. The copy itself then takes 160 M-cycles
+    /// (160 bytes: 0xFE00-0xFE9F). The CPU keeps running the whole time - it just
+    /// can't reach anything but HRAM until the copy is done.
     pub fn tick_dma(&mut self) {
-        // We check if DMA transfer is currently active
-        if !self.dma_active {
-            return;
+        match self.dma_starting {
+            // A transfer whose startup delay is over replaces the old one and
+            // copies its first byte right away
+            Some((source, 0)) => {
+                self.dma_starting = None;
+                self.dma_source = source;
+                self.dma_active = true;
+                self.dma_progress = 0;
+                self.copy_dma_byte();
+            }
+            Some((source, delay)) => {
+                self.dma_starting = Some((source, delay - 1));
+                if self.dma_active {
+                    self.copy_dma_byte();
+                }
+            }
+            None => {
+                if self.dma_active {
+                    self.copy_dma_byte();
+                }
+            }
         }
-        
+    }
+    
+    /// This copies the next byte of the active transfer to OAM
+    fn copy_dma_byte(&mut self) {
         // We calculate the source and destination addresses for this byte
-        let source_addr = ((self.dma_source as u16) << 8) | (self.dma_progress as u16);
+        let source_addr = self.dma_source | (self.dma_progress as u16);
         
         // We read from source and write to OAM
-        // Note: We need to read directly from memory regions to avoid recursion
-        let byte = match source_addr {
-            0x0000..=0x7FFF => self.rom.get(source_addr as usize).copied().unwrap_or(0xFF),
-            0x8000..=0x9FFF => self.vram[(source_addr - 0x8000) as usize],
-            0xA000..=0xBFFF => self.eram[(source_addr - 0xA000) as usize],
-            0xC000..=0xDFFF => self.wram[(source_addr - 0xC000) as usize],
-            0xE000..=0xFDFF => self.wram[(source_addr - 0xE000) as usize],
-            _ => 0xFF,
-        };
+        let byte = self.dma_read(source_addr);
+        self.dma_bus_byte = byte;
         
         // We write to OAM memory
         self.oam[self.dma_progress as usize] = byte;
@@ -341,15 +779,1226 @@ pub struct Mmu {
         self.dma_progress += 1;
         
         // When we've transferred all 160 bytes, DMA is complete
-        if self.dma_progress >= 160 {
+        if self.dma_progress >= OAM_DMA_LENGTH {
             self.dma_active = false;
         }
     }
     
+    /// This returns the bus the current DMA reads from (sources at 0xE000 and up
+    /// are WRAM mirrors, so they're on the external bus)
+    fn dma_source_bus(&self) -> MemoryBus {
+        if self.dma_source >= 0xE000 {
+            MemoryBus::External
+        } else {
+            memory_bus(self.dma_source)
+        }
+    }
+    
+    /// This reads a byte the way the DMA unit sees it: the normal memory map (with
+    /// ROM/RAM banking), except 0xFE00-0xFFFF which mirrors WRAM like echo RAM does
+    fn dma_read(&self, address: u16) -> u8 {
+        if address >= 0xFE00 {
+            self.wram[(address & 0x1FFF) as usize]
+        } else {
+            self.peek_byte(address)
+        }
+    }
+    
+    /// This advances an internal-clock serial transfer by one M-cycle. Each bit
+    /// shifts SB left and shifts in the received bit; with no cable connected the
+    /// line floats high, so SB ends up 0xFF. When all 8 bits are done, SC bit 7
+    /// clears and the serial interrupt is requested. With a link cable, SB instead
+    /// becomes the peer's byte once it has arrived, and the link is polled for the
+    /// peer's transfers every M-cycle (see serial.rs).
+    pub fn tick_serial(&mut self) {
+        if self.serial_link.is_some() {
+            self.poll_serial_link();
+        }
+        if self.serial_reply_wait > 0 {
+            self.serial_reply_wait -= 1;
+            if self.serial_reply_wait == 0 {
+                // The peer never answered: the line floated high, as with no cable
+                self.finish_serial_transfer(0xFF);
+            }
+            return;
+        }
+        if self.serial_bits_left == 0 {
+            return;
+        }
+        
+        self.serial_counter -= 1;
+        if self.serial_counter > 0 {
+            return;
+        }
+        self.serial_counter = SERIAL_CYCLES_PER_BIT;
+        
+        self.serial_bits_left -= 1;
+        if self.serial_link.is_some() {
+            if self.serial_bits_left == 0 {
+                match self.serial_reply.take() {
+                    Some(byte) => self.finish_serial_transfer(byte),
+                    None => self.serial_reply_wait = SERIAL_REPLY_TIMEOUT,
+                }
+            }
+            return;
+        }
+        
+        self.io_registers[0x01] = (self.io_registers[0x01] << 1) | 0x01;
+        if self.serial_bits_left == 0 {
+            self.io_registers[0x02] &= 0x7F;
+            crate::interrupts::request_interrupt(self, crate::interrupts::INT_SERIAL);
+        }
+    }
+    
+    /// This plugs a link cable into the serial port (or unplugs it with None)
+    pub fn set_serial_link(&mut self, link: Option<Box<dyn SerialLink>>) {
+        self.serial_link = link;
+    }
+    
+    /// This unplugs the link cable and returns it, e.g. to move it to a new machine
+    pub fn take_serial_link(&mut self) -> Option<Box<dyn SerialLink>> {
+        self.serial_link.take()
+    }
+    
+    /// This handles one message from the link peer, if there is one
+    fn poll_serial_link(&mut self) {
+        let Some(message) = self.serial_link.as_mut().and_then(|link| link.receive()) else {
+            return;
+        };
+        // Our own internal-clock transfer is in flight until its byte comes back
+        let clocking = self.serial_bits_left > 0 || self.serial_reply_wait > 0;
+        match message {
+            LinkMessage::Transfer(byte) | LinkMessage::Reply(byte) if clocking => {
+                // A `Transfer` here means both sides clocked at once; either way
+                // this is the peer's byte for our transfer
+                if self.serial_bits_left > 0 {
+                    self.serial_reply = Some(byte);
+                } else {
+                    self.finish_serial_transfer(byte);
+                }
+            }
+            LinkMessage::Transfer(byte) => {
+                // The peer clocked a byte out: our shift register swaps bytes with
+                // it, and a transfer waiting for the external clock completes
+                let ours = self.io_registers[0x01];
+                if let Some(link) = self.serial_link.as_mut() {
+                    link.send(LinkMessage::Reply(ours));
+                }
+                self.io_registers[0x01] = byte;
+                if self.io_registers[0x02] & 0x80 != 0 {
+                    self.io_registers[0x02] &= 0x7F;
+                    crate::interrupts::request_interrupt(self, crate::interrupts::INT_SERIAL);
+                }
+            }
+            // A reply that came after we gave up waiting
+            LinkMessage::Reply(_) => {}
+        }
+    }
+    
+    /// This completes a linked internal-clock transfer with the byte received
+    fn finish_serial_transfer(&mut self, received: u8) {
+        self.io_registers[0x01] = received;
+        self.io_registers[0x02] &= 0x7F;
+        self.serial_bits_left = 0;
+        self.serial_reply = None;
+        self.serial_reply_wait = 0;
+        crate::interrupts::request_interrupt(self, crate::interrupts::INT_SERIAL);
+    }
+    
+    /// This handles a write to the MBC1 registers (0x0000-0x7FFF)
+    fn write_mbc1(&mut self, address: u16, value: u8) {
+        match address {
+            // RAM Enable (0x0000-0x1FFF)
+            0x0000..=0x1FFF => {
+                // Writing 0x0A to this range enables RAM, anything else disables it
+                self.ram_enabled = (value & 0x0F) == 0x0A;
+            }
+            // ROM Bank Number (0x2000-0x3FFF)
+            0x2000..=0x3FFF => {
+                // Lower 5 bits select ROM bank (1-31)
+                let bank = (value & 0x1F) as u16;
+                // Bank 0 is treated as bank 1
+                let old = self.rom_bank;
+                self.rom_bank = if bank == 0 { 1 } else { bank };
+                self.record_bank_switch(BankRegister::Rom, old, self.rom_bank);
+            }
+            // RAM Bank Number or Upper ROM Bank bits (0x4000-0x5FFF)
+            0x4000..=0x5FFF => {
+                // Lower 2 bits - used as RAM bank or upper ROM bank bits
+                let old = self.ram_bank;
+                self.ram_bank = value & 0x03;
+                self.record_bank_switch(BankRegister::Ram, old as u16, self.ram_bank as u16);
+            }
+            // Banking Mode Select (0x6000-0x7FFF)
+            _ => {
+                // 0 = ROM banking mode (default), 1 = RAM banking mode
+                let old = self.banking_mode;
+                self.banking_mode = (value & 0x01) == 0x01;
+                self.record_bank_switch(BankRegister::Mode, old as u16, self.banking_mode as u16);
+            }
+        }
+    }
+    
+    /// This handles a write to the MBC2 registers (0x0000-0x3FFF; 0x4000-0x7FFF
+    /// does nothing). Address bit 8 picks the register: clear for RAM enable,
+    /// set for the ROM bank, so e.g. 0x0000 enables RAM and 0x2100 switches banks.
+    fn write_mbc2(&mut self, address: u16, value: u8) {
+        if address >= 0x4000 {
+            return;
+        }
+        if address & 0x0100 == 0 {
+            self.ram_enabled = (value & 0x0F) == 0x0A;
+        } else {
+            // Lower 4 bits select the ROM bank (1-15), 0 is treated as 1
+            let bank = (value & 0x0F) as u16;
+            let old = self.rom_bank;
+            self.rom_bank = if bank == 0 { 1 } else { bank };
+            self.record_bank_switch(BankRegister::Rom, old, self.rom_bank);
+        }
+    }
+    
+    /// This handles a write to the MBC3 registers (0x0000-0x7FFF)
+    fn write_mbc3(&mut self, address: u16, value: u8) {
+        match address {
+            // RAM and RTC Enable (0x0000-0x1FFF)
+            0x0000..=0x1FFF => {
+                self.ram_enabled = (value & 0x0F) == 0x0A;
+            }
+            // ROM Bank Number (0x2000-0x3FFF)
+            0x2000..=0x3FFF => {
+                // All 7 bits select the ROM bank (1-127). Like MBC1, writing 0
+                // selects bank 1 (Pan Docs); only MBC5 can map bank 0 here.
+                let bank = (value & 0x7F) as u16;
+                let old = self.rom_bank;
+                self.rom_bank = if bank == 0 { 1 } else { bank };
+                self.record_bank_switch(BankRegister::Rom, old, self.rom_bank);
+            }
+            // RAM Bank Number (0x00-0x03) or RTC Register Select (0x08-0x0C)
+            0x4000..=0x5FFF => {
+                let old = self.ram_bank;
+                self.ram_bank = value;
+                self.record_bank_switch(BankRegister::Ram, old as u16, self.ram_bank as u16);
+            }
+            // Latch Clock Data (0x6000-0x7FFF)
+            _ => self.rtc.write_latch(value),
+        }
+    }
+    
+    /// This handles a write to the MBC5 registers (0x0000-0x7FFF)
+    fn write_mbc5(&mut self, address: u16, value: u8) {
+        match address {
+            // RAM Enable (0x0000-0x1FFF)
+            0x0000..=0x1FFF => {
+                self.ram_enabled = (value & 0x0F) == 0x0A;
+            }
+            // ROM Bank Number, low 8 bits (0x2000-0x2FFF). Bank 0 stays bank 0.
+            0x2000..=0x2FFF => {
+                let old = self.rom_bank;
+                self.rom_bank = (self.rom_bank & 0x100) | value as u16;
+                self.record_bank_switch(BankRegister::Rom, old, self.rom_bank);
+            }
+            // ROM Bank Number, bit 8 (0x3000-0x3FFF)
+            0x3000..=0x3FFF => {
+                let old = self.rom_bank;
+                self.rom_bank = (self.rom_bank & 0xFF) | ((value as u16 & 0x01) << 8);
+                self.record_bank_switch(BankRegister::Rom, old, self.rom_bank);
+            }
+            // RAM Bank Number (0x4000-0x5FFF), 4 bits
+            0x4000..=0x5FFF => {
+                let old = self.ram_bank;
+                self.ram_bank = value & 0x0F;
+                self.record_bank_switch(BankRegister::Ram, old as u16, self.ram_bank as u16);
+            }
+            // Nothing is mapped at 0x6000-0x7FFF
+            _ => {}
+        }
+    }
+    
+    /// This returns whether 0xA000-0xBFFF currently maps to an MBC3 RTC register
+    fn rtc_selected(&self) -> bool {
+        self.mbc == Mbc::Mbc3 && (RTC_SECONDS..=RTC_DAY_HIGH).contains(&self.ram_bank)
+    }
+    
+    /// This returns where in `eram` an access to `address` (0xA000-0xBFFF) lands
+    /// with the current RAM bank, or None past the end of the cartridge's RAM
+    fn eram_index(&self, address: u16) -> Option<usize> {
+        if self.mbc == Mbc::Mbc2 {
+            // The 512 half-bytes repeat across the whole area
+            return Some((address as usize - 0xA000) % MBC2_RAM_SIZE);
+        }
+        let bank = match self.mbc {
+            Mbc::Mbc1 if self.banking_mode => self.ram_bank,
+            Mbc::Mbc1 | Mbc::Mbc2 => 0,
+            Mbc::Mbc3 | Mbc::Mbc5 => self.ram_bank,
+        };
+        let index = (bank as usize * RAM_BANK_SIZE) + (address - 0xA000) as usize;
+        (index < self.eram.len()).then_some(index)
+    }
+    
+    /// This returns the currently selected ROM bank register (MBC1: 1-31, MBC2: 1-15,
+    /// MBC3: 1-127, MBC5: 0-511)
+    pub fn rom_bank(&self) -> u16 {
+        self.rom_bank
+    }
+    
+    /// This returns the currently selected RAM bank / upper ROM bits register
+    /// (0-3, or 0x08-0x0C when an MBC3 RTC register is selected)
+    pub fn ram_bank(&self) -> u8 {
+        self.ram_bank
+    }
+    
+    /// This returns whether external RAM is enabled for reads and writes
+    pub fn ram_enabled(&self) -> bool {
+        self.ram_enabled
+    }
+    
+    /// This returns the MBC1 banking mode (false = ROM mode, true = RAM mode)
+    pub fn banking_mode(&self) -> bool {
+        self.banking_mode
+    }
+    
+    /// This stores the live joypad state from the input handler. The game sees it
+    /// through the select lines the next time it reads 0xFF00, so the main loop only
+    /// needs to commit it once per frame instead of writing the register itself.
+    /// A newly pressed button in a selected group requests the joypad interrupt.
+    pub fn set_joypad_state(&mut self, state: u8) {
+        let lines = self.read_joypad();
+        self.joypad_state = state;
+        self.request_joypad_interrupt(lines);
+    }
+
+    /// This returns the cartridge ROM
+    pub fn rom(&self) -> &[u8] {
+        &self.rom
+    }
+
+    /// This returns whether the cartridge header names a battery, so its RAM
+    /// should be kept between sessions
+    pub fn has_battery(&self) -> bool {
+        mbc::has_battery(self.rom.get(0x0147).copied().unwrap_or(0))
+    }
+
+    /// This returns the cartridge RAM (all banks), as a battery save stores it
+    pub fn cartridge_ram(&self) -> &[u8] {
+        &self.eram
+    }
+
+    /// This fills the cartridge RAM from a battery save. A shorter save (e.g. a
+    /// 512-byte MBC2 save) fills the start; bytes past the RAM size are ignored.
+    pub fn load_cartridge_ram(&mut self, data: &[u8]) {
+        let length = data.len().min(self.eram.len());
+        self.eram[..length].copy_from_slice(&data[..length]);
+    }
+
+    /// This returns what a battery save file holds: the cartridge RAM, followed
+    /// by the clock footer on MBC3 cartridges with an RTC (see mbc.rs)
+    pub fn battery_save(&self) -> Vec<u8> {
+        let mut data = self.eram.clone();
+        if mbc::has_rtc(self.rom.get(0x0147).copied().unwrap_or(0)) {
+            data.extend_from_slice(&self.rtc.to_save_bytes());
+        }
+        data
+    }
+
+    /// This restores a battery save from `battery_save`. A clock footer is
+    /// recognized by the save being 48 bytes longer than a whole number of RAM
+    /// banks, which also covers saves of RTC cartridges without RAM.
+    pub fn load_battery_save(&mut self, data: &[u8]) {
+        let ram_length = data.len() - data.len() % RAM_BANK_SIZE;
+        if data.len() % RAM_BANK_SIZE == mbc::RTC_SAVE_SIZE {
+            let mut footer = [0; mbc::RTC_SAVE_SIZE];
+            footer.copy_from_slice(&data[ram_length..]);
+            self.rtc.load_save_bytes(&footer);
+            self.load_cartridge_ram(&data[..ram_length]);
+        } else {
+            self.load_cartridge_ram(data);
+        }
+    }
+
+    /// This writes the memory, banking, DMA, serial and joypad state to a save
+    /// state. The ROMs aren't saved (they come from the loaded cartridge), and
+    /// neither are settings and debugging aids (doctor mode, DMA accuracy, logs,
+    /// the access trace, serial output already printed, the link cable).
+    pub fn save_state(&self, state: &mut StateWriter) {
+        state.bool(self.boot_rom_enabled);
+        state.bytes(&self.vram);
+        state.vec(&self.eram);
+        state.bytes(&self.wram);
+        state.bytes(&self.oam);
+        state.bytes(&self.io_registers);
+        state.bytes(&self.hram);
+        state.u8(self.ie);
+
+        state.bool(self.ram_enabled);
+        state.u16(self.rom_bank);
+        state.u8(self.ram_bank);
+        state.bool(self.banking_mode);
+        self.rtc.save_state(state);
+
+        state.bool(self.dma_active);
+        state.u16(self.dma_source);
+        state.u8(self.dma_progress);
+        state.bool(self.dma_starting.is_some());
+        let (starting_source, starting_delay) = self.dma_starting.unwrap_or((0, 0));
+        state.u16(starting_source);
+        state.u8(starting_delay);
+        state.u8(self.dma_bus_byte);
+
+        state.u8(self.serial_bits_left);
+        state.u16(self.serial_counter);
+        state.bool(self.serial_reply.is_some());
+        state.u8(self.serial_reply.unwrap_or(0));
+        state.u32(self.serial_reply_wait);
+        state.bool(self.div_reset_pending);
+        state.bool(self.stat_line);
+        state.u8(self.joypad_select);
+        state.u8(self.joypad_state);
+    }
+
+    /// This restores what `save_state` wrote
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), String> {
+        self.boot_rom_enabled = state.bool()?;
+        state.bytes(&mut self.vram)?;
+        let eram = state.vec()?;
+        if eram.len() != self.eram.len() {
+            return Err(format!(
+                "Save state has {} bytes of cartridge RAM, expected {}",
+                eram.len(),
+                self.eram.len()
+            ));
+        }
+        self.eram = eram;
+        state.bytes(&mut self.wram)?;
+        state.bytes(&mut self.oam)?;
+        state.bytes(&mut self.io_registers)?;
+        state.bytes(&mut self.hram)?;
+        self.ie = state.u8()?;
+
+        self.ram_enabled = state.bool()?;
+        self.rom_bank = state.u16()?;
+        self.ram_bank = state.u8()?;
+        self.banking_mode = state.bool()?;
+        self.rtc.load_state(state)?;
+
+        self.dma_active = state.bool()?;
+        self.dma_source = state.u16()?;
+        self.dma_progress = state.u8()?;
+        let starting = state.bool()?;
+        let starting_source = state.u16()?;
+        let starting_delay = state.u8()?;
+        self.dma_starting = starting.then_some((starting_source, starting_delay));
+        self.dma_bus_byte = state.u8()?;
+
+        self.serial_bits_left = state.u8()?;
+        self.serial_counter = state.u16()?;
+        let has_reply = state.bool()?;
+        let reply = state.u8()?;
+        self.serial_reply = has_reply.then_some(reply);
+        self.serial_reply_wait = state.u32()?;
+        self.div_reset_pending = state.bool()?;
+        self.stat_line = state.bool()?;
+        self.joypad_select = state.u8()?;
+        self.joypad_state = state.u8()?;
+        Ok(())
+    }
+
+    /// This requests the joypad interrupt if any input line (0xFF00 bits 0-3) went
+    /// from high to low since `before` was read, which is what wakes a game
+    /// waiting for input in HALT or STOP
+    fn request_joypad_interrupt(&mut self, before: u8) {
+        if before & !self.read_joypad() & 0x0F != 0 {
+            crate::interrupts::request_interrupt(self, crate::interrupts::INT_JOYPAD);
+        }
+    }
+    
+    /// This composes the joypad register (0xFF00) value on every CPU read, so a
+    /// game polling it several times sees the latest select lines and the state
+    /// last committed by `set_joypad_state`. Bits 6-7 always read as 1,
+    /// bits 4-5 are the select lines, and the low nibble is the selected group (active-low).
+    /// With both groups selected, a line reads low if a button in either group is pressed.
+    fn read_joypad(&self) -> u8 {
+        let dpad = self.joypad_state & 0x0F;
+        let buttons = self.joypad_state >> 4;
+        let nibble = match self.joypad_select {
+            // P14 and P15 low: both groups drive the lines, so pressed bits combine
+            0x00 => dpad & buttons,
+            // P14 low: d-pad selected
+            0x20 => dpad,
+            // P15 low: action buttons selected
+            0x10 => buttons,
+            // Nothing selected: all lines read high
+            _ => 0x0F,
+        };
+        0xC0 | self.joypad_select | nibble
+    }
+    
+    /// This resets DIV to 0, along with the timer's internal counters (picked up
+    /// on the timer's next tick). A write to 0xFF04 does this, and so does STOP.
+    pub fn reset_div(&mut self) {
+        self.io_registers[0x04] = 0;
+        self.div_reset_pending = true;
+    }
+    
     /// This increments the DIV register directly without triggering the reset logic.
     /// Used by the timer to update DIV every 256 CPU cycles.
     pub fn increment_div(&mut self) {
         // DIV is at 0xFF04, which maps to io_registers[0x04]
         self.io_registers[0x04] = self.io_registers[0x04].wrapping_add(1);
     }
+    
+    /// This stores the PPU's current line in LY (0xFF44), which the CPU can only
+    /// read, and redoes the LY==LYC comparison
+    pub fn set_ly(&mut self, ly: u8) {
+        self.io_registers[0x44] = ly;
+        self.update_lyc_coincidence();
+    }
+    
+    /// This compares LY with LYC and updates the STAT coincidence flag (bit 2),
+    /// then the STAT interrupt line. LY changes (`set_ly`) and LYC writes call
+    /// it. Nothing is compared while the LCD is off.
+    fn update_lyc_coincidence(&mut self) {
+        if self.io_registers[0x40] & 0x80 == 0 {
+            return;
+        }
+        if self.io_registers[0x44] == self.io_registers[0x45] {
+            self.io_registers[0x41] |= 0x04;
+        } else {
+            self.io_registers[0x41] &= !0x04;
+        }
+        self.update_stat_line();
+    }
+    
+    /// This sets the PPU mode STAT reports (bits 0-1) and updates the STAT
+    /// interrupt line, which may request the LCD STAT interrupt on mode entry
+    pub fn set_stat_mode(&mut self, mode: u8) {
+        self.io_registers[0x41] = (self.io_registers[0x41] & !0x03) | (mode & 0x03);
+        self.update_stat_line();
+    }
+    
+    /// This recomputes the STAT interrupt line from the enable bits (3-6) and the
+    /// current mode and coincidence flag, requesting the LCD STAT interrupt only
+    /// on a rising edge ("STAT blocking"). The line is held low while the LCD is off.
+    fn update_stat_line(&mut self) {
+        let stat = self.io_registers[0x41];
+        let mode_source = match stat & 0x03 {
+            0 => stat & 0x08 != 0, // HBlank
+            1 => stat & 0x10 != 0, // VBlank
+            2 => stat & 0x20 != 0, // OAM search
+            _ => false,            // Mode 3 has no source
+        };
+        let coincidence_source = stat & 0x44 == 0x44;
+        let line = self.io_registers[0x40] & 0x80 != 0 && (mode_source || coincidence_source);
+        if line && !self.stat_line {
+            crate::interrupts::request_interrupt(self, crate::interrupts::INT_LCD_STAT);
+        }
+        self.stat_line = line;
+    }
+    
+    /// This returns whether a write to the ROM area is a command for the
+    /// cartridge's bank controller. ROM-only cartridges have none, so every write
+    /// there is stray.
+    fn is_banking_write(&self, address: u16) -> bool {
+        let cartridge_type = self.rom.get(0x0147).copied().unwrap_or(0);
+        !mbc::is_rom_only(cartridge_type) && self.mbc.has_register_at(address)
+    }
+    
+    /// This records a bank switch for `--rom-bank-log` if logging is on and the bank changed
+    fn record_bank_switch(&mut self, register: BankRegister, from: u16, to: u16) {
+        if self.log_banking && from != to {
+            self.bank_switches.push(BankSwitch { register, from, to });
+        }
+    }
+    
+    /// This decodes sprite `index` (0-39) from OAM. It reads the array directly,
+    /// so it works during DMA and in any PPU mode (it's for the renderer and tools).
+    pub fn oam_entry(&self, index: usize) -> OamEntry {
+        let bytes = &self.oam[index * 4..index * 4 + 4];
+        OamEntry {
+            y: bytes[0],
+            x: bytes[1],
+            tile: bytes[2],
+            flags: bytes[3],
+        }
+    }
+    
+    /// This starts recording CPU bus accesses (`read_byte`/`write_byte`), keeping
+    /// the most recent `capacity` of them. Any previous trace is discarded.
+    pub fn enable_access_trace(&mut self, capacity: usize) {
+        self.access_trace = Some(RefCell::new(AccessTrace::new(capacity)));
+    }
+    
+    /// This stops recording accesses and drops the trace
+    pub fn disable_access_trace(&mut self) {
+        self.access_trace = None;
+    }
+    
+    /// This returns whether accesses are being recorded
+    pub fn access_trace_enabled(&self) -> bool {
+        self.access_trace.is_some()
+    }
+    
+    /// This tells the trace which instruction the following accesses belong to.
+    /// The CPU calls it before each opcode fetch; it does nothing when tracing is off.
+    pub fn set_trace_pc(&self, pc: u16) {
+        if let Some(trace) = &self.access_trace {
+            trace.borrow_mut().pc = pc;
+        }
+    }
+    
+    /// This returns the recorded accesses, oldest first (empty when tracing is off)
+    pub fn recent_accesses(&self) -> Vec<MemoryAccess> {
+        match &self.access_trace {
+            Some(trace) => trace.borrow().accesses().copied().collect(),
+            None => Vec::new(),
+        }
+    }
+    
+    /// This returns (and clears) the ROM area writes that weren't banking commands
+    /// recorded since the last call
+    pub fn take_rom_writes(&mut self) -> Vec<RomWrite> {
+        std::mem::take(&mut self.rom_writes)
+    }
+    
+    /// This returns (and clears) the bank switches recorded since the last call
+    pub fn take_bank_switches(&mut self) -> Vec<BankSwitch> {
+        std::mem::take(&mut self.bank_switches)
+    }
+    
+    /// This returns (and clears) the BGP values that changed the palette since the last call
+    pub fn take_bgp_changes(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.bgp_changes)
+    }
+    
+    /// This returns whether an OAM DMA transfer is still in progress
+    pub fn dma_active(&self) -> bool {
+        self.dma_active
+    }
+    
+    /// This reports (and clears) whether DIV was written since the last call
+    pub fn take_div_reset(&mut self) -> bool {
+        std::mem::take(&mut self.div_reset_pending)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn joypad_read_follows_the_latest_committed_state() {
+        let mut mmu = Mmu::new(vec![0; 0x8000]);
+        // Select the action buttons once, like a game polling the joypad
+        mmu.write_byte(0xFF00, 0x10);
+        assert_eq!(mmu.read_byte(0xFF00), 0xDF);
+
+        // A held: the next read sees it without the register being written again
+        mmu.set_joypad_state(0xEF);
+        assert_eq!(mmu.read_byte(0xFF00), 0xDE);
+
+        mmu.set_joypad_state(0xFF);
+        assert_eq!(mmu.read_byte(0xFF00), 0xDF);
+    }
+    #[test]
+    fn hram_routine_runs_during_dma_while_wram_is_blocked() {
+        let mut mmu = Mmu::new(vec![0; 0x8000]);
+        mmu.write_byte(0xC000, 0x42);
+        // LD A,$05 / LDH ($90),A / LD A,($C000)
+        for (offset, byte) in [0x3E, 0x05, 0xE0, 0x90, 0xFA, 0x00, 0xC0].iter().enumerate() {
+            mmu.write_byte(0xFF80 + offset as u16, *byte);
+        }
+        mmu.write_byte(0xFF46, 0xC0);
+        // The startup delay, then the first byte
+        mmu.tick_dma();
+        mmu.tick_dma();
+
+        let mut cpu = crate::cpu::Cpu::new();
+        cpu.registers.pc = 0xFF80;
+        for _ in 0..3 {
+            cpu.tick(&mut mmu).expect("tick failed");
+        }
+
+        assert_eq!(cpu.registers.pc, 0xFF87);
+        assert_eq!(mmu.read_byte(0xFF90), 0x05);
+        assert_eq!(cpu.registers.a, DMA_CONFLICT_VALUE);
+        assert_eq!(mmu.read_byte(0xC000), DMA_CONFLICT_VALUE);
+    }
+
+    #[test]
+    fn nr52_power_off_clears_sound_registers_and_ignores_writes() {
+        let mut mmu = Mmu::new(vec![0; 0x8000]);
+        mmu.write_byte(0xFF11, 0x80);
+        mmu.write_byte(0xFF24, 0x77);
+
+        mmu.write_byte(0xFF26, 0x00);
+        // Unused bits 4-6 still read as 1 with the APU off
+        assert_eq!(mmu.read_byte(0xFF26), 0x70);
+        assert_eq!(mmu.read_byte(0xFF24), 0x00);
+
+        // NR11 is ignored while powered off and stays cleared after powering back on
+        // (its length bits are write-only and read as 1)
+        mmu.write_byte(0xFF11, 0xC0);
+        mmu.write_byte(0xFF26, 0x80);
+        assert_eq!(mmu.read_byte(0xFF26), 0xF0);
+        assert_eq!(mmu.read_byte(0xFF11), 0x3F);
+
+        mmu.write_byte(0xFF11, 0xC0);
+        assert_eq!(mmu.read_byte(0xFF11), 0xFF);
+    }
+
+    #[test]
+    fn joypad_reads_every_select_line_combination() {
+        let mut mmu = Mmu::new(vec![0; 0x8000]);
+        // Right, Up, A and B held (active-low): d-pad nibble 0xA, buttons 0xC
+        mmu.set_joypad_state(0xCA);
+
+        for (select, expected) in [
+            (0x00, 0xC8), // both groups: pressed bits from either read low
+            (0x10, 0xDC), // P15 low: buttons
+            (0x20, 0xEA), // P14 low: d-pad
+            (0x30, 0xFF), // nothing selected: all lines high
+        ] {
+            mmu.write_byte(0xFF00, select);
+            assert_eq!(mmu.read_byte(0xFF00), expected, "select bits 0x{:02X}", select);
+        }
+    }
+
+    #[test]
+    fn serial_transfer_shifts_in_ones_and_interrupts_after_eight_bits() {
+        let mut mmu = Mmu::new(vec![0; 0x8000]);
+        mmu.write_byte(0xFF0F, 0x00);
+        mmu.write_byte(0xFF01, 0x00);
+        mmu.write_byte(0xFF02, 0x81);
+
+        for bit in 1..=8u32 {
+            for _ in 0..SERIAL_CYCLES_PER_BIT {
+                mmu.tick_serial();
+            }
+            // Each bit shifts one floating-high 1 in from the right
+            assert_eq!(mmu.read_byte(0xFF01), (0xFFu16 >> (8 - bit)) as u8, "bit {}", bit);
+            let done = bit == 8;
+            assert_eq!(mmu.read_byte(0xFF02) & 0x80 == 0, done, "bit {}", bit);
+            assert_eq!(mmu.read_byte(0xFF0F) & crate::interrupts::INT_SERIAL != 0, done, "bit {}", bit);
+        }
+    }
+
+    #[test]
+    fn reads_hit_the_right_region_across_the_map() {
+        let mut rom = vec![0; 0x8000];
+        for (address, value) in [(0x0000, 0x01), (0x3FFF, 0x02), (0x4000, 0x03), (0x7FFF, 0x04)] {
+            rom[address] = value;
+        }
+        let mut mmu = Mmu::new(rom);
+        for (address, value) in [
+            (0x8000, 0x10), (0x9FFF, 0x11), // VRAM
+            (0xC000, 0x20), (0xDDFF, 0x22), (0xDFFF, 0x21), // WRAM
+            (0xFE00, 0x30), (0xFE9F, 0x31), // OAM
+            (0xFF06, 0x40),                 // TMA
+            (0xFF80, 0x50), (0xFFFE, 0x51), // HRAM
+            (0xFFFF, 0x1F),                 // IE
+        ] {
+            mmu.write_byte(address, value);
+        }
+
+        for (address, expected) in [
+            (0x0000, 0x01), (0x3FFF, 0x02), (0x4000, 0x03), (0x7FFF, 0x04),
+            (0x8000, 0x10), (0x9FFF, 0x11),
+            (0xA000, 0xFF), (0xBFFF, 0xFF), // no cartridge RAM
+            (0xC000, 0x20), (0xDDFF, 0x22), (0xDFFF, 0x21),
+            (0xE000, 0x20), (0xFDFF, 0x22), // echo RAM
+            (0xFE00, 0x30), (0xFE9F, 0x31),
+            (0xFEA0, 0xFF), (0xFEFF, 0xFF), // unusable
+            (0xFF06, 0x40),
+            (0xFF80, 0x50), (0xFFFE, 0x51),
+            (0xFFFF, 0x1F),
+        ] {
+            assert_eq!(mmu.read_byte(address), expected, "read of 0x{:04X}", address);
+        }
+    }
+
+    #[test]
+    fn bank_switches_are_recorded_once_per_change() {
+        let mut mmu = Mmu::new(vec![0; 0x20000]);
+        mmu.write_byte(0x2000, 0x05); // not logging yet
+        assert!(mmu.take_bank_switches().is_empty());
+
+        mmu.log_banking = true;
+        mmu.write_byte(0x2000, 0x03);
+        mmu.write_byte(0x2000, 0x03); // re-selecting the current bank isn't a switch
+        mmu.write_byte(0x4000, 0x01);
+        mmu.write_byte(0x6000, 0x01);
+        assert_eq!(
+            mmu.take_bank_switches(),
+            vec![
+                BankSwitch { register: BankRegister::Rom, from: 5, to: 3 },
+                BankSwitch { register: BankRegister::Ram, from: 0, to: 1 },
+                BankSwitch { register: BankRegister::Mode, from: 0, to: 1 },
+            ]
+        );
+        assert!(mmu.take_bank_switches().is_empty());
+    }
+
+    #[test]
+    fn accurate_dma_reads_the_source_bus_byte_and_leaves_the_other_bus_usable() {
+        let mut mmu = Mmu::new(vec![0; 0x8000]);
+        mmu.accurate_dma = true;
+        for i in 0..0xA0u16 {
+            mmu.write_byte(0xC000 + i, i as u8 + 1);
+        }
+        mmu.write_byte(0x8000, 0x77);
+        mmu.write_byte(0xFF46, 0xC0);
+        // The startup delay, then three bytes
+        for _ in 0..4 {
+            mmu.tick_dma();
+        }
+
+        // WRAM and ROM are on the DMA bus: they show the byte just copied (0xC002)
+        assert_eq!(mmu.read_byte(0xD123), 0x03);
+        assert_eq!(mmu.read_byte(0x0150), 0x03);
+        // VRAM is on the other bus, OAM is blocked
+        assert_eq!(mmu.read_byte(0x8000), 0x77);
+        mmu.write_byte(0x8001, 0x55);
+        assert_eq!(mmu.read_byte(0x8001), 0x55);
+        assert_eq!(mmu.read_byte(0xFE00), DMA_CONFLICT_VALUE);
+        // Writes to the source bus are dropped
+        mmu.write_byte(0xC100, 0xAA);
+        assert_eq!(mmu.peek_byte(0xC100), 0x00);
+    }
+
+    #[test]
+    fn accurate_dma_lets_io_writes_through() {
+        let mut mmu = Mmu::new(vec![0; 0x8000]);
+        mmu.accurate_dma = true;
+        mmu.write_byte(0xFF46, 0xC0);
+        mmu.tick_dma();
+        mmu.tick_dma();
+
+        mmu.write_byte(0xFFFF, 0x05);
+        mmu.write_byte(0xFF0F, 0x04);
+        mmu.write_byte(0xFF40, 0x11);
+        assert_eq!(mmu.read_byte(0xFFFF), 0x05);
+        assert_eq!(mmu.read_byte(0xFF0F) & 0x1F, 0x04);
+        assert_eq!(mmu.read_byte(0xFF40), 0x11);
+
+        // Without accurate DMA, only HRAM is reachable
+        let mut mmu = Mmu::new(vec![0; 0x8000]);
+        mmu.write_byte(0xFF46, 0xC0);
+        mmu.tick_dma();
+        mmu.tick_dma();
+        mmu.write_byte(0xFF40, 0x11);
+        assert_eq!(mmu.peek_byte(0xFF40), 0x91);
+    }
+
+    #[test]
+    fn accurate_dma_can_be_restarted_mid_transfer() {
+        let mut mmu = Mmu::new(vec![0; 0x8000]);
+        mmu.accurate_dma = true;
+        for i in 0..0xA0u16 {
+            mmu.write_byte(0xC000 + i, 0x11);
+            mmu.write_byte(0xD000 + i, 0x22);
+        }
+        mmu.write_byte(0xFF46, 0xC0);
+        for _ in 0..10 {
+            mmu.tick_dma();
+        }
+        mmu.write_byte(0xFF46, 0xD0);
+        // The startup delay, then the whole second transfer
+        for _ in 0..=OAM_DMA_LENGTH {
+            mmu.tick_dma();
+        }
+
+        assert!(!mmu.dma_active);
+        assert!((0xFE00..=0xFE9F).all(|address| mmu.read_byte(address) == 0x22));
+    }
+
+    #[test]
+    fn dma_copies_first_byte_after_startup_delay() {
+        let mut mmu = Mmu::new(vec![0; 0x8000]);
+        mmu.write_byte(0xC000, 0x5A);
+        mmu.write_byte(0xFF46, 0xC0);
+
+        // The startup delay copies nothing and doesn't block the CPU yet
+        mmu.tick_dma();
+        assert_eq!(mmu.peek_byte(0xFE00), 0x00);
+        assert!(!mmu.dma_active());
+        assert_eq!(mmu.read_byte(0xC000), 0x5A);
+
+        mmu.tick_dma();
+        assert_eq!(mmu.peek_byte(0xFE00), 0x5A);
+        assert!(mmu.dma_active());
+    }
+
+    #[test]
+    fn echo_ram_mirrors_wram_up_to_fdff() {
+        let mut mmu = Mmu::new(vec![0; 0x8000]);
+        mmu.write_byte(0xDDFF, 0x42);
+        assert_eq!(mmu.read_byte(0xFDFF), 0x42);
+        mmu.write_byte(0xE000, 0x24);
+        assert_eq!(mmu.read_byte(0xC000), 0x24);
+
+        // 0xFE00 is OAM, not the WRAM byte at 0xDE00
+        mmu.write_byte(0xFE00, 0x99);
+        assert_eq!(mmu.read_byte(0xDE00), 0x00);
+        assert_eq!(mmu.read_byte(0xFE00), 0x99);
+    }
+
+    #[test]
+    fn oam_entry_decodes_a_sprites_bytes() {
+        let mut mmu = Mmu::new(vec![0; 0x8000]);
+        for (offset, value) in [0x20, 0x18, 0x42, 0xB0].into_iter().enumerate() {
+            mmu.write_byte(0xFE00 + offset as u16, value);
+        }
+
+        let sprite = mmu.oam_entry(0);
+        assert_eq!(sprite, OamEntry { y: 0x20, x: 0x18, tile: 0x42, flags: 0xB0 });
+        assert!(sprite.behind_background());
+        assert!(!sprite.y_flip());
+        assert!(sprite.x_flip());
+        assert!(sprite.use_obp1());
+        assert_eq!(mmu.oam_entry(1), OamEntry { y: 0, x: 0, tile: 0, flags: 0 });
+    }
+
+    #[test]
+    fn fresh_battery_ram_reads_zero_once_enabled() {
+        let mut rom = vec![0; 0x8000];
+        rom[0x0147] = 0x03; // MBC1+RAM+BATTERY
+        rom[0x0149] = 0x02; // 8KB
+        let mut mmu = Mmu::new(rom);
+        assert_eq!(mmu.read_byte(0xA000), 0xFF);
+
+        mmu.write_byte(0x0000, 0x0A);
+        assert_eq!(mmu.read_byte(0xA000), 0x00);
+        assert_eq!(mmu.read_byte(0xBFFF), 0x00);
+        mmu.write_byte(0xA123, 0x5A);
+        assert_eq!(mmu.read_byte(0xA123), 0x5A);
+
+        mmu.write_byte(0x0000, 0x00);
+        assert_eq!(mmu.read_byte(0xA123), 0xFF);
+        mmu.write_byte(0x0000, 0x0A);
+        assert_eq!(mmu.read_byte(0xA123), 0x5A);
+    }
+
+    #[test]
+    fn sound_registers_read_their_power_on_values() {
+        let mmu = Mmu::new(vec![0; 0x8000]);
+        // Pan Docs power-up values as read back through the masks
+        let expected = [
+            (0xFF10, 0x80), (0xFF11, 0xBF), (0xFF12, 0xF3), (0xFF13, 0xFF), (0xFF14, 0xBF),
+            (0xFF16, 0x3F), (0xFF17, 0x00), (0xFF18, 0xFF), (0xFF19, 0xBF),
+            (0xFF1A, 0x7F), (0xFF1B, 0xFF), (0xFF1C, 0x9F), (0xFF1D, 0xFF), (0xFF1E, 0xBF),
+            (0xFF20, 0xFF), (0xFF21, 0x00), (0xFF22, 0x00), (0xFF23, 0xBF),
+            (0xFF24, 0x77), (0xFF25, 0xF3), (0xFF26, 0xF1),
+        ];
+        for (address, value) in expected {
+            assert_eq!(mmu.peek_byte(address), value, "0x{:04X}", address);
+        }
+        for address in [0xFF15, 0xFF1F].into_iter().chain(0xFF27..=0xFF2F) {
+            assert_eq!(mmu.peek_byte(address), 0xFF, "0x{:04X}", address);
+        }
+    }
+
+    #[test]
+    fn sound_register_writes_read_back_through_the_mask() {
+        let mut mmu = Mmu::new(vec![0; 0x8000]);
+        mmu.write_byte(0xFF11, 0x80); // duty 2, length 0
+        assert_eq!(mmu.read_byte(0xFF11), 0xBF);
+        mmu.write_byte(0xFF13, 0x12); // frequency low is write-only
+        assert_eq!(mmu.read_byte(0xFF13), 0xFF);
+        mmu.write_byte(0xFF12, 0x5A); // NR12 reads back whole
+        assert_eq!(mmu.read_byte(0xFF12), 0x5A);
+    }
+
+    #[test]
+    fn access_trace_records_reads_and_writes_with_the_instruction_pc() {
+        // LD A,(0xC000); LD (0xC001),A
+        let mut rom = vec![0; 0x8000];
+        rom[0x0100..0x0106].copy_from_slice(&[0xFA, 0x00, 0xC0, 0xEA, 0x01, 0xC0]);
+        let mut mmu = Mmu::new(rom);
+        mmu.write_byte(0xC000, 0x42);
+        assert!(mmu.recent_accesses().is_empty());
+        mmu.enable_access_trace(16);
+
+        let mut cpu = crate::cpu::Cpu::new();
+        cpu.tick(&mut mmu).expect("LD A,(u16) failed");
+        cpu.tick(&mut mmu).expect("LD (u16),A failed");
+
+        let access = |pc, address, value, kind| MemoryAccess { pc, address, value, kind };
+        assert_eq!(mmu.recent_accesses(), [
+            access(0x0100, 0x0100, 0xFA, AccessKind::Read),
+            access(0x0100, 0x0101, 0x00, AccessKind::Read),
+            access(0x0100, 0x0102, 0xC0, AccessKind::Read),
+            access(0x0100, 0xC000, 0x42, AccessKind::Read),
+            access(0x0103, 0x0103, 0xEA, AccessKind::Read),
+            access(0x0103, 0x0104, 0x01, AccessKind::Read),
+            access(0x0103, 0x0105, 0xC0, AccessKind::Read),
+            access(0x0103, 0xC001, 0x42, AccessKind::Write),
+        ]);
+
+        mmu.disable_access_trace();
+        assert!(mmu.recent_accesses().is_empty());
+    }
+
+    #[test]
+    fn boot_rom_overlays_the_cartridge_until_ff50() {
+        let mut rom = vec![0; 0x8000];
+        rom[0x0000] = 0xC3;
+        rom[0x0100] = 0x00;
+        let mut boot = vec![0; BOOT_ROM_SIZE];
+        boot[0x0000] = 0x31;
+        let mut mmu = Mmu::with_boot_rom(rom, boot);
+
+        assert_eq!(mmu.read_byte(0x0000), 0x31);
+        // Only 0x0000-0x00FF is covered
+        assert_eq!(mmu.read_byte(0x0100), 0x00);
+
+        mmu.write_byte(0xFF50, 0x01);
+        assert_eq!(mmu.read_byte(0x0000), 0xC3);
+    }
+
+    /// This builds a 2MB MBC3+RAM cartridge (type 0x10, 32KB RAM) whose switchable
+    /// banks each start with their own bank number
+    fn mbc3_mmu() -> Mmu {
+        let mut rom = vec![0; 128 * ROM_BANK_SIZE];
+        rom[0x0147] = 0x10;
+        rom[0x0149] = 0x03;
+        for bank in 1..128 {
+            rom[bank * ROM_BANK_SIZE] = bank as u8;
+        }
+        Mmu::new(rom)
+    }
+
+    #[test]
+    fn mbc3_selects_a_7_bit_rom_bank_and_maps_bank_0_to_1() {
+        let mut mmu = mbc3_mmu();
+        mmu.write_byte(0x2000, 0x7F);
+        assert_eq!(mmu.read_byte(0x4000), 0x7F);
+        mmu.write_byte(0x2000, 0x25);
+        assert_eq!(mmu.read_byte(0x4000), 0x25);
+        mmu.write_byte(0x2000, 0x00);
+        assert_eq!(mmu.read_byte(0x4000), 0x01);
+        assert_eq!(mmu.rom_bank(), 1);
+    }
+
+    #[test]
+    fn mbc3_ram_banks_are_separate() {
+        let mut mmu = mbc3_mmu();
+        mmu.write_byte(0x0000, 0x0A);
+        mmu.write_byte(0x4000, 0x02);
+        mmu.write_byte(0xA000, 0x22);
+        mmu.write_byte(0x4000, 0x00);
+        mmu.write_byte(0xA000, 0x11);
+
+        assert_eq!(mmu.read_byte(0xA000), 0x11);
+        mmu.write_byte(0x4000, 0x02);
+        assert_eq!(mmu.read_byte(0xA000), 0x22);
+        mmu.write_byte(0x4000, 0x01);
+        assert_eq!(mmu.read_byte(0xA000), 0x00);
+    }
+
+    #[test]
+    fn mbc3_rtc_registers_read_the_latched_copy() {
+        let mut mmu = mbc3_mmu();
+        mmu.write_byte(0x0000, 0x0A);
+        mmu.write_byte(0x4000, RTC_SECONDS);
+        mmu.write_byte(0xA000, 42);
+        assert_eq!(mmu.read_byte(0xA000), 0);
+
+        // Only a 0x00 then 0x01 write latches
+        mmu.write_byte(0x6000, 0x01);
+        assert_eq!(mmu.read_byte(0xA000), 0);
+        mmu.write_byte(0x6000, 0x00);
+        mmu.write_byte(0x6000, 0x01);
+        assert_eq!(mmu.read_byte(0xA000), 42);
+    }
+
+    #[test]
+    fn mbc5_selects_a_9_bit_rom_bank_including_bank_0() {
+        let mut rom = vec![0; 512 * ROM_BANK_SIZE];
+        rom[0x0147] = 0x19;
+        for bank in 1..512 {
+            rom[bank * ROM_BANK_SIZE] = bank as u8;
+            rom[bank * ROM_BANK_SIZE + 1] = (bank >> 8) as u8;
+        }
+        let mut mmu = Mmu::new(rom);
+
+        mmu.write_byte(0x2000, 0xFF);
+        mmu.write_byte(0x3000, 0x01);
+        assert_eq!(mmu.rom_bank(), 0x1FF);
+        assert_eq!(mmu.read_byte(0x4000), 0xFF);
+        assert_eq!(mmu.read_byte(0x4001), 0x01);
+
+        // Clearing bit 8 keeps the low byte
+        mmu.write_byte(0x3000, 0x00);
+        assert_eq!(mmu.rom_bank(), 0xFF);
+        assert_eq!(mmu.read_byte(0x4001), 0x00);
+
+        // Bank 0 is mapped as itself, not as bank 1
+        mmu.write_byte(0x2000, 0x00);
+        assert_eq!(mmu.rom_bank(), 0);
+        assert_eq!(mmu.read_byte(0x4000), 0x00);
+        assert_eq!(mmu.read_byte(0x4147), 0x19);
+    }
+
+    #[test]
+    fn lyc_write_matching_ly_sets_coincidence_and_interrupt() {
+        let mut mmu = Mmu::new(vec![0; 0x8000]);
+        mmu.set_ly(0x10);
+        mmu.write_byte(0xFF45, 0x20);
+        mmu.write_byte(0xFF41, 0x40); // LYC=LY interrupt source
+        mmu.write_byte(0xFF0F, 0x00);
+        assert_eq!(mmu.read_byte(0xFF41) & 0x04, 0);
+
+        mmu.write_byte(0xFF45, 0x10);
+        assert_eq!(mmu.read_byte(0xFF41) & 0x04, 0x04);
+        assert_eq!(mmu.read_byte(0xFF0F) & crate::interrupts::INT_LCD_STAT, crate::interrupts::INT_LCD_STAT);
+    }
+
+    #[test]
+    fn mbc2_address_bit_8_selects_the_register() {
+        let mut rom = vec![0; 16 * ROM_BANK_SIZE];
+        rom[0x0147] = 0x06;
+        for bank in 1..16 {
+            rom[bank * ROM_BANK_SIZE] = bank as u8;
+        }
+        let mut mmu = Mmu::new(rom);
+
+        // Bit 8 set: ROM bank, only the low nibble counts and 0 maps to 1
+        mmu.write_byte(0x2100, 0x37);
+        assert_eq!(mmu.read_byte(0x4000), 0x07);
+        mmu.write_byte(0x0100, 0x00);
+        assert_eq!(mmu.rom_bank(), 1);
+
+        // Bit 8 clear: RAM enable, even in the 0x2000-0x3FFF half
+        mmu.write_byte(0x2000, 0x0A);
+        assert_eq!(mmu.rom_bank(), 1);
+        mmu.write_byte(0xA000, 0x05);
+        assert_eq!(mmu.read_byte(0xA000), 0xF5);
+        mmu.write_byte(0x0000, 0x00);
+        assert_eq!(mmu.read_byte(0xA000), 0xFF);
+    }
+
+    #[test]
+    fn mbc2_ram_keeps_the_low_nibble_and_repeats_every_512_bytes() {
+        let mut rom = vec![0; 0x8000];
+        rom[0x0147] = 0x06;
+        let mut mmu = Mmu::new(rom);
+        mmu.write_byte(0x0000, 0x0A);
+
+        mmu.write_byte(0xA010, 0xAB);
+        assert_eq!(mmu.read_byte(0xA010), 0xFB);
+        assert_eq!(mmu.read_byte(0xA210), 0xFB);
+        assert_eq!(mmu.read_byte(0xBE10), 0xFB);
+
+        mmu.write_byte(0xBFFF, 0x03);
+        assert_eq!(mmu.read_byte(0xA1FF), 0xF3);
+    }
+
+    #[test]
+    fn ie_keeps_all_8_bits_and_hram_is_plain_ram() {
+        let mut mmu = Mmu::new(vec![0; 0x8000]);
+        mmu.write_byte(0xFFFF, 0xFF);
+        assert_eq!(mmu.read_byte(0xFFFF), 0xFF);
+        mmu.write_byte(0xFFFF, 0xA5);
+        assert_eq!(mmu.read_byte(0xFFFF), 0xA5);
+
+        assert!((0xFF80..=0xFFFE).all(|address| mmu.read_byte(address) == 0));
+        mmu.write_byte(0xFF80, 0x12);
+        mmu.write_byte(0xFFFE, 0x34);
+        assert_eq!(mmu.read_byte(0xFF80), 0x12);
+        assert_eq!(mmu.read_byte(0xFFFE), 0x34);
+    }
+
+    #[test]
+    fn rom_writes_that_are_not_banking_commands_are_recorded() {
+        let mut rom_only = Mmu::new(vec![0; 0x8000]);
+        rom_only.log_rom_writes = true;
+        rom_only.write_byte(0x2000, 0x05);
+        assert_eq!(rom_only.take_rom_writes(), [RomWrite { address: 0x2000, value: 0x05 }]);
+        assert!(rom_only.take_rom_writes().is_empty());
+
+        // On MBC1 the same write selects a bank
+        let mut rom = vec![0; 8 * ROM_BANK_SIZE];
+        rom[0x0147] = 0x01;
+        rom[5 * ROM_BANK_SIZE] = 0x55;
+        let mut mbc1 = Mmu::new(rom);
+        mbc1.log_rom_writes = true;
+        mbc1.write_byte(0x2000, 0x05);
+        assert!(mbc1.take_rom_writes().is_empty());
+        assert_eq!(mbc1.read_byte(0x4000), 0x55);
+
+        // MBC5 has nothing at 0x6000-0x7FFF
+        let mut rom = vec![0; 0x8000];
+        rom[0x0147] = 0x19;
+        let mut mbc5 = Mmu::new(rom);
+        mbc5.log_rom_writes = true;
+        mbc5.write_byte(0x3000, 0x01);
+        mbc5.write_byte(0x6000, 0x01);
+        assert_eq!(mbc5.take_rom_writes(), [RomWrite { address: 0x6000, value: 0x01 }]);
+    }
+
+    #[test]
+    fn fast_serial_completes_the_transfer_when_sc_is_written() {
+        let mut mmu = Mmu::new(vec![0; 0x8000]);
+        mmu.fast_serial = true;
+        mmu.write_byte(0xFF0F, 0x00);
+        mmu.write_byte(0xFF01, b'K');
+        mmu.write_byte(0xFF02, 0x81);
+
+        assert_eq!(mmu.serial_output, "K");
+        assert_eq!(mmu.read_byte(0xFF01), 0xFF);
+        assert_eq!(mmu.read_byte(0xFF02) & 0x80, 0);
+        assert_eq!(mmu.read_byte(0xFF0F) & crate::interrupts::INT_SERIAL, crate::interrupts::INT_SERIAL);
+
+        // Nothing is left to shift afterwards
+        mmu.write_byte(0xFF0F, 0x00);
+        for _ in 0..SERIAL_CYCLES_PER_BIT * 8 {
+            mmu.tick_serial();
+        }
+        assert_eq!(mmu.read_byte(0xFF0F) & crate::interrupts::INT_SERIAL, 0);
+
+        // An external-clock transfer still waits for a peer
+        mmu.write_byte(0xFF02, 0x80);
+        assert_eq!(mmu.read_byte(0xFF02) & 0x80, 0x80);
+    }
+
+    #[test]
+    fn linked_machines_swap_bytes_over_a_channel_pair() {
+        let (a_end, b_end) = serial::channel_pair();
+        let mut master = Mmu::new(vec![0; 0x8000]);
+        let mut slave = Mmu::new(vec![0; 0x8000]);
+        master.set_serial_link(Some(Box::new(a_end)));
+        slave.set_serial_link(Some(Box::new(b_end)));
+        for mmu in [&mut master, &mut slave] {
+            mmu.write_byte(0xFF0F, 0x00);
+        }
+
+        // The slave waits on the external clock, the master clocks the transfer
+        slave.write_byte(0xFF01, 0x99);
+        slave.write_byte(0xFF02, 0x80);
+        master.write_byte(0xFF01, 0x42);
+        master.write_byte(0xFF02, 0x81);
+
+        // The slave's side completes as soon as the master's byte arrives
+        slave.tick_serial();
+        assert_eq!(slave.read_byte(0xFF01), 0x42);
+        assert_eq!(slave.read_byte(0xFF02) & 0x80, 0);
+        assert_eq!(slave.read_byte(0xFF0F) & crate::interrupts::INT_SERIAL, crate::interrupts::INT_SERIAL);
+
+        // The master still takes its 8 bits' worth of M-cycles
+        for _ in 0..SERIAL_CYCLES_PER_BIT * 8 - 1 {
+            master.tick_serial();
+        }
+        assert_eq!(master.read_byte(0xFF02) & 0x80, 0x80);
+        master.tick_serial();
+        assert_eq!(master.read_byte(0xFF01), 0x99);
+        assert_eq!(master.read_byte(0xFF02) & 0x80, 0);
+        assert_eq!(master.read_byte(0xFF0F) & crate::interrupts::INT_SERIAL, crate::interrupts::INT_SERIAL);
+    }
+
+    #[test]
+    fn link_messages_round_trip_through_their_wire_bytes() {
+        for message in [serial::LinkMessage::Transfer(0x42), serial::LinkMessage::Reply(0xFF)] {
+            assert_eq!(serial::LinkMessage::from_bytes(message.to_bytes()), Some(message));
+        }
+        assert_eq!(serial::LinkMessage::from_bytes([0x07, 0x00]), None);
+    }
 }
@@ -0,0 +1,150 @@
+// REMINDER: Read AGENTS.md file before continuing development
+//
+// Serial Link - Connecting two Game Boys
+//
+// This file holds the link cable between two emulator instances. The link works
+// on whole bytes rather than single clock pulses:
+//
+//   - The side using the internal clock (SC = 0x81) sends `Transfer(SB)` when the
+//     transfer starts. Its 8 bits still take 8 x 128 M-cycles; when they're done
+//     and the peer's `Reply` has arrived, SB becomes the peer's byte.
+//   - The other side answers every `Transfer` right away with `Reply(SB)` and
+//     takes the received byte into SB, like its shift register would when clocked
+//     from outside. If it had a transfer waiting for the external clock (SC = 0x80)
+//     that transfer completes.
+//   - If both sides start an internal-clock transfer at once, each one's
+//     `Transfer` is taken as the other's reply, so both still swap bytes.
+//
+// Either way the side whose transfer completes gets SC bit 7 cleared and the
+// serial interrupt requested (see `Mmu::tick_serial`). `channel_pair` links two
+// machines in the same process; `TcpLink` links two running emulators.
+
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::mpsc::{self, Receiver, Sender};
+
+/// A message sent over the link cable
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkMessage {
+    /// The sender clocked out this byte with its internal clock and wants the
+    /// receiver's byte back
+    Transfer(u8),
+    /// The receiver's byte, answering a `Transfer`
+    Reply(u8),
+}
+
+impl LinkMessage {
+    /// This encodes the message as its 2 bytes on the wire: a tag, then the data
+    pub fn to_bytes(self) -> [u8; 2] {
+        match self {
+            LinkMessage::Transfer(byte) => [0x01, byte],
+            LinkMessage::Reply(byte) => [0x02, byte],
+        }
+    }
+
+    /// This decodes a message from its 2 bytes on the wire
+    pub fn from_bytes(bytes: [u8; 2]) -> Option<Self> {
+        match bytes[0] {
+            0x01 => Some(LinkMessage::Transfer(bytes[1])),
+            0x02 => Some(LinkMessage::Reply(bytes[1])),
+            _ => None,
+        }
+    }
+}
+
+/// This trait is one end of a link cable. The MMU polls it once per M-cycle
+/// while linked, so `receive` must not block.
+pub trait SerialLink {
+    /// This sends a message to the other end
+    fn send(&mut self, message: LinkMessage);
+
+    /// This returns the next message from the other end, if one has arrived
+    fn receive(&mut self) -> Option<LinkMessage>;
+}
+
+/// This struct is one end of an in-process link made with `channel_pair`
+pub struct ChannelLink {
+    sender: Sender<LinkMessage>,
+    receiver: Receiver<LinkMessage>,
+}
+
+/// This creates two connected ends of an in-process link cable, e.g. to run two
+/// machines side by side in one program
+pub fn channel_pair() -> (ChannelLink, ChannelLink) {
+    let (a_sender, b_receiver) = mpsc::channel();
+    let (b_sender, a_receiver) = mpsc::channel();
+    (
+        ChannelLink { sender: a_sender, receiver: a_receiver },
+        ChannelLink { sender: b_sender, receiver: b_receiver },
+    )
+}
+
+impl SerialLink for ChannelLink {
+    fn send(&mut self, message: LinkMessage) {
+        // A dropped other end behaves like an unplugged cable
+        let _ = self.sender.send(message);
+    }
+
+    fn receive(&mut self) -> Option<LinkMessage> {
+        self.receiver.try_recv().ok()
+    }
+}
+
+/// This struct is a link cable over TCP. A background thread reads the peer's
+/// messages into a channel, so polling never waits on the network.
+pub struct TcpLink {
+    stream: TcpStream,
+    receiver: Receiver<LinkMessage>,
+    /// Cleared after the first failed send, so a lost peer is reported once
+    connected: bool,
+}
+
+impl TcpLink {
+    /// This waits for the other emulator to connect to `address` (e.g. 0.0.0.0:5000)
+    pub fn listen<A: ToSocketAddrs>(address: A) -> Result<Self, String> {
+        let listener = TcpListener::bind(address).map_err(|e| format!("Failed to listen for a link peer: {}", e))?;
+        let (stream, _) = listener
+            .accept()
+            .map_err(|e| format!("Failed to accept a link peer: {}", e))?;
+        TcpLink::new(stream)
+    }
+
+    /// This connects to an emulator listening at `address` (e.g. 192.168.1.2:5000)
+    pub fn connect<A: ToSocketAddrs>(address: A) -> Result<Self, String> {
+        let stream = TcpStream::connect(address).map_err(|e| format!("Failed to connect to the link peer: {}", e))?;
+        TcpLink::new(stream)
+    }
+
+    /// This starts the reader thread for a connected stream
+    fn new(stream: TcpStream) -> Result<Self, String> {
+        // Messages are 2 bytes and latency matters more than throughput
+        stream.set_nodelay(true).map_err(|e| e.to_string())?;
+        let mut reader = stream.try_clone().map_err(|e| e.to_string())?;
+        let (sender, receiver) = mpsc::channel();
+        std::thread::spawn(move || {
+            let mut bytes = [0; 2];
+            while reader.read_exact(&mut bytes).is_ok() {
+                match LinkMessage::from_bytes(bytes) {
+                    Some(message) if sender.send(message).is_ok() => {}
+                    Some(_) => return,
+                    None => eprintln!("Warning: ignoring unknown link message {:02X} {:02X}", bytes[0], bytes[1]),
+                }
+            }
+            eprintln!("Link peer disconnected");
+        });
+        Ok(TcpLink { stream, receiver, connected: true })
+    }
+}
+
+impl SerialLink for TcpLink {
+    fn send(&mut self, message: LinkMessage) {
+        if self.connected && let Err(e) = self.stream.write_all(&message.to_bytes()) {
+            eprintln!("Link peer lost: {}", e);
+            self.connected = false;
+        }
+    }
+
+    fn receive(&mut self) -> Option<LinkMessage> {
+        self.receiver.try_recv().ok()
+    }
+}
@@ -0,0 +1,95 @@
+// REMINDER: Read AGENTS.md file before continuing development
+//
+// Memory Access Trace - Recent CPU bus accesses
+//
+// This file holds the optional trace the MMU fills from `read_byte` and
+// `write_byte` (the CPU bus), for analyzing a game's access patterns in the
+// debugger. It keeps only the most recent accesses in a ring buffer; when the
+// trace is off the MMU skips it entirely.
+
+use std::collections::VecDeque;
+use std::fmt;
+
+/// Default number of accesses kept when tracing is turned on from the debugger
+pub const DEFAULT_TRACE_CAPACITY: usize = 4096;
+
+/// Whether an access read or wrote memory
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessKind {
+    Read,
+    Write,
+}
+
+/// One CPU bus access
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryAccess {
+    /// Address of the instruction that made the access
+    pub pc: u16,
+    pub address: u16,
+    /// Value read, or value the CPU tried to write
+    pub value: u8,
+    pub kind: AccessKind,
+}
+
+impl fmt::Display for MemoryAccess {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let kind = match self.kind {
+            AccessKind::Read => "R",
+            AccessKind::Write => "W",
+        };
+        write!(f, "PC=0x{:04X} {} [{:04X}] = {:02X}", self.pc, kind, self.address, self.value)
+    }
+}
+
+/// This struct keeps the most recent accesses, oldest first
+pub struct AccessTrace {
+    accesses: VecDeque<MemoryAccess>,
+    capacity: usize,
+    /// PC of the instruction being executed, set by the CPU before each fetch
+    pub pc: u16,
+}
+
+impl AccessTrace {
+    /// This creates an empty trace keeping at most `capacity` accesses
+    pub fn new(capacity: usize) -> Self {
+        let capacity = capacity.max(1);
+        AccessTrace {
+            accesses: VecDeque::with_capacity(capacity),
+            capacity,
+            pc: 0,
+        }
+    }
+
+    /// This records one access, dropping the oldest when the trace is full
+    pub fn record(&mut self, address: u16, value: u8, kind: AccessKind) {
+        if self.accesses.len() == self.capacity {
+            self.accesses.pop_front();
+        }
+        self.accesses.push_back(MemoryAccess {
+            pc: self.pc,
+            address,
+            value,
+            kind,
+        });
+    }
+
+    /// This returns the recorded accesses, oldest first
+    pub fn accesses(&self) -> impl Iterator<Item = &MemoryAccess> {
+        self.accesses.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn full_trace_drops_the_oldest_access() {
+        let mut trace = AccessTrace::new(2);
+        for address in 0xC000..0xC003 {
+            trace.record(address, 0, AccessKind::Read);
+        }
+        let addresses: Vec<u16> = trace.accesses().map(|access| access.address).collect();
+        assert_eq!(addresses, [0xC001, 0xC002]);
+    }
+}
@@ -6,6 +6,25 @@
 // It operates as a state machine with four states: OAM Search, Pixel Transfer,
 // HBlank, and VBlank. The PPU runs at 456 dots per scanline (154 scanlines per frame)
 // generating the 160x144 pixel display using tiles from VRAM.
+//
+// LCDC (0xFF40) is never cached: each bit is read at the point it matters, so
+// games can change it mid-frame or mid-line for raster effects:
+//   bit 7 (LCD enable)            every dot
+//   bit 5 (window enable)         during mode 2, for the WY latch (see below),
+//                                 then every dot until the window starts on a
+//                                 line; once it has, the rest of the line stays window
+//   bits 1-2 (OBJ enable, size)   at the OAM scan at the end of mode 2, so a
+//                                 change shows up from the next line
+//   bits 3 and 6 (BG/window map)  at every tile fetch (fetcher step 0)
+//   bit 4 (BG/window tile data)   at every tile data fetch (fetcher steps 1-2)
+//
+// The window has a second, per-frame gate: it can only appear once LY has equaled
+// WY (0xFF4A) during mode 2 of some line while the window was enabled. From then
+// on it can start on every line of the frame (wherever WX puts it), even if WY is
+// moved afterwards; a frame where LY never matches WY shows no window at all.
+
+use crate::mmu::{OamEntry, OAM_SPRITE_COUNT};
+use crate::savestate::{StateReader, StateWriter};
 
 /// PPU state machine states
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -20,6 +39,58 @@ pub enum PpuState {
     VBlank,
 }
 
+/// Sprites the PPU draws on one scanline at most (the rest are dropped in OAM order)
+pub const MAX_SPRITES_PER_LINE: usize = 10;
+
+/// A sprite picked by the OAM scan for the current line, with its row of pixels
+/// already fetched and flipped
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct LineSprite {
+    oam: OamEntry,
+    /// Color IDs of the sprite's row on this line, leftmost pixel first (0 = transparent)
+    pixels: [u8; 8],
+}
+
+impl LineSprite {
+    /// This returns the sprite's color ID at screen column `x`, if it covers it
+    fn pixel_at(&self, x: u8) -> Option<u8> {
+        let column = (x as u16 + 8).checked_sub(self.oam.x as u16)?;
+        self.pixels.get(column as usize).copied()
+    }
+}
+
+/// What a scanline hook sees at the end of each visible line
+pub struct Scanline<'a> {
+    /// Line that was just drawn (0-143)
+    pub ly: u8,
+    /// The line's 160 shades as written to the framebuffer
+    pub pixels: &'a [u8],
+    /// Register values when the line finished
+    pub scx: u8,
+    pub scy: u8,
+    pub lcdc: u8,
+    pub bgp: u8,
+}
+
+/// Callback run at the end of every visible scanline (see `Ppu::set_scanline_hook`)
+pub type ScanlineHook = Box<dyn FnMut(&Scanline)>;
+
+/// Dots in one scanline, whatever the split between modes
+pub const DOTS_PER_LINE: u16 = 456;
+
+/// How many dots a visible scanline spent in each mode. The three always add up
+/// to `DOTS_PER_LINE`: a longer mode 3 (scrolling, window, sprites) comes out of
+/// the HBlank budget.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LineTiming {
+    /// Mode 2 (OAM search)
+    pub oam_search: u16,
+    /// Mode 3 (pixel transfer)
+    pub pixel_transfer: u16,
+    /// Mode 0 (HBlank)
+    pub hblank: u16,
+}
+
 /// This struct represents the PPU's state including timing, current scanline,
 /// pixel FIFO, and the framebuffer that gets sent to the display
 pub struct Ppu {
@@ -32,6 +103,12 @@ pub struct Ppu {
     /// Current scanline being drawn (LY register, 0-153)
     ly: u8,
     
+    /// Dots run since the current frame started at line 0 (0-70223)
+    frame_dots: u32,
+    
+    /// Length in dots of the last complete frame (0 until one completes)
+    last_frame_dots: u32,
+    
     /// Current X position in scanline (0-159) - pixels pushed to screen
     x: u8,
     
@@ -42,6 +119,26 @@ pub struct Ppu {
     /// Pixel FIFO for background pixels (holds color IDs 0-3)
     bg_fifo: Vec<u8>,
     
+    /// Sprites on the current line from the OAM scan, in drawing priority order
+    line_sprites: Vec<LineSprite>,
+    
+    /// Background pixels still to drop at the start of the line for SCX's fine
+    /// scroll (SCX % 8), which also makes mode 3 that many dots longer. Also used
+    /// for the window pixels left of the screen when WX < 7.
+    scx_discard: u8,
+    
+    /// Whether the fetcher switched to the window on this line
+    fetching_window: bool,
+    
+    /// Window line counter: the row of the window drawn next. It only advances on
+    /// lines where the window was actually drawn, so a window hidden for a few
+    /// lines (WX moved off-screen, or LCDC bit 5 cleared) resumes where it left off.
+    window_line: u8,
+    
+    /// Set once LY has matched WY with the window enabled this frame; until then
+    /// the window can't start on any line. Cleared at the start of each frame.
+    window_triggered_this_frame: bool,
+    
     /// Tile data being fetched
     tile_id: u8,
     tile_data_low: u8,
@@ -50,8 +147,24 @@ pub struct Ppu {
     /// Framebuffer holding pixel data (160x144 pixels, 4 shades of gray)
     pub framebuffer: [u8; 160 * 144],
     
+    /// Mode durations of each visible line, as last drawn (for the timing overlay)
+    pub line_timings: [LineTiming; 144],
+    
     /// Frame complete flag
     frame_ready: bool,
+    
+    /// LCD enable state seen on the previous dot, to catch off->on transitions
+    lcd_was_on: bool,
+    
+    /// Set for the first frame after the LCD is switched on, which hardware doesn't show
+    warming_up: bool,
+    
+    /// Optional per-scanline callback for raster-effect debugging (None = no cost)
+    scanline_hook: Option<ScanlineHook>,
+    
+    /// Debugging aid: while set, `tick` does nothing, so LY, STAT and the
+    /// framebuffer hold still while the CPU and timer keep running
+    pub frozen: bool,
 }
 
 impl Ppu {
@@ -61,107 +174,164 @@ impl Ppu {
             state: PpuState::OamSearch,
             dots: 0,
             ly: 0,
+            frame_dots: 0,
+            last_frame_dots: 0,
             x: 0,
             fetcher_x: 0,
             fetcher_step: 0,
             bg_fifo: Vec::with_capacity(16),
+            line_sprites: Vec::with_capacity(MAX_SPRITES_PER_LINE),
+            scx_discard: 0,
+            fetching_window: false,
+            window_line: 0,
+            window_triggered_this_frame: false,
             tile_id: 0,
             tile_data_low: 0,
             tile_data_high: 0,
             framebuffer: [0; 160 * 144],
+            line_timings: [LineTiming::default(); 144],
             frame_ready: false,
+            // The boot ROM hands over with the LCD already on
+            lcd_was_on: true,
+            warming_up: false,
+            scanline_hook: None,
+            frozen: false,
         }
     }
     
+    /// This registers a callback run at the end of each visible scanline (when the
+    /// line's pixels are done, as mode 3 ends) with LY, the line's pixels and the
+    /// scroll/LCDC/BGP values at that point, so tools can record per-line register
+    /// state while diagnosing raster effects. It replaces any previous hook.
+    pub fn set_scanline_hook(&mut self, hook: ScanlineHook) {
+        self.scanline_hook = Some(hook);
+    }
+    
+    /// This removes the scanline hook
+    pub fn clear_scanline_hook(&mut self) {
+        self.scanline_hook = None;
+    }
+    
     /// This advances the PPU by one dot (T-cycle), updating its state and potentially
     /// rendering pixels. Returns true when a frame is complete (VBlank starts).
     pub fn tick(&mut self, mmu: &mut crate::mmu::Mmu) -> bool {
+        if self.frozen {
+            return false;
+        }
+        
         // Check if LCD is enabled (LCDC bit 7)
-        let lcdc = mmu.read_byte(0xFF40);
+        let lcdc = mmu.peek_byte(0xFF40);
         if (lcdc & 0x80) == 0 {
-            // LCD is off - don't advance PPU
+            // LCD is off - don't advance PPU. Switching it off resets the PPU to the
+            // start of line 0 with LY=0 and STAT reporting mode 0.
+            if self.lcd_was_on {
+                self.lcd_was_on = false;
+                self.state = PpuState::OamSearch;
+                self.dots = 0;
+                self.ly = 0;
+                self.frame_dots = 0;
+                self.window_line = 0;
+                self.window_triggered_this_frame = false;
+                mmu.set_ly(0);
+                mmu.set_stat_mode(0);
+            }
             return false;
         }
         
+        if !self.lcd_was_on {
+            // The LCD was just switched on: the first frame it draws is never shown
+            self.lcd_was_on = true;
+            self.warming_up = true;
+        }
+        
         self.dots += 1;
+        self.frame_dots += 1;
         
         // We handle each PPU mode based on current state
         match self.state {
             PpuState::OamSearch => {
-                // Mode 2: We scan OAM for sprites overlapping this scanline
-                // We update STAT register to show mode 2
-                let stat = mmu.read_byte(0xFF41);
-                let new_stat = (stat & 0xFC) | 0x02;
-                mmu.write_byte(0xFF41, new_stat);
-                
-                // Check if we should request STAT interrupt for mode 2
-                if (stat & 0x20) != 0 && (stat & 0x03) != 0x02 {
-                    // Mode 2 interrupt enabled and we just entered mode 2
-                    crate::interrupts::request_interrupt(mmu, crate::interrupts::INT_LCD_STAT);
+                // Mode 2: We scan OAM for sprites overlapping this scanline.
+                // LY matching WY here arms the window for the rest of the frame.
+                if lcdc & 0x20 != 0 && mmu.peek_byte(0xFF4A) == self.ly {
+                    self.window_triggered_this_frame = true;
                 }
-                
                 if self.dots >= 80 {
+                    self.line_timings[self.ly as usize].oam_search = self.dots;
+                    self.scan_oam(mmu);
                     self.state = PpuState::PixelTransfer;
                     self.x = 0;
                     self.fetcher_x = 0;
                     self.fetcher_step = 0;
                     self.bg_fifo.clear();
+                    self.scx_discard = mmu.peek_byte(0xFF43) & 0x07;
+                    self.fetching_window = false;
                 }
             }
             
             PpuState::PixelTransfer => {
                 // Mode 3: We fetch tiles and push pixels to the screen
-                // We update STAT register to show mode 3
-                let stat = mmu.read_byte(0xFF41);
-                mmu.write_byte(0xFF41, (stat & 0xFC) | 0x03);
-                
+                if !self.fetching_window && self.window_reached(mmu) {
+                    self.start_window(mmu);
+                }
                 self.fetch_pixel(mmu);
                 
                 // We try to push a pixel from FIFO to screen if we have enough
                 if !self.bg_fifo.is_empty() && self.x < 160 {
                     let color_id = self.bg_fifo.remove(0);
-                    let color = self.get_color(color_id, mmu);
-                    let index = (self.ly as usize * 160) + self.x as usize;
-                    self.framebuffer[index] = color;
-                    self.x += 1;
+                    if self.scx_discard > 0 {
+                        // Still scrolling past the first tile's leftmost pixels
+                        self.scx_discard -= 1;
+                    } else {
+                        let color = self.mix_sprites(color_id, mmu);
+                        let index = (self.ly as usize * 160) + self.x as usize;
+                        self.framebuffer[index] = color;
+                        self.x += 1;
+                    }
                 }
                 
                 // When we've rendered all 160 pixels, we move to HBlank
                 if self.x >= 160 {
                     self.state = PpuState::HBlank;
+                    let timing = &mut self.line_timings[self.ly as usize];
+                    timing.pixel_transfer = self.dots - timing.oam_search;
+                    if self.fetching_window {
+                        self.window_line += 1;
+                    }
+                    
+                    if let Some(hook) = self.scanline_hook.as_mut() {
+                        let start = self.ly as usize * 160;
+                        hook(&Scanline {
+                            ly: self.ly,
+                            pixels: &self.framebuffer[start..start + 160],
+                            scx: mmu.peek_byte(0xFF43),
+                            scy: mmu.peek_byte(0xFF42),
+                            lcdc: mmu.peek_byte(0xFF40),
+                            bgp: mmu.peek_byte(0xFF47),
+                        });
+                    }
                 }
             }
             
             PpuState::HBlank => {
                 // Mode 0: We wait until the scanline completes (456 dots total)
-                // We update STAT register to show mode 0
-                let stat = mmu.read_byte(0xFF41);
-                let new_stat = (stat & 0xFC) | 0x00;
-                mmu.write_byte(0xFF41, new_stat);
-                
-                // Check if we should request STAT interrupt for mode 0 (HBlank)
-                if (stat & 0x08) != 0 && (stat & 0x03) != 0x00 {
-                    // Mode 0 interrupt enabled and we just entered mode 0
-                    crate::interrupts::request_interrupt(mmu, crate::interrupts::INT_LCD_STAT);
-                }
-                
-                if self.dots >= 456 {
+                if self.dots >= DOTS_PER_LINE {
+                    let timing = &mut self.line_timings[self.ly as usize];
+                    timing.hblank = self.dots - timing.oam_search - timing.pixel_transfer;
                     self.dots = 0;
-                    self.ly += 1;
-                    mmu.write_byte(0xFF44, self.ly);  // Update LY register
+                    self.set_ly(self.ly + 1, mmu);
                     
                     // After scanline 143, we enter VBlank
                     if self.ly >= 144 {
                         self.state = PpuState::VBlank;
                         self.frame_ready = true;
+                        if self.warming_up {
+                            // The first frame after LCD enable is presented blank
+                            // (VBlank still fires, since the PPU timing is running)
+                            self.warming_up = false;
+                            self.framebuffer.fill(0);
+                        }
                         // Request VBlank interrupt
                         crate::interrupts::request_interrupt(mmu, crate::interrupts::INT_VBLANK);
-                        
-                        // Also check if mode 1 (VBlank) STAT interrupt is enabled
-                        let stat = mmu.read_byte(0xFF41);
-                        if (stat & 0x10) != 0 {
-                            crate::interrupts::request_interrupt(mmu, crate::interrupts::INT_LCD_STAT);
-                        }
                     } else {
                         self.state = PpuState::OamSearch;
                     }
@@ -170,31 +340,134 @@ impl Ppu {
             
             PpuState::VBlank => {
                 // Mode 1: We wait for remaining scanlines (144-153)
-                // We update STAT register to show mode 1
-                let stat = mmu.read_byte(0xFF41);
-                mmu.write_byte(0xFF41, (stat & 0xFC) | 0x01);
-                
                 if self.dots >= 456 {
                     self.dots = 0;
-                    self.ly += 1;
-                    mmu.write_byte(0xFF44, self.ly);  // Update LY register
                     
                     // After scanline 153, we restart from scanline 0
-                    if self.ly > 153 {
-                        self.ly = 0;
-                        mmu.write_byte(0xFF44, 0);
+                    if self.ly >= 153 {
+                        self.set_ly(0, mmu);
                         self.state = PpuState::OamSearch;
+                        self.last_frame_dots = self.frame_dots;
+                        self.frame_dots = 0;
+                        self.window_line = 0;
+                        self.window_triggered_this_frame = false;
+                    } else {
+                        self.set_ly(self.ly + 1, mmu);
                     }
                 }
             }
         }
         
+        // STAT follows the mode we're in now; entering a mode whose interrupt source
+        // is enabled raises the STAT line (see `Mmu::set_stat_mode`)
+        mmu.set_stat_mode(self.mode());
+        
         // We return and clear the frame_ready flag
         let ready = self.frame_ready;
         self.frame_ready = false;
         ready
     }
     
+    /// This moves to scanline `ly`, updating the LY register and the LY==LYC
+    /// coincidence flag (which may request the LCD STAT interrupt)
+    fn set_ly(&mut self, ly: u8, mmu: &mut crate::mmu::Mmu) {
+        self.ly = ly;
+        mmu.set_ly(ly);
+    }
+    
+    /// This does the mode 2 OAM scan: it picks the first 10 sprites (in OAM order)
+    /// whose rows cover LY, for 8x8 or 8x16 sprites as LCDC bit 2 selects, and
+    /// fetches each one's row with flipping applied. The list is then ordered by
+    /// DMG priority: lower X first, and OAM order among equal X. Nothing is picked
+    /// while sprites are off (LCDC bit 1).
+    fn scan_oam(&mut self, mmu: &crate::mmu::Mmu) {
+        self.line_sprites.clear();
+        let lcdc = mmu.peek_byte(0xFF40);
+        if lcdc & 0x02 == 0 {
+            return;
+        }
+        let height: u16 = if lcdc & 0x04 != 0 { 16 } else { 8 };
+        
+        for index in 0..OAM_SPRITE_COUNT {
+            let sprite = mmu.oam_entry(index);
+            // OAM Y is screen Y + 16, so row = LY - (Y - 16)
+            let Some(row) = (self.ly as u16 + 16).checked_sub(sprite.y as u16) else {
+                continue;
+            };
+            if row >= height {
+                continue;
+            }
+            
+            let row = if sprite.y_flip() { height - 1 - row } else { row };
+            // 8x16 sprites ignore bit 0 of the tile number: the top half is the
+            // even tile and the bottom half the odd one that follows it
+            let tile = if height == 16 { sprite.tile & 0xFE } else { sprite.tile };
+            // Sprites always use 0x8000 unsigned tile addressing
+            let address = 0x8000 + tile as u16 * 16 + row * 2;
+            let mut pixels = decode_tile_row(mmu.peek_byte(address), mmu.peek_byte(address + 1));
+            if sprite.x_flip() {
+                pixels.reverse();
+            }
+            
+            self.line_sprites.push(LineSprite { oam: sprite, pixels });
+            if self.line_sprites.len() == MAX_SPRITES_PER_LINE {
+                break;
+            }
+        }
+        
+        // A stable sort keeps OAM order for sprites at the same X
+        self.line_sprites.sort_by_key(|sprite| sprite.oam.x);
+    }
+    
+    /// This returns the shade of the pixel at the current X: the first opaque
+    /// sprite pixel in priority order, unless that sprite has the BG-over-OBJ
+    /// flag (bit 7) and the background color isn't 0. Otherwise the background.
+    fn mix_sprites(&self, bg_color_id: u8, mmu: &crate::mmu::Mmu) -> u8 {
+        let sprite = self.line_sprites.iter().find_map(|sprite| {
+            sprite
+                .pixel_at(self.x)
+                .filter(|&color_id| color_id != 0)
+                .map(|color_id| (sprite, color_id))
+        });
+        match sprite {
+            Some((sprite, color_id)) if !sprite.oam.behind_background() || bg_color_id == 0 => {
+                self.get_sprite_color(color_id, sprite.oam.use_obp1(), mmu)
+            }
+            _ => self.get_color(bg_color_id, mmu),
+        }
+    }
+    
+    /// This returns whether the window starts at the current pixel: LCDC bit 5 is
+    /// set, LY has matched WY (0xFF4A) earlier in the frame, and X has reached
+    /// WX - 7 (0xFF4B)
+    fn window_reached(&self, mmu: &crate::mmu::Mmu) -> bool {
+        let lcdc = mmu.peek_byte(0xFF40);
+        let wx = mmu.peek_byte(0xFF4B);
+        lcdc & 0x20 != 0 && self.window_triggered_this_frame && self.x as u16 + 7 >= wx as u16
+    }
+    
+    /// This switches the fetcher from the background to the window: the FIFO
+    /// and any fetch in progress are dropped and fetching restarts at the first
+    /// window tile. With WX < 7 the window starts left of the screen, so its
+    /// first 7 - WX pixels are dropped.
+    fn start_window(&mut self, mmu: &crate::mmu::Mmu) {
+        self.fetching_window = true;
+        self.bg_fifo.clear();
+        self.fetcher_x = 0;
+        self.fetcher_step = 0;
+        self.scx_discard = 7u8.saturating_sub(mmu.peek_byte(0xFF4B));
+    }
+    
+    /// This returns the row (0-7) of the tile being fetched: the window line
+    /// counter for the window, LY + SCY for the background
+    fn tile_line(&self, mmu: &crate::mmu::Mmu) -> u16 {
+        if self.fetching_window {
+            (self.window_line % 8) as u16
+        } else {
+            (self.ly.wrapping_add(mmu.peek_byte(0xFF42)) % 8) as u16
+        }
+    }
+    
     /// This implements the pixel fetcher state machine that reads tiles from VRAM
     /// and pushes pixel data into the FIFO (8 pixels at a time from each tile)
     fn fetch_pixel(&mut self, mmu: &crate::mmu::Mmu) {
@@ -210,46 +483,54 @@ impl Ppu {
         // 3: Push pixels to FIFO
         match self.fetcher_step {
             0 => {
-                // Step 0: We read the tile ID from the background tile map
-                let scx = mmu.read_byte(0xFF43); // Scroll X
-                let scy = mmu.read_byte(0xFF42); // Scroll Y
-                
-                // Calculate tile map position including scroll
-                let map_x = ((self.fetcher_x + (scx / 8)) % 32) as u16;
-                let map_y = (((self.ly + scy) / 8) % 32) as u16;
-                
-                // Read from tile map (we use $9800 map for now, LCDC.3 selects map)
-                let tile_map_addr = 0x9800 + (map_y * 32) + map_x;
-                self.tile_id = mmu.read_byte(tile_map_addr);
-                
-                // Debug: Show what we're fetching
-                if self.ly < 2 && self.fetcher_x < 5 {
-                    eprintln!("Line {}, fetcher_x={}: map_addr={:04X}, tile_id={:02X}", 
-                             self.ly, self.fetcher_x, tile_map_addr, self.tile_id);
-                }
+                // Step 0: We read the tile ID from the background or window tile map.
+                // SCX/SCY and the map select bits are read live here, so a change
+                // shows up from the next tile fetch.
+                let lcdc = mmu.peek_byte(0xFF40);
+                let tile_map_addr = if self.fetching_window {
+                    // The window isn't scrolled: it's drawn from its top-left tile,
+                    // from the map LCDC bit 6 selects
+                    let window_map = if lcdc & 0x40 != 0 { 0x9C00 } else { 0x9800 };
+                    let map_x = (self.fetcher_x % 32) as u16;
+                    let map_y = (self.window_line / 8) as u16;
+                    window_map + (map_y * 32) + map_x
+                } else {
+                    let scx = mmu.peek_byte(0xFF43); // Scroll X
+                    let scy = mmu.peek_byte(0xFF42); // Scroll Y
+                    
+                    // Calculate tile map position including scroll (SCX's fine part is
+                    // handled by dropping pixels when they leave the FIFO)
+                    let map_x = ((self.fetcher_x + (scx / 8)) % 32) as u16;
+                    let map_y = ((self.ly.wrapping_add(scy) / 8) % 32) as u16;
+                    
+                    // LCDC bit 3 selects the background map
+                    let bg_map = if lcdc & 0x08 != 0 { 0x9C00 } else { 0x9800 };
+                    bg_map + (map_y * 32) + map_x
+                };
+                self.tile_id = mmu.peek_byte(tile_map_addr);
                 
                 self.fetcher_step = 1;
             }
             
             1 => {
                 // Step 1: We read the low byte of tile data
-                let scy = mmu.read_byte(0xFF42);
-                let tile_line = ((self.ly + scy) % 8) as u16; // Which line of the tile (0-7)
+                let tile_line = self.tile_line(mmu); // Which line of the tile (0-7)
                 
-                // Calculate tile data address (we use $8000 addressing for now)
-                let tile_data_addr = 0x8000 + (self.tile_id as u16 * 16) + (tile_line * 2);
-                self.tile_data_low = mmu.read_byte(tile_data_addr);
+                // LCDC bit 4 picks unsigned (0x8000) or signed (0x9000) tile numbers
+                let lcdc = mmu.peek_byte(0xFF40);
+                let tile_data_addr = tile_data_address(lcdc, self.tile_id) + (tile_line * 2);
+                self.tile_data_low = mmu.peek_byte(tile_data_addr);
                 
                 self.fetcher_step = 2;
             }
             
             2 => {
                 // Step 2: We read the high byte of tile data
-                let scy = mmu.read_byte(0xFF42);
-                let tile_line = ((self.ly + scy) % 8) as u16;
+                let tile_line = self.tile_line(mmu);
                 
-                let tile_data_addr = 0x8000 + (self.tile_id as u16 * 16) + (tile_line * 2) + 1;
-                self.tile_data_high = mmu.read_byte(tile_data_addr);
+                let lcdc = mmu.peek_byte(0xFF40);
+                let tile_data_addr = tile_data_address(lcdc, self.tile_id) + (tile_line * 2) + 1;
+                self.tile_data_high = mmu.peek_byte(tile_data_addr);
                 
                 self.fetcher_step = 3;
             }
@@ -257,19 +538,9 @@ impl Ppu {
             3 => {
                 // Step 3: We push 8 pixels into the FIFO (only if FIFO is empty empty enough)
                 if self.bg_fifo.len() <= 8 {
-                    // Debug: Print non-zero tiles
-                    if (self.tile_data_low != 0 || self.tile_data_high != 0) && self.ly < 10 {
-                        eprintln!("Tile ID={:02X}, data_low={:02X}, data_high={:02X}, line={}, fetcher_x={}", 
-                                 self.tile_id, self.tile_data_low, self.tile_data_high, self.ly, self.fetcher_x);
-                    }
-                    
                     // We decode the 8 pixels from the two tile data bytes
-                    for bit_pos in (0..8).rev() {
-                        let low_bit = (self.tile_data_low >> bit_pos) & 1;
-                        let high_bit = (self.tile_data_high >> bit_pos) & 1;
-                        let color_id = (high_bit << 1) | low_bit;
-                        self.bg_fifo.push(color_id);
-                    }
+                    let row = decode_tile_row(self.tile_data_low, self.tile_data_high);
+                    self.bg_fifo.extend_from_slice(&row);
                     
                     // Move to next tile
                     self.fetcher_x += 1;
@@ -281,11 +552,50 @@ impl Ppu {
         }
     }
     
+    /// This renders the whole 32x32-tile background map as a 256x256 image of shades
+    /// (0-3), ignoring scrolling and the 160x144 viewport. It honors LCDC bit 3
+    /// (tile map at 0x9800/0x9C00), bit 4 (tile data at 0x8000 unsigned or 0x8800
+    /// signed) and BGP, so it shows what a tile-map debug view should.
+    pub fn render_full_background(&self, mmu: &crate::mmu::Mmu) -> Vec<u8> {
+        let lcdc = mmu.peek_byte(0xFF40);
+        let bgp = mmu.peek_byte(0xFF47);
+        let tile_map = if lcdc & 0x08 != 0 { 0x9C00 } else { 0x9800 };
+        
+        let mut image = vec![0u8; 256 * 256];
+        for map_y in 0..32u16 {
+            for map_x in 0..32u16 {
+                let tile_id = mmu.peek_byte(tile_map + map_y * 32 + map_x);
+                let tile_addr = tile_data_address(lcdc, tile_id);
+                
+                for line in 0..8u16 {
+                    let low = mmu.peek_byte(tile_addr + line * 2);
+                    let high = mmu.peek_byte(tile_addr + line * 2 + 1);
+                    let row = decode_tile_row(low, high);
+                    
+                    let start = (map_y * 8 + line) as usize * 256 + map_x as usize * 8;
+                    for (pixel, &color_id) in image[start..start + 8].iter_mut().zip(row.iter()) {
+                        *pixel = palette_shade(bgp, color_id);
+                    }
+                }
+            }
+        }
+        image
+    }
+    
     /// This converts a color ID (0-3) to an actual color using the BGP palette
     fn get_color(&self, color_id: u8, mmu: &crate::mmu::Mmu) -> u8 {
-        let bgp = mmu.read_byte(0xFF47); // Background palette register
+        let bgp = mmu.peek_byte(0xFF47); // Background palette register
+        
+        palette_shade(bgp, color_id)
+    }
+    
+    /// This converts a sprite color ID (1-3) to an actual color using OBP0 or OBP1,
+    /// as picked by bit 4 of the sprite's attributes. Color 0 is transparent for
+    /// sprites, so callers never draw it.
+    fn get_sprite_color(&self, color_id: u8, use_obp1: bool, mmu: &crate::mmu::Mmu) -> u8 {
+        let obp = mmu.peek_byte(if use_obp1 { 0xFF49 } else { 0xFF48 });
         
-        (bgp >> (color_id * 2)) & 0x03
+        palette_shade(obp, color_id)
     }
     
     /// This returns the current scanline (LY register value)
@@ -293,6 +603,19 @@ impl Ppu {
         self.ly
     }
     
+    /// This returns how many dots have run since the current frame started at line 0
+    pub fn frame_dots(&self) -> u32 {
+        self.frame_dots
+    }
+    
+    /// This returns how many dots the last complete frame took, from the start of
+    /// line 0 to the end of line 153. It should always be 70224 (456 dots x 154
+    /// lines, `timing::CYCLES_PER_FRAME`); anything else means the mode
+    /// transitions gained or lost dots somewhere. Returns 0 before the first frame.
+    pub fn last_frame_dots(&self) -> u32 {
+        self.last_frame_dots
+    }
+    
     /// This returns the current PPU mode for the STAT register
     pub fn mode(&self) -> u8 {
         match self.state {
@@ -302,6 +625,97 @@ impl Ppu {
             PpuState::PixelTransfer => 3,
         }
     }
+
+    /// This writes the timing, fetcher and FIFO state plus the framebuffer to a
+    /// save state (the scanline hook is a debugging aid and isn't saved)
+    pub fn save_state(&self, state: &mut StateWriter) {
+        state.u8(self.mode());
+        state.u16(self.dots);
+        state.u8(self.ly);
+        state.u32(self.frame_dots);
+        state.u32(self.last_frame_dots);
+        state.u8(self.x);
+        state.u8(self.fetcher_x);
+        state.u8(self.fetcher_step);
+        state.vec(&self.bg_fifo);
+        state.u8(self.line_sprites.len() as u8);
+        for sprite in &self.line_sprites {
+            let oam = sprite.oam;
+            state.bytes(&[oam.y, oam.x, oam.tile, oam.flags]);
+            state.bytes(&sprite.pixels);
+        }
+        state.u8(self.scx_discard);
+        state.bool(self.fetching_window);
+        state.u8(self.window_line);
+        state.bool(self.window_triggered_this_frame);
+        state.u8(self.tile_id);
+        state.u8(self.tile_data_low);
+        state.u8(self.tile_data_high);
+        state.bytes(&self.framebuffer);
+        state.bool(self.frame_ready);
+        state.bool(self.lcd_was_on);
+        state.bool(self.warming_up);
+    }
+
+    /// This restores what `save_state` wrote
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), String> {
+        self.state = match state.u8()? {
+            0 => PpuState::HBlank,
+            1 => PpuState::VBlank,
+            2 => PpuState::OamSearch,
+            3 => PpuState::PixelTransfer,
+            mode => return Err(format!("Invalid PPU mode in save state: {}", mode)),
+        };
+        self.dots = state.u16()?;
+        self.ly = state.u8()?;
+        self.frame_dots = state.u32()?;
+        self.last_frame_dots = state.u32()?;
+        self.x = state.u8()?;
+        self.fetcher_x = state.u8()?;
+        self.fetcher_step = state.u8()?;
+        self.bg_fifo = state.vec()?;
+        let sprites = state.u8()? as usize;
+        if sprites > MAX_SPRITES_PER_LINE {
+            return Err(format!("Invalid sprite count in save state: {}", sprites));
+        }
+        self.line_sprites.clear();
+        for _ in 0..sprites {
+            let mut oam = [0; 4];
+            let mut pixels = [0; 8];
+            state.bytes(&mut oam)?;
+            state.bytes(&mut pixels)?;
+            let [y, x, tile, flags] = oam;
+            self.line_sprites.push(LineSprite { oam: OamEntry { y, x, tile, flags }, pixels });
+        }
+        self.scx_discard = state.u8()?;
+        self.fetching_window = state.bool()?;
+        self.window_line = state.u8()?;
+        self.window_triggered_this_frame = state.bool()?;
+        self.tile_id = state.u8()?;
+        self.tile_data_low = state.u8()?;
+        self.tile_data_high = state.u8()?;
+        state.bytes(&mut self.framebuffer)?;
+        self.frame_ready = state.bool()?;
+        self.lcd_was_on = state.bool()?;
+        self.warming_up = state.bool()?;
+        
+        // A corrupt state could otherwise index past the framebuffer or hit the
+        // fetcher's unreachable step, so we only accept values `step` can reach
+        let lines = if self.state == PpuState::VBlank { 144..=153 } else { 0..=143 };
+        if !lines.contains(&self.ly) {
+            return Err(format!("Invalid PPU line in save state: {} in mode {}", self.ly, self.mode()));
+        }
+        if self.dots >= 456 {
+            return Err(format!("Invalid PPU dot in save state: {}", self.dots));
+        }
+        if self.x > 160 || self.fetcher_x > 32 || self.fetcher_step > 3 || self.scx_discard > 7 {
+            return Err("Invalid PPU fetcher position in save state".to_string());
+        }
+        if self.bg_fifo.len() > 16 || self.bg_fifo.iter().any(|&color| color > 3) {
+            return Err("Invalid PPU pixel FIFO in save state".to_string());
+        }
+        Ok(())
+    }
 }
 
 impl Default for Ppu {
@@ -310,3 +724,644 @@ impl Default for Ppu {
     }
 }
 
+/// This looks up the shade (0-3) a palette register (BGP/OBP0/OBP1) assigns to a
+/// color ID: color N uses bits 2N+1..2N, so 0xE4 maps every color to itself
+fn palette_shade(palette: u8, color_id: u8) -> u8 {
+    (palette >> (color_id * 2)) & 0x03
+}
+
+/// This decodes one 8-pixel tile row from its two bit-plane bytes into color IDs
+/// (leftmost pixel first); the high byte supplies bit 1 of each color
+fn decode_tile_row(low: u8, high: u8) -> [u8; 8] {
+    let mut row = [0u8; 8];
+    for (i, color_id) in row.iter_mut().enumerate() {
+        let bit_pos = 7 - i;
+        *color_id = (((high >> bit_pos) & 1) << 1) | ((low >> bit_pos) & 1);
+    }
+    row
+}
+
+/// This returns the address of a tile's data for the addressing mode LCDC bit 4
+/// selects: 0x8000 + id (unsigned), or 0x9000 + id as a signed offset
+fn tile_data_address(lcdc: u8, tile_id: u8) -> u16 {
+    if lcdc & 0x10 != 0 {
+        0x8000 + tile_id as u16 * 16
+    } else {
+        (0x9000i32 + (tile_id as i8 as i32) * 16) as u16
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mmu::Mmu;
+
+    #[test]
+    fn palette_registers_read_back_what_was_written() {
+        let mut mmu = Mmu::new(vec![0; 0x8000]);
+        for (address, value) in [(0xFF47, 0xE4), (0xFF48, 0x1B), (0xFF49, 0xD2)] {
+            mmu.write_byte(address, value);
+            assert_eq!(mmu.read_byte(address), value, "0x{:04X}", address);
+        }
+    }
+
+    #[test]
+    fn palettes_decode_each_color_to_its_shade() {
+        let mut mmu = Mmu::new(vec![0; 0x8000]);
+        mmu.write_byte(0xFF47, 0xE4); // identity
+        mmu.write_byte(0xFF48, 0x1B); // reversed
+        mmu.write_byte(0xFF49, 0xD2); // 2, 0, 1, 3
+        let ppu = Ppu::new();
+
+        for color in 0..4 {
+            assert_eq!(ppu.get_color(color, &mmu), color);
+        }
+        for (color, obp0, obp1) in [(1, 2, 0), (2, 1, 1), (3, 0, 3)] {
+            assert_eq!(ppu.get_sprite_color(color, false, &mmu), obp0, "OBP0 color {}", color);
+            assert_eq!(ppu.get_sprite_color(color, true, &mmu), obp1, "OBP1 color {}", color);
+        }
+    }
+
+    /// This ticks the PPU dot by dot until it reports a finished frame
+    fn run_until_frame(ppu: &mut Ppu, mmu: &mut Mmu) {
+        for _ in 0..2 * 70224 {
+            if ppu.tick(mmu) {
+                return;
+            }
+        }
+        panic!("no frame was finished");
+    }
+
+    #[test]
+    fn first_frame_after_lcd_enable_is_blank() {
+        let mut mmu = Mmu::new(vec![0; 0x8000]);
+        mmu.write_byte(0xFF47, 0xE4);
+        // Tile 0 (used by the whole map) is solid color 3
+        for address in 0x8000..0x8010 {
+            mmu.write_byte(address, 0xFF);
+        }
+        let mut ppu = Ppu::new();
+
+        mmu.write_byte(0xFF40, 0x11);
+        ppu.tick(&mut mmu);
+        mmu.write_byte(0xFF40, 0x91);
+
+        run_until_frame(&mut ppu, &mut mmu);
+        assert!(ppu.framebuffer.iter().all(|&shade| shade == 0));
+        run_until_frame(&mut ppu, &mut mmu);
+        assert!(ppu.framebuffer.iter().all(|&shade| shade == 3));
+    }
+
+    #[test]
+    fn full_background_renders_the_whole_256x256_map() {
+        let mut mmu = Mmu::new(vec![0; 0x8000]);
+        mmu.write_byte(0xFF47, 0xE4);
+        // Tile 1 is solid color 3, tile 2 has color 1 on its left half
+        for line in 0..8 {
+            mmu.write_byte(0x8010 + line * 2, 0xFF);
+            mmu.write_byte(0x8011 + line * 2, 0xFF);
+            mmu.write_byte(0x8020 + line * 2, 0xF0);
+        }
+        mmu.write_byte(0x9800, 1); // tile (0, 0)
+        mmu.write_byte(0x9800 + 3 * 32 + 5, 2); // tile (5, 3)
+        mmu.write_byte(0x9BFF, 1); // tile (31, 31)
+
+        let image = Ppu::new().render_full_background(&mmu);
+        assert_eq!(image.len(), 256 * 256);
+        let pixel = |x: usize, y: usize| image[y * 256 + x];
+        assert_eq!(pixel(0, 0), 3);
+        assert_eq!(pixel(7, 7), 3);
+        assert_eq!(pixel(8, 0), 0);
+        assert_eq!(pixel(40, 24), 1);
+        assert_eq!(pixel(43, 31), 1);
+        assert_eq!(pixel(44, 24), 0);
+        // The last tile lands in the bottom-right corner, outside any 160x144 viewport
+        assert_eq!(pixel(255, 255), 3);
+        assert_eq!(pixel(247, 247), 0);
+    }
+
+    #[test]
+    fn full_background_follows_the_lcdc_map_and_data_selects() {
+        let mut mmu = Mmu::new(vec![0; 0x8000]);
+        mmu.write_byte(0xFF47, 0xE4);
+        // LCDC: map at 0x9C00, signed tile data, so tile 0x80 lives at 0x8800
+        mmu.write_byte(0xFF40, 0x89);
+        for address in 0x8800..0x8810 {
+            mmu.write_byte(address, 0xFF);
+        }
+        mmu.write_byte(0x9C21, 0x80); // tile (1, 1)
+
+        let image = Ppu::new().render_full_background(&mmu);
+        assert_eq!(image[8 * 256 + 8], 3);
+        assert_eq!(image[15 * 256 + 15], 3);
+        assert_eq!(image[0], 0);
+    }
+
+    #[test]
+    fn scroll_and_window_registers_read_back_what_was_written() {
+        let mut mmu = Mmu::new(vec![0; 0x8000]);
+        for (address, value) in [(0xFF42, 0x12), (0xFF43, 0x05), (0xFF4A, 0x40), (0xFF4B, 0x07)] {
+            mmu.write_byte(address, value);
+            assert_eq!(mmu.read_byte(address), value, "0x{:04X}", address);
+        }
+    }
+
+    /// This returns an MMU whose background has tile 1 (solid color 3) in map
+    /// column 0 and tile 2 (color 1 on its left half) in column 1, on every row
+    /// except that row 1 swaps them
+    fn scroll_test_mmu() -> Mmu {
+        let mut mmu = Mmu::new(vec![0; 0x8000]);
+        mmu.write_byte(0xFF47, 0xE4);
+        for line in 0..8 {
+            mmu.write_byte(0x8010 + line * 2, 0xFF);
+            mmu.write_byte(0x8011 + line * 2, 0xFF);
+            mmu.write_byte(0x8020 + line * 2, 0xF0);
+        }
+        for row in 0..32 {
+            let (first, second) = if row == 1 { (2, 1) } else { (1, 2) };
+            mmu.write_byte(0x9800 + row * 32, first);
+            mmu.write_byte(0x9801 + row * 32, second);
+        }
+        mmu
+    }
+
+    /// This returns the first 12 pixels of line `ly` in the framebuffer
+    fn line_start(ppu: &Ppu, ly: usize) -> [u8; 12] {
+        ppu.framebuffer[ly * 160..ly * 160 + 12].try_into().unwrap()
+    }
+
+    #[test]
+    fn scroll_registers_shift_the_background() {
+        for (scx, scy, expected) in [
+            (0, 0, [3, 3, 3, 3, 3, 3, 3, 3, 1, 1, 1, 1]),
+            (5, 0, [3, 3, 3, 1, 1, 1, 1, 0, 0, 0, 0, 0]), // 5 pixels
+            (8, 0, [1, 1, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0]), // one tile
+            (0, 8, [1, 1, 1, 1, 0, 0, 0, 0, 3, 3, 3, 3]), // one map row
+        ] {
+            let mut mmu = scroll_test_mmu();
+            mmu.write_byte(0xFF43, scx);
+            mmu.write_byte(0xFF42, scy);
+            let mut ppu = Ppu::new();
+            run_until_frame(&mut ppu, &mut mmu);
+            run_until_frame(&mut ppu, &mut mmu);
+            assert_eq!(line_start(&ppu, 0), expected, "SCX={} SCY={}", scx, scy);
+        }
+    }
+
+    #[test]
+    fn scx_change_applies_from_the_next_line() {
+        let mut mmu = scroll_test_mmu();
+        let mut ppu = Ppu::new();
+        run_until_frame(&mut ppu, &mut mmu);
+
+        // Change SCX once line 20 has been drawn
+        while mmu.read_byte(0xFF44) != 21 {
+            ppu.tick(&mut mmu);
+        }
+        mmu.write_byte(0xFF43, 5);
+        run_until_frame(&mut ppu, &mut mmu);
+
+        assert_eq!(line_start(&ppu, 20), [3, 3, 3, 3, 3, 3, 3, 3, 1, 1, 1, 1]);
+        assert_eq!(line_start(&ppu, 21), [3, 3, 3, 1, 1, 1, 1, 0, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn scanline_hook_fires_once_per_visible_line_with_its_registers() {
+        use std::cell::RefCell;
+        use std::rc::Rc;
+
+        let mut mmu = Mmu::new(vec![0; 0x8000]);
+        mmu.write_byte(0xFF40, 0x91);
+        let mut ppu = Ppu::new();
+        run_until_frame(&mut ppu, &mut mmu);
+
+        let lines = Rc::new(RefCell::new(Vec::new()));
+        let seen = Rc::clone(&lines);
+        ppu.set_scanline_hook(Box::new(move |line: &Scanline| {
+            seen.borrow_mut().push((line.ly, line.scy, line.bgp, line.lcdc, line.pixels.len()));
+        }));
+
+        // Each line starts with SCY and BGP set to its own number
+        let mut ly = ppu.ly();
+        loop {
+            if ppu.ly() != ly {
+                ly = ppu.ly();
+                mmu.write_byte(0xFF42, ly);
+                mmu.write_byte(0xFF47, ly);
+            }
+            if ppu.tick(&mut mmu) {
+                break;
+            }
+        }
+
+        let lines = lines.borrow();
+        assert_eq!(lines.len(), 144);
+        for (i, &(ly, scy, bgp, lcdc, pixels)) in lines.iter().enumerate() {
+            assert_eq!(ly as usize, i);
+            assert_eq!((scy, bgp, lcdc, pixels), (ly, ly, 0x91, 160), "line {}", ly);
+        }
+    }
+
+    #[test]
+    fn a_frame_takes_70224_dots_and_ends_at_line_0() {
+        let mut mmu = Mmu::new(vec![0; 0x8000]);
+        mmu.write_byte(0xFF40, 0x91);
+        let mut ppu = Ppu::new();
+        assert_eq!(ppu.last_frame_dots(), 0);
+
+        for scx in [0, 5] {
+            mmu.write_byte(0xFF43, scx);
+            // Run to the start of a frame, then through one whole frame
+            while !(ppu.ly() == 0 && ppu.frame_dots() == 0) {
+                ppu.tick(&mut mmu);
+            }
+            let mut dots = 0;
+            loop {
+                ppu.tick(&mut mmu);
+                dots += 1;
+                if ppu.frame_dots() == 0 {
+                    break;
+                }
+            }
+            assert_eq!(dots, 70224, "SCX={}", scx);
+            assert_eq!(ppu.last_frame_dots(), 70224, "SCX={}", scx);
+            assert_eq!(ppu.ly(), 0);
+        }
+    }
+
+    #[test]
+    fn load_state_rejects_out_of_range_values() {
+        let mut state = StateWriter::default();
+        Ppu::new().save_state(&mut state);
+        let good = state.finish();
+        // The state starts with the mode (u8), dots (u16) and LY (u8)
+        for (offset, value) in [(0, 4), (1, 0xFF), (3, 144), (3, 200)] {
+            let mut bad = good.clone();
+            bad[offset] = value;
+            if offset == 1 {
+                bad[2] = 0xFF;
+            }
+            let mut ppu = Ppu::new();
+            assert!(ppu.load_state(&mut StateReader::new(&bad)).is_err(), "byte {} = {}", offset, value);
+        }
+
+        let mut ppu = Ppu::new();
+        ppu.load_state(&mut StateReader::new(&good)).expect("load failed");
+    }
+
+    /// This returns an MMU with the given LCDC and BGP/OBP0 mapping every color to itself
+    fn mmu_with_lcdc(lcdc: u8) -> Mmu {
+        let mut mmu = Mmu::new(vec![0; 0x8000]);
+        mmu.write_byte(0xFF40, lcdc);
+        mmu.write_byte(0xFF47, 0xE4);
+        mmu.write_byte(0xFF48, 0xE4);
+        mmu
+    }
+
+    /// This fills the 16 bytes of tile data at `address` with one color
+    fn solid_tile(mmu: &mut Mmu, address: u16, color_id: u8) {
+        for row in 0..8 {
+            mmu.write_byte(address + row * 2, if color_id & 1 != 0 { 0xFF } else { 0x00 });
+            mmu.write_byte(address + row * 2 + 1, if color_id & 2 != 0 { 0xFF } else { 0x00 });
+        }
+    }
+
+    /// This runs the PPU until it finishes a frame and returns it
+    fn render_frame(mmu: &mut Mmu) -> Ppu {
+        let mut ppu = Ppu::new();
+        while !ppu.tick(mmu) {}
+        ppu
+    }
+
+    /// This returns the shade at screen (x, y)
+    fn pixel(ppu: &Ppu, x: usize, y: usize) -> u8 {
+        ppu.framebuffer[y * 160 + x]
+    }
+
+    #[test]
+    fn window_tile_appears_at_wx_wy() {
+        for (wx, wy) in [(47, 20), (7, 0), (100, 64)] {
+            // LCD, window with the 0x9C00 map, unsigned tile data, BG on
+            let mut mmu = mmu_with_lcdc(0xF1);
+            solid_tile(&mut mmu, 0x8010, 3);
+            mmu.write_byte(0x9C00, 0x01); // the window's top-left tile only
+            mmu.write_byte(0xFF4A, wy);
+            mmu.write_byte(0xFF4B, wx);
+            let ppu = render_frame(&mut mmu);
+
+            let (x, y) = (wx as usize - 7, wy as usize);
+            for (dx, dy) in [(0, 0), (7, 0), (0, 7), (7, 7)] {
+                assert_eq!(pixel(&ppu, x + dx, y + dy), 3, "WX={} WY={}: ({}, {})", wx, wy, x + dx, y + dy);
+            }
+            // The next window tile is blank, like the background around it
+            assert_eq!(pixel(&ppu, x + 8, y), 0, "WX={} WY={}", wx, wy);
+            assert_eq!(pixel(&ppu, x, y + 8), 0, "WX={} WY={}", wx, wy);
+            if x > 0 {
+                assert_eq!(pixel(&ppu, x - 1, y), 0, "WX={} WY={}", wx, wy);
+            }
+            if y > 0 {
+                assert_eq!(pixel(&ppu, x, y - 1), 0, "WX={} WY={}", wx, wy);
+            }
+        }
+    }
+
+    #[test]
+    fn window_line_counts_only_lines_it_was_drawn_on() {
+        // Window map: tile 1 (solid) in the first row, tile 2 (color 1) in the second
+        let mut mmu = mmu_with_lcdc(0xF1);
+        solid_tile(&mut mmu, 0x8010, 3);
+        solid_tile(&mut mmu, 0x8020, 1);
+        mmu.write_byte(0x9C00, 0x01);
+        mmu.write_byte(0x9C20, 0x02);
+        mmu.write_byte(0xFF4A, 0);
+        mmu.write_byte(0xFF4B, 7);
+
+        // Hide the window (WX past the screen) for lines 4-11 halfway through
+        let mut ppu = Ppu::new();
+        loop {
+            let frame_done = ppu.tick(&mut mmu);
+            let wx = if (4..12).contains(&ppu.ly()) { 200 } else { 7 };
+            mmu.write_byte(0xFF4B, wx);
+            if frame_done {
+                break;
+            }
+        }
+
+        // Window lines 0-3 are drawn on LY 0-3, and line 4 resumes on LY 12
+        assert_eq!(pixel(&ppu, 0, 3), 3);
+        assert_eq!(pixel(&ppu, 0, 8), 0);
+        assert_eq!(pixel(&ppu, 0, 12), 3);
+        assert_eq!(pixel(&ppu, 0, 15), 3);
+        assert_eq!(pixel(&ppu, 0, 16), 1);
+    }
+
+    #[test]
+    fn sprite_pixels_land_at_its_position_with_flipping() {
+        // (flags, where the sprite's one opaque corner pixel ends up)
+        for (flags, corner) in [(0x00, (20, 10)), (0x20, (27, 10)), (0x40, (20, 17)), (0x60, (27, 17))] {
+            // LCD, unsigned tile data, sprites (8x8) and BG on
+            let mut mmu = mmu_with_lcdc(0x93);
+            // Tile 2: only the top-left pixel is set (color 3)
+            mmu.write_byte(0x8020, 0x80);
+            mmu.write_byte(0x8021, 0x80);
+            // Sprite 0 at screen (20, 10)
+            for (offset, value) in [26, 28, 0x02, flags].into_iter().enumerate() {
+                mmu.write_byte(0xFE00 + offset as u16, value);
+            }
+            let ppu = render_frame(&mut mmu);
+
+            for y in 10..18 {
+                for x in 20..28 {
+                    // Color 0 is transparent, so the blank background shows through
+                    let expected = if (x, y) == corner { 3 } else { 0 };
+                    assert_eq!(pixel(&ppu, x, y), expected, "flags 0x{:02X}: ({}, {})", flags, x, y);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn sprite_uses_obp1_and_hides_behind_background_colors() {
+        // LCD, unsigned tile data, sprites (8x8) and BG on
+        let mut mmu = mmu_with_lcdc(0x93);
+        mmu.write_byte(0xFF49, 0x5B); // OBP1 maps color 3 to shade 1
+        solid_tile(&mut mmu, 0x8010, 1); // BG tile 1
+        solid_tile(&mut mmu, 0x8020, 3); // sprite tile 2
+        mmu.write_byte(0x9800, 0x01); // BG tile at screen (0, 0)
+        // Sprite 0 over BG color 1 at (4, 0), behind the background
+        for (offset, value) in [16, 12, 0x02, 0x80].into_iter().enumerate() {
+            mmu.write_byte(0xFE00 + offset as u16, value);
+        }
+        // Sprite 1 at (40, 0) using OBP1
+        for (offset, value) in [16, 48, 0x02, 0x10].into_iter().enumerate() {
+            mmu.write_byte(0xFE04 + offset as u16, value);
+        }
+        let ppu = render_frame(&mut mmu);
+
+        assert_eq!(pixel(&ppu, 4, 0), 1); // BG color 1 wins over the sprite
+        assert_eq!(pixel(&ppu, 8, 0), 3); // BG color 0 doesn't
+        assert_eq!(pixel(&ppu, 40, 0), 1); // color 3 through OBP1
+    }
+
+    #[test]
+    fn bg_map_switch_mid_line_applies_from_the_next_tile_fetch() {
+        // Map 0x9800 is all blank tile 0, map 0x9C00 all solid tile 1
+        let mut mmu = mmu_with_lcdc(0x91);
+        solid_tile(&mut mmu, 0x8010, 3);
+        for offset in 0..0x400 {
+            mmu.write_byte(0x9C00 + offset, 0x01);
+        }
+
+        // Switch to the 0x9C00 map once line 0 has drawn 40 pixels
+        let mut ppu = Ppu::new();
+        while !(ppu.ly == 0 && ppu.x >= 40) {
+            ppu.tick(&mut mmu);
+        }
+        mmu.write_byte(0xFF40, 0x99);
+        while !ppu.tick(&mut mmu) {}
+
+        // Pixels already fetched keep the old map; later fetches use the new one
+        assert_eq!(pixel(&ppu, 0, 0), 0);
+        assert_eq!(pixel(&ppu, 39, 0), 0);
+        assert_eq!(pixel(&ppu, 159, 0), 3);
+        assert_eq!(pixel(&ppu, 0, 1), 3);
+    }
+
+    #[test]
+    fn window_enable_toggled_mid_frame_affects_only_later_lines() {
+        // Window (all solid tiles from the 0x9C00 map) at the top-left
+        let mut mmu = mmu_with_lcdc(0xF1);
+        solid_tile(&mut mmu, 0x8010, 3);
+        for offset in 0..0x400 {
+            mmu.write_byte(0x9C00 + offset, 0x01);
+        }
+        mmu.write_byte(0xFF4A, 0);
+        mmu.write_byte(0xFF4B, 7);
+
+        // Turn the window off when LY reaches 20 and back on at 50
+        let mut ppu = Ppu::new();
+        loop {
+            let frame_done = ppu.tick(&mut mmu);
+            match ppu.ly() {
+                20 => mmu.write_byte(0xFF40, 0xD1),
+                50 => mmu.write_byte(0xFF40, 0xF1),
+                _ => {}
+            }
+            if frame_done {
+                break;
+            }
+        }
+
+        assert_eq!(pixel(&ppu, 0, 0), 3);
+        assert_eq!(pixel(&ppu, 80, 19), 3);
+        assert_eq!(pixel(&ppu, 0, 20), 0);
+        assert_eq!(pixel(&ppu, 80, 49), 0);
+        assert_eq!(pixel(&ppu, 0, 50), 3);
+        assert_eq!(pixel(&ppu, 159, 143), 3);
+    }
+
+    #[test]
+    fn tile_data_address_follows_lcdc_bit_4() {
+        // Signed (0x8800) mode: 0x9000 is tile 0, IDs 0x80-0xFF come before it
+        assert_eq!(tile_data_address(0x00, 0x00), 0x9000);
+        assert_eq!(tile_data_address(0x00, 0x7F), 0x97F0);
+        assert_eq!(tile_data_address(0x00, 0x80), 0x8800);
+        assert_eq!(tile_data_address(0x00, 0xFF), 0x8FF0);
+        // Unsigned (0x8000) mode
+        assert_eq!(tile_data_address(0x10, 0x00), 0x8000);
+        assert_eq!(tile_data_address(0x10, 0x80), 0x8800);
+        assert_eq!(tile_data_address(0x10, 0xFF), 0x8FF0);
+    }
+
+    #[test]
+    fn signed_mode_background_draws_tile_0_from_9000() {
+        // LCD and BG on, signed tile data: tile 0 at 0x9000 is solid, 0x8000 is blank
+        let mut mmu = mmu_with_lcdc(0x81);
+        solid_tile(&mut mmu, 0x9000, 2);
+        let ppu = render_frame(&mut mmu);
+        assert_eq!(pixel(&ppu, 0, 0), 2);
+        assert_eq!(pixel(&ppu, 159, 143), 2);
+
+        let mut mmu = mmu_with_lcdc(0x91);
+        solid_tile(&mut mmu, 0x9000, 2);
+        let ppu = render_frame(&mut mmu);
+        assert_eq!(pixel(&ppu, 0, 0), 0);
+    }
+
+    /// This returns whether the LCD STAT interrupt is requested in IF
+    fn stat_requested(mmu: &Mmu) -> bool {
+        mmu.peek_byte(0xFF0F) & crate::interrupts::INT_LCD_STAT != 0
+    }
+
+    #[test]
+    fn lyc_interrupt_fires_when_ly_reaches_lyc() {
+        let mut mmu = mmu_with_lcdc(0x91);
+        mmu.write_byte(0xFF45, 5);
+        mmu.write_byte(0xFF41, 0x40); // LYC=LY source only
+        mmu.write_byte(0xFF0F, 0x00);
+
+        let mut ppu = Ppu::new();
+        while ppu.ly() != 5 {
+            assert!(!stat_requested(&mmu), "requested on LY {}", ppu.ly());
+            ppu.tick(&mut mmu);
+        }
+        assert!(stat_requested(&mmu));
+        assert_eq!(mmu.peek_byte(0xFF41) & 0x04, 0x04);
+
+        // The line stays high for all of LY 5, so it doesn't fire again
+        mmu.write_byte(0xFF0F, 0x00);
+        while ppu.ly() == 5 {
+            ppu.tick(&mut mmu);
+            assert!(!stat_requested(&mmu));
+        }
+        assert_eq!(mmu.peek_byte(0xFF41) & 0x04, 0);
+    }
+
+    #[test]
+    fn lyc_match_without_the_enable_bit_only_sets_the_flag() {
+        let mut mmu = mmu_with_lcdc(0x91);
+        mmu.write_byte(0xFF45, 5);
+        mmu.write_byte(0xFF41, 0x00);
+        mmu.write_byte(0xFF0F, 0x00);
+
+        let mut ppu = Ppu::new();
+        while ppu.ly() != 5 {
+            ppu.tick(&mut mmu);
+        }
+        assert_eq!(mmu.peek_byte(0xFF41) & 0x04, 0x04);
+        assert!(!stat_requested(&mmu));
+    }
+
+    #[test]
+    fn hblank_interrupt_fires_once_per_line() {
+        let mut mmu = mmu_with_lcdc(0x91);
+        mmu.write_byte(0xFF45, 0xFF); // never matches
+        mmu.write_byte(0xFF41, 0x08); // HBlank source only
+        mmu.write_byte(0xFF0F, 0x00);
+
+        let mut ppu = Ppu::new();
+        let mut requests = 0;
+        while ppu.ly() < 10 {
+            ppu.tick(&mut mmu);
+            if stat_requested(&mmu) {
+                assert_eq!(ppu.mode(), 0);
+                requests += 1;
+                mmu.write_byte(0xFF0F, 0x00);
+            }
+        }
+        assert_eq!(requests, 10);
+    }
+
+    #[test]
+    fn line_timings_add_up_to_456_dots() {
+        let mut mmu = mmu_with_lcdc(0x91);
+        let ppu = render_frame(&mut mmu);
+        for timing in &ppu.line_timings {
+            assert_eq!(*timing, LineTiming { oam_search: 80, pixel_transfer: 167, hblank: 209 });
+        }
+
+        // SCX % 8 pixels are dropped at the start of mode 3, out of the HBlank budget
+        mmu.write_byte(0xFF43, 0x03);
+        let ppu = render_frame(&mut mmu);
+        assert_eq!(ppu.line_timings[10], LineTiming { oam_search: 80, pixel_transfer: 170, hblank: 206 });
+    }
+
+    #[test]
+    fn stat_reads_show_modes_2_3_0_across_a_line() {
+        let mut mmu = mmu_with_lcdc(0x91);
+        let mut ppu = Ppu::new();
+        let mut modes = Vec::new();
+        for _ in 0..DOTS_PER_LINE {
+            ppu.tick(&mut mmu);
+            let mode = mmu.read_byte(0xFF41) & 0x03;
+            if modes.last() != Some(&mode) {
+                modes.push(mode);
+            }
+        }
+        // The line's last dot has already moved on to mode 2 of LY 1
+        assert_eq!(modes, [2, 3, 0, 2]);
+        assert_eq!(mmu.read_byte(0xFF44), 1);
+        assert_eq!(mmu.read_byte(0xFF44), ppu.ly());
+    }
+
+    #[test]
+    fn window_latches_wy_once_per_frame() {
+        // Window made of solid tiles at the left edge, BG blank
+        let mut mmu = mmu_with_lcdc(0xF1);
+        solid_tile(&mut mmu, 0x8010, 3);
+        for offset in 0..0x400 {
+            mmu.write_byte(0x9C00 + offset, 0x01);
+        }
+        mmu.write_byte(0xFF4B, 7);
+
+        // WY matches at line 20, then moves below the screen: the window stays
+        mmu.write_byte(0xFF4A, 20);
+        let mut ppu = Ppu::new();
+        loop {
+            let frame_done = ppu.tick(&mut mmu);
+            if ppu.ly() == 30 {
+                mmu.write_byte(0xFF4A, 200);
+            }
+            if frame_done {
+                break;
+            }
+        }
+        assert_eq!(pixel(&ppu, 0, 19), 0);
+        assert_eq!(pixel(&ppu, 0, 20), 3);
+        assert_eq!(pixel(&ppu, 0, 143), 3);
+
+        // WY moved above the current line before it matched: no window this frame
+        mmu.write_byte(0xFF4A, 100);
+        loop {
+            let frame_done = ppu.tick(&mut mmu);
+            if ppu.ly() == 50 {
+                mmu.write_byte(0xFF4A, 10);
+            }
+            if frame_done {
+                break;
+            }
+        }
+        assert_eq!(pixel(&ppu, 0, 60), 0);
+        assert_eq!(pixel(&ppu, 0, 143), 0);
+    }
+}
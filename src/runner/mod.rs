@@ -0,0 +1,581 @@
+// REMINDER: Read AGENTS.md file before continuing development
+//
+// Runner - The emulation loop without any frontend
+//
+// This module drives a GameBoy frame by frame. It owns the machine and the joypad
+// input state, plus everything the loop tracks between instructions: the doctor
+// and bank-switch logs, the reference trace comparison, stuck-PC detection, the frame counter and the cycle budget
+// of the current frame. The SDL frontend in main.rs only polls events, renders
+// and paces frames; a headless caller can use `run()` directly.
+
+use std::io::{BufRead, Write};
+use std::ops::RangeInclusive;
+
+use crate::cpu::CpuError;
+use crate::debug;
+use crate::debug::compare::{CompareEnd, TraceComparer, TraceState};
+use crate::gameboy::GameBoy;
+use crate::input::Input;
+use crate::timing::CYCLES_PER_FRAME;
+
+/// What happened during one `Runner::step_frame`
+pub struct FrameResult {
+    /// The PPU finished at least one frame, so the framebuffer is worth presenting
+    pub frame_ready: bool,
+    /// The frame limit was reached; the run should end
+    pub finished: bool,
+}
+
+/// This struct runs a GameBoy instruction by instruction or frame by frame and
+/// keeps the loop's bookkeeping, so the loop can be tested and reused without SDL
+pub struct Runner {
+    pub gb: GameBoy,
+    pub input: Input,
+
+    /// ROM the machine was created from, kept for `reset`
+    rom: Vec<u8>,
+    /// Boot ROM run from power-on (`--boot`), kept for `reset`
+    boot_rom: Option<Vec<u8>>,
+
+    /// Gameboy Doctor log (`--log`), written before each instruction
+    doctor_log: Option<Box<dyn Write>>,
+    /// PC ranges the Doctor log is limited to (`--trace-filter`); empty logs everything
+    trace_filter: Vec<RangeInclusive<u16>>,
+    /// Bank switch timeline (`--rom-bank-log`)
+    bank_log: Option<Box<dyn Write>>,
+    /// Reference trace checked before each instruction (`--compare-trace`)
+    trace_compare: Option<TraceComparer>,
+    /// How the trace comparison ended; the run stops once it's set
+    compare_end: Option<CompareEnd>,
+
+    /// Frames the PPU has completed
+    frame_count: u64,
+    /// Stop after this many frames
+    max_frames: Option<u64>,
+    /// T-cycles run in the current frame
+    frame_cycles: u32,
+
+    /// PC before the previous instruction and how many times in a row it repeated
+    last_pc: u16,
+    pc_stuck_count: u32,
+
+    /// While paused, `step_frame` doesn't run anything (single `step`s and
+    /// `advance_frame` still work)
+    paused: bool,
+
+    /// CPU instructions run by `step` (steps spent halted don't count)
+    instructions: u64,
+
+    /// Speed the frontend should pace frames at, as a multiple of Game Boy speed
+    /// (1.0 = real time, 0 = uncapped); see `set_speed`
+    speed: f32,
+}
+
+impl Runner {
+    /// This creates a runner for the given ROM with a fresh machine in the post-boot state
+    pub fn new(rom: Vec<u8>) -> Self {
+        Runner {
+            gb: GameBoy::new(rom.clone()),
+            input: Input::new(),
+            rom,
+            boot_rom: None,
+            doctor_log: None,
+            trace_filter: Vec::new(),
+            bank_log: None,
+            trace_compare: None,
+            compare_end: None,
+            frame_count: 0,
+            max_frames: None,
+            frame_cycles: 0,
+            last_pc: 0,
+            pc_stuck_count: 0,
+            paused: false,
+            instructions: 0,
+            speed: 1.0,
+        }
+    }
+
+    /// This creates a runner whose machine starts at power-on and runs the given
+    /// boot ROM before the cartridge
+    pub fn with_boot_rom(rom: Vec<u8>, boot: Vec<u8>) -> Self {
+        let mut runner = Runner::new(rom);
+        runner.boot_rom = Some(boot);
+        runner.gb = runner.new_machine();
+        runner
+    }
+    
+    /// This creates a fresh machine for the ROM, running the boot ROM if there is one
+    fn new_machine(&self) -> GameBoy {
+        match &self.boot_rom {
+            Some(boot) => GameBoy::with_boot_rom(self.rom.clone(), boot.clone()),
+            None => GameBoy::new(self.rom.clone()),
+        }
+    }
+    
+    /// This enables the Gameboy Doctor log (and doctor mode, which makes LY read 0x90)
+    pub fn set_doctor_log(&mut self, log: Box<dyn Write>) {
+        self.gb.set_doctor_mode(true);
+        self.doctor_log = Some(log);
+    }
+
+    /// This limits the Gameboy Doctor log to instructions whose PC is in one of
+    /// the given ranges, so a single routine can be traced without logging the
+    /// whole run. An empty list logs every instruction again.
+    pub fn set_trace_filter(&mut self, ranges: Vec<RangeInclusive<u16>>) {
+        self.trace_filter = ranges;
+    }
+
+    /// This enables the bank switch timeline
+    pub fn set_bank_log(&mut self, log: Box<dyn Write>) {
+        self.gb.mmu.log_banking = true;
+        self.bank_log = Some(log);
+    }
+
+    /// This checks every instruction against a reference trace (Gameboy Doctor or
+    /// JSON lines, see `debug::compare`) and stops the run at the first
+    /// difference or at the end of the reference. Doctor mode is turned on since
+    /// reference logs are recorded with LY reading 0x90.
+    pub fn set_compare_trace(&mut self, reference: Box<dyn BufRead>) {
+        self.gb.set_doctor_mode(true);
+        self.trace_compare = Some(TraceComparer::new(reference));
+        self.compare_end = None;
+    }
+    
+    /// This returns how the trace comparison ended, once it has
+    pub fn compare_end(&self) -> Option<&CompareEnd> {
+        self.compare_end.as_ref()
+    }
+    
+    /// This makes `step_frame` report `finished` once this many frames have completed
+    pub fn set_max_frames(&mut self, max_frames: Option<u64>) {
+        self.max_frames = max_frames;
+    }
+
+    /// This sets the speed multiplier for fast-forward (2.0 = twice as fast, 0 =
+    /// as fast as the host allows, 1.0 = back to real time). Emulation itself is
+    /// unchanged (every frame still runs its full 70224 cycles and VBlank), only
+    /// the frontend's pacing reads it through `speed`; `run` is always uncapped.
+    pub fn set_speed(&mut self, multiplier: f32) {
+        self.speed = multiplier;
+    }
+
+    /// This returns the speed multiplier set with `set_speed`
+    pub fn speed(&self) -> f32 {
+        self.speed
+    }
+
+    /// This returns how many CPU instructions have run (steps spent halted or
+    /// stopped aren't counted)
+    pub fn instruction_count(&self) -> u64 {
+        self.instructions
+    }
+
+    /// This returns how many frames the PPU has completed
+    pub fn frame_count(&self) -> u64 {
+        self.frame_count
+    }
+
+    /// This pauses emulation: `step_frame` returns without running anything until
+    /// `resume` (`advance_frame` still runs one frame at a time)
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    /// This resumes emulation after `pause`
+    pub fn resume(&mut self) {
+        self.paused = false;
+    }
+
+    /// This returns whether emulation is paused
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// This pauses a running runner or resumes a paused one, returning whether
+    /// it's paused now
+    pub fn toggle_pause(&mut self) -> bool {
+        self.paused = !self.paused;
+        self.paused
+    }
+
+    /// This restarts the ROM from the post-boot state (or from power-on through
+    /// the boot ROM, if the runner has one). Settings made on the machine
+    /// (strictness, doctor mode, logging flags, DMA accuracy, fast serial, frozen
+    /// timer or PPU, the link cable) carry over, as do the logs and input
+    /// configuration; the frame and instruction counters start again from 0.
+    /// Cartridge RAM and the RTC are kept, as they are when a real Game Boy is
+    /// switched off and on.
+    pub fn reset(&mut self) {
+        let mut gb = self.new_machine();
+        gb.mmu.load_battery_save(&self.gb.mmu.battery_save());
+        gb.cpu.strictness = self.gb.cpu.strictness;
+        gb.mmu.doctor_mode = self.gb.mmu.doctor_mode;
+        gb.mmu.log_palette = self.gb.mmu.log_palette;
+        gb.mmu.log_banking = self.gb.mmu.log_banking;
+        gb.mmu.log_rom_writes = self.gb.mmu.log_rom_writes;
+        gb.mmu.accurate_dma = self.gb.mmu.accurate_dma;
+        gb.mmu.fast_serial = self.gb.mmu.fast_serial;
+        gb.timer.frozen = self.gb.timer.frozen;
+        gb.ppu.frozen = self.gb.ppu.frozen;
+        gb.mmu.set_serial_link(self.gb.mmu.take_serial_link());
+        self.gb = gb;
+
+        self.frame_count = 0;
+        self.frame_cycles = 0;
+        self.instructions = 0;
+        self.last_pc = 0;
+        self.pc_stuck_count = 0;
+    }
+
+    /// This returns the text the game has sent over the serial port since the
+    /// last call (Blargg test ROMs print their results this way) and clears it.
+    /// Until it's taken the text keeps accumulating in `Mmu::serial_output`.
+    pub fn take_serial_output(&mut self) -> String {
+        std::mem::take(&mut self.gb.mmu.serial_output)
+    }
+
+    /// This commits the joypad state to the MMU (exposed through 0xFF00) and
+    /// advances the input's frame counter. The frontend calls it once per frame
+    /// after handling input events.
+    pub fn commit_input(&mut self) {
+        self.input.tick_frame();
+        self.gb.mmu.set_joypad_state(self.input.read_joypad());
+    }
+
+    /// This runs one frame's worth of cycles (`CYCLES_PER_FRAME`). Counting cycles
+    /// (rather than waiting for the PPU) also covers the case where the LCD is off
+    /// and no frame is ever signaled. When the frame limit is reached the frame
+    /// ends early so the framebuffer holds exactly that frame.
+    pub fn step_frame(&mut self) -> Result<FrameResult, CpuError> {
+        if self.paused {
+            return Ok(FrameResult {
+                frame_ready: false,
+                finished: false,
+            });
+        }
+        self.run_frame()
+    }
+
+    /// This runs exactly one frame like `step_frame` does, even while paused, so
+    /// a paused game can be stepped frame by frame. It stays paused afterwards.
+    pub fn advance_frame(&mut self) -> Result<FrameResult, CpuError> {
+        self.run_frame()
+    }
+
+    /// This runs cycles until the current frame's worth is done (see `step_frame`)
+    fn run_frame(&mut self) -> Result<FrameResult, CpuError> {
+        let mut result = FrameResult {
+            frame_ready: false,
+            finished: false,
+        };
+        while self.frame_cycles < CYCLES_PER_FRAME {
+            let frame_ready = self.step()?;
+            if self.compare_end.is_some() {
+                result.finished = true;
+                return Ok(result);
+            }
+            if frame_ready {
+                result.frame_ready = true;
+
+                // Stop once we've reached the requested number of frames
+                if self.max_frames.is_some_and(|max| self.frame_count >= max) {
+                    result.finished = true;
+                    return Ok(result);
+                }
+            }
+        }
+        self.frame_cycles -= CYCLES_PER_FRAME;
+
+        Ok(result)
+    }
+
+    /// This runs one instruction with all the loop bookkeeping (logs, trace
+    /// comparison, stuck-PC detection) and returns whether the PPU
+    /// completed a frame. Once the trace comparison has ended nothing runs.
+    pub fn step(&mut self) -> Result<bool, CpuError> {
+        if self.compare_end.is_some() {
+            return Ok(false);
+        }
+        
+        // Compare the state against the reference trace before the instruction runs
+        if let Some(ref mut comparer) = self.trace_compare
+            && !self.gb.cpu.halted
+            && !self.gb.cpu.stopped
+        {
+            let state = TraceState::capture(&self.gb.cpu, &self.gb.mmu);
+            if let Some(end) = comparer.check(&state) {
+                self.compare_end = Some(end);
+                self.trace_compare = None;
+                return Ok(false);
+            }
+        }
+        
+        // Log CPU state for Gameboy Doctor (before executing next instruction)
+        // Format: A:00 F:11 B:22 C:33 D:44 E:55 H:66 L:77 SP:8888 PC:9999 PCMEM:AA,BB,CC,DD
+        if let Some(ref mut file) = self.doctor_log {
+            let pc = self.gb.cpu.registers.pc;
+            let in_filter = self.trace_filter.is_empty() || self.trace_filter.iter().any(|range| range.contains(&pc));
+            if !self.gb.cpu.halted && !self.gb.cpu.stopped && in_filter {
+                let pcmem0 = self.gb.mmu.read_byte(pc);
+                let pcmem1 = self.gb.mmu.read_byte(pc.wrapping_add(1));
+                let pcmem2 = self.gb.mmu.read_byte(pc.wrapping_add(2));
+                let pcmem3 = self.gb.mmu.read_byte(pc.wrapping_add(3));
+
+                let written = writeln!(file, "{} PCMEM:{:02X},{:02X},{:02X},{:02X}",
+                    self.gb.cpu.registers.doctor_string(),
+                    pcmem0, pcmem1, pcmem2, pcmem3
+                );
+                // A log that can't be written (full disk, closed pipe) is dropped
+                // rather than ending the run
+                if let Err(e) = written {
+                    eprintln!("Failed to write CPU log, logging stopped: {}", e);
+                    self.doctor_log = None;
+                }
+            }
+        }
+
+        // Track if PC is stuck in a loop
+        let current_pc = self.gb.cpu.registers.pc;
+        if current_pc == self.last_pc {
+            self.pc_stuck_count += 1;
+            if self.pc_stuck_count.is_multiple_of(1_000_000) {
+                eprintln!("Warning: PC stuck at 0x{:04X} for {} iterations", current_pc, self.pc_stuck_count);
+            }
+        } else {
+            if self.pc_stuck_count > 10000 {
+                eprintln!("PC was stuck at 0x{:04X} for {} iterations, now at 0x{:04X}", self.last_pc, self.pc_stuck_count, current_pc);
+            }
+            self.pc_stuck_count = 0;
+            self.last_pc = current_pc;
+        }
+
+        // Run one CPU instruction along with interrupts, timer, DMA and PPU
+        let idle = self.gb.cpu.halted || self.gb.cpu.stopped;
+        let step = self.gb.step()?;
+        if !idle {
+            self.instructions += 1;
+        }
+        self.frame_cycles += step.cycles as u32 * 4;
+
+        // Warn about writes to the ROM area that weren't banking commands
+        for write in self.gb.mmu.take_rom_writes() {
+            eprintln!("{}", debug::format_rom_write(self.gb.cycles, current_pc, &write));
+        }
+
+        // Log any bank switches the instruction made, with the PC it ran from
+        if let Some(ref mut file) = self.bank_log {
+            for switch in self.gb.mmu.take_bank_switches() {
+                if let Err(e) = writeln!(file, "{}", debug::format_bank_switch(self.gb.cycles, current_pc, &switch)) {
+                    eprintln!("Failed to write bank log, logging stopped: {}", e);
+                    self.bank_log = None;
+                    break;
+                }
+            }
+        }
+
+        if step.frame_ready {
+            self.frame_count += 1;
+
+            // Log palette changes made during this frame (palette animation debugging)
+            for bgp in self.gb.mmu.take_bgp_changes() {
+                println!("{}", debug::format_palette_change(self.frame_count, bgp));
+            }
+        }
+
+        Ok(step.frame_ready)
+    }
+
+    /// This runs frames as fast as possible until the frame limit is reached (or
+    /// forever without one). Input is committed between frames like the frontend does.
+    /// Pausing only applies to `step_frame`, so a paused runner still runs here.
+    pub fn run(&mut self) -> Result<(), CpuError> {
+        loop {
+            if self.run_frame()?.finished {
+                return Ok(());
+            }
+            self.commit_input();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io;
+
+    /// This builds a 32KB ROM that loops forever at the 0x0100 entry point (JR -2)
+    fn looping_rom() -> Vec<u8> {
+        let mut rom = vec![0; 0x8000];
+        rom[0x0100..0x0102].copy_from_slice(&[0x18, 0xFE]);
+        rom
+    }
+
+    /// A log whose writes always fail, like a pipe whose reader has gone away
+    struct ClosedPipe;
+
+    impl Write for ClosedPipe {
+        fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+            Err(io::Error::new(io::ErrorKind::BrokenPipe, "closed pipe"))
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn step_frame_runs_several_frames_headlessly() {
+        let mut runner = Runner::new(looping_rom());
+        for frame in 1..=5 {
+            let result = runner.step_frame().expect("frame failed");
+            assert!(result.frame_ready, "frame {} wasn't presented", frame);
+            assert!(!result.finished);
+            assert_eq!(runner.frame_count(), frame);
+        }
+        assert_eq!(runner.gb.cpu.registers.pc, 0x0100);
+        assert!(runner.gb.cycles > 0);
+
+        // Paused, step_frame runs nothing
+        runner.pause();
+        assert!(!runner.step_frame().expect("paused frame failed").frame_ready);
+        assert_eq!(runner.frame_count(), 5);
+
+        // advance_frame runs exactly one frame and stays paused
+        assert!(runner.advance_frame().expect("advanced frame failed").frame_ready);
+        assert_eq!(runner.frame_count(), 6);
+        assert!(runner.is_paused());
+        assert!(!runner.step_frame().expect("paused frame failed").frame_ready);
+        assert_eq!(runner.frame_count(), 6);
+    }
+
+    #[test]
+    fn run_ends_at_the_frame_limit_even_while_paused() {
+        let mut runner = Runner::new(looping_rom());
+        runner.set_max_frames(Some(3));
+        runner.pause();
+        runner.run().expect("run failed");
+        assert_eq!(runner.frame_count(), 3);
+    }
+
+    #[test]
+    fn failed_log_write_stops_logging_instead_of_panicking() {
+        let mut runner = Runner::new(looping_rom());
+        runner.set_doctor_log(Box::new(ClosedPipe));
+        runner.set_max_frames(Some(2));
+        runner.run().expect("run failed");
+        assert!(runner.doctor_log.is_none());
+        assert_eq!(runner.frame_count(), 2);
+    }
+
+    #[test]
+    fn input_is_committed_once_per_frame() {
+        let mut runner = Runner::new(looping_rom());
+        runner.gb.mmu.write_byte(0xFF00, 0x10); // select the buttons
+
+        runner.input.press(crate::input::Button::A);
+        runner.step_frame().expect("frame failed");
+        assert_eq!(runner.gb.mmu.read_byte(0xFF00) & 0x0F, 0x0F);
+
+        // A shows up once the frontend commits the frame's input
+        runner.commit_input();
+        assert_eq!(runner.gb.mmu.read_byte(0xFF00) & 0x0F, 0x0E);
+    }
+
+    #[test]
+    fn reset_keeps_frozen_components() {
+        let mut runner = Runner::new(looping_rom());
+        runner.gb.timer.frozen = true;
+        runner.gb.ppu.frozen = true;
+        runner.step_frame().expect("frame failed");
+        runner.reset();
+        assert!(runner.gb.timer.frozen);
+        assert!(runner.gb.ppu.frozen);
+        assert_eq!(runner.frame_count(), 0);
+    }
+
+    /// A log that keeps what's written so a test can read it back
+    #[derive(Clone, Default)]
+    struct SharedLog(std::rc::Rc<std::cell::RefCell<Vec<u8>>>);
+
+    impl Write for SharedLog {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn trace_filter_only_logs_instructions_in_range() {
+        // 0x0100: JP $0150; 0x0150: NOP x3, JP $0200; 0x0200: JR -2
+        let mut rom = vec![0; 0x8000];
+        rom[0x0100..0x0103].copy_from_slice(&[0xC3, 0x50, 0x01]);
+        rom[0x0150..0x0156].copy_from_slice(&[0x00, 0x00, 0x00, 0xC3, 0x00, 0x02]);
+        rom[0x0200..0x0202].copy_from_slice(&[0x18, 0xFE]);
+
+        let log = SharedLog::default();
+        let mut runner = Runner::new(rom);
+        runner.set_doctor_log(Box::new(log.clone()));
+        runner.set_trace_filter(vec![0x0150..=0x0160]);
+        runner.step_frame().expect("frame failed");
+
+        let text = String::from_utf8(log.0.borrow().clone()).unwrap();
+        let pcs: Vec<&str> = text.lines().map(|line| &line[line.find("PC:").unwrap() + 3..][..4]).collect();
+        assert_eq!(pcs, ["0150", "0151", "0152", "0153"]);
+    }
+
+    #[test]
+    fn headless_run_leaves_serial_output_to_the_caller() {
+        // Sends the zero-terminated string at 0x0150 over serial one byte at a
+        // time, waiting for each transfer to finish, then loops forever
+        let mut rom = vec![0; 0x8000];
+        rom[0x0100..0x0117].copy_from_slice(&[
+            0x21, 0x50, 0x01, // 0100: LD HL,$0150
+            0x2A,             // 0103: LD A,(HL+)
+            0xB7,             // 0104: OR A
+            0x28, 0x0E,       // 0105: JR Z,$0115
+            0xE0, 0x01,       // 0107: LDH ($01),A   ; SB = character
+            0x3E, 0x81,       // 0109: LD A,$81
+            0xE0, 0x02,       // 010B: LDH ($02),A   ; start an internal-clock transfer
+            0xF0, 0x02,       // 010D: LDH A,($02)
+            0xCB, 0x7F,       // 010F: BIT 7,A
+            0x20, 0xFA,       // 0111: JR NZ,$010D   ; until the transfer completes
+            0x18, 0xEE,       // 0113: JR $0103
+            0x18, 0xFE,       // 0115: JR $0115
+        ]);
+        rom[0x0150..0x0157].copy_from_slice(b"Passed\0");
+
+        let mut runner = Runner::new(rom);
+        runner.set_max_frames(Some(10));
+        runner.run().expect("run failed");
+
+        assert_eq!(runner.frame_count(), 10);
+        assert_eq!(runner.take_serial_output(), "Passed");
+        assert_eq!(runner.take_serial_output(), "");
+    }
+
+    #[test]
+    fn reset_keeps_fast_serial() {
+        let mut runner = Runner::new(looping_rom());
+        runner.gb.mmu.fast_serial = true;
+        runner.reset();
+        assert!(runner.gb.mmu.fast_serial);
+    }
+
+    #[test]
+    fn set_speed_only_changes_pacing() {
+        let mut runner = Runner::new(looping_rom());
+        assert_eq!(runner.speed(), 1.0);
+        runner.set_speed(4.0);
+        assert_eq!(runner.speed(), 4.0);
+
+        // A frame still runs its full 70224 dots (17556 M-cycles)
+        runner.step_frame().expect("frame failed");
+        let cycles = runner.gb.cycles;
+        runner.step_frame().expect("frame failed");
+        assert!((runner.gb.cycles - cycles).abs_diff(17556) < 8);
+    }
+}
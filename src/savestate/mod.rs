@@ -0,0 +1,319 @@
+// REMINDER: Read AGENTS.md file before continuing development
+//
+// Save States - Snapshots of the whole machine
+//
+// This module holds the format save states are written in. A state is a
+// `SaveState` serialized with serde, either with bincode (compact, the default)
+// or serde_json (readable, for debugging). It carries a header (format name,
+// layout version, emulator version and a fingerprint of the ROM), the CPU
+// registers and cycle count as named fields, and the rest of the machine as
+// bytes. Those bytes come from each component writing its own fields in a fixed
+// order through `StateWriter` and reading them back in the same order through
+// `StateReader` (see the `save_state`/`load_state` methods on Mmu, Ppu and Timer,
+// and `GameBoy::save_state_as` which ties them together). Numbers in them are
+// little-endian; variable-length data is prefixed with its length as a u32.
+//
+// A JSON state looks like this, with the machine bytes as a hex string:
+//
+//   {
+//     "format": "rustiboa-snt save state",
+//     "version": 9,
+//     "emulator": "0.1.0",
+//     "rom_fingerprint": 2166136261,
+//     "cpu": { "a": 1, "f": 176, ..., "pc": 336, "ime": true, ... },
+//     "cycles": 123456,
+//     "machine": "0000c0ff..."
+//   }
+//
+// Every field is loaded, so editing a register in a JSON state changes the
+// restored machine. A state from another layout version or emulator build, or
+// for another ROM, is rejected with an error instead of being loaded into the
+// wrong layout. `SaveState::decode` tells the two formats apart by their first
+// byte: JSON states start with `{`.
+
+use serde::{Deserialize, Serialize};
+
+/// Layout version of the state; bump it whenever a field is added, removed or
+/// reordered
+pub const STATE_VERSION: u16 = 9;
+
+/// Version of the emulator that wrote the state. Component internals can change
+/// between builds without a format bump, so states only load in the same version.
+const EMULATOR_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Value of the "format" field that identifies a save state
+const FORMAT_NAME: &str = "rustiboa-snt save state";
+
+/// How save states are written to disk
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StateFormat {
+    /// Compact binary, serialized with bincode
+    #[default]
+    Bincode,
+    /// A readable JSON document, serialized with serde_json
+    Json,
+}
+
+impl std::str::FromStr for StateFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "bincode" => Ok(StateFormat::Bincode),
+            "json" => Ok(StateFormat::Json),
+            _ => Err(format!("Unknown save state format: {} (expected bincode or json)", s)),
+        }
+    }
+}
+
+/// This struct is a whole save state as it's written to disk
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SaveState {
+    /// Always `FORMAT_NAME`
+    pub format: String,
+    /// Layout version (`STATE_VERSION`)
+    pub version: u16,
+    /// Emulator version that wrote the state
+    pub emulator: String,
+    /// FNV-1a hash of the ROM the state was made with
+    pub rom_fingerprint: u32,
+    pub cpu: CpuState,
+    /// Total M-cycles run since power-on
+    pub cycles: u64,
+    /// Memory, banking, PPU and timer, in `StateWriter` layout
+    #[serde(with = "machine_bytes")]
+    pub machine: Vec<u8>,
+}
+
+/// This struct holds the CPU registers and execution state in a save state
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CpuState {
+    pub a: u8,
+    pub f: u8,
+    pub b: u8,
+    pub c: u8,
+    pub d: u8,
+    pub e: u8,
+    pub h: u8,
+    pub l: u8,
+    pub sp: u16,
+    pub pc: u16,
+    pub ime: bool,
+    pub ime_pending: bool,
+    pub halted: bool,
+    pub halt_bug: bool,
+    pub stopped: bool,
+    pub last_m_cycles: u8,
+}
+
+impl SaveState {
+    /// This creates a state for the given ROM with the current header
+    pub fn new(rom: &[u8], cpu: CpuState, cycles: u64, machine: Vec<u8>) -> Self {
+        SaveState {
+            format: FORMAT_NAME.to_string(),
+            version: STATE_VERSION,
+            emulator: EMULATOR_VERSION.to_string(),
+            rom_fingerprint: rom_fingerprint(rom),
+            cpu,
+            cycles,
+            machine,
+        }
+    }
+
+    /// This serializes the state in the given format
+    pub fn encode(&self, format: StateFormat) -> Vec<u8> {
+        // Neither serializer can fail on these plain fields
+        match format {
+            StateFormat::Bincode => bincode::serialize(self).expect("Failed to serialize save state"),
+            StateFormat::Json => {
+                let mut json = serde_json::to_vec_pretty(self).expect("Failed to serialize save state");
+                json.push(b'\n');
+                json
+            }
+        }
+    }
+
+    /// This reads a state in either format (JSON states start with `{`) and
+    /// checks its header against the current build and the given ROM
+    pub fn decode(bytes: &[u8], rom: &[u8]) -> Result<Self, String> {
+        let state: SaveState = if bytes.trim_ascii_start().first() == Some(&b'{') {
+            serde_json::from_slice(bytes).map_err(|e| format!("Not a save state: {}", e))?
+        } else {
+            bincode::deserialize(bytes).map_err(|_| "Not a save state")?
+        };
+
+        if state.format != FORMAT_NAME {
+            return Err("Not a save state".to_string());
+        }
+        if state.version != STATE_VERSION {
+            return Err(format!(
+                "Save state format version {} isn't supported (expected {})",
+                state.version, STATE_VERSION
+            ));
+        }
+        if state.emulator != EMULATOR_VERSION {
+            return Err(format!(
+                "Save state was written by emulator version {}, this is {}",
+                state.emulator, EMULATOR_VERSION
+            ));
+        }
+        if state.rom_fingerprint != rom_fingerprint(rom) {
+            return Err("Save state was made with a different ROM".to_string());
+        }
+        Ok(state)
+    }
+}
+
+/// This (de)serializes the machine bytes as a hex string in readable formats
+/// (JSON) and as plain bytes otherwise (bincode)
+mod machine_bytes {
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            let hex: String = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+            serializer.serialize_str(&hex)
+        } else {
+            serializer.serialize_bytes(bytes)
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        if !deserializer.is_human_readable() {
+            return Vec::<u8>::deserialize(deserializer);
+        }
+        let hex = String::deserialize(deserializer)?;
+        if !hex.is_ascii() || hex.len() % 2 != 0 {
+            return Err(D::Error::custom("malformed \"machine\" field"));
+        }
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16))
+            .collect::<Result<Vec<u8>, _>>()
+            .map_err(|_| D::Error::custom("malformed \"machine\" field"))
+    }
+}
+
+/// This returns the FNV-1a hash of a ROM, stored in the header so a state isn't
+/// loaded into a different game
+pub fn rom_fingerprint(rom: &[u8]) -> u32 {
+    rom.iter().fold(0x811C_9DC5, |hash, &byte| (hash ^ byte as u32).wrapping_mul(0x0100_0193))
+}
+
+/// This struct collects the machine bytes of a save state
+#[derive(Default)]
+pub struct StateWriter {
+    bytes: Vec<u8>,
+}
+
+impl StateWriter {
+    pub fn u8(&mut self, value: u8) {
+        self.bytes.push(value);
+    }
+
+    pub fn bool(&mut self, value: bool) {
+        self.u8(value as u8);
+    }
+
+    pub fn u16(&mut self, value: u16) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    pub fn u32(&mut self, value: u32) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    pub fn u64(&mut self, value: u64) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    /// This writes bytes whose length the reader already knows (fixed-size arrays)
+    pub fn bytes(&mut self, bytes: &[u8]) {
+        self.bytes.extend_from_slice(bytes);
+    }
+
+    /// This writes bytes prefixed with their length
+    pub fn vec(&mut self, bytes: &[u8]) {
+        self.u32(bytes.len() as u32);
+        self.bytes(bytes);
+    }
+
+    /// This returns the finished state
+    pub fn finish(self) -> Vec<u8> {
+        self.bytes
+    }
+}
+
+/// This struct reads the machine bytes of a save state back, failing on truncated data
+pub struct StateReader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> StateReader<'a> {
+    /// This returns a reader at the start of the machine bytes
+    pub fn new(bytes: &'a [u8]) -> Self {
+        StateReader { bytes, position: 0 }
+    }
+
+    /// This returns the next `length` bytes
+    fn take(&mut self, length: usize) -> Result<&'a [u8], String> {
+        let end = self.position + length;
+        let bytes = self
+            .bytes
+            .get(self.position..end)
+            .ok_or("Save state is truncated")?;
+        self.position = end;
+        Ok(bytes)
+    }
+
+    pub fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn bool(&mut self) -> Result<bool, String> {
+        Ok(self.u8()? != 0)
+    }
+
+    pub fn u16(&mut self) -> Result<u16, String> {
+        let mut bytes = [0; 2];
+        self.bytes(&mut bytes)?;
+        Ok(u16::from_le_bytes(bytes))
+    }
+
+    pub fn u32(&mut self) -> Result<u32, String> {
+        let mut bytes = [0; 4];
+        self.bytes(&mut bytes)?;
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    pub fn u64(&mut self) -> Result<u64, String> {
+        let mut bytes = [0; 8];
+        self.bytes(&mut bytes)?;
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    /// This fills a fixed-size buffer
+    pub fn bytes(&mut self, buffer: &mut [u8]) -> Result<(), String> {
+        buffer.copy_from_slice(self.take(buffer.len())?);
+        Ok(())
+    }
+
+    /// This reads length-prefixed bytes
+    pub fn vec(&mut self) -> Result<Vec<u8>, String> {
+        let length = self.u32()? as usize;
+        Ok(self.take(length)?.to_vec())
+    }
+
+    /// This checks that the whole state was read
+    pub fn finish(&self) -> Result<(), String> {
+        if self.position != self.bytes.len() {
+            return Err(format!(
+                "Save state has {} unexpected bytes at the end",
+                self.bytes.len() - self.position
+            ));
+        }
+        Ok(())
+    }
+}
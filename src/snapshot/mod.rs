@@ -0,0 +1,292 @@
+// REMINDER: Read AGENTS.md file before continuing development
+//
+// Frame Snapshots - Reference frames for pixel-perfect checks
+//
+// This module saves and loads the PPU framebuffer as a compact reference file and
+// compares frames pixel by pixel. A rendering test ROM (like dmg-acid2) is run for
+// a fixed number of frames, and its last frame is checked against a stored reference
+// so rendering regressions show up as exact pixel counts and locations.
+//
+// File format: the 160x144 shade indices (0-3) packed 4 pixels per byte, first
+// pixel in the top two bits, row by row - 5760 bytes per frame.
+
+use std::fs;
+use std::io;
+
+const SCREEN_WIDTH: usize = 160;
+const SCREEN_HEIGHT: usize = 144;
+
+/// Size of a packed frame file in bytes (2 bits per pixel)
+pub const PACKED_FRAME_SIZE: usize = SCREEN_WIDTH * SCREEN_HEIGHT / 4;
+
+/// Mismatched pixels listed individually in a report before it gets summarized
+const REPORTED_MISMATCHES: usize = 10;
+
+/// Shades used by `diff_image`: differing pixels are black, matching ones white
+const DIFF_MISMATCH_SHADE: u8 = 3;
+const DIFF_MATCH_SHADE: u8 = 0;
+
+/// This struct describes how a frame differs from its reference
+pub struct FrameDiff {
+    /// Number of pixels whose shade differs
+    pub mismatched: usize,
+    /// (x, y, actual, expected) for the first mismatches in scan order
+    pub first: Vec<(usize, usize, u8, u8)>,
+    /// Bounding box (min x, min y, max x, max y) of every mismatch
+    pub bounds: Option<(usize, usize, usize, usize)>,
+}
+
+impl FrameDiff {
+    /// This returns whether the frames match exactly
+    pub fn is_match(&self) -> bool {
+        self.mismatched == 0
+    }
+
+    /// This formats the mismatch count, bounding box and first few differing pixels
+    pub fn report(&self) -> String {
+        if self.is_match() {
+            return "Frame matches reference\n".to_string();
+        }
+
+        let mut out = format!(
+            "Frame differs from reference: {} of {} pixels mismatched\n",
+            self.mismatched,
+            SCREEN_WIDTH * SCREEN_HEIGHT
+        );
+        if let Some((min_x, min_y, max_x, max_y)) = self.bounds {
+            out.push_str(&format!(
+                "Mismatch area: ({}, {}) to ({}, {})\n",
+                min_x, min_y, max_x, max_y
+            ));
+        }
+        for &(x, y, actual, expected) in &self.first {
+            out.push_str(&format!(
+                "  ({:3}, {:3}): shade {} (expected {})\n",
+                x, y, actual, expected
+            ));
+        }
+        if self.mismatched > self.first.len() {
+            out.push_str(&format!("  ... and {} more\n", self.mismatched - self.first.len()));
+        }
+        out
+    }
+}
+
+/// This packs a framebuffer of shade indices into the reference file format
+pub fn pack(framebuffer: &[u8; SCREEN_WIDTH * SCREEN_HEIGHT]) -> Vec<u8> {
+    framebuffer
+        .chunks(4)
+        .map(|pixels| {
+            pixels
+                .iter()
+                .fold(0u8, |byte, &shade| (byte << 2) | (shade & 0x03))
+        })
+        .collect()
+}
+
+/// This unpacks a reference file back into shade indices, checking its size
+pub fn unpack(bytes: &[u8]) -> Result<Box<[u8; SCREEN_WIDTH * SCREEN_HEIGHT]>, String> {
+    if bytes.len() != PACKED_FRAME_SIZE {
+        return Err(format!(
+            "Reference frame is {} bytes, expected {}",
+            bytes.len(),
+            PACKED_FRAME_SIZE
+        ));
+    }
+
+    let mut framebuffer = Box::new([0u8; SCREEN_WIDTH * SCREEN_HEIGHT]);
+    for (i, &byte) in bytes.iter().enumerate() {
+        for pixel in 0..4 {
+            framebuffer[i * 4 + pixel] = (byte >> (6 - pixel * 2)) & 0x03;
+        }
+    }
+    Ok(framebuffer)
+}
+
+/// This writes a framebuffer to a reference file (used to regenerate references)
+pub fn save(path: &str, framebuffer: &[u8; SCREEN_WIDTH * SCREEN_HEIGHT]) -> io::Result<()> {
+    fs::write(path, pack(framebuffer))
+}
+
+/// This reads a reference file. A missing file is reported as `Ok(None)` so
+/// callers can skip the comparison when the fixture isn't available.
+pub fn load(path: &str) -> Result<Option<Box<[u8; SCREEN_WIDTH * SCREEN_HEIGHT]>>, String> {
+    match fs::read(path) {
+        Ok(bytes) => unpack(&bytes).map(Some),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(format!("Failed to read reference frame {}: {}", path, e)),
+    }
+}
+
+/// This compares a frame against its reference pixel by pixel
+pub fn compare(
+    actual: &[u8; SCREEN_WIDTH * SCREEN_HEIGHT],
+    expected: &[u8; SCREEN_WIDTH * SCREEN_HEIGHT],
+) -> FrameDiff {
+    let mut diff = FrameDiff {
+        mismatched: 0,
+        first: Vec::new(),
+        bounds: None,
+    };
+
+    for (index, (&a, &e)) in actual.iter().zip(expected.iter()).enumerate() {
+        let (a, e) = (a & 0x03, e & 0x03);
+        if a == e {
+            continue;
+        }
+
+        let (x, y) = (index % SCREEN_WIDTH, index / SCREEN_WIDTH);
+        diff.mismatched += 1;
+        if diff.first.len() < REPORTED_MISMATCHES {
+            diff.first.push((x, y, a, e));
+        }
+        diff.bounds = Some(match diff.bounds {
+            None => (x, y, x, y),
+            Some((min_x, min_y, max_x, max_y)) => {
+                (min_x.min(x), min_y.min(y), max_x.max(x), max_y.max(y))
+            }
+        });
+    }
+
+    diff
+}
+
+/// This builds a frame that highlights where two frames differ: mismatched pixels
+/// are the darkest shade and matching ones the lightest, so the result can be shown
+/// or saved like any other frame. It also returns the number of mismatched pixels.
+pub fn diff_image(
+    actual: &[u8; SCREEN_WIDTH * SCREEN_HEIGHT],
+    expected: &[u8; SCREEN_WIDTH * SCREEN_HEIGHT],
+) -> (Box<[u8; SCREEN_WIDTH * SCREEN_HEIGHT]>, usize) {
+    let mut image = Box::new([DIFF_MATCH_SHADE; SCREEN_WIDTH * SCREEN_HEIGHT]);
+    let mut mismatched = 0;
+
+    for (pixel, (&a, &e)) in image.iter_mut().zip(actual.iter().zip(expected.iter())) {
+        if a & 0x03 != e & 0x03 {
+            *pixel = DIFF_MISMATCH_SHADE;
+            mismatched += 1;
+        }
+    }
+
+    (image, mismatched)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gameboy::GameBoy;
+    use std::path::{Path, PathBuf};
+
+    /// Environment variable that makes the tests write their references instead of comparing
+    const UPDATE_VAR: &str = "RUSTIBOA_UPDATE_FRAMES";
+
+    /// This returns the path of a file in tests/fixtures
+    fn fixture(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures").join(name)
+    }
+
+    /// This runs `rom` for `frames` frames and checks the last one against the named
+    /// reference, or rewrites the reference when RUSTIBOA_UPDATE_FRAMES is set
+    fn check_frame(rom: Vec<u8>, frames: u64, reference: &str) {
+        let mut gb = GameBoy::new(rom);
+        let mut frames_run = 0;
+        while frames_run < frames {
+            if gb.step().expect("CPU error").frame_ready {
+                frames_run += 1;
+            }
+        }
+        let frame = &gb.ppu.framebuffer;
+
+        let path = fixture("frames").join(reference);
+        let path = path.to_str().expect("fixture path isn't UTF-8");
+        if std::env::var_os(UPDATE_VAR).is_some() {
+            std::fs::create_dir_all(fixture("frames")).expect("failed to create tests/fixtures/frames");
+            save(path, frame).expect("failed to write reference frame");
+            eprintln!("Reference frame written: {}", path);
+            return;
+        }
+
+        let expected = load(path)
+            .expect("failed to read reference frame")
+            .unwrap_or_else(|| panic!("{} is missing, run with {}=1 to create it", path, UPDATE_VAR));
+        let diff = compare(frame, &expected);
+        assert!(diff.is_match(), "{}", diff.report());
+    }
+
+    /// Turns the LCD off, copies 4 tiles from 0x0150 to 0x8000, fills the BG map
+    /// with a pattern of them, then turns the LCD back on and loops forever
+    const DRAW_TILES: [u8; 43] = [
+        0xF3,             // 0100: DI
+        0xAF,             // 0101: XOR A
+        0xE0, 0x40,       // 0102: LDH ($40),A   ; LCD off
+        0x21, 0x00, 0x80, // 0104: LD HL,$8000
+        0x11, 0x50, 0x01, // 0107: LD DE,$0150
+        0x0E, 0x40,       // 010A: LD C,$40      ; 4 tiles of 16 bytes
+        0x1A,             // 010C: LD A,(DE)
+        0x22,             // 010D: LD (HL+),A
+        0x13,             // 010E: INC DE
+        0x0D,             // 010F: DEC C
+        0x20, 0xFA,       // 0110: JR NZ,$010C
+        0x21, 0x00, 0x98, // 0112: LD HL,$9800
+        0x7D,             // 0115: LD A,L
+        0xCB, 0x37,       // 0116: SWAP A
+        0xAD,             // 0118: XOR L
+        0xE6, 0x03,       // 0119: AND $03       ; tile = mix of the row and column bits
+        0x22,             // 011B: LD (HL+),A
+        0x7C,             // 011C: LD A,H
+        0xFE, 0x9C,       // 011D: CP $9C
+        0x20, 0xF4,       // 011F: JR NZ,$0115   ; until the whole map is filled
+        0x3E, 0xE4,       // 0121: LD A,$E4
+        0xE0, 0x47,       // 0123: LDH ($47),A   ; BGP = 3,2,1,0
+        0x3E, 0x91,       // 0125: LD A,$91
+        0xE0, 0x40,       // 0127: LDH ($40),A   ; LCD and BG on, tiles at 0x8000
+        0x18, 0xFE,       // 0129: JR $0129
+    ];
+
+    /// Tile data for DRAW_TILES: blank, solid, checkerboard and vertical stripes
+    fn tile_data() -> Vec<u8> {
+        let mut tiles = vec![0x00; 16];
+        tiles.extend([0xFF; 16]);
+        tiles.extend([0x55, 0x55, 0xAA, 0xAA].repeat(4));
+        tiles.extend([0x0F, 0x33].repeat(8));
+        tiles
+    }
+
+    #[test]
+    fn background_tiles_match_reference() {
+        let mut rom = vec![0; 0x8000];
+        rom[0x0100..0x0100 + DRAW_TILES.len()].copy_from_slice(&DRAW_TILES);
+        let tiles = tile_data();
+        rom[0x0150..0x0150 + tiles.len()].copy_from_slice(&tiles);
+
+        check_frame(rom, 10, "tiles.frame");
+    }
+
+    #[test]
+    fn dmg_acid2_matches_reference() {
+        let rom = match std::fs::read(fixture("dmg-acid2.gb")) {
+            Ok(rom) => rom,
+            Err(_) => {
+                eprintln!("Skipping dmg-acid2: put the ROM at tests/fixtures/dmg-acid2.gb to run it");
+                return;
+            }
+        };
+
+        check_frame(rom, 60, "dmg-acid2.frame");
+    }
+
+    #[test]
+    fn diff_image_marks_each_mismatched_pixel() {
+        let frame = [2u8; SCREEN_WIDTH * SCREEN_HEIGHT];
+        let (image, mismatched) = diff_image(&frame, &frame);
+        assert_eq!(mismatched, 0);
+        assert!(image.iter().all(|&shade| shade == DIFF_MATCH_SHADE));
+
+        let mut changed = frame;
+        changed[5 * SCREEN_WIDTH + 7] = 1;
+        let (image, mismatched) = diff_image(&changed, &frame);
+        assert_eq!(mismatched, 1);
+        assert_eq!(image[5 * SCREEN_WIDTH + 7], DIFF_MISMATCH_SHADE);
+        assert_eq!(image.iter().filter(|&&shade| shade == DIFF_MISMATCH_SHADE).count(), 1);
+    }
+}
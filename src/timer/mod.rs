@@ -6,9 +6,13 @@
 // register (DIV) that increments at 16384 Hz, and a programmable timer (TIMA)
 // that can run at 4 different frequencies selected by TAC. When TIMA overflows,
 // it loads the value from TMA and requests a timer interrupt.
+//
+// Both counters run from the very first instruction after the post-boot handoff,
+// so startup delay loops that poll DIV or TIMA terminate without a boot ROM.
 
 use crate::mmu::Mmu;
 use crate::interrupts;
+use crate::savestate::{StateReader, StateWriter};
 
 /// Timer frequencies in M-cycles (CPU clock / 4)
 /// These are the number of M-cycles between TIMA increments
@@ -24,6 +28,10 @@ pub struct Timer {
     
     /// Timer counter (increments based on TAC frequency to update TIMA)
     tima_counter: u16,
+    
+    /// Debugging aid: while set, `tick` does nothing, so DIV and TIMA stop
+    /// and no timer interrupts are raised
+    pub frozen: bool,
 }
 
 impl Timer {
@@ -32,12 +40,22 @@ impl Timer {
         Timer {
             div_counter: 0,
             tima_counter: 0,
+            frozen: false,
         }
     }
     
     /// This advances the timer by the specified number of M-cycles,
     /// updating DIV and TIMA registers and requesting timer interrupt on overflow
     pub fn tick(&mut self, cycles: u8, mmu: &mut Mmu) {
+        // A DIV write during the last instruction resets the whole divider chain.
+        // It's taken even while frozen, so an old write can't reset DIV on unfreeze.
+        if mmu.take_div_reset() {
+            self.reset_counters(mmu);
+        }
+        if self.frozen {
+            return;
+        }
+        
         // Update DIV register (increments at 16384 Hz = every 64 M-cycles)
         self.div_counter += cycles as u16;
         if self.div_counter >= 64 {
@@ -46,38 +64,73 @@ impl Timer {
         }
         
         // Check if timer is enabled (bit 2 of TAC)
-        let tac = mmu.read_byte(0xFF07);
+        let tac = mmu.peek_byte(0xFF07);
         if tac & 0x04 == 0 {
             // Timer disabled - reset counter when disabled
             self.tima_counter = 0;
             return;
         }
         
-        // Get timer frequency from TAC bits 0-1
-        let frequency = match tac & 0x03 {
-            0 => TIMER_FREQ_4096,
-            1 => TIMER_FREQ_262144,
-            2 => TIMER_FREQ_65536,
-            3 => TIMER_FREQ_16384,
-            _ => unreachable!(),
-        };
-        
         // Update TIMA based on selected frequency
+        let frequency = timer_frequency(tac);
         self.tima_counter += cycles as u16;
         while self.tima_counter >= frequency {
             self.tima_counter -= frequency;
-            
-            let tima = mmu.read_byte(0xFF05);
-            if tima == 0xFF {
-                // TIMA overflow: load TMA value and request timer interrupt
-                let tma = mmu.read_byte(0xFF06);
-                mmu.write_byte(0xFF05, tma);
-                interrupts::request_interrupt(mmu, interrupts::INT_TIMER);
-            } else {
-                // Normal increment
-                mmu.write_byte(0xFF05, tima + 1);
-            }
+            increment_tima(mmu);
+        }
+    }
+}
+
+impl Timer {
+    /// This resets the divider and TIMA counters after a DIV write. On hardware
+    /// DIV and TIMA share one counter, and TIMA ticks on a falling edge of the bit
+    /// TAC selects. If that bit was 1 (our TIMA counter past the halfway point),
+    /// zeroing the counter makes it fall, so TIMA increments one extra time.
+    fn reset_counters(&mut self, mmu: &mut Mmu) {
+        let tac = mmu.peek_byte(0xFF07);
+        if tac & 0x04 != 0 && self.tima_counter >= timer_frequency(tac) / 2 {
+            increment_tima(mmu);
         }
+        self.div_counter = 0;
+        self.tima_counter = 0;
+    }
+
+    /// This writes the divider and TIMA counters to a save state
+    pub fn save_state(&self, state: &mut StateWriter) {
+        state.u16(self.div_counter);
+        state.u16(self.tima_counter);
+    }
+
+    /// This restores what `save_state` wrote
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), String> {
+        self.div_counter = state.u16()?;
+        self.tima_counter = state.u16()?;
+        Ok(())
+    }
+}
+
+/// This returns the M-cycles between TIMA increments for TAC bits 0-1
+fn timer_frequency(tac: u8) -> u16 {
+    match tac & 0x03 {
+        0 => TIMER_FREQ_4096,
+        1 => TIMER_FREQ_262144,
+        2 => TIMER_FREQ_65536,
+        3 => TIMER_FREQ_16384,
+        _ => unreachable!(),
+    }
+}
+
+/// This increments TIMA, reloading TMA and requesting the timer interrupt on overflow
+fn increment_tima(mmu: &mut Mmu) {
+    let tima = mmu.peek_byte(0xFF05);
+    if tima == 0xFF {
+        // TIMA overflow: load TMA value and request timer interrupt
+        let tma = mmu.peek_byte(0xFF06);
+        mmu.poke_byte(0xFF05, tma);
+        interrupts::request_interrupt(mmu, interrupts::INT_TIMER);
+    } else {
+        // Normal increment
+        mmu.poke_byte(0xFF05, tima + 1);
     }
 }
 
@@ -86,3 +139,97 @@ impl Default for Timer {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::Timer;
+    use crate::gameboy::GameBoy;
+    use crate::mmu::Mmu;
+
+    /// This returns a timer and MMU with TIMA at 0, counting every 4 M-cycles
+    fn fast_timer() -> (Timer, Mmu) {
+        let mut mmu = Mmu::new(vec![0; 0x8000]);
+        mmu.write_byte(0xFF05, 0x00);
+        mmu.write_byte(0xFF07, 0x05);
+        (Timer::new(), mmu)
+    }
+
+    #[test]
+    fn div_write_restarts_the_tima_period() {
+        let (mut timer, mut mmu) = fast_timer();
+        timer.tick(1, &mut mmu);
+        mmu.write_byte(0xFF04, 0x00);
+
+        // Without the write TIMA would tick after 4 M-cycles; it now waits 4 more
+        // from the reset (taken at the start of the next tick)
+        timer.tick(3, &mut mmu);
+        assert_eq!(mmu.peek_byte(0xFF05), 0x00);
+        timer.tick(1, &mut mmu);
+        assert_eq!(mmu.peek_byte(0xFF05), 0x01);
+    }
+
+    #[test]
+    fn div_write_past_half_a_period_ticks_tima_early() {
+        let (mut timer, mut mmu) = fast_timer();
+        timer.tick(2, &mut mmu);
+        mmu.write_byte(0xFF04, 0x00);
+
+        // The selected counter bit falls from 1 to 0, so TIMA ticks right away
+        timer.tick(1, &mut mmu);
+        assert_eq!(mmu.peek_byte(0xFF05), 0x01);
+        timer.tick(2, &mut mmu);
+        assert_eq!(mmu.peek_byte(0xFF05), 0x01);
+        timer.tick(1, &mut mmu);
+        assert_eq!(mmu.peek_byte(0xFF05), 0x02);
+    }
+
+    #[test]
+    fn div_counts_from_the_first_instruction() {
+        let mut gb = GameBoy::with_code(&[0x18, 0xFE]); // JR $0100
+        let div = gb.mmu.peek_byte(0xFF04);
+        while !gb.step().expect("step failed").frame_ready {}
+
+        // One increment every 64 M-cycles since power-on
+        assert!(gb.cycles > 64);
+        assert_eq!(gb.mmu.peek_byte(0xFF04), div.wrapping_add((gb.cycles / 64) as u8));
+    }
+
+    #[test]
+    fn div_polling_loop_ends() {
+        let mut gb = GameBoy::with_code(&[
+            0xF0, 0x04, // 0100: LDH A,($04)
+            0xFE, 0x10, // 0102: CP $10
+            0x20, 0xFA, // 0104: JR NZ,$0100
+            0x18, 0xFE, // 0106: JR $0106
+        ]);
+        // DIV takes every value within 256 increments
+        while gb.cpu.registers.pc != 0x0106 && gb.cycles < 257 * 64 {
+            gb.step().expect("step failed");
+        }
+        assert_eq!(gb.cpu.registers.pc, 0x0106);
+    }
+
+    #[test]
+    fn frozen_ppu_keeps_ly_while_div_advances() {
+        let mut gb = GameBoy::with_code(&[0x18, 0xFE]); // JR $0100
+        gb.ppu.frozen = true;
+        let ly = gb.mmu.peek_byte(0xFF44);
+        let div = gb.mmu.peek_byte(0xFF04);
+
+        // Enough steps to cross several scanlines (114 M-cycles each)
+        for _ in 0..1000 {
+            gb.step().expect("step failed");
+        }
+        assert_eq!(gb.mmu.peek_byte(0xFF44), ly);
+        assert_eq!(gb.mmu.peek_byte(0xFF04), div.wrapping_add((gb.cycles / 64) as u8));
+    }
+
+    #[test]
+    fn div_write_while_frozen_is_not_replayed_on_unfreeze() {
+        let mut gb = GameBoy::with_code(&[0x18, 0xFE]); // JR $0100
+        gb.timer.frozen = true;
+        gb.mmu.write_byte(0xFF04, 0x00);
+        gb.step().expect("step failed");
+        assert!(!gb.mmu.take_div_reset());
+    }
+}
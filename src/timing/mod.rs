@@ -0,0 +1,422 @@
+// REMINDER: Read AGENTS.md file before continuing development
+//
+// Frame Timing
+//
+// This module paces emulation to real Game Boy speed. The DMG draws one frame every
+// 70224 dots (T-cycles) at 4.194304 MHz, which is about 59.73 frames per second.
+// Instead of sleeping after every instruction, we run a whole frame's worth of
+// cycles as fast as possible and then sleep once for whatever time is left over.
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// T-cycles (dots) in one frame: 456 dots per scanline x 154 scanlines
+pub const CYCLES_PER_FRAME: u32 = 70224;
+
+/// DMG clock rate in T-cycles (dots) per second
+pub const CLOCK_HZ: u64 = 4_194_304;
+
+/// Real-time length of one frame (70224 / 4194304 Hz = 16.742706 ms)
+pub const FRAME_DURATION: Duration = Duration::from_nanos(16_742_706);
+
+/// Fast-forward speed (multiple of Game Boy speed) used while the turbo key is held
+pub const DEFAULT_TURBO_SPEED: f32 = 4.0;
+
+/// Frames `--benchmark` runs when no `--max-frames` is given (about a minute of
+/// emulated time)
+pub const DEFAULT_BENCHMARK_FRAMES: u64 = 3600;
+
+/// Most recent frame times kept for the performance report (10 minutes at ~60 FPS)
+const FRAME_STATS_CAPACITY: usize = 36_000;
+
+/// Upper edges (in ms) of the frame time histogram buckets; the last bucket is open-ended
+const HISTOGRAM_EDGES_MS: [f64; 6] = [8.0, 16.0, 17.0, 20.0, 33.0, 50.0];
+
+/// This returns the real-time length of one frame when running at `speed` times
+/// Game Boy speed (2.0 = twice as fast). A speed of 0 (or anything not positive
+/// and finite) means uncapped: frames get no time slice and nothing sleeps.
+pub fn frame_slot(speed: f32) -> Duration {
+    if speed > 0.0 && speed.is_finite() {
+        FRAME_DURATION.div_f32(speed)
+    } else {
+        Duration::ZERO
+    }
+}
+
+/// This computes how long to sleep after emulating a frame that took `elapsed`
+/// of wall-clock time at `speed` times Game Boy speed. A host running faster than
+/// the target sleeps off the difference; a host running slower doesn't sleep at all.
+pub fn frame_sleep_duration(elapsed: Duration, speed: f32) -> Duration {
+    frame_slot(speed).saturating_sub(elapsed)
+}
+
+/// This struct keeps emulation at real speed by sleeping once per frame. Frames
+/// get fixed time slices one `FRAME_DURATION` apart, so time lost to oversleeping
+/// in one frame is made up in the next instead of slowly dragging the rate below
+/// 59.73 FPS. `set_speed` shortens or lengthens the slices for fast-forward.
+pub struct FrameLimiter {
+    /// When the current frame's time slice started on the ideal timeline
+    slot_start: Instant,
+    /// When the previous frame ended (wall-clock), for measuring frame times
+    frame_start: Instant,
+    /// Multiple of Game Boy speed the slices are sized for (1.0 = real time, 0 = uncapped)
+    speed: f32,
+}
+
+impl FrameLimiter {
+    /// This creates a limiter with the first frame starting now
+    pub fn new() -> Self {
+        let now = Instant::now();
+        FrameLimiter {
+            slot_start: now,
+            frame_start: now,
+            speed: 1.0,
+        }
+    }
+
+    /// This sets the speed later frames are paced at, as a multiple of Game Boy
+    /// speed (see `frame_slot`). The timeline continues from the current slice, so
+    /// switching back to 1.0 resumes real time without a burst of catch-up frames.
+    pub fn set_speed(&mut self, speed: f32) {
+        self.speed = speed;
+    }
+
+    /// This sleeps off the rest of the current frame's time slice and starts the next frame.
+    /// It returns the wall-clock length of the frame that just ended (including the sleep).
+    pub fn wait_for_next_frame(&mut self) -> Duration {
+        let slot = frame_slot(self.speed);
+        let sleep = frame_sleep_duration(self.slot_start.elapsed(), self.speed);
+        if !sleep.is_zero() {
+            std::thread::sleep(sleep);
+        }
+        let now = Instant::now();
+        let frame_time = now - self.frame_start;
+        self.frame_start = now;
+
+        self.slot_start = next_slot_start(self.slot_start, now, slot);
+        frame_time
+    }
+}
+
+/// This returns when the time slice after the one starting at `slot_start` begins,
+/// given that the frame ended at `now`. Slices normally follow each other `slot`
+/// apart; a host that fell more than a slice behind (a stall, the debug console)
+/// starts over from now rather than rushing frames to catch up.
+fn next_slot_start(slot_start: Instant, now: Instant, slot: Duration) -> Instant {
+    let next = slot_start + slot;
+    if now.saturating_duration_since(next) > slot {
+        now
+    } else {
+        next
+    }
+}
+
+impl Default for FrameLimiter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// How often `FpsCounter` reports the frame rate
+const FPS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// This struct measures the frame rate actually achieved, one reading per second
+pub struct FpsCounter {
+    /// When the current measurement started
+    interval_start: Instant,
+    /// Frames counted since then
+    frames: u32,
+}
+
+impl FpsCounter {
+    /// This starts measuring now
+    pub fn new() -> Self {
+        FpsCounter {
+            interval_start: Instant::now(),
+            frames: 0,
+        }
+    }
+
+    /// This counts a presented frame and returns the frame rate once a second has
+    /// passed since the last reading
+    pub fn frame(&mut self) -> Option<f64> {
+        self.frames += 1;
+        let elapsed = self.interval_start.elapsed();
+        if elapsed < FPS_INTERVAL {
+            return None;
+        }
+        let fps = self.frames as f64 / elapsed.as_secs_f64();
+        self.interval_start = Instant::now();
+        self.frames = 0;
+        Some(fps)
+    }
+}
+
+impl Default for FpsCounter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// This returns how many times faster than a real DMG the emulator ran: the
+/// emulated time (`t_cycles` at 4.194304 MHz) divided by the wall-clock time it took
+pub fn realtime_ratio(t_cycles: u64, elapsed: Duration) -> f64 {
+    if elapsed.is_zero() {
+        return 0.0;
+    }
+    t_cycles as f64 / CLOCK_HZ as f64 / elapsed.as_secs_f64()
+}
+
+/// This struct holds the result of an unpaced benchmark run (`--benchmark`)
+pub struct Benchmark {
+    /// Frames the PPU completed
+    pub frames: u64,
+    /// CPU instructions executed
+    pub instructions: u64,
+    /// T-cycles emulated
+    pub t_cycles: u64,
+    /// Wall-clock time the run took
+    pub elapsed: Duration,
+}
+
+impl Benchmark {
+    /// This formats the speed as a multiple of real time, plus frames and
+    /// instructions per wall-clock second
+    pub fn report(&self) -> String {
+        let seconds = self.elapsed.as_secs_f64().max(f64::MIN_POSITIVE);
+        let mut out = String::new();
+        out.push_str("=== Benchmark ===\n");
+        out.push_str(&format!(
+            "Frames: {}  Instructions: {}  Wall time: {:.3} s\n",
+            self.frames, self.instructions, self.elapsed.as_secs_f64()
+        ));
+        out.push_str(&format!("Speed: {:.1}x realtime\n", realtime_ratio(self.t_cycles, self.elapsed)));
+        out.push_str(&format!("Frames/s: {:.1}\n", self.frames as f64 / seconds));
+        out.push_str(&format!("Instructions/s: {:.2} M\n", self.instructions as f64 / seconds / 1_000_000.0));
+        out
+    }
+}
+
+/// This struct collects per-frame wall-clock times so stutters that an average
+/// FPS hides show up in percentiles and a histogram. Only the most recent
+/// `FRAME_STATS_CAPACITY` frames are kept, while the totals cover the whole run.
+pub struct FrameStats {
+    /// Recent frame times, oldest first
+    durations: VecDeque<Duration>,
+    /// Frames recorded over the whole run
+    total_frames: u64,
+    /// Wall-clock time of every recorded frame added together
+    total_time: Duration,
+}
+
+impl FrameStats {
+    /// This creates an empty set of frame statistics
+    pub fn new() -> Self {
+        FrameStats {
+            durations: VecDeque::new(),
+            total_frames: 0,
+            total_time: Duration::ZERO,
+        }
+    }
+
+    /// This records one frame's wall-clock time, dropping the oldest once full
+    pub fn record(&mut self, frame_time: Duration) {
+        if self.durations.len() == FRAME_STATS_CAPACITY {
+            self.durations.pop_front();
+        }
+        self.durations.push_back(frame_time);
+        self.total_frames += 1;
+        self.total_time += frame_time;
+    }
+
+    /// This returns the nearest-rank percentile (0-100) of the buffered frame
+    /// times, or None before any frame was recorded
+    pub fn percentile(&self, percent: f64) -> Option<Duration> {
+        if self.durations.is_empty() {
+            return None;
+        }
+        let mut sorted: Vec<Duration> = self.durations.iter().copied().collect();
+        sorted.sort_unstable();
+        let rank = (percent / 100.0 * sorted.len() as f64).ceil() as usize;
+        Some(sorted[rank.clamp(1, sorted.len()) - 1])
+    }
+
+    /// This returns the average frames per second achieved over the whole run
+    pub fn average_fps(&self) -> f64 {
+        if self.total_time.is_zero() {
+            return 0.0;
+        }
+        self.total_frames as f64 / self.total_time.as_secs_f64()
+    }
+
+    /// This formats the performance report: frame count, average FPS, p50/p95/p99
+    /// and a histogram of the buffered frame times
+    pub fn report(&self) -> String {
+        let mut out = String::new();
+        out.push_str("=== Frame Times ===\n");
+        out.push_str(&format!(
+            "Frames: {}  Average: {:.2} FPS\n",
+            self.total_frames,
+            self.average_fps()
+        ));
+
+        let (Some(p50), Some(p95), Some(p99)) =
+            (self.percentile(50.0), self.percentile(95.0), self.percentile(99.0))
+        else {
+            return out;
+        };
+        out.push_str(&format!(
+            "p50: {:.2} ms  p95: {:.2} ms  p99: {:.2} ms\n",
+            p50.as_secs_f64() * 1000.0,
+            p95.as_secs_f64() * 1000.0,
+            p99.as_secs_f64() * 1000.0
+        ));
+
+        // We count frames per bucket, then draw each bucket as a bar scaled to 40 columns
+        let mut counts = [0usize; HISTOGRAM_EDGES_MS.len() + 1];
+        for duration in &self.durations {
+            let ms = duration.as_secs_f64() * 1000.0;
+            let bucket = HISTOGRAM_EDGES_MS
+                .iter()
+                .position(|&edge| ms < edge)
+                .unwrap_or(HISTOGRAM_EDGES_MS.len());
+            counts[bucket] += 1;
+        }
+        let max = counts.iter().copied().max().unwrap_or(0).max(1);
+        for (bucket, &count) in counts.iter().enumerate() {
+            let label = match bucket {
+                0 => format!("< {} ms", HISTOGRAM_EDGES_MS[0]),
+                b if b == HISTOGRAM_EDGES_MS.len() => format!(">= {} ms", HISTOGRAM_EDGES_MS[b - 1]),
+                b => format!("{}-{} ms", HISTOGRAM_EDGES_MS[b - 1], HISTOGRAM_EDGES_MS[b]),
+            };
+            out.push_str(&format!("{:>10} | {:<40} {}\n", label, "#".repeat(count * 40 / max), count));
+        }
+
+        out
+    }
+}
+
+impl Default for FrameStats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frame_duration_matches_one_frame_of_dots() {
+        let nanos = CYCLES_PER_FRAME as u128 * 1_000_000_000 / 4_194_304;
+        assert_eq!(FRAME_DURATION.as_nanos(), nanos);
+    }
+
+    #[test]
+    fn fast_host_sleeps_off_the_rest_of_the_frame() {
+        let elapsed = Duration::from_millis(4);
+        assert_eq!(frame_sleep_duration(elapsed, 1.0), FRAME_DURATION - elapsed);
+        assert_eq!(frame_sleep_duration(Duration::ZERO, 1.0), FRAME_DURATION);
+        // A host that is behind doesn't sleep at all
+        assert_eq!(frame_sleep_duration(Duration::from_millis(20), 1.0), Duration::ZERO);
+    }
+
+    #[test]
+    fn percentiles_use_the_nearest_rank() {
+        let mut stats = FrameStats::new();
+        assert_eq!(stats.percentile(50.0), None);
+        // 1 ms .. 100 ms, recorded out of order
+        for ms in (1..=100).rev() {
+            stats.record(Duration::from_millis(ms));
+        }
+        assert_eq!(stats.percentile(50.0), Some(Duration::from_millis(50)));
+        assert_eq!(stats.percentile(95.0), Some(Duration::from_millis(95)));
+        assert_eq!(stats.percentile(99.0), Some(Duration::from_millis(99)));
+        assert_eq!(stats.percentile(0.0), Some(Duration::from_millis(1)));
+    }
+
+    #[test]
+    fn report_lists_fps_percentiles_and_histogram_buckets() {
+        let mut stats = FrameStats::new();
+        for _ in 0..3 {
+            stats.record(Duration::from_millis(10));
+        }
+        stats.record(Duration::from_millis(50));
+
+        let report = stats.report();
+        let lines: Vec<&str> = report.lines().collect();
+        assert_eq!(lines[0], "=== Frame Times ===");
+        // 4 frames in 80 ms
+        assert_eq!(lines[1], "Frames: 4  Average: 50.00 FPS");
+        assert_eq!(lines[2], "p50: 10.00 ms  p95: 50.00 ms  p99: 50.00 ms");
+        assert_eq!(lines.len(), 3 + HISTOGRAM_EDGES_MS.len() + 1);
+        let counts: Vec<&str> = lines[3..].iter().map(|l| l.rsplit(' ').next().unwrap()).collect();
+        assert_eq!(counts.iter().filter(|&&c| c == "3").count(), 1);
+        assert_eq!(counts.iter().filter(|&&c| c == "1").count(), 1);
+    }
+
+    #[test]
+    fn slices_stay_on_a_fixed_timeline() {
+        let start = Instant::now();
+        // A frame that overslept by 2 ms still starts the next slice one frame
+        // after the last, so the next sleep is 2 ms shorter
+        let late = start + FRAME_DURATION + Duration::from_millis(2);
+        let next = next_slot_start(start, late, FRAME_DURATION);
+        assert_eq!(next, start + FRAME_DURATION);
+        assert_eq!(
+            frame_sleep_duration(late - next, 1.0),
+            FRAME_DURATION - Duration::from_millis(2)
+        );
+
+        // A frame that ended early doesn't move the timeline either
+        let early = start + Duration::from_millis(3);
+        assert_eq!(next_slot_start(start, early, FRAME_DURATION), start + FRAME_DURATION);
+    }
+
+    #[test]
+    fn stalled_host_starts_over_instead_of_catching_up() {
+        let start = Instant::now();
+        let stalled = start + FRAME_DURATION * 5;
+        assert_eq!(next_slot_start(start, stalled, FRAME_DURATION), stalled);
+
+        // Less than a slice behind is still made up on the timeline
+        let behind = start + FRAME_DURATION * 2 - Duration::from_millis(1);
+        assert_eq!(next_slot_start(start, behind, FRAME_DURATION), start + FRAME_DURATION);
+    }
+
+    #[test]
+    fn frame_slot_scales_with_speed_and_zero_is_uncapped() {
+        // Dividing by an f32 can be a few nanoseconds off
+        let close = |a: Duration, b: Duration| a.as_nanos().abs_diff(b.as_nanos()) < 10;
+        assert_eq!(frame_slot(1.0), FRAME_DURATION);
+        assert!(close(frame_slot(4.0), FRAME_DURATION / 4));
+        assert!(close(frame_slot(0.5), FRAME_DURATION * 2));
+        for uncapped in [0.0, -1.0, f32::NAN, f32::INFINITY] {
+            assert_eq!(frame_slot(uncapped), Duration::ZERO, "speed {}", uncapped);
+        }
+        // Uncapped frames never sleep
+        assert_eq!(frame_sleep_duration(Duration::ZERO, 0.0), Duration::ZERO);
+        assert!(close(
+            frame_sleep_duration(Duration::from_millis(1), 4.0),
+            FRAME_DURATION / 4 - Duration::from_millis(1)
+        ));
+    }
+
+    #[test]
+    fn realtime_ratio_compares_emulated_and_wall_time() {
+        // One emulated second in one wall second is real time
+        assert_eq!(realtime_ratio(CLOCK_HZ, Duration::from_secs(1)), 1.0);
+        // 350 emulated seconds in one wall second
+        assert_eq!(realtime_ratio(350 * CLOCK_HZ, Duration::from_secs(1)), 350.0);
+        // One emulated second taking two wall seconds is half speed
+        assert_eq!(realtime_ratio(CLOCK_HZ, Duration::from_secs(2)), 0.5);
+        // 60 frames in 100 ms is about 10x (a frame is slightly over 1/60 s)
+        let ratio = realtime_ratio(60 * CYCLES_PER_FRAME as u64, Duration::from_millis(100));
+        assert!((ratio - 10.046).abs() < 0.001, "ratio was {}", ratio);
+    }
+
+    #[test]
+    fn realtime_ratio_is_zero_without_elapsed_time() {
+        assert_eq!(realtime_ratio(CLOCK_HZ, Duration::ZERO), 0.0);
+    }
+}
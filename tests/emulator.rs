@@ -0,0 +1,51 @@
+// REMINDER: Read AGENTS.md file before continuing development
+//
+// Emulator Integration Tests
+//
+// These tests drive the library the way an embedder would: build an `Emulator`
+// from ROM bytes and step it, checking only what the public API shows.
+
+use rustiboa_snt::{EmuError, Emulator};
+
+/// This returns the path of a ROM in tests/fixtures
+fn fixture(name: &str) -> std::path::PathBuf {
+    std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures").join(name)
+}
+
+/// This builds a 32KB ROM with `code` placed at the 0x0100 entry point
+fn rom_with_entry(code: &[u8]) -> Vec<u8> {
+    let mut rom = vec![0; 0x8000];
+    rom[0x0100..0x0100 + code.len()].copy_from_slice(code);
+    rom
+}
+
+#[test]
+fn emulator_steps_from_entry_point() {
+    // NOP; JP $0150; at $0150: LD A,$42
+    let mut rom = rom_with_entry(&[0x00, 0xC3, 0x50, 0x01]);
+    rom[0x0150..0x0152].copy_from_slice(&[0x3E, 0x42]);
+    let mut emulator = Emulator::new(rom);
+    assert_eq!(emulator.cpu.registers.pc, 0x0100);
+    assert_eq!(emulator.cycles, 0);
+
+    let step = emulator.step().expect("NOP failed");
+    assert_eq!(step.cycles, 1);
+    assert_eq!(emulator.cpu.registers.pc, 0x0101);
+    assert_eq!(emulator.cycles, 1);
+
+    let step = emulator.step().expect("JP failed");
+    assert_eq!(step.cycles, 4);
+    assert_eq!(emulator.cpu.registers.pc, 0x0150);
+    assert_eq!(emulator.cycles, 5);
+
+    emulator.step().expect("LD A,n failed");
+    assert_eq!(emulator.cpu.registers.a, 0x42);
+    assert_eq!(emulator.cpu.registers.pc, 0x0152);
+    assert_eq!(emulator.cycles, 7);
+}
+
+#[test]
+fn from_rom_path_reports_a_missing_rom() {
+    let result = Emulator::from_rom_path(fixture("missing.gb"));
+    assert!(matches!(result, Err(EmuError::Rom(_))));
+}